@@ -1,8 +1,9 @@
 use std::io::Result;
-extern crate prost_build;
+extern crate tonic_build;
 
 fn main() -> Result<()> {
     let protos = &["src/proto/api.proto"];
-    prost_build::compile_protos(protos, &["src/proto/"])?;
+    // 生成消息类型以及 gRPC 服务端与客户端代码
+    tonic_build::configure().compile_protos(protos, &["src/proto/"])?;
     Ok(())
 }
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llm_api::utils::config::ApiDefaultsConfig;
+use llm_api::utils::response_parser::parse_chat_response;
+
+// 任意上游响应体都不能导致 panic；解析成功时至少包含一个 choice，token 数不为负
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(response) = parse_chat_response(text, &ApiDefaultsConfig::default()) {
+        assert!(!response.choices.is_empty());
+        let usage = &response.usage;
+        assert!(usage.prompt_tokens >= 0 && usage.completion_tokens >= 0 && usage.total_tokens >= 0);
+        // 构造的响应必须能再次序列化
+        serde_json::to_string(&response).unwrap();
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llm_api::utils::response_parser::split_status_trailer;
+
+// 拆分出的响应体必须是原输出的前缀，且不包含状态码所在的末行
+fuzz_target!(|data: &[u8]| {
+    let output = String::from_utf8_lossy(data);
+    let (body, _status) = split_status_trailer(&output);
+    assert!(output.starts_with(body));
+    assert!(body.len() < output.len() || body.is_empty());
+});
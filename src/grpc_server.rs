@@ -0,0 +1,285 @@
+use crate::{log_info, tr};
+use crate::handlers::chat_completion_handler::{TaskSender, process_chat_completion};
+use crate::models::api_model::{AppState, ChatResponseJson};
+use crate::proto::llm_cache_server::{LlmCache, LlmCacheServer};
+use crate::proto::{
+    ChatChoice, ChatMessage, ChatRequest, ChatResponse, HitRate, MemoryCacheStats, StatsRequest,
+    StatsResponse, TopQuestion, TopQuestionsRequest, TopQuestionsResponse, Usage,
+};
+use crate::server::{listen_address, shutdown_signal};
+use crate::utils::admin_auth::{authorize_admin, bearer_token};
+use crate::utils::analytics::top_questions;
+use crate::utils::error::AppError;
+use crate::utils::hit_stats::HitRateSnapshot;
+use crate::utils::systemd;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "0.0.0.0".to_string(),
+            port: 50051,
+        }
+    }
+}
+
+type SharedState = Arc<(Arc<AppState>, TaskSender, TaskSender)>;
+
+// 聊天请求在改写规则中匹配的路径
+const CHAT_COMPLETION_PATH: &str = "/api.LlmCache/ChatCompletion";
+
+/// gRPC 服务实现，与 HTTP 接口共享同一个 AppState
+pub struct LlmCacheService {
+    app_state: SharedState,
+}
+
+impl LlmCacheService {
+    pub fn new(app_state: SharedState) -> Self {
+        Self { app_state }
+    }
+
+    // 统计与排行与 HTTP 的 /admin/* 接口一样，需要在 authorization 元数据中携带 admin.token
+    fn authorize_admin<T>(&self, request: &Request<T>) -> Result<(), AppError> {
+        let provided = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(bearer_token);
+        authorize_admin(provided, &self.app_state.0.config.admin.token)
+    }
+}
+
+// HTTP 状态码对应的 gRPC 状态码
+fn status_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    }
+}
+
+fn to_status(e: AppError) -> Status {
+    Status::new(status_code(e.status()), e.message())
+}
+
+// 从错误响应体中取出错误信息，非 JSON 时使用原始内容
+fn error_message(body: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned())
+}
+
+fn to_proto_response(response: ChatResponseJson) -> ChatResponse {
+    ChatResponse {
+        id: response.id,
+        object: response.object,
+        created: response.created,
+        model: response.model,
+        choices: response
+            .choices
+            .into_iter()
+            .map(|choice| ChatChoice {
+                index: choice.index,
+                finish_reason: choice.finish_reason,
+                message: Some(ChatMessage {
+                    role: choice.message.role,
+                    content: choice.message.content,
+                }),
+            })
+            .collect(),
+        usage: Some(Usage {
+            prompt_tokens: response.usage.prompt_tokens,
+            completion_tokens: response.usage.completion_tokens,
+            total_tokens: response.usage.total_tokens,
+        }),
+        system_fingerprint: response.system_fingerprint,
+    }
+}
+
+fn to_proto_hit_rate(snapshot: HitRateSnapshot) -> HitRate {
+    HitRate {
+        window_minutes: snapshot.window_minutes,
+        memory_hits: snapshot.memory_hits,
+        db_hits: snapshot.db_hits,
+        misses: snapshot.misses,
+        bypass: snapshot.bypass,
+        hit_rate: snapshot.hit_rate,
+    }
+}
+
+#[tonic::async_trait]
+impl LlmCache for LlmCacheService {
+    async fn chat_completion(
+        &self,
+        request: Request<ChatRequest>,
+    ) -> Result<Response<ChatResponse>, Status> {
+        // gRPC 自身的元数据不转发给上游，其余元数据按 HTTP 请求头处理；
+        // accept-encoding 也一并去掉，响应体在下面按 JSON 读取，不能是压缩后的缓存命中响应
+        let mut headers = request.metadata().clone().into_headers();
+        let grpc_keys: Vec<_> = headers
+            .keys()
+            .filter(|key| {
+                let key = key.as_str();
+                key == "content-type"
+                    || key == "te"
+                    || key == "accept-encoding"
+                    || key.starts_with("grpc-")
+            })
+            .cloned()
+            .collect();
+        for key in grpc_keys {
+            headers.remove(key);
+        }
+
+        let request = request.into_inner();
+        if request.stream {
+            return Err(Status::invalid_argument(tr!(
+                "gRPC 接口不支持流式请求",
+                "The gRPC API does not support streaming requests"
+            )));
+        }
+        // 经由 JSON 转换，未设置的字段使用与 HTTP 接口一致的默认值
+        let mut payload = json!({
+            "model": request.model,
+            "messages": request
+                .messages
+                .into_iter()
+                .map(|message| json!({ "role": message.role, "content": message.content }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(temperature) = request.temperature {
+            payload["temperature"] = json!(temperature);
+        }
+        if request.max_tokens != 0 {
+            payload["max_tokens"] = json!(request.max_tokens);
+        }
+        let payload = serde_json::from_value(payload).map_err(|e| {
+            Status::invalid_argument(tr!("无效的请求: {}", "Invalid request: {}", e))
+        })?;
+
+        let response = process_chat_completion(
+            self.app_state.clone(),
+            CHAT_COMPLETION_PATH,
+            headers,
+            payload,
+        )
+        .await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| {
+                Status::internal(tr!(
+                    "读取响应失败: {}",
+                    "Failed to read the response: {}",
+                    e
+                ))
+            })?;
+        if !status.is_success() {
+            return Err(Status::new(status_code(status), error_message(&body)));
+        }
+
+        let response: ChatResponseJson = serde_json::from_slice(&body).map_err(|e| {
+            Status::internal(tr!(
+                "解析响应失败: {}",
+                "Failed to parse the response: {}",
+                e
+            ))
+        })?;
+        Ok(Response::new(to_proto_response(response)))
+    }
+
+    async fn get_stats(
+        &self,
+        request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        self.authorize_admin(&request).map_err(to_status)?;
+        let state = &self.app_state.0;
+        let memory_cache = state.memory_cache.as_ref().map(|cache| {
+            let (overflow_flushes, overflow_dropped) = cache.overflow_stats();
+            MemoryCacheStats {
+                items: cache.cache_count() as u64,
+                pending_writes: cache.pending_count() as u64,
+                bytes: cache.memory_bytes() as u64,
+                overflow_flushes,
+                overflow_dropped,
+            }
+        });
+
+        Ok(Response::new(StatsResponse {
+            window: Some(to_proto_hit_rate(state.hit_stats.window())),
+            lifetime: Some(to_proto_hit_rate(state.hit_stats.lifetime())),
+            memory_cache,
+        }))
+    }
+
+    async fn top_questions(
+        &self,
+        request: Request<TopQuestionsRequest>,
+    ) -> Result<Response<TopQuestionsResponse>, Status> {
+        self.authorize_admin(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let limit = if request.limit == 0 { 20 } else { request.limit };
+        let preview_chars = if request.preview_chars == 0 {
+            200
+        } else {
+            request.preview_chars as usize
+        };
+
+        let items = top_questions(&self.app_state.0.db, limit, preview_chars)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(TopQuestionsResponse {
+            items: items
+                .into_iter()
+                .map(|item| TopQuestion {
+                    answer_key: item.answer_key,
+                    preview: item.preview.unwrap_or_default(),
+                    hit_count: item.hit_count,
+                    size: item.size,
+                    question_count: item.question_count,
+                    created_at: item.created_at,
+                    last_hit_at: item.last_hit_at.unwrap_or_default(),
+                })
+                .collect(),
+        }))
+    }
+}
+
+/// 启动 gRPC 服务，收到退出信号时与 HTTP 服务一同停止
+pub async fn start_grpc_server(
+    app_state: SharedState,
+    config: GrpcConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = match systemd::take_listener(1) {
+        Some(listener) => TcpListener::from_std(listener)?,
+        None => TcpListener::bind(listen_address(&config.host, config.port)).await?,
+    };
+    let bind_address = listener.local_addr()?;
+    log_info!("gRPC 服务正在监听: {}", "gRPC server listening on {}", bind_address);
+
+    tonic::transport::Server::builder()
+        .add_service(LlmCacheServer::new(LlmCacheService::new(app_state)))
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), shutdown_signal())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use crate::handlers::chat_completion_handler::TaskSender;
+use crate::models::api_model::AppState;
+use crate::tr;
+use crate::utils::ab_test::ab_report;
+use crate::utils::analytics::top_questions;
+use crate::utils::cache_dry_run::dry_run_totals;
+use crate::utils::cache_epoch::{bump_cache_epoch, current_epoch};
+use crate::utils::cache_maintenance::maintenance_history;
+use crate::utils::config_dump::sanitized_config;
+use crate::utils::dashboard::{DASHBOARD_HTML, recent_requests};
+use crate::utils::db_writer::db_write_stats;
+use crate::utils::error::AppError;
+use crate::utils::live_events::{EventFilter, serve_subscriber, sse_stream, subscribe};
+use crate::utils::prometheus::render_metrics;
+use crate::utils::websocket::accept_key;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Query, Request, State},
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+type SharedState = Arc<(Arc<AppState>, TaskSender, TaskSender)>;
+
+// 处理 /admin/endpoints 路由：各上游端点的延迟百分位、成功/失败次数与进行中的请求数
+pub async fn get_endpoint_stats(State(app_state): State<SharedState>) -> Json<serde_json::Value> {
+    let state = &app_state.0;
+
+    let endpoints: Vec<serde_json::Value> = state
+        .api_endpoints
+        .iter()
+        .map(|endpoint| {
+            json!({
+                "model": endpoint.model,
+                "weight": endpoint.weight,
+                "stats": state.endpoint_stats.snapshot(&endpoint.url),
+            })
+        })
+        .collect();
+
+    Json(json!({ "endpoints": endpoints }))
+}
+
+// 处理 /admin/ab 路由：按 A/B 分组汇总请求数、成功率、延迟与回答长度
+pub async fn get_ab_report(
+    State(app_state): State<SharedState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let report = ab_report(&app_state.0.db).await?;
+    Ok(Json(report))
+}
+
+// 处理 /admin/stats 路由：滚动窗口与累计的缓存命中率，以及内存缓存的占用情况
+pub async fn get_stats(State(app_state): State<SharedState>) -> Json<serde_json::Value> {
+    let state = &app_state.0;
+
+    let memory_cache = state.memory_cache.as_ref().map(|cache| cache.stats());
+
+    Json(json!({
+        "cache_hit_rate": {
+            "window": state.hit_stats.window(),
+            "lifetime": state.hit_stats.lifetime(),
+        },
+        "memory_cache": memory_cache,
+        "db_writes": db_write_stats(),
+        "cache_epoch": current_epoch(),
+        // 缓存演练模式下本应写入的不同问题条数与编码后总字节数
+        "cache_dry_run": state.config.cache.dry_run.then(dry_run_totals),
+    }))
+}
+
+// 处理 /admin/config 路由：生效的运行配置（含默认值与覆盖项），密钥与凭据已隐藏
+pub async fn get_config(State(app_state): State<SharedState>) -> Json<serde_json::Value> {
+    Json(sanitized_config(&app_state.0.config))
+}
+
+// 处理 /metrics 路由：Prometheus 文本格式的内存缓存、缓存命中与数据库写入指标
+pub async fn get_metrics(State(app_state): State<SharedState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        render_metrics(&app_state.0),
+    )
+}
+
+// 处理 POST /admin/cache/invalidate 路由：将全局缓存纪元加一，此前写入的缓存立即全部视为未命中（不删除数据）
+pub async fn invalidate_cache(
+    State(app_state): State<SharedState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let epoch = bump_cache_epoch(&app_state.0.db).await?;
+    Ok(Json(json!({ "epoch": epoch })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    // 返回条数，默认 20，最多 500
+    limit: Option<i64>,
+    // 回答预览的字符数，默认 200，最多 1000
+    preview_chars: Option<usize>,
+}
+
+// 处理 /admin/analytics/top 路由：命中次数最多的缓存回答（解压后的预览、命中次数、大小、最近命中时间）
+pub async fn get_top_questions(
+    State(app_state): State<SharedState>,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = query.limit.unwrap_or(20);
+    let preview_chars = query.preview_chars.unwrap_or(200);
+    let items = top_questions(&app_state.0.db, limit, preview_chars).await?;
+    Ok(Json(json!({ "items": items })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceQuery {
+    // 返回条数，默认 20，最多 1000
+    limit: Option<i64>,
+}
+
+// 处理 /admin/maintenance 路由：最近的缓存维护记录（耗时、删除条数、回收字节数与错误），最新的在前
+pub async fn get_maintenance_history(
+    State(app_state): State<SharedState>,
+    Query(query): Query<MaintenanceQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 1000);
+    let runs = maintenance_history(&app_state.0.db, limit).await?;
+    Ok(Json(json!({ "runs": runs })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    // 返回条数，默认 50
+    limit: Option<usize>,
+}
+
+// 处理 /admin/requests/recent 路由：内存中保留的最近请求摘要，最新的在前
+pub async fn get_recent_requests(Query(query): Query<RecentQuery>) -> Json<serde_json::Value> {
+    let items = recent_requests(query.limit.unwrap_or(50));
+    Json(json!({ "items": items }))
+}
+
+// 处理 /dashboard 路由：内置仪表盘页面，数据由页面从管理接口获取
+pub async fn get_dashboard() -> Html<&'static str> {
+    Html(DASHBOARD_HTML)
+}
+
+// 处理 /admin/ws 路由：升级为 WebSocket 后以 JSON 文本帧推送实时事件
+// （请求、缓存命中、写入与维护），查询参数 types（逗号分隔的事件类型）与 errors=true 用于过滤
+pub async fn live_events_ws(
+    Query(filter): Query<EventFilter>,
+    mut request: Request,
+) -> Result<Response, AppError> {
+    let headers = request.headers();
+    let is_upgrade = headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    let key = headers
+        .get(header::SEC_WEBSOCKET_KEY)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| is_upgrade)
+        .ok_or_else(|| {
+            AppError::BadRequest(tr!(
+                "需要 WebSocket 升级请求",
+                "A WebSocket upgrade request is required"
+            ))
+        })?;
+    let accept = accept_key(key);
+
+    // 先订阅，握手完成之前发生的事件也不会丢失
+    let events = subscribe();
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        if let Ok(upgraded) = on_upgrade.await {
+            serve_subscriber(TokioIo::new(upgraded), events, filter).await;
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// 处理 /admin/events 路由：以 SSE 推送与 /admin/ws 相同的实时事件，过滤参数也相同
+pub async fn live_events_sse(Query(filter): Query<EventFilter>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        Body::from_stream(sse_stream(subscribe(), filter)),
+    )
+        .into_response()
+}
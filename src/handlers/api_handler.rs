@@ -1,40 +1,168 @@
-use crate::models::api_model::{
-    AppState, ChatChoice, ChatMessageJson, ChatResponseJson, Usage, select_api_endpoint,
-};
+use crate::{log_warn, tr};
+use crate::models::api_model::{AppState, ChatResponseJson, select_api_endpoint};
 use axum::{
+    body::Body,
     extract::{Json, State},
-    http::StatusCode,
+    http::{Method, StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use std::collections::HashMap;
 use std::sync::Arc;
-use crate::utils::config::Config;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader, Lines};
+use crate::utils::config::{Config, TlsConfig};
+use crate::utils::error::{AppError, MAX_UPSTREAM_ERROR_BODY};
+use crate::utils::content_filter::StreamFilter;
+use crate::utils::response_parser::{parse_chat_response, parse_error, split_status_trailer};
+use crate::utils::unix_socket::{is_unix_url, send_unix_socket_request};
+
+/// 待执行的 curl 命令。请求头、代理凭据与请求体经 `-K -` 从标准输入以配置文件格式传给 curl，
+/// 不出现在进程参数中，其他本地用户无法通过 ps 或 /proc/*/cmdline 读到 API Key 与问题内容
+pub struct CurlCommand {
+    pub command: tokio::process::Command,
+    stdin_config: String,
+}
+
+impl CurlCommand {
+    // 追加一行 curl 配置：name = "value"
+    fn option(&mut self, name: &str, value: &str) {
+        self.stdin_config.push_str(name);
+        self.stdin_config.push_str(" = \"");
+        for c in value.chars() {
+            match c {
+                '\\' => self.stdin_config.push_str("\\\\"),
+                '"' => self.stdin_config.push_str("\\\""),
+                '\n' => self.stdin_config.push_str("\\n"),
+                '\r' => self.stdin_config.push_str("\\r"),
+                '\t' => self.stdin_config.push_str("\\t"),
+                c => self.stdin_config.push(c),
+            }
+        }
+        self.stdin_config.push_str("\"\n");
+    }
+
+    /// 设置请求体（按原样发送，不解释开头的 @）
+    pub fn data(&mut self, payload: &str) -> &mut Self {
+        self.option("data-raw", payload);
+        self
+    }
+
+    /// 启动 curl 并写入标准输入中的配置，stdout 与 stderr 的设置由调用方决定
+    pub fn spawn(mut self) -> std::io::Result<tokio::process::Child> {
+        let mut child = self
+            .command
+            .arg("-K")
+            .arg("-")
+            .stdin(std::process::Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let config = std::mem::take(&mut self.stdin_config);
+            tokio::spawn(async move {
+                use tokio::io::AsyncWriteExt;
+                // curl 先读完配置再发起请求，写完后关闭标准输入
+                let _ = stdin.write_all(config.as_bytes()).await;
+            });
+        }
+        Ok(child)
+    }
+
+    /// 执行 curl 并收集全部输出
+    pub async fn output(mut self) -> std::io::Result<std::process::Output> {
+        self.command
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        self.spawn()?.wait_with_output().await
+    }
+}
+
+// 构造 curl 命令：TLS、出站代理、请求头与连接超时均来自配置，整体超时由调用方设置
+pub fn build_curl_command(
+    headers: &HashMap<String, String>,
+    tls: &TlsConfig,
+    config: &Config,
+) -> CurlCommand {
+    let mut curl = CurlCommand {
+        command: tokio::process::Command::new("curl"),
+        stdin_config: String::new(),
+    };
+    let command = &mut curl.command;
+
+    // TLS 证书校验策略与额外根证书
+    if tls.accept_invalid_certs {
+        command.arg("--insecure");
+    }
+    if !tls.ca_bundle_path.is_empty() {
+        command.arg("--cacert").arg(&tls.ca_bundle_path);
+    }
+
+    // 双向 TLS 客户端证书
+    if !tls.client_cert_path.is_empty() && !tls.client_key_path.is_empty() {
+        command
+            .arg("--cert")
+            .arg(&tls.client_cert_path)
+            .arg("--key")
+            .arg(&tls.client_key_path);
+    }
+
+    command
+        .arg("-sS") // 静默模式，但显示错误
+        .arg("--compressed") // 客户端可能声明了 Accept-Encoding，由 curl 负责解压
+        .arg("-X")
+        .arg("POST")
+        .arg("--connect-timeout")
+        .arg(config.proxy.connect_timeout_seconds.to_string());
+
+    // 出站代理（curl 原生支持 http:// 与 socks5:// 代理地址），地址中可能带有凭据
+    let outbound_proxy = &config.http_client.outbound_proxy;
+    if outbound_proxy.enabled && !outbound_proxy.url.is_empty() {
+        curl.option("proxy", &outbound_proxy.url);
+        if !outbound_proxy.username.is_empty() {
+            curl.option(
+                "proxy-user",
+                &format!("{}:{}", outbound_proxy.username, outbound_proxy.password),
+            );
+        }
+        if !outbound_proxy.no_proxy.is_empty() {
+            curl.option("noproxy", &outbound_proxy.no_proxy);
+        }
+    }
+
+    // 透传合并后的请求头（客户端请求头 + api_headers），缺省时补充 Content-Type
+    for (key, value) in headers {
+        curl.option("header", &format!("{}: {}", key, value));
+    }
+    if !headers.keys().any(|k| k.eq_ignore_ascii_case("content-type")) {
+        curl.option("header", "Content-Type: application/json");
+    }
+
+    curl
+}
 
 // 使用 curl 发送请求函数
 pub async fn send_request_with_curl(
     url: &str,
     payload: &str,
+    headers: &HashMap<String, String>,
+    tls: &TlsConfig,
     config: &Config,
-) -> Result<ChatResponseJson, (StatusCode, String)> {
-    // 使用较短的超时设置，避免长时间阻塞
+) -> Result<ChatResponseJson, AppError> {
+    let mut curl = build_curl_command(headers, tls, config);
+    curl.data(payload);
+    curl.command
+        .arg("--max-time")
+        .arg(config.proxy.request_timeout_seconds.to_string())
+        .arg("-D")
+        .arg("-") // 响应头写在响应体之前，用于读取 Retry-After
+        .arg("--suppress-connect-headers")
+        .arg("--write-out")
+        .arg("\n%{http_code}") // 末行追加上游状态码，用于透传错误响应
+        .arg(url)
+        .kill_on_drop(true);
+
+    // 外层超时比 curl 的 --max-time 稍长，确保优先由 curl 报告超时原因
     let curl_command = tokio::time::timeout(
-        std::time::Duration::from_secs(config.proxy.request_timeout_seconds),
-        tokio::process::Command::new("curl")
-            .arg("-sS") // 静默模式，但显示错误
-            .arg("-X")
-            .arg("POST")
-            .arg("-H")
-            .arg("Content-Type: application/json")
-            .arg("-H")
-            .arg("Accept: application/json")
-            .arg("-H")
-            .arg("User-Agent: llm_api_rust_client/1.0")
-            .arg("--connect-timeout")
-            .arg("5") // 连接超时5秒
-            .arg("--max-time")
-            .arg("10") // 总超时10秒
-            .arg("-d")
-            .arg(payload)
-            .arg(url)
-            .output(),
+        std::time::Duration::from_secs(config.proxy.request_timeout_seconds + 5),
+        curl.output(),
     )
     .await;
 
@@ -43,19 +171,20 @@ pub async fn send_request_with_curl(
         Ok(output_result) => match output_result {
             Ok(output) => output,
             Err(e) => {
-                println!("curl命令执行失败: {}", e);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("curl命令执行失败: {}", e),
-                ));
+                log_warn!("curl命令执行失败: {}", "curl command failed: {}", e);
+                return Err(AppError::Internal(tr!(
+                    "curl命令执行失败: {}",
+                    "Failed to run curl: {}",
+                    e
+                )));
             }
         },
         Err(_) => {
-            println!("curl命令执行超时");
-            return Err((
-                StatusCode::GATEWAY_TIMEOUT,
-                "curl命令执行超时，请检查 API URL 是否正确".to_string(),
-            ));
+            log_warn!("curl命令执行超时", "curl command timed out");
+            return Err(AppError::GatewayTimeout(tr!(
+                "curl命令执行超时，请检查 API URL 是否正确",
+                "curl timed out, check that the API URL is correct"
+            )));
         }
     };
 
@@ -66,159 +195,273 @@ pub async fn send_request_with_curl(
 
         // 检查是否包含常见错误
         if stderr.contains("timed out") || stderr.contains("Connection refused") {
-            println!("curl连接失败: {}", stderr);
-            return Err((
-                StatusCode::BAD_GATEWAY,
-                format!("无法连接到上游服务器: {}", stderr),
-            ));
+            log_warn!("curl连接失败: {}", "curl connection failed: {}", stderr);
+            return Err(AppError::BadGateway(tr!(
+                "无法连接到上游服务器: {}",
+                "Cannot connect to the upstream server: {}",
+                stderr
+            )));
         }
 
-        eprintln!("curl命令失败: stderr={}, stdout={}", stderr, stdout);
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("curl命令失败 (状态码={})", curl_output.status),
-        ));
+        log_warn!(
+            "curl命令失败: stderr={}, stdout={}",
+            "curl command failed: stderr={}, stdout={}",
+            stderr,
+            stdout
+        );
+        return Err(AppError::Internal(tr!(
+            "curl命令失败 (状态码={})",
+            "curl failed (status={})",
+            curl_output.status
+        )));
+    }
+
+    // 先读出响应头，再拆分响应体与末行的状态码
+    let mut lines = curl_output.stdout.as_slice().lines();
+    let head = read_response_head(&mut lines).await.ok().flatten();
+    let output_text = String::from_utf8_lossy(lines.into_inner());
+    let (response_text, status_code) = split_status_trailer(&output_text);
+
+    // 上游返回非成功状态码时原样透传状态码与响应体
+    if let Some(status) = status_code.and_then(|code| StatusCode::from_u16(code).ok())
+        && !status.is_success()
+    {
+        return Err(AppError::Upstream {
+            status,
+            body: String::from_utf8_lossy(
+                &response_text.as_bytes()[..response_text.len().min(MAX_UPSTREAM_ERROR_BODY)],
+            )
+            .into_owned(),
+            retry_after: head.and_then(|head| head.retry_after),
+        });
     }
 
     // 解析响应
-    let response_text = String::from_utf8_lossy(&curl_output.stdout).to_string();
-
-    match serde_json::from_str::<ChatResponseJson>(&response_text) {
-        Ok(response) => Ok(response),
-        Err(e) => {
-            match serde_json::from_str::<serde_json::Value>(&response_text) {
-                Ok(generic_json) => {
-                    // 尝试提取必要的字段并构造 ChatResponseJson
-                    let choices = match generic_json.get("choices") {
-                        Some(choices) => {
-                            if let Some(choices_array) = choices.as_array() {
-                                choices_array
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(idx, choice)| {
-                                        let content = match choice
-                                            .get("message")
-                                            .and_then(|m| m.get("content"))
-                                        {
-                                            Some(content) => {
-                                                content.as_str().unwrap_or("").to_string()
-                                            }
-                                            None => "".to_string(),
-                                        };
-
-                                        let role =
-                                            match choice.get("message").and_then(|m| m.get("role"))
-                                            {
-                                                Some(role) => {
-                                                    role.as_str().unwrap_or(&config.api_defaults.default_role).to_string()
-                                                }
-                                                None => config.api_defaults.default_role.clone(),
-                                            };
-
-                                        let finish_reason = match choice.get("finish_reason") {
-                                            Some(reason) => {
-                                                reason.as_str().unwrap_or(&config.api_defaults.default_finish_reason).to_string()
-                                            }
-                                            None => config.api_defaults.default_finish_reason.clone(),
-                                        };
-
-                                        ChatChoice {
-                                            index: idx as i32,
-                                            logprobs: None,
-                                            finish_reason,
-                                            message: ChatMessageJson { role, content },
-                                        }
-                                    })
-                                    .collect()
-                            } else {
-                                vec![]
-                            }
-                        }
-                        None => vec![],
-                    };
-
-                    if choices.is_empty() {
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("解析curl响应失败: {}", e),
-                        ));
-                    }
+    parse_chat_response(response_text, &config.api_defaults)
+        .map_err(|e| {
+            let context = tr!("解析curl响应失败", "Failed to parse the curl response");
+            parse_error(&config.api_defaults, &context, e)
+        })
+}
 
-                    // 构造一个有效的响应对象
-                    let response = ChatResponseJson {
-                        id: generic_json
-                            .get("id")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                        object: generic_json
-                            .get("object")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_object)
-                            .to_string(),
-                        created: generic_json
-                            .get("created")
-                            .and_then(|v| v.as_i64())
-                            .unwrap_or(chrono::Utc::now().timestamp()),
-                        model: generic_json
-                            .get("model")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                        choices,
-                        usage: Usage {
-                            prompt_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("prompt_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                            completion_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("completion_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                            total_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("total_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                        },
-                        stats: serde_json::Value::Null,
-                        system_fingerprint: generic_json
-                            .get("system_fingerprint")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                    };
-
-                    Ok(response)
-                }
-                Err(parse_err) => {
-                    println!("解析为通用JSON也失败: {}", parse_err);
-                    Err((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("解析curl响应失败: {}", e),
-                    ))
-                }
+// curl -D - 输出的响应头中与转发相关的部分
+struct ResponseHead {
+    status: StatusCode,
+    retry_after: Option<String>,
+}
+
+// 读取 curl -D - 写在响应体之前的响应头，跳过 1xx 中间响应；curl 未收到响应即退出时返回 None
+async fn read_response_head<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+) -> std::io::Result<Option<ResponseHead>> {
+    loop {
+        let Some(status_line) = lines.next_line().await? else {
+            return Ok(None);
+        };
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .and_then(|code| StatusCode::from_u16(code).ok());
+        let mut retry_after = None;
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.trim().eq_ignore_ascii_case("retry-after")
+            {
+                retry_after = Some(value.trim().to_string());
+            }
+        }
+        match status {
+            Some(status) if status.is_informational() => continue,
+            Some(status) => {
+                return Ok(Some(ResponseHead {
+                    status,
+                    retry_after,
+                }));
+            }
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    tr!(
+                        "无法解析上游响应的状态行: {}",
+                        "Cannot parse the upstream status line: {}",
+                        status_line
+                    ),
+                ));
             }
         }
     }
 }
 
+// 使用 curl 发送流式请求（-N 关闭输出缓冲），逐行将上游 SSE 数据转发给客户端。
+// 流式回答的总时长没有上限，因此不设 --max-time，只限制连接时间与两次输出之间的空闲时间
+// （proxy.response_read_timeout_seconds）；上游返回非成功状态码时在开始转发前以该状态码返回错误
+pub async fn stream_request_with_curl(
+    url: &str,
+    payload: &str,
+    headers: &HashMap<String, String>,
+    tls: &TlsConfig,
+    config: &Config,
+    permit: tokio::sync::OwnedSemaphorePermit,
+    filter: Option<StreamFilter>,
+) -> Result<Response, AppError> {
+    let mut curl = build_curl_command(headers, tls, config);
+    curl.data(payload);
+    curl.command
+        .arg("-N")
+        .arg("-D")
+        .arg("-") // 响应头先于响应体写入标准输出
+        .arg("--suppress-connect-headers")
+        .arg(url)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true); // 客户端断开时终止 curl 进程
+
+    let mut child = curl.spawn().map_err(|e| {
+        log_warn!("curl命令执行失败: {}", "curl command failed: {}", e);
+        AppError::Internal(tr!("curl命令执行失败: {}", "Failed to run curl: {}", e))
+    })?;
+
+    let stdout = child.stdout.take().ok_or_else(|| {
+        AppError::Internal(tr!("无法读取curl输出", "Cannot read the curl output"))
+    })?;
+    let mut lines = BufReader::new(stdout).lines();
+    let idle_timeout = Duration::from_secs(config.proxy.response_read_timeout_seconds);
+
+    let head = match tokio::time::timeout(idle_timeout, read_response_head(&mut lines)).await {
+        Ok(Ok(Some(head))) => head,
+        Ok(Ok(None)) => {
+            // curl 未收到响应即退出，错误原因在标准错误中
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            log_warn!("curl连接失败: {}", "curl connection failed: {}", stderr.trim());
+            return Err(AppError::BadGateway(tr!(
+                "无法连接到上游服务器: {}",
+                "Cannot connect to the upstream server: {}",
+                stderr.trim()
+            )));
+        }
+        Ok(Err(e)) => {
+            log_warn!(
+                "读取curl流式输出失败: {}",
+                "Failed to read curl streaming output: {}",
+                e
+            );
+            return Err(AppError::BadGateway(tr!(
+                "读取上游响应失败: {}",
+                "Failed to read the upstream response: {}",
+                e
+            )));
+        }
+        Err(_) => {
+            log_warn!("等待上游响应超时", "Timed out waiting for the upstream response");
+            return Err(AppError::GatewayTimeout(tr!(
+                "等待上游响应超时",
+                "Timed out waiting for the upstream response"
+            )));
+        }
+    };
+
+    // 非成功状态码：读取（有限长度的）错误响应体，按上游状态码返回，不以事件流转发
+    if !head.status.is_success() {
+        let mut body = Vec::new();
+        let mut reader = lines.into_inner().take(MAX_UPSTREAM_ERROR_BODY as u64);
+        let _ = tokio::time::timeout(idle_timeout, reader.read_to_end(&mut body)).await;
+        return Err(AppError::Upstream {
+            status: head.status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            retry_after: head.retry_after,
+        });
+    }
+
+    // 子进程与并发许可随流一起存活，直到转发结束；内容过滤命中时提前结束流并终止 curl
+    let stream = futures::stream::unfold(
+        Some((lines, child, permit, filter)),
+        move |state| async move {
+            let (mut lines, child, permit, mut filter) = state?;
+            let Ok(next) = tokio::time::timeout(idle_timeout, lines.next_line()).await else {
+                log_warn!(
+                    "上游流式输出空闲超过 {} 秒，结束转发",
+                    "Upstream stream idle for more than {} seconds, closing it",
+                    idle_timeout.as_secs()
+                );
+                let e = std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    tr!("上游流式输出空闲超时", "The upstream stream went idle"),
+                );
+                return Some((Err(e), None));
+            };
+            match next {
+                Ok(Some(line)) => {
+                    if let Some(end) = filter.as_mut().and_then(|f| f.check_line(&line)) {
+                        return Some((Ok(end), None));
+                    }
+                    Some((
+                        Ok::<_, std::io::Error>(format!("{}\n", line)),
+                        Some((lines, child, permit, filter)),
+                    ))
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    log_warn!(
+                        "读取curl流式输出失败: {}",
+                        "Failed to read curl streaming output: {}",
+                        e
+                    );
+                    Some((Err(e), None))
+                }
+            }
+        },
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+// 将客户端请求头转换为可转发的键值对（过滤逐跳头）
+fn forwardable_headers(headers: &axum::http::HeaderMap) -> std::collections::HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(key, _)| {
+            let key = key.as_str();
+            !key.eq_ignore_ascii_case("host")
+                && !key.eq_ignore_ascii_case("connection")
+                && !key.eq_ignore_ascii_case("content-length")
+        })
+        .filter_map(|(key, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|v| (key.as_str().to_string(), v.to_string()))
+        })
+        .collect()
+}
+
 // 处理 /v1/models 路由的请求
 pub async fn get_models(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
     config: &Config,
-) -> Result<String, (StatusCode, String)> {
+) -> Result<String, AppError> {
     // 选择 API 端点
     let endpoint = match select_api_endpoint(&state.api_endpoints) {
         Some(ep) => ep,
         None => {
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                "没有可用的 API 端点".to_string(),
-            ));
+            return Err(AppError::ServiceUnavailable(tr!(
+                "没有可用的 API 端点",
+                "No API endpoint is available"
+            )));
         }
     };
 
@@ -228,16 +471,28 @@ pub async fn get_models(
         format!("{}/v1/models", endpoint.url)
     };
 
-    // 创建新的客户端，设置短超时
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(config.proxy.request_timeout_seconds))
-        .connect_timeout(std::time::Duration::from_secs(config.proxy.connect_timeout_seconds))
-        .danger_accept_invalid_certs(true)
-        .no_proxy()
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
+    // Unix 域套接字端点通过套接字转发
+    if is_unix_url(&target_url) {
+        let headers = forwardable_headers(&headers);
+        let response = send_unix_socket_request(
+            Method::GET,
+            &target_url,
+            &headers,
+            None,
+            std::time::Duration::from_secs(config.proxy.request_timeout_seconds),
+        )
+        .await?;
+        if !response.status.is_success() {
+            return Err(response.into_error());
+        }
+        return Ok(response.body);
+    }
 
-    let mut req_builder = client.get(&target_url);
+    // 复用该端点的共享客户端（含端点 TLS 与出站代理配置），按请求设置短超时
+    let mut req_builder = state
+        .client_for(&endpoint)
+        .get(&target_url)
+        .timeout(std::time::Duration::from_secs(config.proxy.request_timeout_seconds));
 
     // 添加所有请求头
     for (key, value) in headers.iter() {
@@ -252,40 +507,44 @@ pub async fn get_models(
             Ok(result) => match result {
                 Ok(res) => res,
                 Err(e) => {
-                    println!("模型列表请求失败: {}", e);
+                    log_warn!("模型列表请求失败: {}", "Model list request failed: {}", e);
                     // 更详细的错误类型判断
                     if e.is_connect() {
-                        return Err((
-                            StatusCode::BAD_GATEWAY,
-                            format!("无法连接到上游服务器(连接错误): {}", e),
-                        ));
+                        return Err(AppError::BadGateway(tr!(
+                            "无法连接到上游服务器(连接错误): {}",
+                            "Cannot connect to the upstream server (connection error): {}",
+                            e
+                        )));
                     } else if e.is_timeout() {
-                        return Err((
-                            StatusCode::GATEWAY_TIMEOUT,
-                            format!("上游服务器响应超时: {}", e),
-                        ));
+                        return Err(AppError::GatewayTimeout(tr!(
+                            "上游服务器响应超时: {}",
+                            "Upstream server timed out: {}",
+                            e
+                        )));
                     } else {
-                        return Err((
-                            StatusCode::BAD_GATEWAY,
-                            format!("请求上游服务器失败: {}", e),
-                        ));
+                        return Err(AppError::BadGateway(tr!(
+                            "请求上游服务器失败: {}",
+                            "Upstream request failed: {}",
+                            e
+                        )));
                     }
                 }
             },
             Err(_) => {
-                println!("模型列表请求超时");
-                return Err((
-                    StatusCode::GATEWAY_TIMEOUT,
-                    "请求上游服务器超时，请检查 API URL 是否正确".to_string(),
-                ));
+                log_warn!("模型列表请求超时", "Model list request timed out");
+                return Err(AppError::GatewayTimeout(tr!(
+                    "请求上游服务器超时，请检查 API URL 是否正确",
+                    "Upstream request timed out, check that the API URL is correct"
+                )));
             }
         };
 
     if !response.status().is_success() {
-        return Err((
-            response.status(),
-            format!("上游服务器返回错误: {:?}", response),
-        ));
+        return Err(AppError::from_upstream_response(
+            response,
+            std::time::Duration::from_secs(config.proxy.response_read_timeout_seconds),
+        )
+        .await);
     }
 
     // 添加响应读取超时
@@ -293,16 +552,17 @@ pub async fn get_models(
         match tokio::time::timeout(std::time::Duration::from_secs(config.proxy.response_read_timeout_seconds), response.text()).await {
             Ok(Ok(text)) => text,
             Ok(Err(e)) => {
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("读取响应失败: {}", e),
-                ));
+                return Err(AppError::Internal(tr!(
+                    "读取响应失败: {}",
+                    "Failed to read the response: {}",
+                    e
+                )));
             }
             Err(_) => {
-                return Err((
-                    StatusCode::GATEWAY_TIMEOUT,
-                    "读取上游服务器响应超时".to_string(),
-                ));
+                return Err(AppError::GatewayTimeout(tr!(
+                    "读取上游服务器响应超时",
+                    "Timed out reading the upstream response"
+                )));
             }
         };
 
@@ -315,15 +575,15 @@ pub async fn get_embeddings(
     headers: axum::http::HeaderMap,
     Json(payload): Json<serde_json::Value>,
     config: &Config,
-) -> Result<String, (StatusCode, String)> {
+) -> Result<String, AppError> {
     // 选择 API 端点
     let endpoint = match select_api_endpoint(&state.api_endpoints) {
         Some(ep) => ep,
         None => {
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                "没有可用的 API 端点".to_string(),
-            ));
+            return Err(AppError::ServiceUnavailable(tr!(
+                "没有可用的 API 端点",
+                "No API endpoint is available"
+            )));
         }
     };
 
@@ -333,16 +593,28 @@ pub async fn get_embeddings(
         format!("{}/v1/embeddings", endpoint.url)
     };
 
-    // 创建新的客户端，设置短超时
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(config.proxy.request_timeout_seconds))
-        .connect_timeout(std::time::Duration::from_secs(config.proxy.connect_timeout_seconds))
-        .danger_accept_invalid_certs(true)
-        .no_proxy()
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
+    // Unix 域套接字端点通过套接字转发
+    if is_unix_url(&target_url) {
+        let headers = forwardable_headers(&headers);
+        let response = send_unix_socket_request(
+            Method::POST,
+            &target_url,
+            &headers,
+            Some(payload.to_string()),
+            std::time::Duration::from_secs(config.proxy.request_timeout_seconds),
+        )
+        .await?;
+        if !response.status.is_success() {
+            return Err(response.into_error());
+        }
+        return Ok(response.body);
+    }
 
-    let mut req_builder = client.post(&target_url);
+    // 复用该端点的共享客户端（含端点 TLS 与出站代理配置），按请求设置短超时
+    let mut req_builder = state
+        .client_for(&endpoint)
+        .post(&target_url)
+        .timeout(std::time::Duration::from_secs(config.proxy.request_timeout_seconds));
 
     // 添加所有请求头
     for (key, value) in headers.iter() {
@@ -361,40 +633,44 @@ pub async fn get_embeddings(
         Ok(result) => match result {
             Ok(res) => res,
             Err(e) => {
-                println!("嵌入请求失败: {}", e);
+                log_warn!("嵌入请求失败: {}", "Embedding request failed: {}", e);
                 // 更详细的错误类型判断
                 if e.is_connect() {
-                    return Err((
-                        StatusCode::BAD_GATEWAY,
-                        format!("无法连接到上游服务器(连接错误): {}", e),
-                    ));
+                    return Err(AppError::BadGateway(tr!(
+                        "无法连接到上游服务器(连接错误): {}",
+                        "Cannot connect to the upstream server (connection error): {}",
+                        e
+                    )));
                 } else if e.is_timeout() {
-                    return Err((
-                        StatusCode::GATEWAY_TIMEOUT,
-                        format!("上游服务器响应超时: {}", e),
-                    ));
+                    return Err(AppError::GatewayTimeout(tr!(
+                        "上游服务器响应超时: {}",
+                        "Upstream server timed out: {}",
+                        e
+                    )));
                 } else {
-                    return Err((
-                        StatusCode::BAD_GATEWAY,
-                        format!("请求上游服务器失败: {}", e),
-                    ));
+                    return Err(AppError::BadGateway(tr!(
+                        "请求上游服务器失败: {}",
+                        "Upstream request failed: {}",
+                        e
+                    )));
                 }
             }
         },
         Err(_) => {
-            println!("嵌入请求超时");
-            return Err((
-                StatusCode::GATEWAY_TIMEOUT,
-                "请求上游服务器超时，请检查 API URL 是否正确".to_string(),
-            ));
+            log_warn!("嵌入请求超时", "Embedding request timed out");
+            return Err(AppError::GatewayTimeout(tr!(
+                "请求上游服务器超时，请检查 API URL 是否正确",
+                "Upstream request timed out, check that the API URL is correct"
+            )));
         }
     };
 
     if !response.status().is_success() {
-        return Err((
-            response.status(),
-            format!("上游服务器返回错误: {:?}", response),
-        ));
+        return Err(AppError::from_upstream_response(
+            response,
+            std::time::Duration::from_secs(config.proxy.response_read_timeout_seconds),
+        )
+        .await);
     }
 
     // 添加响应读取超时
@@ -402,16 +678,17 @@ pub async fn get_embeddings(
         match tokio::time::timeout(std::time::Duration::from_secs(config.proxy.response_read_timeout_seconds), response.text()).await {
             Ok(Ok(text)) => text,
             Ok(Err(e)) => {
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("读取响应失败: {}", e),
-                ));
+                return Err(AppError::Internal(tr!(
+                    "读取响应失败: {}",
+                    "Failed to read the response: {}",
+                    e
+                )));
             }
             Err(_) => {
-                return Err((
-                    StatusCode::GATEWAY_TIMEOUT,
-                    "读取上游服务器响应超时".to_string(),
-                ));
+                return Err(AppError::GatewayTimeout(tr!(
+                    "读取上游服务器响应超时",
+                    "Timed out reading the upstream response"
+                )));
             }
         };
 
@@ -1,200 +1,490 @@
-use crate::handlers::api_handler::send_request_with_curl;
-use crate::handlers::proxy_handler::send_proxied_request;
+use crate::{log_debug, log_error, log_info, log_trace, log_warn, tr};
+use crate::handlers::api_handler::{send_request_with_curl, stream_request_with_curl};
+use crate::handlers::proxy_handler::{parse_chat_response, send_proxied_request};
 use crate::models::api_model::{
-    AppState, ChatChoice, ChatMessageJson, ChatRequestJson, ChatResponseJson, Usage,
-    select_api_endpoint,
+    ApiEndpoint, AppState, ChatChoice, ChatMessageJson, ChatRequestJson, ChatResponseJson,
+    TrimOverride, Usage, select_api_endpoint, select_fallback_endpoint,
+};
+use crate::utils::ab_test::{record_ab_result, select_ab_endpoint};
+use crate::utils::answer_codec::{
+    answer_hit_body, answer_stamp, decode_answer_async, encode_answer_async,
+};
+use crate::utils::cache_dry_run::record_would_cache;
+use crate::utils::cache_key::{KeySource, store_key_source};
+use crate::utils::cache_epoch::current_epoch;
+use crate::utils::content_filter::StreamFilter;
+use crate::utils::audit::{AuditRecord, record_audit};
+use crate::utils::dashboard::{RecentRequest, record_request};
+use crate::utils::context_trim::{
+    TokenCounter, TrimStrategy, calculate_total_tokens, trim_context, trim_context_smart,
+    trim_middle_out, trim_sliding_window,
+};
+use crate::utils::db_writer::{DbWriter, HitKey, record_hit};
+use crate::utils::encryption::decrypt_blob;
+use crate::utils::endpoint_stats::endpoint_label;
+use crate::utils::error::AppError;
+use crate::utils::hit_stats::CacheOutcome;
+use crate::utils::live_events::{self, LiveEvent};
+use crate::utils::json_stream::stream_json;
+use crate::utils::plugin::{RequestContext, ResponseContext};
+use crate::utils::prompt_cache::{annotate_payload, cache_breakpoints};
+use crate::utils::redis_cache::{RedisCache, redis_cache};
+use crate::utils::replication;
+use crate::utils::rewrite::{
+    RewriteRule, apply_header_rewrites, apply_payload_rewrites, matching_rewrites,
+    payload_rewrite_key,
 };
-use crate::utils::context_trim::{trim_context, trim_context_smart};
-use crate::utils::db_writer::DbWriter;
 use crate::utils::config::Config;
-// Local simple logger to ensure request_id is always printed without relying on external modules
-fn log_with_id(request_id: &str, message: &str) {
-    println!("[{}] {}", request_id, message);
-}
+use crate::utils::unix_socket::{is_unix_url, send_unix_socket_request};
+use crate::utils::upstream_queue::AcquireError;
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    http::{HeaderMap, HeaderValue, Method, header},
     response::{IntoResponse, Response},
 };
-use brotli::CompressorWriter;
+use futures::FutureExt;
 use futures::future::BoxFuture;
 use sha2::{Digest, Sha256};
-use std::io::Write;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TrySendError;
 use uuid::Uuid;
 
 pub type TaskSender = tokio::sync::mpsc::Sender<BoxFuture<'static, ()>>;
 
+// 将后台任务提交到专用线程池，通道已满或已关闭时回退到当前运行时执行
+fn submit_task(tx: &TaskSender, task: BoxFuture<'static, ()>) {
+    if let Err(e) = tx.try_send(task) {
+        let task = match e {
+            TrySendError::Full(task) | TrySendError::Closed(task) => task,
+        };
+        tokio::spawn(task);
+    }
+}
+
+// 缓存命中的压缩数据及其元信息
+struct CachedAnswer {
+    // 内存缓存命中时与内存缓存共享同一份数据
+    data: Arc<Vec<u8>>,
+    // 写入缓存的时间（Unix 秒）
+    created_at: Option<i64>,
+    version: i64,
+    from_memory: bool,
+}
+
 // 缓存查询的异步函数
 async fn query_cache(
-    db: Arc<sqlx::SqlitePool>,
+    state: &AppState,
     question_key: String,
     cache_version: u8,
-    cache_override_mode: bool,
-    memory_cache: Option<&Arc<crate::utils::memory_cache::MemoryCache>>,
-    cache_enabled: bool,
+    tx_hit: &TaskSender,
     request_id: &str,
-) -> Result<Option<Vec<u8>>, sqlx::Error> {
+) -> Result<Option<CachedAnswer>, String> {
+    let db = state.db.clone();
+
     // 如果内存缓存已禁用，直接查询数据库
-    if !cache_enabled {
-        return query_db_cache(db, question_key, cache_version, cache_override_mode).await;
+    if !state.cache_enabled {
+        return query_store_cache(db, question_key, cache_version, tx_hit).await;
     }
 
-    // 如果启用了内存缓存，先从内存中查找
-    if let Some(cache) = memory_cache {
-        if let Some(data) = cache.get(&question_key) {
-            log_with_id(request_id, "内存缓存命中");
-            return Ok(Some(data));
+    // 如果启用了内存缓存，先从内存中查找（写入时的缓存版本或全局缓存纪元低于当前值时视为未命中）
+    if let Some(cache) = &state.memory_cache
+        && let Some(data) = cache.get(&question_key)
+    {
+        let stamp = answer_stamp(&data);
+        let version = stamp.cache_version.unwrap_or_default();
+        if version >= cache_version && stamp.cache_epoch >= current_epoch() {
+            log_debug!("[{}] 内存缓存命中", "[{}] Memory cache hit", request_id);
+
+            // 内存命中同样计入数据库中的命中统计（尚未写入数据库的条目跳过）
+            let key = question_key.clone();
+            submit_task(tx_hit, async move {
+                if let Some(redis) = redis_cache() {
+                    if let Err(e) = redis.record_question_hit(&key).await {
+                        log_warn!(
+                            "更新缓存命中计数失败: {}",
+                            "Failed to update cache hit count: {}",
+                            e
+                        );
+                    }
+                } else {
+                    record_hit(&db, HitKey::Question(key)).await;
+                }
+            }
+            .boxed());
+
+            return Ok(Some(CachedAnswer {
+                data,
+                created_at: cache.inserted_at(&question_key),
+                version: version as i64,
+                from_memory: true,
+            }));
         }
+        log_debug!(
+            "[{}] 内存缓存的版本低于当前模型的缓存版本，或写入于全局缓存失效之前",
+            "[{}] Memory cache entry is older than the model's cache version or the global cache epoch",
+            request_id
+        );
     }
 
-    log_with_id(request_id, "内存缓存未命中，查询数据库");
-    query_db_cache(db, question_key, cache_version, cache_override_mode).await
+    log_debug!(
+        "[{}] 内存缓存未命中，查询数据库",
+        "[{}] Memory cache miss, querying the database",
+        request_id
+    );
+    query_store_cache(db, question_key, cache_version, tx_hit).await
 }
 
-// 数据库缓存查询函数
-async fn query_db_cache(
+// 按配置的缓存存储后端（SQLite 或 Redis）查询不低于 cache_version 的缓存
+async fn query_store_cache(
     db: Arc<sqlx::SqlitePool>,
     question_key: String,
     cache_version: u8,
-    cache_override_mode: bool,
-) -> Result<Option<Vec<u8>>, sqlx::Error> {
-    let result = if cache_override_mode {
-        sqlx::query_as::<_, (Vec<u8>, String)>(
-            "SELECT a.response, a.key 
-             FROM questions q 
-             JOIN answers a ON q.answer_key = a.key 
-             WHERE q.key = ? AND a.version >= ?
-             LIMIT 1",
-        )
-        .bind(question_key.clone())
-        .bind(cache_version)
-        .fetch_optional(&*db)
-        .await?
-    } else {
-        sqlx::query_as::<_, (Vec<u8>, String)>(
-            "SELECT a.response, a.key 
-             FROM questions q 
-             JOIN answers a ON q.answer_key = a.key 
-             WHERE q.key = ?
-             LIMIT 1",
-        )
-        .bind(question_key.clone())
-        .fetch_optional(&*db)
+    tx_hit: &TaskSender,
+) -> Result<Option<CachedAnswer>, String> {
+    let cached = match redis_cache() {
+        Some(redis) => {
+            query_redis_cache(redis, question_key, cache_version, tx_hit)
+                .await
+                .map_err(|e| tr!("Redis 查询错误: {}", "Redis query error: {}", e))?
+        }
+        None => query_db_cache(db, question_key, cache_version, tx_hit)
+            .await
+            .map_err(|e| tr!("数据库查询错误: {}", "Database query error: {}", e))?,
+    };
+    let Some(mut cached) = cached else {
+        return Ok(None);
+    };
+
+    // 解密缓存数据（未加密的数据原样返回），无法解密时按未命中处理
+    match decrypt_blob(Arc::unwrap_or_clone(cached.data)) {
+        Ok(data) => {
+            cached.data = Arc::new(data);
+            Ok(Some(cached))
+        }
+        Err(e) => {
+            log_error!("{}", "{}", e);
+            Ok(None)
+        }
+    }
+}
+
+// Redis 缓存查询函数，命中规则与数据库查询一致
+async fn query_redis_cache(
+    redis: &'static RedisCache,
+    question_key: String,
+    cache_version: u8,
+    tx_hit: &TaskSender,
+) -> Result<Option<CachedAnswer>, redis::RedisError> {
+    let Some(answer) = redis
+        .get(&question_key, cache_version, current_epoch())
         .await?
+    else {
+        return Ok(None);
     };
 
-    // 如果找到缓存项，更新答案表中的命中计数
-    if let Some((_, answer_key)) = &result {
+    // 在缓存命中线程池中更新命中次数与最近命中时间
+    let answer_key = answer.answer_key.clone();
+    submit_task(tx_hit, async move {
+        if let Err(e) = redis.record_hit(&answer_key).await {
+            log_warn!("更新缓存命中计数失败: {}", "Failed to update cache hit count: {}", e);
+        }
+    }
+    .boxed());
+
+    Ok(Some(CachedAnswer {
+        data: Arc::new(answer.response),
+        created_at: Some(answer.created_at),
+        version: answer.version,
+        from_memory: false,
+    }))
+}
+
+// 数据库缓存查询函数
+async fn query_db_cache(
+    db: Arc<sqlx::SqlitePool>,
+    question_key: String,
+    cache_version: u8,
+    tx_hit: &TaskSender,
+) -> Result<Option<CachedAnswer>, sqlx::Error> {
+    // 低于当前模型缓存版本或全局缓存纪元的回答视为未命中
+    let result = sqlx::query_as::<_, (Vec<u8>, String, i64, i64)>(
+        "SELECT a.response, a.key, a.created_at, a.version
+         FROM questions q 
+         JOIN answers a ON q.answer_key = a.key 
+         WHERE q.key = ? AND a.version >= ? AND a.epoch >= ?
+         LIMIT 1",
+    )
+    .bind(question_key.clone())
+    .bind(cache_version)
+    .bind(current_epoch() as i64)
+    .fetch_optional(&*db)
+    .await?;
+
+    // 如果找到缓存项，在缓存命中线程池中更新答案表的命中计数
+    if let Some((_, answer_key, _, _)) = &result {
         let db_clone = db.clone();
         let answer_key_clone = answer_key.clone();
 
-        tokio::spawn(async move {
-            // 更新命中次数
-            match sqlx::query("UPDATE answers SET hit_count = hit_count + 1 WHERE key = ?")
-                .bind(answer_key_clone)
-                .execute(&*db_clone)
-                .await
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("更新缓存命中计数失败: {}", e);
-                }
-            }
-        });
+        submit_task(tx_hit, async move {
+            // 更新命中次数与最近命中时间
+            record_hit(&db_clone, HitKey::Answer(answer_key_clone)).await;
+        }
+        .boxed());
+    }
+
+    Ok(result.map(|(data, _, created_at, version)| CachedAnswer {
+        data: Arc::new(data),
+        created_at: Some(created_at),
+        version,
+        from_memory: false,
+    }))
+}
+
+// 从 X-Trim-Disabled / X-Trim-Max-Tokens / X-Trim-Mode 请求头解析裁切覆盖参数
+fn trim_override_from_headers(headers: &axum::http::HeaderMap) -> TrimOverride {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+
+    TrimOverride {
+        max_tokens: header("x-trim-max-tokens").and_then(|v| v.parse().ok()),
+        mode: header("x-trim-mode").filter(|v| !v.is_empty()),
+        disabled: header("x-trim-disabled")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1"),
+    }
+}
+
+// 客户端的 Accept-Encoding 是否接受 br（q=0 表示明确拒绝）
+fn accepts_brotli(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            params.next().is_some_and(|name| name.eq_ignore_ascii_case("br"))
+                && !params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                })
+        })
+}
+
+// 缓存中只保存了回答内容，按请求模型对应的分词器估算 token 用量
+fn estimate_cached_usage(payload: &ChatRequestJson, content: &str, config: &Config) -> Usage {
+    let counter = TokenCounter::for_model(&config.context_trim, &payload.model);
+    let prompt_tokens = calculate_total_tokens(&payload.messages, counter);
+    // 单条消息计数包含 3 个 token 的格式开销，回答部分不计入
+    let completion_tokens = counter.count(content).saturating_sub(3);
+    Usage {
+        prompt_tokens: prompt_tokens as i32,
+        completion_tokens: completion_tokens as i32,
+        total_tokens: (prompt_tokens + completion_tokens) as i32,
+        estimated: Some(true),
     }
+}
 
-    Ok(result.map(|(data, _)| data))
+// 为响应附加缓存元信息头（X-Cache-Key、X-Cache-Age 等），值为空或不合法时跳过
+fn with_headers<const N: usize>(
+    mut response: Response,
+    headers: [(&'static str, Option<String>); N],
+) -> Response {
+    for (name, value) in headers {
+        if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
 }
 
-// 处理解压缩缓存内容
+// 解码缓存内容并构造响应
 async fn process_cached_response(
-    compressed_data: Vec<u8>,
+    compressed_data: Arc<Vec<u8>>,
     payload: ChatRequestJson,
     request_id: &str,
     config: &Config,
-) -> Result<Json<ChatResponseJson>, (StatusCode, String)> {
-    let mut decompressed = Vec::new();
-    let mut decompressor =
-        brotli::Decompressor::new(compressed_data.as_slice(), compressed_data.len());
-
-    match std::io::copy(&mut decompressor, &mut decompressed) {
-        Ok(_) => match String::from_utf8(decompressed) {
-            Ok(message_content) => {
-                let response = ChatResponseJson {
-                    id: Uuid::new_v4().to_string(),
-                    object: config.api_defaults.default_object.clone(),
-                    created: chrono::Utc::now().timestamp(),
-                    model: payload.model.clone(),
-                    choices: vec![ChatChoice {
-                        index: 0,
-                        logprobs: None,
-                        finish_reason: "stop_from_cache".to_string(),
-                        message: ChatMessageJson {
-                            role: config.api_defaults.default_role.clone(),
-                            content: message_content,
-                        },
-                    }],
-                    usage: Usage {
-                        prompt_tokens: 0,
-                        completion_tokens: 0,
-                        total_tokens: 0,
-                    },
-                    stats: serde_json::Value::Null,
-                    system_fingerprint: config.api_defaults.cache_system_fingerprint.clone(),
-                };
+) -> Result<Json<ChatResponseJson>, AppError> {
+    let stored = decode_answer_async(compressed_data)
+        .await
+        .map_err(AppError::Internal)?;
 
-                log_with_id(request_id, "缓存命中");
-                Ok(Json(response))
-            }
-            Err(e) => Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("解析缓存内容失败: {}", e),
-            )),
-        },
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("解压缩缓存数据失败: {}", e),
-        )),
+    // 先估算用量，之后直接移出回答内容，避免复制长回答
+    let usage = estimate_cached_usage(&payload, stored.content(), config);
+    let choices = stored.choices.into_iter().map(|choice| {
+        let content = choice.message.map(|message| message.content);
+        (choice.index, content.unwrap_or_default())
+    });
+    let response = cache_hit_response(choices, usage, &payload, config);
+
+    log_debug!("[{}] 缓存命中", "[{}] Cache hit", request_id);
+    Ok(Json(response))
+}
+
+// 按 (index, 回答内容) 构造缓存命中的响应
+fn cache_hit_response(
+    choices: impl Iterator<Item = (i32, String)>,
+    usage: Usage,
+    payload: &ChatRequestJson,
+    config: &Config,
+) -> ChatResponseJson {
+    let defaults = &config.api_defaults;
+    let (finish_reason, system_fingerprint) = if defaults.compatible_cache_hits {
+        ("stop", &defaults.default_system_fingerprint)
+    } else {
+        ("stop_from_cache", &defaults.cache_system_fingerprint)
+    };
+    let choices = choices
+        .map(|(index, content)| ChatChoice {
+            index,
+            logprobs: None,
+            finish_reason: finish_reason.to_string(),
+            message: ChatMessageJson {
+                role: defaults.default_role.clone(),
+                content,
+                ..Default::default()
+            },
+        })
+        .collect();
+    ChatResponseJson {
+        id: Uuid::new_v4().to_string(),
+        object: defaults.default_object.clone(),
+        created: chrono::Utc::now().timestamp(),
+        model: payload.model.clone(),
+        choices,
+        usage,
+        stats: serde_json::Value::Null,
+        system_fingerprint: system_fingerprint.clone(),
     }
 }
 
-// 发送API请求函数
+// 发送API请求并记录端点的延迟与成功/失败统计
 async fn send_api_request(
-    client: reqwest::Client,
+    state: &AppState,
+    endpoint: &ApiEndpoint,
+    target_url: String,
+    payload_json: String,
+    headers: &std::collections::HashMap<String, String>,
+) -> Result<(ChatResponseJson, HeaderMap), AppError> {
+    // 回放模式直接返回记录的响应，不访问上游
+    if let Some(replay) = state.upstream_replay.as_ref().filter(|r| r.is_replay()) {
+        let response = replay.replay(&payload_json, &state.config.api_defaults)?;
+        return Ok((response, HeaderMap::new()));
+    }
+
+    let tracker = state.endpoint_stats.start(&endpoint.url);
+    let start_time = Instant::now();
+    let recorded_payload = state.upstream_replay.as_ref().map(|_| payload_json.clone());
+    let result = send_api_request_inner(state, endpoint, target_url, payload_json, headers).await;
+    if let (Some(replay), Some(payload)) = (&state.upstream_replay, recorded_payload) {
+        replay.record(
+            &endpoint_label(&endpoint.url),
+            &payload,
+            result.as_ref().map(|(r, _)| r),
+        );
+    }
+    tracker.finish(result.as_ref().err().map(|e| e.to_string()).as_deref());
+
+    let tags = [("model", endpoint.model.as_deref().unwrap_or("unknown"))];
+    state.statsd.timing(
+        "upstream.latency",
+        start_time.elapsed().as_millis() as u64,
+        &tags,
+    );
+    let counter = if result.is_ok() {
+        "upstream.success"
+    } else {
+        "upstream.error"
+    };
+    state.statsd.incr(counter, &tags);
+    result
+}
+
+// 发送API请求函数
+async fn send_api_request_inner(
+    state: &AppState,
+    endpoint: &ApiEndpoint,
     target_url: String,
     payload_json: String,
-    permit: tokio::sync::OwnedSemaphorePermit,
-    use_curl: bool,
-    use_proxy: bool,
     headers: &std::collections::HashMap<String, String>,
-    config: &crate::utils::config::Config,
-) -> Result<ChatResponseJson, (StatusCode, String)> {
-    // 记录信号量使用
-    let _permit = permit;
+) -> Result<(ChatResponseJson, HeaderMap), AppError> {
+    let config = &state.config;
     let request_id = uuid::Uuid::new_v4()
         .to_string()
         .chars()
         .take(8)
         .collect::<String>();
     let start_time = Instant::now();
+    // 端点配置了 http_client.timeout_seconds 时以其作为整个上游请求的超时
+    let request_timeout = Duration::from_secs(
+        endpoint
+            .http_client
+            .timeout_seconds
+            .unwrap_or(config.proxy.request_timeout_seconds),
+    );
+
+    // Unix 域套接字端点直接通过套接字发送请求
+    if is_unix_url(&target_url) {
+        log_debug!(
+            "[{}] 通过 Unix 套接字发送请求",
+            "[{}] Sending request over Unix socket",
+            request_id
+        );
+        let response = send_unix_socket_request(
+            Method::POST,
+            &target_url,
+            headers,
+            Some(payload_json),
+            request_timeout,
+        )
+        .await?;
+        if !response.status.is_success() {
+            return Err(response.into_error());
+        }
+        let parsed = parse_chat_response(&response.body, config, &request_id)?;
+        return Ok((parsed, response.headers));
+    }
+
+    // 配置了专用 TLS 或 HTTP 客户端参数的端点使用独立客户端
+    let endpoint_client = state.endpoint_clients.get(&endpoint.client_key());
 
     // 根据配置选择请求方式
-    if use_curl {
-        println!("[{}] 使用curl模式发送请求", request_id);
-        return send_request_with_curl(&target_url, &payload_json, config).await;
-    } else if use_proxy {
-        println!("[{}] 使用代理模式发送请求", request_id);
-        let result = send_proxied_request(&target_url, &payload_json, headers, config, &request_id).await;
-        println!(
+    if state.use_curl {
+        log_debug!("[{}] 使用curl模式发送请求", "[{}] Sending request in curl mode", request_id);
+        let tls = endpoint.effective_tls(&config.http_client.tls);
+        // curl 模式不读取上游响应头
+        let response =
+            send_request_with_curl(&target_url, &payload_json, headers, &tls, config).await?;
+        return Ok((response, HeaderMap::new()));
+    } else if state.use_proxy {
+        log_debug!("[{}] 使用代理模式发送请求", "[{}] Sending request in proxy mode", request_id);
+        let result = send_proxied_request(
+            &target_url,
+            &payload_json,
+            headers,
+            config,
+            &request_id,
+            endpoint_client,
+            request_timeout,
+        )
+        .await;
+        log_debug!(
             "[{}] 代理请求已完成 ({:?})",
+            "[{}] Proxy request completed ({:?})",
             request_id,
             start_time.elapsed()
         );
         return result;
     }
 
+    let client = endpoint_client.unwrap_or(&state.client);
+
     // 创建请求构建器
     let mut request_builder = client.post(&target_url);
 
@@ -209,49 +499,53 @@ async fn send_api_request(
 
     // 发送请求
     let response = match tokio::time::timeout(
-        Duration::from_secs(config.proxy.request_timeout_seconds), // 增加超时时间
+        request_timeout,
         request_builder.body(payload_json).send(),
     )
     .await
     {
         Ok(Ok(response)) => response,
         Ok(Err(e)) => {
-            println!("[{}] 请求失败: {}", request_id, e);
+            log_warn!("[{}] 请求失败: {}", "[{}] Request failed: {}", request_id, e);
             if e.is_connect() {
-                return Err((
-                    StatusCode::BAD_GATEWAY,
-                    format!("无法连接到上游服务器(连接错误): {}", e),
-                ));
+                return Err(AppError::BadGateway(tr!(
+                    "无法连接到上游服务器(连接错误): {}",
+                    "Cannot connect to the upstream server (connection error): {}",
+                    e
+                )));
             } else if e.is_timeout() {
-                return Err((
-                    StatusCode::GATEWAY_TIMEOUT,
-                    format!("上游服务器响应超时: {}", e),
-                ));
+                return Err(AppError::GatewayTimeout(tr!(
+                    "上游服务器响应超时: {}",
+                    "Upstream server timed out: {}",
+                    e
+                )));
             } else {
-                return Err((
-                    StatusCode::BAD_GATEWAY,
-                    format!("请求上游服务器失败: {}", e),
-                ));
+                return Err(AppError::BadGateway(tr!(
+                    "请求上游服务器失败: {}",
+                    "Upstream request failed: {}",
+                    e
+                )));
             }
         }
         Err(_) => {
-            println!("[{}] 请求发送超时", request_id);
-            return Err((
-                StatusCode::GATEWAY_TIMEOUT,
-                "请求上游服务器超时".to_string(),
-            ));
+            log_warn!("[{}] 请求发送超时", "[{}] Request send timed out", request_id);
+            return Err(AppError::GatewayTimeout(tr!(
+                "请求上游服务器超时",
+                "Upstream request timed out"
+            )));
         }
     };
 
     // 检查状态码
     if !response.status().is_success() {
-        return Err((
-            StatusCode::from_u16(response.status().as_u16())
-                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            format!("上游服务器返回错误: {:?}", response),
-        ));
+        return Err(AppError::from_upstream_response(
+            response,
+            Duration::from_secs(config.proxy.response_read_timeout_seconds),
+        )
+        .await);
     }
 
+    let response_headers = response.headers().clone();
     let text = match tokio::time::timeout(
         Duration::from_secs(config.proxy.response_read_timeout_seconds), // 增加读取超时时间
         response.text(),
@@ -260,349 +554,567 @@ async fn send_api_request(
     {
         Ok(Ok(text)) => text,
         Ok(Err(e)) => {
-            println!("[{}] 读取响应体失败: {}", request_id, e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("读取响应体失败: {}", e),
-            ));
+            log_warn!(
+                "[{}] 读取响应体失败: {}",
+                "[{}] Failed to read response body: {}",
+                request_id,
+                e
+            );
+            return Err(AppError::Internal(tr!(
+                "读取响应体失败: {}",
+                "Failed to read the response body: {}",
+                e
+            )));
         }
         Err(_) => {
-            println!("[{}] 读取上游服务器响应超时", request_id);
-            return Err((
-                StatusCode::GATEWAY_TIMEOUT,
-                "读取上游服务器响应超时".to_string(),
-            ));
+            log_warn!(
+                "[{}] 读取上游服务器响应超时",
+                "[{}] Timed out reading the upstream response",
+                request_id
+            );
+            return Err(AppError::GatewayTimeout(tr!(
+                "读取上游服务器响应超时",
+                "Timed out reading the upstream response"
+            )));
         }
     };
 
-    match serde_json::from_str::<ChatResponseJson>(&text) {
-        Ok(json) => Ok(json),
-        Err(e) => {
-            match serde_json::from_str::<serde_json::Value>(&text) {
-                Ok(generic_json) => {
-                    // 尝试提取必要的字段并构造 ChatResponseJson
-                    let choices = match generic_json.get("choices") {
-                        Some(choices) => {
-                            if let Some(choices_array) = choices.as_array() {
-                                choices_array
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(idx, choice)| {
-                                        let content = match choice
-                                            .get("message")
-                                            .and_then(|m| m.get("content"))
-                                        {
-                                            Some(content) => {
-                                                content.as_str().unwrap_or("").to_string()
-                                            }
-                                            None => "".to_string(),
-                                        };
-
-                                        let role =
-                                            match choice.get("message").and_then(|m| m.get("role"))
-                                            {
-                                                Some(role) => {
-                                                    role.as_str().unwrap_or(&config.api_defaults.default_role).to_string()
-                                                }
-                                                None => config.api_defaults.default_role.clone(),
-                                            };
-
-                                        let finish_reason = match choice.get("finish_reason") {
-                                            Some(reason) => {
-                                                reason.as_str().unwrap_or(&config.api_defaults.default_finish_reason).to_string()
-                                            }
-                                            None => config.api_defaults.default_finish_reason.clone(),
-                                        };
-
-                                        ChatChoice {
-                                            index: idx as i32,
-                                            logprobs: None,
-                                            finish_reason,
-                                            message: ChatMessageJson { role, content },
-                                        }
-                                    })
-                                    .collect()
-                            } else {
-                                vec![]
-                            }
-                        }
-                        None => vec![],
-                    };
+    Ok((parse_chat_response(&text, config, &request_id)?, response_headers))
+}
 
-                    if choices.is_empty() {
-                        println!("[{}] 无法从通用JSON中提取有效的消息内容", request_id);
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("解析响应JSON失败: {}", e),
-                        ));
-                    }
+// chat_completion
+#[axum::debug_handler]
+pub async fn chat_completion(
+    State(app_state): State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+    Json(payload): Json<ChatRequestJson>,
+) -> Response {
+    process_chat_completion(app_state, uri.path(), headers, payload).await
+}
 
-                    let response = ChatResponseJson {
-                        id: generic_json
-                            .get("id")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                        object: generic_json
-                            .get("object")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_object)
-                            .to_string(),
-                        created: generic_json
-                            .get("created")
-                            .and_then(|v| v.as_i64())
-                            .unwrap_or(chrono::Utc::now().timestamp()),
-                        model: generic_json
-                            .get("model")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                        choices,
-                        usage: Usage {
-                            prompt_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("prompt_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                            completion_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("completion_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                            total_tokens: generic_json
-                                .get("usage")
-                                .and_then(|u| u.get("total_tokens"))
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                        },
-                        stats: serde_json::Value::Null,
-                        system_fingerprint: generic_json
-                            .get("system_fingerprint")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-                            .to_string(),
-                    };
+/// 处理一次聊天请求并记录审计日志，HTTP 与 gRPC 接口共用
+pub async fn process_chat_completion(
+    app_state: Arc<(Arc<AppState>, TaskSender, TaskSender)>,
+    path: &str,
+    headers: axum::http::HeaderMap,
+    payload: ChatRequestJson,
+) -> Response {
+    let started = Instant::now();
+    let mut audit = AuditRecord::default();
+    let response =
+        handle_chat_completion(app_state.clone(), path, headers, payload, &mut audit).await;
 
-                    Ok(response)
-                }
-                Err(parse_err) => {
-                    println!("[{}] 解析为通用JSON也失败: {}", request_id, parse_err);
-                    Err((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("解析响应JSON失败: {}", e),
-                    ))
-                }
+    // 记录审计日志与仪表盘的最近请求（流式响应按响应头返回时计时）
+    let (state, _, tx_miss) = &*app_state;
+    let latency_ms = started.elapsed().as_millis() as i64;
+    let status_code = response.status().as_u16();
+    live_events::publish(LiveEvent::RequestFinished {
+        request_id: audit.request_id.clone(),
+        status_code,
+        latency_ms: latency_ms as u64,
+    });
+    let dashboard = &state.config.dashboard;
+    if dashboard.enabled {
+        let recent = RecentRequest {
+            timestamp: chrono::Utc::now().timestamp(),
+            request_id: audit.request_id.clone(),
+            model: audit.model.clone(),
+            cache_status: audit.cache_status,
+            endpoint: audit.endpoint.clone(),
+            latency_ms: latency_ms as u64,
+            status_code,
+        };
+        record_request(recent, dashboard.recent_requests);
+    }
+    let audit_config = &state.config.audit;
+    if audit_config.enabled {
+        let db = state.db.clone();
+        let max_rows = audit_config.max_rows;
+        submit_task(tx_miss, async move {
+            if let Err(e) = record_audit(&db, &audit, latency_ms, status_code, max_rows).await {
+                log_error!(
+                    "[{}] 写入审计日志失败: {}",
+                    "[{}] Failed to write audit log: {}",
+                    audit.request_id,
+                    e
+                );
             }
         }
+        .boxed());
     }
+
+    response
 }
 
-// chat_completion
-#[axum::debug_handler]
-pub async fn chat_completion(
-    State(app_state): State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
+async fn handle_chat_completion(
+    app_state: Arc<(Arc<AppState>, TaskSender, TaskSender)>,
+    path: &str,
     headers: axum::http::HeaderMap,
-    Json(payload): Json<ChatRequestJson>,
+    mut payload: ChatRequestJson,
+    audit: &mut AuditRecord,
 ) -> Response {
     let request_id = uuid::Uuid::new_v4()
         .to_string()
         .chars()
         .take(8)
         .collect::<String>();
+    audit.request_id = request_id.clone();
 
-    let (state, _tx_hit, _tx_miss) = {
+    let (state, tx_hit, tx_miss) = {
         let (state_ref, tx_hit_ref, tx_miss_ref) = &*app_state;
         (state_ref.clone(), tx_hit_ref.clone(), tx_miss_ref.clone())
     };
 
-    // 提取用户消息并计算问题的哈希作为键
-    let user_message = match payload
-        .messages
-        .iter()
-        .find(|msg| msg.role.to_lowercase() == "user")
+    // 客户端未指定模型时使用配置的默认模型
+    if payload.model.is_empty()
+        && let Some(model) = &state.config.default_model
     {
-        Some(msg) => msg,
-        None => {
-            println!("[{}] 错误: 未找到用户消息", request_id);
-            return (StatusCode::BAD_REQUEST, "未找到用户消息").into_response();
-        }
+        payload.model = model.clone();
+    }
+    audit.model = payload.model.clone();
+    live_events::publish(LiveEvent::RequestStarted {
+        request_id: request_id.clone(),
+        model: payload.model.clone(),
+        stream: payload.stream,
+    });
+
+    if let Err(e) = payload.validate_sampling() {
+        log_warn!("[{}] 请求参数无效: {}", "[{}] Invalid request parameter: {}", request_id, e);
+        return AppError::BadRequest(e).into_response();
+    }
+
+    // 执行请求插件（提示词模板、system prompt 注入等），在路由与计算缓存键之前
+    let plugin_ctx = RequestContext {
+        request_id: &request_id,
+        headers: &headers,
     };
+    if let Err(e) = state.plugins.pre_routing(&plugin_ctx, &mut payload) {
+        return e.into_response();
+    }
 
-    let mut hasher = Sha256::new();
-    hasher.update(user_message.content.as_bytes());
-    let question_key = hex::encode(hasher.finalize());
+    // 按请求路径、客户端请求的模型与请求头匹配改写规则。规则在转发上游之前才应用，
+    // 其中修改请求参数的部分需计入缓存键
+    let rewrites = matching_rewrites(&state.config.rewrites, path, &payload.model, &headers);
 
-    // 选择API端点
-    let selected_endpoint = if !state.api_endpoints.is_empty() {
+    // 按缓存键配置（取哪条用户消息、是否混入系统消息、上下文与采样参数）计算问题的哈希作为键
+    let mut key_source = KeySource::from_request(&payload);
+    key_source.rewrites = payload_rewrite_key(&rewrites);
+    // 缓存版本按模型区分时，模型也必须计入缓存键，否则不同模型会按各自的版本读到对方的回答
+    if !state.config.model_cache_versions.is_empty() {
+        key_source.model = Some(payload.model.clone());
+    }
+    let Some(question_key) = key_source.question_key(&state.config.cache) else {
+        log_warn!("[{}] 错误: 未找到用户消息", "[{}] Error: no user message found", request_id);
+        return AppError::BadRequest(tr!("未找到用户消息", "No user message found")).into_response();
+    };
+    // 开启 store_key_source 时与回答一起保存，缓存键策略变更后可用 rehash 子命令迁移
+    let key_source = state.config.cache.store_key_source.then_some(key_source);
+
+    // 选择API端点：插件指定的端点优先，其次按 A/B 对比的比例在两组端点之间分配
+    let plugin_endpoint = state
+        .plugins
+        .select_endpoint(&plugin_ctx, &payload, &state.api_endpoints);
+    let ab_selection = if plugin_endpoint.is_some() {
+        None
+    } else {
+        select_ab_endpoint(&state.api_endpoints, &state.config.ab_test)
+    };
+    let ab_arm = ab_selection.as_ref().map(|(_, arm)| arm.clone());
+    let selected_endpoint = if let Some(endpoint) = plugin_endpoint {
+        endpoint
+    } else if let Some((endpoint, _)) = ab_selection {
+        endpoint
+    } else if !state.api_endpoints.is_empty() {
         match select_api_endpoint(&state.api_endpoints) {
             Some(endpoint) => endpoint,
             None => {
-                println!("[{}] 错误: 没有可用的API端点", request_id);
-                return (StatusCode::SERVICE_UNAVAILABLE, "没有可用的 API 端点").into_response();
+                log_error!(
+                    "[{}] 错误: 没有可用的API端点",
+                    "[{}] Error: no API endpoint available",
+                    request_id
+                );
+                return AppError::ServiceUnavailable(tr!(
+                    "没有可用的 API 端点",
+                    "No API endpoint is available"
+                ))
+                    .into_response();
             }
         }
     } else {
-        println!("[{}] 错误: API端点列表为空", request_id);
-        return (StatusCode::SERVICE_UNAVAILABLE, "没有配置 API 端点").into_response();
+        log_error!(
+            "[{}] 错误: API端点列表为空",
+            "[{}] Error: API endpoint list is empty",
+            request_id
+        );
+        return AppError::ServiceUnavailable(tr!(
+            "没有配置 API 端点",
+            "No API endpoint is configured"
+        )).into_response();
     };
 
     // 如果是流式请求，跳过缓存
     let skip_cache = payload.stream;
+    // 按客户端请求的模型确定缓存版本
+    let cache_version = state.config.cache_version_for(&payload.model);
 
     // 查询缓存（除非是流式请求）
     let cache_result = if skip_cache {
         Ok(None)
     } else {
         query_cache(
-            state.db.clone(),
+            &state,
             question_key.clone(),
-            selected_endpoint.version,
-            state.cache_override_mode,
-            state.memory_cache.as_ref(),
-            state.cache_enabled,
+            cache_version,
+            &tx_hit,
             &request_id,
         )
         .await
     };
 
+    // 记录本次请求的缓存结果，用于滚动命中率统计
+    let outcome = match &cache_result {
+        Ok(Some(cached)) if cached.from_memory => CacheOutcome::MemoryHit,
+        Ok(Some(_)) => CacheOutcome::DbHit,
+        _ if skip_cache => CacheOutcome::Bypass,
+        _ => CacheOutcome::Miss,
+    };
+    state.hit_stats.record(outcome);
+    audit.key_hash = Some(question_key.clone());
+    audit.cache_status = Some(outcome.as_str());
+    audit.endpoint = Some(endpoint_label(&selected_endpoint.url));
+    let live_event = match outcome {
+        CacheOutcome::MemoryHit => Some(LiveEvent::CacheHit {
+            request_id: request_id.clone(),
+            source: "memory",
+        }),
+        CacheOutcome::DbHit => Some(LiveEvent::CacheHit {
+            request_id: request_id.clone(),
+            source: "db",
+        }),
+        CacheOutcome::Miss => Some(LiveEvent::CacheMiss {
+            request_id: request_id.clone(),
+            endpoint: endpoint_label(&selected_endpoint.url),
+        }),
+        CacheOutcome::Bypass => None,
+    };
+    if let Some(event) = live_event {
+        live_events::publish(event);
+    }
+    state.statsd.incr(
+        outcome.metric_name(),
+        &[(
+            "model",
+            selected_endpoint.model.as_deref().unwrap_or("unknown"),
+        )],
+    );
+
     match cache_result {
-        Ok(Some(compressed_data)) => {
-            log_with_id(&request_id, "缓存命中");
-            match process_cached_response(compressed_data, payload, &request_id, &state.config).await {
-                Ok(json) => {
-                    println!("[{}] 成功处理缓存响应", request_id);
+        Ok(Some(cached)) => {
+            log_debug!("[{}] 缓存命中", "[{}] Cache hit", request_id);
+            let age = cached
+                .created_at
+                .map(|ts| (chrono::Utc::now().timestamp() - ts).max(0).to_string());
+            let brotli_enabled = state.config.cache.brotli_hit_responses;
+            let cache_headers = [
+                ("x-cache", Some("HIT".to_string())),
+                ("x-cache-key", Some(question_key.clone())),
+                ("x-cache-age", age),
+                ("x-cache-version", Some(cached.version.to_string())),
+                (
+                    "vary",
+                    brotli_enabled.then(|| "accept-encoding".to_string()),
+                ),
+            ];
+            let model = payload.model.clone();
+            let structured_output = payload.structured_output();
+            let response_ctx = ResponseContext {
+                request_id: &request_id,
+                model: &model,
+                structured_output,
+            };
+
+            // 客户端接受 br 且没有插件需要处理本次命中时，直接返回写入时保存的 brotli 响应体
+            if brotli_enabled
+                && accepts_brotli(&headers)
+                && !state.plugins.handles_cache_hit(&response_ctx)
+                && let Some(body) = answer_hit_body(&cached.data)
+            {
+                log_debug!(
+                    "[{}] 返回保存的 brotli 命中响应体 ({} 字节)",
+                    "[{}] Serving the stored brotli hit body ({} bytes)",
+                    request_id,
+                    body.len()
+                );
+                let response = (
+                    [
+                        (header::CONTENT_TYPE, "application/json"),
+                        (header::CONTENT_ENCODING, "br"),
+                    ],
+                    body,
+                )
+                    .into_response();
+                return with_headers(response, cache_headers);
+            }
+
+            match process_cached_response(cached.data, payload, &request_id, &state.config).await {
+                Ok(mut json) => {
+                    // 执行响应插件的缓存命中钩子
+                    if let Err(e) = state.plugins.post_cache_hit(&response_ctx, &mut json.0) {
+                        return e.into_response();
+                    }
+                    log_debug!("[{}] 成功处理缓存响应", "[{}] Cached response served", request_id);
+
+                    // 较大的回答边序列化边输出，不生成完整的响应体字符串
+                    let threshold = state.config.cache.stream_threshold_bytes;
+                    let content_len: usize =
+                        json.0.choices.iter().map(|c| c.message.content.len()).sum();
+                    if threshold > 0 && content_len >= threshold {
+                        log_debug!(
+                            "[{}] 缓存回答较大 ({} 字节)，流式输出响应",
+                            "[{}] Cached answer is large ({} bytes), streaming the response",
+                            request_id,
+                            content_len
+                        );
+                        let response = (
+                            [(header::CONTENT_TYPE, "application/json")],
+                            stream_json(json.0),
+                        )
+                            .into_response();
+                        return with_headers(response, cache_headers);
+                    }
+
                     // 序列化后体哈希（仅日志诊断，不改变返回）
                     if let Ok(body) = serde_json::to_string(&json.0) {
                         let mut hasher = Sha256::new();
                         hasher.update(body.as_bytes());
                         let hash = hex::encode(hasher.finalize());
-                        println!(
+                        log_trace!(
+                            "[cache_hit_before_send] body.len={}, body.sha256={}",
                             "[cache_hit_before_send] body.len={}, body.sha256={}",
                             body.len(),
                             &hash[..std::cmp::min(16, hash.len())]
                         );
                     }
-                    json.into_response()
+                    with_headers(json.into_response(), cache_headers)
                 }
-                Err((status, message)) => {
-                    println!(
-                        "[{}] 处理缓存响应错误: {} - {}",
-                        request_id, status, message
+                Err(e) => {
+                    log_error!(
+                        "[{}] 处理缓存响应错误: {}",
+                        "[{}] Failed to serve cached response: {}",
+                        request_id,
+                        e
                     );
-                    (status, message).into_response()
+                    e.into_response()
                 }
             }
         }
         Ok(None) => {
-            log_with_id(&request_id, "缓存未命中. 进行API请求");
+            log_debug!(
+                "[{}] 缓存未命中. 进行API请求",
+                "[{}] Cache miss, sending the API request",
+                request_id
+            );
 
-            // 获取信号量
-            println!(
-                "[{}] 尝试获取信号量许可... (当前可用: {})",
+            // 获取上游并发许可
+            let queue = &state.upstream_queue;
+            log_trace!(
+                "[{}] 尝试获取信号量许可... (当前可用: {}，等待中: {})",
+                "[{}] Acquiring semaphore permit... (available: {}, waiting: {})",
                 request_id,
-                state.semaphore.available_permits()
+                queue.available_permits(),
+                queue.waiting()
             );
-
-            // 设置获取信号量的超时
-            let permit = match tokio::time::timeout(
-                Duration::from_secs(10), // 10秒超时
-                state.semaphore.clone().acquire_owned(),
-            )
-            .await
-            {
-                Ok(Ok(p)) => {
-                    println!(
+            // 按排队深度与所选端点的平均延迟估算 Retry-After
+            let retry_after = |waiting| {
+                let stats = state.endpoint_stats.snapshot(&selected_endpoint.url);
+                queue.retry_after_secs(waiting, stats.latency_ms_avg)
+            };
+            let permit = match queue.acquire().await {
+                Ok(p) => {
+                    log_trace!(
                         "[{}] 成功获取信号量许可 (剩余: {})",
+                        "[{}] Semaphore permit acquired (remaining: {})",
                         request_id,
-                        state.semaphore.available_permits()
+                        queue.available_permits()
                     );
                     p
                 }
-                Ok(Err(e)) => {
-                    println!("[{}] 获取信号量许可失败: {}", request_id, e);
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "获取并发许可失败").into_response();
+                Err(AcquireError::Closed) => {
+                    log_error!(
+                        "[{}] 获取信号量许可失败: 信号量已关闭",
+                        "[{}] Failed to acquire semaphore permit: semaphore closed",
+                        request_id
+                    );
+                    return AppError::Internal(tr!(
+                        "获取并发许可失败",
+                        "Failed to acquire a concurrency permit"
+                    )).into_response();
                 }
-                Err(_) => {
-                    println!("[{}] 获取信号量许可超时", request_id);
-                    return (StatusCode::SERVICE_UNAVAILABLE, "服务器忙，请稍后再试")
-                        .into_response();
+                Err(AcquireError::Timeout { waiting }) => {
+                    let retry_after_secs = retry_after(waiting);
+                    log_warn!(
+                        "[{}] 获取信号量许可超时（等待中: {}），建议 {} 秒后重试",
+                        "[{}] Timed out acquiring semaphore permit ({} waiting), retry after {} s",
+                        request_id,
+                        waiting,
+                        retry_after_secs
+                    );
+                    return AppError::TooManyRequests {
+                        message: tr!(
+                            "服务器忙，请稍后再试",
+                            "Server is busy, please try again later"
+                        ),
+                        retry_after_secs,
+                    }
+                    .into_response();
+                }
+                Err(AcquireError::QueueFull { waiting }) => {
+                    let retry_after_secs = retry_after(waiting);
+                    log_warn!(
+                        "[{}] 等待队列已满（等待中: {}），建议 {} 秒后重试",
+                        "[{}] Upstream wait queue is full ({} waiting), retry after {} s",
+                        request_id,
+                        waiting,
+                        retry_after_secs
+                    );
+                    return AppError::TooManyRequests {
+                        message: tr!(
+                            "服务器忙，等待队列已满，请稍后再试",
+                            "Server is busy and the wait queue is full, please try again later"
+                        ),
+                        retry_after_secs,
+                    }
+                    .into_response();
                 }
             };
 
-            let target_url = if selected_endpoint.url.ends_with('/') {
-                format!("{}v1/chat/completions", selected_endpoint.url)
-            } else {
-                format!("{}/v1/chat/completions", selected_endpoint.url)
-            };
+            let target_url = chat_completions_url(&selected_endpoint.url);
 
             // 创建请求载荷的副本
             let mut payload_clone = payload.clone();
 
+            // 合并单次请求的裁切覆盖参数（请求体 x_trim 优先于 X-Trim-* 请求头）
+            let trim_override = payload
+                .x_trim
+                .clone()
+                .unwrap_or_default()
+                .or(trim_override_from_headers(&headers));
+            let trim_enabled = match trim_override.disabled {
+                Some(true) => false,
+                _ if trim_override.max_tokens.is_some() || trim_override.mode.is_some() => true,
+                _ => state.context_trim_enabled,
+            };
+            let default_strategy = TrimStrategy::from_config(
+                &state.config.context_trim.strategy,
+                state.context_trim_smart_enabled,
+            );
+            let strategy = match trim_override.mode.as_deref() {
+                Some(mode) => TrimStrategy::parse(mode).unwrap_or_else(|| {
+                    log_warn!(
+                        "[{}] 未知的请求级裁切模式 {}，使用默认策略",
+                        "[{}] Unknown per-request trim mode {}, using the default strategy",
+                        request_id,
+                        mode
+                    );
+                    default_strategy
+                }),
+                None => default_strategy,
+            };
+            if !trim_override.is_empty() {
+                log_debug!(
+                    "[{}] 使用请求级裁切参数: {:?}",
+                    "[{}] Using per-request trim parameters: {:?}",
+                    request_id,
+                    trim_override
+                );
+            }
+
             // 如果启用了上下文裁切，则根据开关选择裁切模式
-            if state.context_trim_enabled {
-                println!("[{}] 上下文裁切已启用", request_id);
-                if state.context_trim_smart_enabled {
-                    println!(
+            if trim_enabled {
+                log_debug!("[{}] 上下文裁切已启用", "[{}] Context trimming enabled", request_id);
+                // 按实际发送给上游的模型选择分词器
+                let token_counter = TokenCounter::for_model(
+                    &state.config.context_trim,
+                    selected_endpoint
+                        .model
+                        .as_deref()
+                        .unwrap_or(&payload_clone.model),
+                );
+                if strategy == TrimStrategy::Importance {
+                    log_debug!(
                         "[{}] 智能裁切已启用，模式: {}, API摘要: {}",
-                        request_id, state.summary_mode, state.summary_api_enabled
+                        "[{}] Smart trimming enabled, mode: {}, API summary: {}",
+                        request_id,
+                        state.summary_mode,
+                        state.summary_api_enabled
                     );
-                    // 为摘要请求准备专用请求头（支持从环境变量注入摘要API Key）
-                    let mut summary_headers = state.api_headers.clone();
-                    if state.summary_api_enabled {
-                        if let Ok(k) = std::env::var(&state.summary_api_key_env) {
-                            // 若未显式提供授权头，则默认使用 Bearer 方案
-                            let has_auth = summary_headers
-                                .keys()
-                                .any(|h| h.eq_ignore_ascii_case("authorization"));
-                            if !has_auth && !k.is_empty() {
-                                summary_headers
-                                    .insert("Authorization".to_string(), format!("Bearer {}", k));
-                            }
-                        }
-                    }
-
                     payload_clone.messages = trim_context_smart(
                         &payload_clone.messages,
-                        state.context_smart_max_tokens,
+                        trim_override
+                            .max_tokens
+                            .unwrap_or(state.context_smart_max_tokens),
                         state.per_message_overhead,
                         state.min_keep_pairs,
                         state.summary_aggressiveness,
                         &state.summary_mode,
                         state.summary_api_enabled,
                         &state.summary_api_endpoints,
+                        state.summary_api_key.as_deref(),
                         state.summary_api_max_tokens,
                         state.summary_api_temperature,
                         state.summary_api_timeout_seconds,
                         &state.client,
                         &state.api_endpoints,
-                        &summary_headers,
+                        &state.api_headers,
+                        token_counter,
                     )
                     .await;
                 } else {
-                    payload_clone.messages =
-                        trim_context(&payload_clone.messages, state.max_context_tokens);
+                    let max_tokens = trim_override.max_tokens.unwrap_or(state.max_context_tokens);
+                    log_debug!(
+                        "[{}] 裁切策略: {:?}",
+                        "[{}] Trim strategy: {:?}",
+                        request_id,
+                        strategy
+                    );
+                    payload_clone.messages = match strategy {
+                        TrimStrategy::SlidingWindow => {
+                            trim_sliding_window(&payload_clone.messages, max_tokens, token_counter)
+                        }
+                        TrimStrategy::MiddleOut => {
+                            trim_middle_out(&payload_clone.messages, max_tokens, token_counter)
+                        }
+                        _ => trim_context(&payload_clone.messages, max_tokens, token_counter),
+                    };
                 }
             }
 
-            // 如果端点配置了model，则使用端点配置的model
-            if let Some(model) = selected_endpoint.model {
-                payload_clone.model = model;
-            }
-
-            // 如果配置了思考参数，则设置enable_thinking参数
-            if state.enable_thinking.is_some() {
-                payload_clone.enable_thinking = state.enable_thinking;
-            }
+            // 保留裁切后的请求，换端点重试时按新端点的配置重新准备
+            let trimmed_payload = payload_clone.clone();
+            prepare_endpoint_payload(
+                &state,
+                &selected_endpoint,
+                &rewrites,
+                &mut payload_clone,
+                &request_id,
+            );
 
             // 序列化请求负载
-            let payload_json = match serde_json::to_string(&payload_clone) {
+            let payload_json = match serialize_payload(&state, &selected_endpoint, &payload_clone) {
                 Ok(json) => json,
                 Err(e) => {
-                    println!("[{}] 序列化请求负载失败: {}", request_id, e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("序列化请求负载失败: {}", e),
-                    )
+                    log_error!(
+                        "[{}] 序列化请求负载失败: {}",
+                        "[{}] Failed to serialize request payload: {}",
+                        request_id,
+                        e
+                    );
+                    return AppError::Internal(tr!(
+                        "序列化请求负载失败: {}",
+                        "Failed to serialize the request payload: {}",
+                        e
+                    ))
                         .into_response();
                 }
             };
@@ -616,6 +1128,7 @@ pub async fn chat_completion(
                     if !key_lower.contains("connection")
                         && !key_lower.contains("host")
                         && !key_lower.contains("content-length")
+                        && !key_lower.starts_with("x-trim-")
                     {
                         client_headers.insert(key.as_str().to_string(), v.to_string());
                     }
@@ -626,145 +1139,548 @@ pub async fn chat_completion(
             for (key, value) in &state.api_headers {
                 client_headers.insert(key.clone(), value.clone());
             }
+            apply_header_rewrites(&rewrites, &mut client_headers);
 
-            let api_result = send_api_request(
-                state.client.clone(),
-                target_url,
+            // curl 模式下的流式请求直接转发上游 SSE 输出（流式响应不缓存）
+            if payload.stream && state.use_curl && !is_unix_url(&target_url) {
+                log_debug!(
+                    "[{}] 使用curl模式发送流式请求",
+                    "[{}] Sending streaming request in curl mode",
+                    request_id
+                );
+                let tls = selected_endpoint.effective_tls(&state.config.http_client.tls);
+                return match stream_request_with_curl(
+                    &target_url,
+                    &payload_json,
+                    &client_headers,
+                    &tls,
+                    &state.config,
+                    permit,
+                    state
+                        .plugins
+                        .content_filter()
+                        .map(|filter| StreamFilter::new(filter.clone(), &request_id)),
+                )
+                .await
+                {
+                    Ok(response) => response,
+                    Err(e) => e.into_response(),
+                };
+            }
+
+            // 持有信号量许可直到请求（含可能的重试）结束
+            let _permit = permit;
+            let upstream_start = Instant::now();
+            let mut api_result = send_api_request(
+                &state,
+                &selected_endpoint,
+                target_url.clone(),
                 payload_json,
-                permit,
-                state.use_curl,
-                state.use_proxy,
                 &client_headers,
-                &state.config,
             )
             .await;
 
+            // 上游报告上下文超长时，以更低的预算智能裁切后自动重试一次
+            if let Err(e) = &api_result
+                && e.is_context_overflow()
+                && state.config.context_trim.overflow_retry
+            {
+                log_info!(
+                    "[{}] 上游报告上下文超长，尝试裁切后重试",
+                    "[{}] Upstream reported context overflow, retrying with trimmed context",
+                    request_id
+                );
+                if let Some(retry_json) = shrink_payload_for_retry(
+                    &state,
+                    &selected_endpoint,
+                    &payload_clone,
+                    &request_id,
+                )
+                .await
+                {
+                    api_result = send_api_request(
+                        &state,
+                        &selected_endpoint,
+                        target_url,
+                        retry_json,
+                        &client_headers,
+                    )
+                    .await;
+                    match &api_result {
+                        Ok(_) => log_info!(
+                            "[{}] 裁切后重试成功",
+                            "[{}] Retry after trimming succeeded",
+                            request_id
+                        ),
+                        Err(e) => log_warn!(
+                            "[{}] 裁切后重试仍失败: {}",
+                            "[{}] Retry after trimming still failed: {}",
+                            request_id,
+                            e
+                        ),
+                    }
+                }
+            }
+
+            // 上游响应无法解析（如反向代理返回的 HTML 错误页）时换一个端点重试一次；
+            // A/B 对比的请求不换端点，以免混淆两组的结果
+            let mut upstream_endpoint = selected_endpoint.url.clone();
+            if let Err(e) = &api_result
+                && e.is_invalid_upstream_response()
+                && state.config.parse_failure_fallback
+                && ab_arm.is_none()
+                && let Some(fallback) =
+                    select_fallback_endpoint(&state.api_endpoints, &selected_endpoint.url)
+            {
+                log_warn!(
+                    "[{}] 无法解析端点 {} 的响应，改用端点 {} 重试: {}",
+                    "[{}] Could not parse the response from {}, retrying on {}: {}",
+                    request_id,
+                    endpoint_label(&selected_endpoint.url),
+                    endpoint_label(&fallback.url),
+                    e
+                );
+                let mut fallback_payload = trimmed_payload;
+                prepare_endpoint_payload(
+                    &state,
+                    &fallback,
+                    &rewrites,
+                    &mut fallback_payload,
+                    &request_id,
+                );
+                if let Ok(fallback_json) = serialize_payload(&state, &fallback, &fallback_payload) {
+                    api_result = send_api_request(
+                        &state,
+                        &fallback,
+                        chat_completions_url(&fallback.url),
+                        fallback_json,
+                        &client_headers,
+                    )
+                    .await;
+                    upstream_endpoint = fallback.url;
+                    audit.endpoint = Some(endpoint_label(&upstream_endpoint));
+                }
+            }
+            let upstream_headers = [
+                ("x-cache", Some("MISS".to_string())),
+                ("x-cache-key", Some(question_key.clone())),
+                ("x-cache-version", Some(cache_version.to_string())),
+                ("x-upstream-endpoint", Some(endpoint_label(&upstream_endpoint))),
+            ];
+
+            // 挑出允许透传给客户端的上游响应头
+            let (mut api_result, passthrough_headers) = match api_result {
+                Ok((response_json, headers)) => {
+                    (Ok(response_json), state.config.response_headers.select(&headers))
+                }
+                Err(e) => (Err(e), Vec::new()),
+            };
+
+            // 执行响应插件（回答长度上限、内容过滤等），插件可决定回答是否写入缓存
+            let mut cacheable = true;
+            let response_ctx = ResponseContext {
+                request_id: &request_id,
+                model: &payload_clone.model,
+                structured_output: payload_clone.structured_output(),
+            };
+            api_result = api_result.and_then(|mut response_json| {
+                cacheable = state
+                    .plugins
+                    .pre_cache_store(&response_ctx, &mut response_json)?;
+                Ok(response_json)
+            });
+
+            // 记录 A/B 分组的处理结果
+            if let Some(arm) = ab_arm {
+                let db = state.db.clone();
+                let request_id = request_id.clone();
+                let endpoint_url = selected_endpoint.url.clone();
+                let latency_ms = upstream_start.elapsed().as_millis() as i64;
+                let response_chars = api_result
+                    .as_ref()
+                    .ok()
+                    .and_then(|r| r.choices.first())
+                    .map_or(0, |c| c.message.content.chars().count() as i64);
+                let success = api_result.is_ok();
+                log_debug!(
+                    "[{}] A/B 分组: {}，耗时: {} ms，回答长度: {}",
+                    "[{}] A/B arm: {}, latency: {} ms, answer length: {}",
+                    request_id,
+                    arm,
+                    latency_ms,
+                    response_chars
+                );
+                submit_task(&tx_miss, async move {
+                    if let Err(e) = record_ab_result(
+                        &db,
+                        &request_id,
+                        &arm,
+                        &endpoint_url,
+                        latency_ms,
+                        response_chars,
+                        success,
+                    )
+                    .await
+                    {
+                        log_warn!(
+                            "[{}] 记录 A/B 结果失败: {}",
+                            "[{}] Failed to record A/B result: {}",
+                            request_id,
+                            e
+                        );
+                    }
+                }
+                .boxed());
+            }
+
             match &api_result {
                 Ok(response_json) => {
                     let response_clone = response_json.clone();
-                    let db_clone = state.db.clone();
+                    // 启用 brotli 命中响应时按本次请求生成命中响应体，随回答一起写入
+                    let hit_payload = state
+                        .config
+                        .cache
+                        .brotli_hit_responses
+                        .then(|| payload.clone());
 
-                    // 在后台执行缓存操作（如果不是流式请求）
-                    if !skip_cache {
-                        tokio::spawn(async move {
+                    // 在缓存未命中线程池中执行缓存操作（流式请求与插件标记为不缓存的回答除外）
+                    if !skip_cache && cacheable {
+                        submit_task(&tx_miss, async move {
                             cache_response(
+                                &state,
                                 response_clone,
                                 question_key,
-                                db_clone,
-                                selected_endpoint.version,
-                                state.memory_cache.clone(),
-                                state.cache_enabled,
-                                state.batch_write_size,
-                                &state.config,
+                                key_source,
+                                cache_version,
+                                hit_payload,
                             )
                             .await;
-                        });
+                        }
+                        .boxed());
                     }
 
                     if let Ok(body) = serde_json::to_string(response_json) {
                         let mut hasher = Sha256::new();
                         hasher.update(body.as_bytes());
                     }
-                    Json(response_json.clone()).into_response()
+                    let mut response = with_headers(
+                        Json(response_json.clone()).into_response(),
+                        upstream_headers,
+                    );
+                    response.headers_mut().extend(passthrough_headers);
+                    response
                 }
-                Err((status, msg)) => (status.clone(), msg.clone()).into_response(),
+                Err(e) => e.clone().into_response(),
             }
         }
         Err(e) => {
-            // 数据库查询错误
-            println!("[{}] 数据库查询错误: {}", request_id, e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("数据库查询错误: {}", e),
-            )
-                .into_response()
+            // 缓存查询错误
+            log_warn!("[{}] {}", "[{}] {}", request_id, e);
+            AppError::Internal(e).into_response()
         }
     }
 }
 
+// 端点的 chat completions 地址
+fn chat_completions_url(endpoint_url: &str) -> String {
+    if endpoint_url.ends_with('/') {
+        format!("{}v1/chat/completions", endpoint_url)
+    } else {
+        format!("{}/v1/chat/completions", endpoint_url)
+    }
+}
+
+// 按端点配置准备发送给上游的请求：端点模型、参数覆盖、思考参数与改写规则
+fn prepare_endpoint_payload(
+    state: &AppState,
+    endpoint: &ApiEndpoint,
+    rewrites: &[&RewriteRule],
+    payload: &mut ChatRequestJson,
+    request_id: &str,
+) {
+    // 如果端点配置了model，则使用端点配置的model
+    if let Some(model) = &endpoint.model {
+        payload.model = model.clone();
+    }
+
+    // 应用端点配置的请求参数覆盖（改写规则在其之后应用，优先级更高）
+    if !endpoint.overrides.is_empty() {
+        log_debug!(
+            "[{}] 应用端点参数覆盖: {:?}",
+            "[{}] Applying endpoint parameter overrides: {:?}",
+            request_id,
+            endpoint.overrides
+        );
+        endpoint.overrides.apply(payload);
+    }
+
+    // 端点配置的思考参数优先，其次为全局配置；都未设置时保持客户端发送的值
+    if let Some(enable_thinking) = endpoint.enable_thinking.or(state.enable_thinking) {
+        payload.enable_thinking = Some(enable_thinking);
+    }
+
+    apply_payload_rewrites(rewrites, payload, request_id);
+    // 强制模型模式下改写规则不能改变发送给上游的模型
+    if state.config.force_model.enabled
+        && let Some(model) = &endpoint.model
+    {
+        payload.model = model.clone();
+    }
+}
+
+// 序列化发送给上游的请求，端点启用提示词缓存标记时为稳定的长前缀添加 cache_control
+fn serialize_payload(
+    state: &AppState,
+    endpoint: &ApiEndpoint,
+    payload: &ChatRequestJson,
+) -> serde_json::Result<String> {
+    let config = &state.config.prompt_cache;
+    if !endpoint.prompt_cache_hints.unwrap_or(config.enabled) {
+        return serde_json::to_string(payload);
+    }
+    let counter = TokenCounter::for_model(&state.config.context_trim, &payload.model);
+    let breakpoints = cache_breakpoints(&payload.messages, counter, config.min_prefix_tokens);
+    if breakpoints.is_empty() {
+        return serde_json::to_string(payload);
+    }
+    let mut value = serde_json::to_value(payload)?;
+    annotate_payload(&mut value, &breakpoints);
+    serde_json::to_string(&value)
+}
+
+// 按低于当前上下文的 token 预算智能裁切消息，返回重新序列化的请求负载；
+// 与首次请求一样经 serialize_payload 添加提示词缓存标记，无法进一步缩减时返回 None
+async fn shrink_payload_for_retry(
+    state: &AppState,
+    endpoint: &ApiEndpoint,
+    payload: &ChatRequestJson,
+    request_id: &str,
+) -> Option<String> {
+    let trim_config = &state.config.context_trim;
+    let counter = TokenCounter::for_model(trim_config, &payload.model);
+    let current_tokens = calculate_total_tokens(&payload.messages, counter)
+        + state.per_message_overhead * payload.messages.len();
+    let budget =
+        (current_tokens as f32 * trim_config.overflow_retry_ratio.clamp(0.1, 0.95)) as usize;
+    log_debug!(
+        "[{}] 重试裁切: 当前token {}，目标预算 {}",
+        "[{}] Retry trimming: current tokens {}, target budget {}",
+        request_id,
+        current_tokens,
+        budget
+    );
+
+    let mut retry_payload = payload.clone();
+    retry_payload.messages = trim_context_smart(
+        &payload.messages,
+        budget,
+        state.per_message_overhead,
+        state.min_keep_pairs,
+        state.summary_aggressiveness,
+        &state.summary_mode,
+        state.summary_api_enabled,
+        &state.summary_api_endpoints,
+        state.summary_api_key.as_deref(),
+        state.summary_api_max_tokens,
+        state.summary_api_temperature,
+        state.summary_api_timeout_seconds,
+        &state.client,
+        &state.api_endpoints,
+        &state.api_headers,
+        counter,
+    )
+    .await;
+
+    let trimmed_tokens = calculate_total_tokens(&retry_payload.messages, counter)
+        + state.per_message_overhead * retry_payload.messages.len();
+    if trimmed_tokens >= current_tokens {
+        log_warn!(
+            "[{}] 无法进一步裁切上下文，放弃重试",
+            "[{}] Context cannot be trimmed further, giving up the retry",
+            request_id
+        );
+        return None;
+    }
+
+    serialize_payload(state, endpoint, &retry_payload).ok()
+}
+
 // 缓存响应函数
 async fn cache_response(
+    state: &AppState,
     response_json: ChatResponseJson,
     question_key: String,
-    db: Arc<sqlx::SqlitePool>,
+    key_source: Option<KeySource>,
     cache_version: u8,
-    memory_cache: Option<Arc<crate::utils::memory_cache::MemoryCache>>,
-    cache_enabled: bool,
-    batch_write_size: usize,
-    config: &Config,
+    hit_payload: Option<ChatRequestJson>,
 ) {
     if response_json.choices.is_empty() {
-        eprintln!("上游 API 返回的 choices 数组为空，跳过缓存");
+        log_warn!(
+            "上游 API 返回的 choices 数组为空，跳过缓存",
+            "Upstream API returned an empty choices array, skipping cache"
+        );
         return;
     }
 
     let message_content = &response_json.choices[0].message.content;
     if message_content.is_empty() {
-        eprintln!("上游 API 返回的 message 内容为空，跳过缓存");
+        log_warn!(
+            "上游 API 返回的 message 内容为空，跳过缓存",
+            "Upstream API returned empty message content, skipping cache"
+        );
         return;
     }
 
-    // 压缩消息内容
-    let message_bytes = message_content.as_bytes();
-    let mut compressed = Vec::with_capacity(message_bytes.len() / 2); // 预分配大小
-    {
-        let mut compressor = CompressorWriter::new(&mut compressed, 4096, 11, 22);
-        if let Err(e) = compressor.write_all(message_bytes) {
-            eprintln!("压缩响应失败: {}", e);
-            return;
-        }
-        if let Err(e) = compressor.flush() {
-            eprintln!("刷新压缩器失败: {}", e);
-            return;
+    // 演练模式下只需要原始大小用于计算压缩率
+    let raw_size = if state.config.cache.dry_run {
+        serde_json::to_vec(&response_json).map_or(0, |body| body.len())
+    } else {
+        0
+    };
+
+    // 命中响应体与缓存命中时构造的响应相同，只是 id、时间与用量在写入时确定
+    let hit_body = hit_payload.and_then(|payload| {
+        let usage = estimate_cached_usage(&payload, message_content, &state.config);
+        let choices = response_json
+            .choices
+            .iter()
+            .map(|choice| (choice.index, choice.message.content.clone()));
+        let response = cache_hit_response(choices, usage, &payload, &state.config);
+        serde_json::to_vec(&response).ok()
+    });
+
+    // 编码为缓存存储格式（正文压缩）
+    let compressed =
+        match encode_answer_async(response_json, cache_version, current_epoch(), hit_body).await {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                log_error!("{}", "{}", e);
+                return;
+            }
+        };
+
+    // 缓存演练：只记录本应写入的内容，不写入也不推送给其他节点
+    if state.config.cache.dry_run {
+        record_would_cache(
+            &question_key,
+            raw_size,
+            compressed.len(),
+            state.config.api_defaults.cache_max_size_bytes,
+        );
+        return;
+    }
+
+    if store_answer(state, question_key.clone(), compressed.clone(), cache_version).await {
+        if let Some(source) = key_source
+            && let Err(e) = store_key_source(&state.db, &question_key, &source).await
+        {
+            log_warn!("{}", "{}", e);
         }
+        // 推送给其他节点（未启用缓存复制时忽略）
+        replication::publish(question_key, compressed, cache_version);
     }
+}
 
+/// 将编码后的回答写入内存缓存或数据库，超过大小上限时跳过并返回 false。
+/// 本节点产生的回答与其他节点复制来的回答共用该写入流程
+pub async fn store_answer(
+    state: &AppState,
+    question_key: String,
+    compressed: Vec<u8>,
+    cache_version: u8,
+) -> bool {
+    let config = &state.config;
+    let db = state.db.clone();
+    let batch_trigger = state.batch_trigger.clone();
     let data_size = compressed.len() as i64;
     let cache_max_size = config.api_defaults.cache_max_size_bytes as i64;
 
     // 如果压缩后大小超过限制，跳过缓存
     if data_size > cache_max_size {
-        eprintln!(
+        log_warn!(
             "响应体积过大 ({} bytes)，超过缓存限制 ({} bytes)，跳过缓存",
-            data_size, cache_max_size
+            "Response too large ({} bytes), exceeds the cache limit ({} bytes), skipping cache",
+            data_size,
+            cache_max_size
         );
-        return;
+        return false;
     }
 
-    // 计算响应内容的哈希作为答案的 key
-    let mut hasher = Sha256::new();
-    hasher.update(message_bytes);
-
     // 如果启用了内存缓存，先添加到内存缓存
-    if cache_enabled {
-        if let Some(cache) = memory_cache {
+    if state.cache_enabled {
+        if let Some(cache) = state.memory_cache.clone() {
+            let max_pending_writes = config.cache.max_pending_writes;
+            let drop_on_overflow = config.cache.pending_overflow_policy == "drop";
+
             // 将响应添加到内存缓存
             tokio::spawn(async move {
-                cache.insert(question_key, compressed.clone()).await;
+                cache.insert(question_key, compressed);
+                batch_trigger.record_arrival();
 
-                // 如果待写入队列达到了批量写入阈值，执行批量写入
-                if cache.pending_count() >= batch_write_size {
-                    println!(
+                let pending_count = cache.pending_count();
+                let batch_write_size = batch_trigger.threshold();
+                if max_pending_writes > 0 && pending_count > max_pending_writes {
+                    // 数据库写入跟不上，待写入队列超限：按策略丢弃或立即全部写入
+                    if drop_on_overflow {
+                        let dropped = cache.drop_excess_pending(max_pending_writes);
+                        let (flushes, total_dropped) = cache.overflow_stats();
+                        log_warn!(
+                            "待写入队列超过上限 ({})，丢弃 {} 项 (累计同步刷新: {}，累计丢弃: {})",
+                            "Pending write queue exceeded its limit ({}), dropped {} items (total synchronous flushes: {}, total dropped: {})",
+                            max_pending_writes,
+                            dropped,
+                            flushes,
+                            total_dropped
+                        );
+                    } else {
+                        cache.record_overflow_flush();
+                        batch_trigger.mark_flushed();
+                        let pending_items = cache.take_pending_writes(pending_count);
+                        let db_writer = DbWriter::new(db, cache_version);
+                        let (success, failed) = db_writer.batch_write(pending_items).await;
+                        let (flushes, total_dropped) = cache.overflow_stats();
+                        log_warn!(
+                            "待写入队列超过上限 ({})，同步写入完成，成功: {}，失败: {} (累计同步刷新: {}，累计丢弃: {})",
+                            "Pending write queue exceeded its limit ({}), synchronous write done, succeeded: {}, failed: {} (total synchronous flushes: {}, total dropped: {})",
+                            max_pending_writes,
+                            success,
+                            failed,
+                            flushes,
+                            total_dropped
+                        );
+                    }
+                } else if pending_count >= batch_write_size {
+                    // 如果待写入队列达到了批量写入阈值（自适应时随负载变化），执行批量写入
+                    log_debug!(
                         "内存缓存待写入队列达到阈值 ({})，执行批量写入",
+                        "Memory cache pending write queue reached the threshold ({}), writing a batch",
                         batch_write_size
                     );
+                    batch_trigger.mark_flushed();
                     let pending_items = cache.take_pending_writes(batch_write_size);
 
                     // 创建数据库写入工具并执行批量写入
                     let db_writer = DbWriter::new(db, cache_version);
                     let (success, failed) = db_writer.batch_write(pending_items).await;
-                    println!("批量写入完成，成功: {}，失败: {}", success, failed);
+                    log_debug!(
+                        "批量写入完成，成功: {}，失败: {}",
+                        "Batch write finished, succeeded: {}, failed: {}",
+                        success,
+                        failed
+                    );
                 }
             });
-            return; // 已经添加到内存缓存，不需要继续执行
+            return true; // 已经添加到内存缓存，不需要继续执行
         }
     }
 
     // 如果没有启用内存缓存，或内存缓存创建失败，直接写入数据库
     let db_writer = DbWriter::new(db, cache_version);
     if db_writer.write_single(question_key, compressed).await {
-        println!("成功写入响应到数据库");
+        log_debug!("成功写入响应到数据库", "Response written to the database");
+        true
     } else {
-        eprintln!("写入响应到数据库失败");
+        log_error!("写入响应到数据库失败", "Failed to write response to the database");
+        false
     }
 }
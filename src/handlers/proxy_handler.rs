@@ -1,29 +1,37 @@
-use crate::models::api_model::{ChatChoice, ChatMessageJson, ChatResponseJson, Usage};
+use crate::{log_debug, log_error, log_warn, tr};
+use crate::models::api_model::ChatResponseJson;
 use crate::utils::config::Config;
-use axum::http::StatusCode;
+use crate::utils::error::AppError;
+use crate::utils::http_client::apply_connection_options;
+use crate::utils::response_parser::{parse_chat_response as parse_lenient, parse_error};
+use axum::http::HeaderMap;
 use std::sync::OnceLock;
 use std::time::{Duration};
 
 // 全局HTTP客户端
 static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
-fn get_optimized_client(config: &Config) -> &'static reqwest::Client {
+pub fn get_optimized_client(config: &Config) -> &'static reqwest::Client {
     HTTP_CLIENT.get_or_init(|| {
-        reqwest::Client::builder()
+        let builder = reqwest::Client::builder()
             .timeout(Duration::from_secs(config.proxy.request_timeout_seconds))
             .connect_timeout(Duration::from_secs(config.proxy.connect_timeout_seconds))
-            .danger_accept_invalid_certs(true)
             .pool_max_idle_per_host(20) // 增加连接池大小
             .tcp_keepalive(Some(Duration::from_secs(60)))
             .tcp_nodelay(true) // 启用TCP NoDelay
-            .no_proxy()
             .http2_adaptive_window(true) // 使用HTTP/2时自适应窗口大小
             .http2_initial_stream_window_size(1024 * 1024) // 1MB初始窗口大小
             .http2_keep_alive_interval(Some(Duration::from_secs(20)))
-            .http2_keep_alive_timeout(Duration::from_secs(20))
-            .build()
+            .http2_keep_alive_timeout(Duration::from_secs(20));
+
+        apply_connection_options(builder, &config.http_client)
+            .and_then(|builder| Ok(builder.build()?))
             .unwrap_or_else(|e| {
-                eprintln!("创建HTTP客户端失败: {}，使用默认配置", e);
+                log_error!(
+                    "创建HTTP客户端失败: {}，使用默认配置",
+                    "Failed to create HTTP client: {}, using the default configuration",
+                    e
+                );
                 reqwest::Client::new()
             })
     })
@@ -33,8 +41,8 @@ fn get_optimized_client(config: &Config) -> &'static reqwest::Client {
 async fn with_timeout<T, E>(
     duration: Duration,
     future: impl std::future::Future<Output = Result<T, E>>,
-    timeout_msg: &'static str,
-) -> Result<T, (StatusCode, String)>
+    timeout_msg: String,
+) -> Result<T, AppError>
 where
     E: std::fmt::Display,
 {
@@ -46,23 +54,26 @@ where
 
             // 根据错误类型返回不同状态码
             if err_msg.contains("connect") || err_msg.contains("connection") {
-                Err((
-                    StatusCode::BAD_GATEWAY,
-                    format!("无法连接到上游服务器: {}", e),
-                ))
+                Err(AppError::BadGateway(tr!(
+                    "无法连接到上游服务器: {}",
+                    "Cannot connect to the upstream server: {}",
+                    e
+                )))
             } else if err_msg.contains("timeout") {
-                Err((
-                    StatusCode::GATEWAY_TIMEOUT,
-                    format!("上游服务器响应超时: {}", e),
-                ))
+                Err(AppError::GatewayTimeout(tr!(
+                    "上游服务器响应超时: {}",
+                    "Upstream server timed out: {}",
+                    e
+                )))
             } else {
-                Err((
-                    StatusCode::BAD_GATEWAY,
-                    format!("请求上游服务器失败: {}", e),
-                ))
+                Err(AppError::BadGateway(tr!(
+                    "请求上游服务器失败: {}",
+                    "Upstream request failed: {}",
+                    e
+                )))
             }
         }
-        Err(_) => Err((StatusCode::GATEWAY_TIMEOUT, timeout_msg.to_string())),
+        Err(_) => Err(AppError::GatewayTimeout(timeout_msg)),
     }
 }
 
@@ -72,13 +83,15 @@ pub async fn send_proxied_request(
     headers: &std::collections::HashMap<String, String>,
     config: &Config,
     request_id: &str,
-) -> Result<ChatResponseJson, (StatusCode, String)> {
+    endpoint_client: Option<&reqwest::Client>,
+    request_timeout: Duration,
+) -> Result<(ChatResponseJson, HeaderMap), AppError> {
     // 使用外部传入的请求 ID 进行日志追踪
     // 开始时间日志已移除，不再记录耗时信息
-    println!("[{}] 代理请求开始: {}", request_id, target_url);
+    log_debug!("[{}] 代理请求开始: {}", "[{}] Proxy request started: {}", request_id, target_url);
 
-    // 使用优化的全局客户端
-    let optimized_client = get_optimized_client(config);
+    // 优先使用端点专用客户端，否则使用优化的全局客户端
+    let optimized_client = endpoint_client.unwrap_or_else(|| get_optimized_client(config));
 
     // 创建请求构建器
     let mut request_builder = optimized_client.post(target_url);
@@ -93,151 +106,46 @@ pub async fn send_proxied_request(
     }
 
     let response = with_timeout(
-        Duration::from_secs(config.proxy.request_timeout_seconds),
+        request_timeout,
         request_builder.body(payload_json.to_owned()).send(),
-        "连接上游服务器超时",
+        tr!("连接上游服务器超时", "Timed out connecting to the upstream server"),
     )
     .await?;
 
     // 检查响应状态
     if !response.status().is_success() {
-        return Err((
-            StatusCode::from_u16(response.status().as_u16())
-                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            format!("上游服务器返回错误: {:?}", response),
-        ));
+        return Err(AppError::from_upstream_response(
+            response,
+            Duration::from_secs(config.proxy.response_read_timeout_seconds),
+        )
+        .await);
     }
 
+    let response_headers = response.headers().clone();
     let text = with_timeout(
         Duration::from_secs(config.proxy.response_read_timeout_seconds),
         response.text(),
-        "读取上游服务器响应超时",
+        tr!("读取上游服务器响应超时", "Timed out reading the upstream response"),
     )
     .await?;
 
-    // 解析JSON
-    match serde_json::from_str::<ChatResponseJson>(&text) {
-        Ok(json) => Ok(json),
-        Err(e) => {
-            // println!("[{}] 解析JSON失败: {} ({:?})", request_id, e, start_time.elapsed());
-
-            match serde_json::from_str::<serde_json::Value>(&text) {
-                Ok(generic_json) => {
-                    let choices = extract_choices_from_json(&generic_json, config);
-
-                    if choices.is_empty() {
-                        // println!("[{}] 无法从通用JSON中提取有效的消息内容", request_id);
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("解析响应JSON失败: {}", e),
-                        ));
-                    }
-
-                    let response = construct_response_from_json(generic_json, choices, config);
-
-                    println!("[{}] 成功构造兼容的响应对象", request_id);
-                    Ok(response)
-                }
-                Err(parse_err) => {
-                    println!("[{}] 解析为通用JSON也失败: {}", request_id, parse_err);
-                    Err((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("解析响应JSON失败: {}", e),
-                    ))
-                }
-            }
-        }
-    }
-}
-
-fn extract_choices_from_json(generic_json: &serde_json::Value, config: &Config) -> Vec<ChatChoice> {
-    match generic_json.get("choices") {
-        Some(choices) => {
-            if let Some(choices_array) = choices.as_array() {
-                choices_array
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, choice)| {
-                        let content = match choice.get("message").and_then(|m| m.get("content")) {
-                            Some(content) => content.as_str().unwrap_or("").to_string(),
-                            None => "".to_string(),
-                        };
-
-                        let role = match choice.get("message").and_then(|m| m.get("role")) {
-                            Some(role) => role.as_str().unwrap_or(&config.api_defaults.default_role).to_string(),
-                            None => config.api_defaults.default_role.clone(),
-                        };
-
-                        let finish_reason = match choice.get("finish_reason") {
-                            Some(reason) => reason.as_str().unwrap_or(&config.api_defaults.default_finish_reason).to_string(),
-                            None => config.api_defaults.default_finish_reason.clone(),
-                        };
-
-                        ChatChoice {
-                            index: idx as i32,
-                            logprobs: None,
-                            finish_reason,
-                            message: ChatMessageJson { role, content },
-                        }
-                    })
-                    .collect()
-            } else {
-                vec![]
-            }
-        }
-        None => vec![],
-    }
+    Ok((parse_chat_response(&text, config, request_id)?, response_headers))
 }
 
-// 从JSON构造响应对象
-fn construct_response_from_json(
-    generic_json: serde_json::Value,
-    choices: Vec<ChatChoice>,
+// 解析上游响应体，严格解析失败时尝试从通用JSON中构造兼容的响应对象
+pub fn parse_chat_response(
+    text: &str,
     config: &Config,
-) -> ChatResponseJson {
-    ChatResponseJson {
-        id: generic_json
-            .get("id")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-            .to_string(),
-        object: generic_json
-            .get("object")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&config.api_defaults.default_object)
-            .to_string(),
-        created: generic_json
-            .get("created")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(chrono::Utc::now().timestamp()),
-        model: generic_json
-            .get("model")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-            .to_string(),
-        choices,
-        usage: Usage {
-            prompt_tokens: generic_json
-                .get("usage")
-                .and_then(|u| u.get("prompt_tokens"))
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0) as i32,
-            completion_tokens: generic_json
-                .get("usage")
-                .and_then(|u| u.get("completion_tokens"))
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0) as i32,
-            total_tokens: generic_json
-                .get("usage")
-                .and_then(|u| u.get("total_tokens"))
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0) as i32,
-        },
-        stats: serde_json::Value::Null,
-        system_fingerprint: generic_json
-            .get("system_fingerprint")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&config.api_defaults.default_system_fingerprint)
-            .to_string(),
-    }
+    request_id: &str,
+) -> Result<ChatResponseJson, AppError> {
+    parse_lenient(text, &config.api_defaults).map_err(|e| {
+        log_warn!(
+            "[{}] 解析响应JSON失败: {}",
+            "[{}] Failed to parse response JSON: {}",
+            request_id,
+            e
+        );
+        let context = tr!("解析响应JSON失败", "Failed to parse response JSON");
+        parse_error(&config.api_defaults, &context, e)
+    })
 }
@@ -0,0 +1,84 @@
+use crate::{log_debug, log_warn, tr};
+use crate::handlers::chat_completion_handler::{TaskSender, store_answer};
+use crate::models::api_model::AppState;
+use crate::proto::ReplicationBatch;
+use crate::utils::answer_codec::decode_answer;
+use crate::utils::cache_epoch::raise_cache_epoch;
+use crate::utils::error::AppError;
+use crate::utils::replication::{REPLICATION_SECRET_HEADER, node_id, secret_matches};
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use axum::{Json, extract::State};
+use prost::Message;
+use serde_json::json;
+use std::sync::Arc;
+
+type SharedState = Arc<(Arc<AppState>, TaskSender, TaskSender)>;
+
+// 处理 /internal/replicate 路由：写入其他节点推送的缓存条目（不再转发，避免循环）
+pub async fn receive_replication(
+    State(app_state): State<SharedState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let state = &app_state.0;
+    let config = &state.config.replication;
+    let Some(own_node_id) = node_id() else {
+        return Err(AppError::ServiceUnavailable(tr!(
+            "未启用缓存复制",
+            "Cache replication is not enabled"
+        )));
+    };
+    // 该路由与公开接口共用监听地址，未配置密钥时同样拒绝，避免任何客户端写入缓存
+    let provided = headers
+        .get(REPLICATION_SECRET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !secret_matches(provided, &config.shared_secret) {
+        return Err(AppError::Forbidden(tr!(
+            "缓存复制密钥不正确",
+            "Incorrect cache replication secret"
+        )));
+    }
+
+    let batch = ReplicationBatch::decode(body).map_err(|e| {
+        AppError::BadRequest(tr!(
+            "无法解析复制数据: {}",
+            "Cannot parse the replication data: {}",
+            e
+        ))
+    })?;
+    if batch.origin == own_node_id {
+        return Ok(Json(json!({ "accepted": 0 })));
+    }
+    // 先同步缓存失效，再写入条目
+    raise_cache_epoch(&state.db, batch.epoch).await?;
+
+    let mut accepted = 0;
+    for entry in batch.entries {
+        // 跳过无法解码的条目，避免写入损坏的数据
+        if let Err(e) = decode_answer(&entry.answer) {
+            log_warn!(
+                "忽略来自节点 {} 的无效缓存条目: {}",
+                "Ignoring invalid cache entry from node {}: {}",
+                batch.origin,
+                e
+            );
+            continue;
+        }
+        let Ok(version) = u8::try_from(entry.version) else {
+            continue;
+        };
+        if store_answer(state, entry.question_key, entry.answer, version).await {
+            accepted += 1;
+        }
+    }
+
+    log_debug!(
+        "已接收节点 {} 复制的缓存条目 {} 个",
+        "Received {} replicated cache entries from node {}",
+        batch.origin,
+        accepted
+    );
+    Ok(Json(json!({ "accepted": accepted })))
+}
@@ -7,10 +7,17 @@ pub mod models {
 }
 
 pub mod handlers {
+    pub mod admin_handler;
     pub mod api_handler;
     pub mod chat_completion_handler;
     pub mod proxy_handler;
+    pub mod replication_handler;
 }
 
 pub mod utils;
 pub mod server;
+pub mod grpc_server;
+
+// 集成测试辅助（模拟上游与测试服务），仅在启用 test-support 特性时编译
+#[cfg(feature = "test-support")]
+pub mod test_support;
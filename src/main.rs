@@ -1,21 +1,125 @@
-use llm_api::models::api_model::AppState;
-use llm_api::server::{create_router, start_server};
-use llm_api::utils::cache_maintenance::start_maintenance_task;
+use llm_api::{log_error, log_info, tr};
+use llm_api::grpc_server::start_grpc_server;
+use llm_api::server::{
+    build_app_state, create_router, create_task_channels, start_background_tasks, start_server,
+};
+use llm_api::utils::bench::{BenchOptions, run_bench};
+use llm_api::utils::cache_epoch::{load_cache_epoch, start_epoch_sync_task};
+use llm_api::utils::cache_maintenance::report_consistency;
+use llm_api::utils::compact::run_compact;
 use llm_api::utils::config::load_config;
+use llm_api::utils::config_include::take_profile_arg;
+use llm_api::utils::daemon::{PidFile, spawn_background, take_daemon_args};
 use llm_api::utils::db::{create_db_pool, init_db, optimize_db};
-use llm_api::utils::http_client::create_http_client;
-use llm_api::utils::idle_flush::{IdleFlushConfig, IdleFlushManager};
-use llm_api::utils::memory_cache::MemoryCache;
+use llm_api::utils::encryption::init_encryption;
+use llm_api::utils::exit_flush::PendingFlushGuard;
+use llm_api::utils::inspect::run_inspect;
+use llm_api::utils::purge::run_purge;
+use llm_api::utils::redis_cache::init_redis_cache;
+use llm_api::utils::rehash::run_rehash;
+use llm_api::utils::replication::init_replication;
+use llm_api::utils::warmup::warm_up_endpoints;
+use llm_api::utils::webhook::init_webhooks;
 use std::sync::Arc;
-use tokio::sync::{Semaphore, mpsc};
 
 #[tokio::main]
 async fn main() {
+    // --profile 可出现在任意位置，取出后其余参数交给子命令解析
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args = raw_args.clone();
+    let profile = match take_profile_arg(&mut args) {
+        Ok(profile) => profile,
+        Err(e) => {
+            log_error!("{}", "{}", e);
+            return;
+        }
+    };
+    let daemon_options = match take_daemon_args(&mut args) {
+        Ok(options) => options,
+        Err(e) => {
+            log_error!("{}", "{}", e);
+            return;
+        }
+    };
+
     // 加载配置
-    let config = match load_config() {
+    let config = match load_config(profile.as_deref()) {
         Ok(config) => config,
         Err(e) => {
-            eprintln!("加载配置失败: {}", e);
+            log_error!("加载配置失败: {}", "Failed to load config: {}", e);
+            return;
+        }
+    };
+
+    // bench 子命令：压测已启动的本地服务后退出
+    if args.first().map(String::as_str) == Some("bench") {
+        let result = match BenchOptions::parse(&args[1..], &config) {
+            Ok(options) => run_bench(options).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", tr!("压测失败: {}", "Benchmark failed: {}", e));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // inspect 子命令：按问题键打印数据库中的缓存记录后退出
+    if args.first().map(String::as_str) == Some("inspect") {
+        if let Err(e) = run_inspect(&args[1..], &config).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // purge 子命令：按模型、写入时间与命中次数删除数据库中的缓存记录后退出
+    if args.first().map(String::as_str) == Some("purge") {
+        if let Err(e) = run_purge(&args[1..], &config).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // compact 子命令：写回 WAL 并执行 VACUUM 与 ANALYZE 后退出
+    if args.first().map(String::as_str) == Some("compact") {
+        if let Err(e) = run_compact(&args[1..], &config).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // rehash 子命令：缓存键策略变更后按当前配置迁移问题键后退出
+    if args.first().map(String::as_str) == Some("rehash") {
+        if let Err(e) = run_rehash(&args[1..], &config).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // --daemon：配置加载成功后以相同参数在后台重新启动，当前进程退出
+    if daemon_options.daemon {
+        match spawn_background(&raw_args, &daemon_options) {
+            Ok(pid) => log_info!(
+                "已转入后台运行，进程号 {}，输出写入 {}",
+                "Running in the background as PID {}, output goes to {}",
+                pid,
+                daemon_options.log_file().display()
+            ),
+            Err(e) => {
+                log_error!("转入后台运行失败: {}", "Failed to start in the background: {}", e)
+            }
+        }
+        return;
+    }
+    // 持有到 main 结束，正常退出时删除进程号文件
+    let _pidfile = match daemon_options.pidfile.as_deref().map(PidFile::create).transpose() {
+        Ok(pidfile) => pidfile,
+        Err(e) => {
+            log_error!("{}", "{}", e);
             return;
         }
     };
@@ -24,108 +128,116 @@ async fn main() {
     let pool = match create_db_pool(&config.database_url, &config.database).await {
         Ok(pool) => pool,
         Err(e) => {
-            eprintln!("创建数据库连接池失败: {}", e);
+            log_error!("创建数据库连接池失败: {}", "Failed to create database pool: {}", e);
             return;
         }
     };
 
     // 初始化数据库
     if let Err(e) = init_db(&pool).await {
-        eprintln!("初始化数据库失败: {}", e);
+        log_error!("初始化数据库失败: {}", "Failed to initialize database: {}", e);
         return;
     }
 
+    // 检查问题、答案与缓存键来源之间的关联（Redis 后端的缓存不在 SQLite 中）
+    if !config.cache.uses_redis() {
+        report_consistency(&pool).await;
+    }
+
     // 优化数据库
-    if let Err(e) = optimize_db(&pool).await {
-        eprintln!("优化数据库失败: {}", e);
+    if let Err(e) = optimize_db(&pool, &config.database).await {
+        log_error!("优化数据库失败: {}", "Failed to optimize database: {}", e);
+        return;
+    }
+
+    // 缓存加密需在任何缓存读写之前启用
+    if let Err(e) = init_encryption(&config.encryption) {
+        log_error!("初始化缓存加密失败: {}", "Failed to initialize cache encryption: {}", e);
         return;
     }
 
-    // 创建HTTP客户端
-    let http_client = match create_http_client(&config.http_client) {
-        Ok(client) => client,
+    // 使用 Redis 作为缓存存储后端（审计日志等仍写入 SQLite）
+    if config.cache.uses_redis()
+        && let Err(e) = init_redis_cache(&config.cache.redis).await
+    {
+        log_error!(
+            "连接 Redis 缓存后端失败: {}",
+            "Failed to connect to the Redis cache backend: {}",
+            e
+        );
+        return;
+    }
+
+    // 读取全局缓存纪元（Redis 后端的纪元由各节点共享，需在连接 Redis 之后读取）
+    match load_cache_epoch(&pool).await {
+        Ok(0) => {}
+        Ok(epoch) => log_info!("全局缓存纪元: {}", "Global cache epoch: {}", epoch),
         Err(e) => {
-            eprintln!("创建HTTP客户端失败: {}", e);
+            log_error!("读取全局缓存纪元失败: {}", "Failed to read global cache epoch: {}", e);
             return;
         }
-    };
+    }
+    start_epoch_sync_task(config.cache.redis.epoch_refresh_seconds);
 
-    // 创建缓存命中和未命中的任务发送器
-    let (tx_hit, _) = mpsc::channel(config.cache_hit_pool_size);
-    let (tx_miss, _) = mpsc::channel(config.cache_miss_pool_size);
-
-    // 初始化内存缓存
-    let memory_cache = if config.cache.enabled && config.cache.max_items > 0 {
-        println!("初始化内存缓存，最大容量: {} 条", config.cache.max_items);
-        Some(Arc::new(MemoryCache::new(config.cache.max_items)))
-    } else {
-        println!("内存缓存功能已禁用");
-        None
-    };
+    // 初始化维护与异常事件的 Webhook 通知
+    init_webhooks(&config.webhooks, &config.http_client);
+    init_replication(&config.replication);
+
+    // 创建缓存命中和未命中的专用线程池及任务发送器
+    let (tx_hit, tx_miss, hit_runtime, miss_runtime) =
+        create_task_channels(config.cache_hit_pool_size, config.cache_miss_pool_size);
 
     // 创建应用状态
-    let config_clone = config.clone();
-    let shared_state = Arc::new(AppState {
-        db: Arc::new(pool.clone()),
-        client: http_client,
-        api_endpoints: config.api_endpoints.clone(),
-        max_concurrent_requests: config.max_concurrent_requests,
-        semaphore: Arc::new(Semaphore::new(config.max_concurrent_requests)),
-        cache_override_mode: config.cache_override_mode,
-        use_curl: config.use_curl,
-        use_proxy: config.use_proxy,
-        enable_thinking: config.enable_thinking,
-        api_headers: config.api_headers.clone(),
-        memory_cache: memory_cache.clone(),
-        cache_enabled: config.cache.enabled,
-        batch_write_size: config.cache.batch_write_size,
-        context_trim_enabled: config.context_trim.enabled,
-        max_context_tokens: config.context_trim.max_context_tokens,
-        context_trim_smart_enabled: config.context_trim.smart_enabled,
-        context_smart_max_tokens: config.context_trim.smart_max_tokens,
-        per_message_overhead: config.context_trim.per_message_overhead,
-        min_keep_pairs: config.context_trim.min_keep_pairs,
-        summary_aggressiveness: config.context_trim.summary_aggressiveness,
-        summary_mode: config.context_trim.summary_mode.clone(),
-        summary_api_enabled: config.context_trim.summary_api.enabled,
-        summary_api_endpoints: config.context_trim.summary_api.endpoints.clone(),
-        summary_api_key_env: config.context_trim.summary_api.api_key_env.clone(),
-        summary_api_max_tokens: config.context_trim.summary_api.max_tokens,
-        summary_api_temperature: config.context_trim.summary_api.temperature,
-        summary_api_timeout_seconds: config.context_trim.summary_api.timeout_seconds,
-        config: config_clone,
-    });
-
-    // 启动缓存维护任务
-    if config.cache_maintenance.enabled {
-        println!("启动缓存维护任务");
-        start_maintenance_task(Arc::new(pool.clone()), config.cache_maintenance.clone());
-    }
-
-    // 启动空闲刷新任务
-    if config.idle_flush.enabled
-        && memory_cache.is_some()
-        && config.idle_flush.idle_timeout_seconds > 0
-    {
-        println!("启动空闲刷新任务");
-        let idle_config = IdleFlushConfig::from_yaml_config(&config.idle_flush);
+    let shared_state = match build_app_state(&config, Arc::new(pool.clone())) {
+        Ok(state) => Arc::new(state),
+        Err(e) => {
+            log_error!("{}", "{}", e);
+            return;
+        }
+    };
 
-        let idle_manager = Arc::new(
-            IdleFlushManager::new(memory_cache.clone().unwrap(), idle_config)
-                .with_db(Arc::new(pool.clone()), config.cache_version),
-        );
+    // 退出（包括 panic）时将内存中的缓存写入数据库
+    let flush_guard = shared_state
+        .memory_cache
+        .clone()
+        .map(|cache| PendingFlushGuard::new(cache, Arc::new(pool.clone()), config.cache_version));
 
-        idle_manager.clone().start_flush_task().await;
-        println!("空闲刷新任务已启动");
-    }
+    // 启动数据库写入任务、缓存维护与内存缓存相关的后台任务
+    start_background_tasks(&shared_state).await;
+
+    // 预热上游端点连接
+    warm_up_endpoints(&shared_state, &config.warmup).await;
 
     let app_state = Arc::new((shared_state.clone(), tx_hit, tx_miss));
 
+    // 启动 gRPC 服务，与 HTTP 服务共享状态与缓存
+    if config.grpc.enabled {
+        let grpc_state = app_state.clone();
+        let grpc_config = config.grpc.clone();
+        tokio::spawn(async move {
+            if let Err(e) = start_grpc_server(grpc_state, grpc_config).await {
+                log_error!("gRPC 服务启动失败: {}", "Failed to start gRPC server: {}", e);
+            }
+        });
+    }
+
     // 创建路由
     let app = create_router(app_state);
 
     // 启动服务器
     if let Err(e) = start_server(app, &config).await {
-        eprintln!("服务器启动失败: {}", e);
+        log_error!("服务器启动失败: {}", "Server failed to start: {}", e);
+    }
+
+    // 服务器停止后写入内存中尚未持久化的缓存
+    if let Some(guard) = &flush_guard {
+        guard.flush().await;
+    }
+
+    // 关闭专用线程池（运行时不能在异步上下文中直接 drop）
+    for runtime in [hit_runtime, miss_runtime] {
+        if let Ok(runtime) = Arc::try_unwrap(runtime) {
+            runtime.shutdown_background();
+        }
     }
 }
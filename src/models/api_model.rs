@@ -1,14 +1,18 @@
+use crate::tr;
 use crate::utils::memory_cache::MemoryCache;
 use rand::prelude::*;
 use rand_distr::weighted::WeightedIndex;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::sync::Arc;
-use tokio::sync::Semaphore;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ChatRequestJson {
+    // 客户端可省略（或为 null），省略时使用配置的 default_model
+    #[serde(default, deserialize_with = "deserialize_null_as_empty")]
     pub model: String,
+    // 使用提示词模板时可省略，模板展开的消息会放在其之前
+    #[serde(default)]
     pub messages: Vec<ChatMessageJson>,
     #[serde(default = "default_temperature")]
     pub temperature: f32,
@@ -18,6 +22,130 @@ pub struct ChatRequestJson {
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable_thinking: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopSequences>,
+    // 常用采样参数，未设置时不发送给上游（使用上游的默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    // 结构化输出格式（如 {"type": "json_object"} 或 json_schema），原样转发给上游并计入缓存键
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    // 单次请求的上下文裁切覆盖参数，仅供本服务使用，不转发给上游
+    #[serde(default, skip_serializing)]
+    pub x_trim: Option<TrimOverride>,
+    // 提示词模板名称及其变量，由本服务展开为消息，不转发给上游
+    #[serde(default, skip_serializing)]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub variables: Option<std::collections::HashMap<String, serde_json::Value>>,
+    // 本服务不处理的其他字段（如 top_p、presence_penalty、response_format 与厂商扩展字段），原样转发给上游
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ChatRequestJson {
+    /// 检查采样参数的取值范围（与 OpenAI 接口一致），返回第一处错误
+    pub fn validate_sampling(&self) -> Result<(), String> {
+        check_range("temperature", Some(self.temperature), 0.0, 2.0)?;
+        check_range("top_p", self.top_p, 0.0, 1.0)?;
+        check_range("frequency_penalty", self.frequency_penalty, -2.0, 2.0)?;
+        check_range("presence_penalty", self.presence_penalty, -2.0, 2.0)
+    }
+
+    /// 是否请求了 JSON 结构化输出（response_format 的 type 为 json_object 或 json_schema）
+    pub fn structured_output(&self) -> bool {
+        self.response_format
+            .as_ref()
+            .and_then(|format| format.get("type"))
+            .and_then(|kind| kind.as_str())
+            .is_some_and(|kind| kind == "json_object" || kind == "json_schema")
+    }
+
+    /// 参与缓存键的采样参数，未设置的参数记为空，停止序列按顺序列出
+    pub fn sampling_key(&self) -> String {
+        fn opt<T: std::fmt::Display>(value: Option<T>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_default()
+        }
+        let stop = self
+            .stop
+            .clone()
+            .map(StopSequences::into_vec)
+            .unwrap_or_default()
+            .join("\0");
+        format!(
+            "temperature={};top_p={};frequency_penalty={};presence_penalty={};seed={};max_tokens={};stop={}",
+            self.temperature,
+            opt(self.top_p),
+            opt(self.frequency_penalty),
+            opt(self.presence_penalty),
+            opt(self.seed),
+            self.max_tokens,
+            stop
+        )
+    }
+}
+
+fn check_range(name: &str, value: Option<f32>, min: f32, max: f32) -> Result<(), String> {
+    match value {
+        Some(value) if !(min..=max).contains(&value) => Err(tr!(
+            "{} 应在 {} 到 {} 之间，收到 {}",
+            "{} must be between {} and {}, got {}",
+            name,
+            min,
+            max,
+            value
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 单次请求的上下文裁切覆盖参数，来自请求体 `x_trim` 字段或 `X-Trim-*` 请求头
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TrimOverride {
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub disabled: Option<bool>,
+}
+
+impl TrimOverride {
+    /// 逐字段合并，当前值优先，缺失的字段使用 fallback 中的值
+    pub fn or(self, fallback: TrimOverride) -> TrimOverride {
+        TrimOverride {
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            mode: self.mode.or(fallback.mode),
+            disabled: self.disabled.or(fallback.disabled),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_tokens.is_none() && self.mode.is_none() && self.disabled.is_none()
+    }
+}
+
+/// 停止序列，兼容单个字符串与字符串数组两种写法
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum StopSequences {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl StopSequences {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            StopSequences::Single(stop) => vec![stop],
+            StopSequences::Multiple(stops) => stops,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,12 +179,48 @@ pub struct Usage {
     pub completion_tokens: i32,
     #[serde(default)]
     pub total_tokens: i32,
+    // 为 true 时表示 token 数为本地估算值（如缓存命中的响应），而非上游返回的实际用量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ChatMessageJson {
     pub role: String,
+    // 携带 tool_calls 的 assistant 消息 content 可能为 null，统一按空字符串处理
+    #[serde(default, deserialize_with = "deserialize_null_as_empty")]
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    // 旧版 function calling 的调用参数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<serde_json::Value>,
+}
+
+impl ChatMessageJson {
+    /// 是否为发起工具调用的 assistant 消息
+    pub fn has_tool_calls(&self) -> bool {
+        self.role.eq_ignore_ascii_case("assistant")
+            && [&self.tool_calls, &self.function_call]
+                .iter()
+                .any(|calls| calls.as_ref().is_some_and(|calls| !calls.is_null()))
+    }
+
+    /// 是否为工具（或旧版 function）的返回结果消息
+    pub fn is_tool_result(&self) -> bool {
+        self.role.eq_ignore_ascii_case("tool") || self.role.eq_ignore_ascii_case("function")
+    }
+}
+
+fn deserialize_null_as_empty<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -64,25 +228,118 @@ pub struct ApiEndpoint {
     pub url: String,
     pub weight: u8,
     pub model: Option<String>,
-    #[serde(default = "default_version")]
-    pub version: u8,
+    // 端点专用的 TLS 配置（如双向 TLS 客户端证书），设置的字段覆盖全局 http_client.tls，未设置的沿用全局值
+    #[serde(default)]
+    pub tls: Option<crate::utils::config::TlsConfig>,
+    // A/B 对比分组（"a" 或 "b"），启用 ab_test 时按比例在两组之间分配流量
+    #[serde(default)]
+    pub ab_arm: Option<String>,
+    // 转发到该端点时设置的 enable_thinking（如 Qwen 系列），未设置时使用全局 enable_thinking
+    #[serde(default)]
+    pub enable_thinking: Option<bool>,
+    // 转发到该端点时覆盖的请求参数
+    #[serde(default)]
+    pub overrides: EndpointOverrides,
+    // 是否为转发到该端点的请求添加提示词前缀缓存标记，未设置时使用全局 prompt_cache.enabled
+    #[serde(default)]
+    pub prompt_cache_hints: Option<bool>,
+    // 端点专用的 HTTP 客户端参数（连接池、超时、HTTP 版本、出站代理），覆盖全局 http_client
+    #[serde(default)]
+    pub http_client: crate::utils::config::HttpClientOverrides,
+}
+
+impl ApiEndpoint {
+    /// 端点独立 HTTP 客户端的索引：同一 URL 可配置多个 TLS 或客户端参数不同的端点，
+    /// 因此按 URL 与这些配置共同区分
+    pub fn client_key(&self) -> String {
+        serde_json::json!([self.url, self.tls, self.http_client]).to_string()
+    }
+
+    /// 端点实际使用的 TLS 配置：专用配置逐字段覆盖全局 http_client.tls
+    pub fn effective_tls(
+        &self,
+        global: &crate::utils::config::TlsConfig,
+    ) -> crate::utils::config::TlsConfig {
+        self.tls
+            .as_ref()
+            .map_or_else(|| global.clone(), |tls| tls.merged_over(global))
+    }
+}
+
+/// 端点专用的请求参数，设置的字段覆盖客户端发送的值，未设置的保持不变
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct EndpointOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    // 替换请求中的 stop 列表，可写单个字符串或字符串数组
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopSequences>,
+}
+
+impl EndpointOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.max_tokens.is_none()
+            && self.top_p.is_none()
+            && self.frequency_penalty.is_none()
+            && self.presence_penalty.is_none()
+            && self.seed.is_none()
+            && self.stop.is_none()
+    }
+
+    /// 将覆盖参数应用到发送给该端点的请求参数
+    pub fn apply(&self, payload: &mut ChatRequestJson) {
+        if let Some(temperature) = self.temperature {
+            payload.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            payload.max_tokens = max_tokens;
+        }
+        if self.top_p.is_some() {
+            payload.top_p = self.top_p;
+        }
+        if self.frequency_penalty.is_some() {
+            payload.frequency_penalty = self.frequency_penalty;
+        }
+        if self.presence_penalty.is_some() {
+            payload.presence_penalty = self.presence_penalty;
+        }
+        if self.seed.is_some() {
+            payload.seed = self.seed;
+        }
+        if let Some(stop) = &self.stop {
+            payload.stop = Some(stop.clone());
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<SqlitePool>,
     pub client: reqwest::Client,
+    pub endpoint_clients: std::collections::HashMap<String, reqwest::Client>,
     pub api_endpoints: Vec<ApiEndpoint>,
     pub max_concurrent_requests: usize,
-    pub semaphore: Arc<Semaphore>,
-    pub cache_override_mode: bool,
+    // 上游并发许可及其等待队列
+    pub upstream_queue: Arc<crate::utils::upstream_queue::UpstreamQueue>,
     pub use_curl: bool,
     pub use_proxy: bool,
     pub enable_thinking: Option<bool>,
     pub api_headers: std::collections::HashMap<String, String>,
     pub memory_cache: Option<Arc<MemoryCache>>,
     pub cache_enabled: bool,
-    pub batch_write_size: usize,
+    // 批量写入触发器（固定阈值或自适应阈值）
+    pub batch_trigger: Arc<crate::utils::adaptive_batch::BatchWriteTrigger>,
     pub context_trim_enabled: bool,
     pub max_context_tokens: usize,
     pub context_trim_smart_enabled: bool,
@@ -93,11 +350,19 @@ pub struct AppState {
     pub summary_mode: String,
     pub summary_api_enabled: bool,
     pub summary_api_endpoints: Vec<ApiEndpoint>,
-    pub summary_api_key_env: String,
+    // 启动时从 summary_api.api_key_env 指定的环境变量读取的摘要 API Key
+    pub summary_api_key: Option<String>,
     pub summary_api_max_tokens: i32,
     pub summary_api_temperature: f32,
     pub summary_api_timeout_seconds: u64,
     pub config: crate::utils::config::Config,
+    pub endpoint_stats: Arc<crate::utils::endpoint_stats::EndpointStats>,
+    pub hit_stats: Arc<crate::utils::hit_stats::HitRateStats>,
+    pub statsd: Arc<crate::utils::statsd::StatsdClient>,
+    // 请求与响应插件
+    pub plugins: crate::utils::plugin::PluginRegistry,
+    // 上游请求录制 / 回放（upstream_replay.mode 为 off 时为 None）
+    pub upstream_replay: Option<Arc<crate::utils::upstream_replay::UpstreamReplay>>,
 }
 
 fn default_system_fingerprint() -> String {
@@ -120,8 +385,17 @@ fn default_finish_reason() -> String {
     "unknown".to_string()
 }
 
-fn default_version() -> u8 {
-    0
+impl AppState {
+    /// 选择实际处理该端点请求的客户端：端点独立客户端优先，其次是代理模式的共享客户端与全局客户端
+    pub fn client_for(&self, endpoint: &ApiEndpoint) -> &reqwest::Client {
+        match self.endpoint_clients.get(&endpoint.client_key()) {
+            Some(client) => client,
+            None if self.use_proxy => {
+                crate::handlers::proxy_handler::get_optimized_client(&self.config)
+            }
+            None => &self.client,
+        }
+    }
 }
 
 pub fn select_api_endpoint(endpoints: &[ApiEndpoint]) -> Option<ApiEndpoint> {
@@ -150,3 +424,16 @@ pub fn select_api_endpoint(endpoints: &[ApiEndpoint]) -> Option<ApiEndpoint> {
         Err(_) => Some((*valid_endpoints[0]).clone()),
     }
 }
+
+/// 按权重选择一个与 `excluded_url` 不同的端点，用于换端点重试；没有其他可用端点时返回 None
+pub fn select_fallback_endpoint(
+    endpoints: &[ApiEndpoint],
+    excluded_url: &str,
+) -> Option<ApiEndpoint> {
+    let candidates: Vec<ApiEndpoint> = endpoints
+        .iter()
+        .filter(|endpoint| endpoint.url != excluded_url && endpoint.weight > 0)
+        .cloned()
+        .collect();
+    select_api_endpoint(&candidates)
+}
@@ -1,87 +1,504 @@
+use crate::{log_error, log_info, log_warn, tr};
+use crate::handlers::admin_handler::{
+    get_ab_report, get_config, get_dashboard, get_endpoint_stats, get_maintenance_history,
+    get_metrics, get_recent_requests, get_stats, get_top_questions, invalidate_cache,
+    live_events_sse, live_events_ws,
+};
 use crate::handlers::api_handler::{get_embeddings, get_models};
 use crate::handlers::chat_completion_handler::{TaskSender, chat_completion};
+use crate::handlers::replication_handler::receive_replication;
 use crate::models::api_model::AppState;
+use crate::utils::adaptive_batch::{BatchWriteTrigger, start_adaptive_flush_task};
+use crate::utils::admin_auth::require_admin_token;
+use crate::utils::cache_maintenance::start_maintenance_task;
+use crate::utils::config::Config;
+use crate::utils::db_writer::init_db_writer;
+use crate::utils::endpoint_stats::EndpointStats;
+use crate::utils::error::AppError;
+use crate::utils::hit_stats::{HitRateStats, start_hit_rate_report_task};
+use crate::utils::http_client::{create_endpoint_clients, create_http_client};
+use crate::utils::idle_flush::{IdleFlushConfig, IdleFlushManager};
+use crate::utils::memory_cache::{MemoryCache, start_expiry_task};
+use crate::utils::memory_pressure::start_memory_pressure_task;
+use crate::utils::plugin::PluginRegistry;
+use crate::utils::statsd::{StatsdClient, start_statsd_gauge_task};
+use crate::utils::upstream_queue::UpstreamQueue;
+use crate::utils::upstream_replay::UpstreamReplay;
+use crate::utils::{live_events, systemd};
 use axum::Router;
 use axum::{
-    Json,
-    extract::State,
+    BoxError, Json,
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, Request, State},
+    http::header,
+    middleware::{self, Next},
+    response::IntoResponse,
     routing::{get, post},
 };
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use sqlx::SqlitePool;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tower::ServiceBuilder;
+use tower::timeout::TimeoutLayer;
+
+/// 按配置创建应用状态（HTTP 客户端、内存缓存、插件、上游录制 / 回放等），服务启动与集成测试共用
+pub fn build_app_state(config: &Config, pool: Arc<SqlitePool>) -> Result<AppState, String> {
+    // 创建HTTP客户端
+    let http_client = create_http_client(&config.http_client).map_err(|e| {
+        tr!("创建HTTP客户端失败: {}", "Failed to create HTTP client: {}", e)
+    })?;
+
+    // 为配置了专用 TLS 或 HTTP 客户端参数的端点创建独立客户端
+    let endpoint_clients = create_endpoint_clients(&config.api_endpoints, &config.http_client)
+        .map_err(|e| e.to_string())?;
+
+    // 初始化内存缓存
+    let memory_cache = if config.cache.enabled && config.cache.max_items > 0 {
+        log_info!(
+            "初始化内存缓存，最大容量: {} 条",
+            "Memory cache initialized, capacity: {} items",
+            config.cache.max_items
+        );
+        Some(Arc::new(MemoryCache::new(
+            config.cache.max_items,
+            config.cache.memory_ttl_seconds,
+        )))
+    } else {
+        log_info!("内存缓存功能已禁用", "Memory cache disabled");
+        None
+    };
+
+    // 启动时读取摘要 API 的密钥，仅用于发往摘要专用端点的请求
+    let summary_api = &config.context_trim.summary_api;
+    let summary_api_key = if summary_api.enabled && !summary_api.api_key_env.is_empty() {
+        match std::env::var(&summary_api.api_key_env) {
+            Ok(key) if !key.is_empty() => {
+                log_info!(
+                    "已从环境变量 {} 读取摘要 API Key",
+                    "Summary API key read from environment variable {}",
+                    summary_api.api_key_env
+                );
+                Some(key)
+            }
+            _ => {
+                log_warn!(
+                    "未设置环境变量 {}，摘要请求将不携带 API Key",
+                    "Environment variable {} is not set, summary requests will carry no API key",
+                    summary_api.api_key_env
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // 注册内置的请求与响应插件
+    let plugins = PluginRegistry::from_config(config)?;
+
+    // 上游请求录制 / 回放
+    let upstream_replay = UpstreamReplay::from_config(&config.upstream_replay)?.map(Arc::new);
+
+    Ok(AppState {
+        db: pool,
+        client: http_client,
+        endpoint_clients,
+        api_endpoints: config.api_endpoints.clone(),
+        max_concurrent_requests: config.max_concurrent_requests,
+        upstream_queue: Arc::new(UpstreamQueue::new(
+            config.max_concurrent_requests,
+            &config.upstream_queue,
+        )),
+        use_curl: config.use_curl,
+        use_proxy: config.use_proxy,
+        enable_thinking: config.enable_thinking,
+        api_headers: config.api_headers.clone(),
+        memory_cache,
+        cache_enabled: config.cache.enabled,
+        // 批量写入触发器：未启用自适应时使用固定的 batch_write_size
+        batch_trigger: Arc::new(BatchWriteTrigger::new(
+            config.cache.adaptive_batch.clone(),
+            config.cache.batch_write_size,
+        )),
+        context_trim_enabled: config.context_trim.enabled,
+        max_context_tokens: config.context_trim.max_context_tokens,
+        context_trim_smart_enabled: config.context_trim.smart_enabled,
+        context_smart_max_tokens: config.context_trim.smart_max_tokens,
+        per_message_overhead: config.context_trim.per_message_overhead,
+        min_keep_pairs: config.context_trim.min_keep_pairs,
+        summary_aggressiveness: config.context_trim.summary_aggressiveness,
+        summary_mode: config.context_trim.summary_mode.clone(),
+        summary_api_enabled: config.context_trim.summary_api.enabled,
+        summary_api_endpoints: config.context_trim.summary_api.endpoints.clone(),
+        summary_api_key,
+        summary_api_max_tokens: config.context_trim.summary_api.max_tokens,
+        summary_api_temperature: config.context_trim.summary_api.temperature,
+        summary_api_timeout_seconds: config.context_trim.summary_api.timeout_seconds,
+        config: config.clone(),
+        endpoint_stats: Arc::new(EndpointStats::new()),
+        // 滚动缓存命中率统计
+        hit_stats: Arc::new(HitRateStats::new(config.hit_stats.window_minutes)),
+        statsd: Arc::new(StatsdClient::new(&config.statsd)),
+        plugins,
+        upstream_replay,
+    })
+}
+
+/// 启动依赖应用状态的后台任务：唯一的数据库写入任务、命中率与 StatsD 上报、缓存维护，
+/// 以及内存缓存的空闲刷新、自适应批量写入、过期清理与内存压力监控
+pub async fn start_background_tasks(state: &Arc<AppState>) {
+    let config = &state.config;
+    init_db_writer(state.db.clone(), &config.database);
+    start_hit_rate_report_task(state.hit_stats.clone(), config.hit_stats.clone());
+
+    // 定期推送缓存与上游的瞬时指标
+    start_statsd_gauge_task(state.clone(), config.statsd.gauge_interval_seconds);
+
+    // 启动缓存维护任务
+    if config.cache_maintenance.enabled {
+        log_info!("启动缓存维护任务", "Starting cache maintenance task");
+        start_maintenance_task(
+            state.db.clone(),
+            config.cache_maintenance.clone(),
+            config.database.vacuum_min_free_ratio,
+        );
+    }
+
+    let Some(cache) = &state.memory_cache else {
+        return;
+    };
+
+    // 启动空闲刷新任务
+    if config.idle_flush.enabled && config.idle_flush.idle_timeout_seconds > 0 {
+        log_info!("启动空闲刷新任务", "Starting idle flush task");
+        let idle_config = IdleFlushConfig::from_yaml_config(&config.idle_flush);
+
+        let idle_manager = Arc::new(
+            IdleFlushManager::new(cache.clone(), idle_config)
+                .with_db(state.db.clone(), config.cache_version),
+        );
+
+        idle_manager.clone().start_flush_task().await;
+        log_info!("空闲刷新任务已启动", "Idle flush task started");
+    }
+
+    // 启动按时间触发的自适应批量写入任务
+    start_adaptive_flush_task(
+        cache.clone(),
+        state.db.clone(),
+        config.cache_version,
+        state.batch_trigger.clone(),
+    );
+
+    // 启动内存缓存过期清理任务
+    start_expiry_task(cache.clone());
+
+    // 启动内存压力监控任务
+    if config.memory_pressure.enabled {
+        start_memory_pressure_task(
+            cache.clone(),
+            state.db.clone(),
+            config.cache_version,
+            config.memory_pressure.clone(),
+        );
+    }
+}
 
 // 创建路由配置
 pub fn create_router(app_state: Arc<(Arc<AppState>, TaskSender, TaskSender)>) -> Router {
-    let v1_router = Router::new()
-        .route("/v1/chat/completions", post(chat_completion))
-        .route(
-            "/v1/models",
-            get(
-                |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
-                 headers: axum::http::HeaderMap| async move {
-                    get_models(State(state.0.0.clone()), headers, &state.0.0.config).await
-                },
-            ),
-        )
-        .route(
-            "/v1/embeddings",
-            post(
-                |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
-                 headers: axum::http::HeaderMap,
-                 payload: Json<serde_json::Value>| async move {
-                    get_embeddings(State(state.0.0.clone()), headers, payload, &state.0.0.config).await
-                },
-            ),
-        );
+    let server_config = &app_state.0.config.server;
 
-    let no_prefix_router = Router::new()
+    let models_handler = |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
+                          headers: axum::http::HeaderMap| async move {
+        get_models(State(state.0.0.clone()), headers, &state.0.0.config).await
+    };
+    let embeddings_handler = |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
+                              headers: axum::http::HeaderMap,
+                              payload: Json<serde_json::Value>| async move {
+        get_embeddings(State(state.0.0.clone()), headers, payload, &state.0.0.config).await
+    };
+
+    // 对话补全与嵌入请求需要等待上游生成，使用较长的超时
+    let completion_router = Router::new()
+        .route("/v1/chat/completions", post(chat_completion))
+        .route("/v1/embeddings", post(embeddings_handler))
         .route("/chat/completions", post(chat_completion))
-        .route(
-            "/models",
-            get(
-                |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
-                 headers: axum::http::HeaderMap| async move {
-                    get_models(State(state.0.0.clone()), headers, &state.0.0.config).await
-                },
-            ),
-        )
-        .route(
-            "/embeddings",
-            post(
-                |state: State<Arc<(Arc<AppState>, TaskSender, TaskSender)>>,
-                 headers: axum::http::HeaderMap,
-                 payload: Json<serde_json::Value>| async move {
-                    get_embeddings(State(state.0.0.clone()), headers, payload, &state.0.0.config).await
-                },
-            ),
-        );
+        .route("/embeddings", post(embeddings_handler));
+    let completion_router =
+        with_timeout(completion_router, server_config.completion_timeout_seconds);
+
+    // 模型列表与管理接口应当很快返回，使用较短的超时
+    let short_router = Router::new()
+        .route("/v1/models", get(models_handler))
+        .route("/models", get(models_handler))
+        .route("/metrics", get(get_metrics));
 
-    Router::new()
-        .merge(v1_router)
-        .merge(no_prefix_router)
-        // 并发限制
-        .layer(tower::limit::ConcurrencyLimitLayer::new(
-            app_state.0.max_concurrent_requests,
+    // 管理接口可查看配置与缓存内容并使缓存失效，必须携带 admin.token
+    let admin_router = Router::new()
+        .route("/admin/endpoints", get(get_endpoint_stats))
+        .route("/admin/ab", get(get_ab_report))
+        .route("/admin/stats", get(get_stats))
+        .route("/admin/config", get(get_config))
+        .route("/admin/cache/invalidate", post(invalidate_cache))
+        .route("/admin/analytics/top", get(get_top_questions))
+        .route("/admin/maintenance", get(get_maintenance_history))
+        .route("/admin/ws", get(live_events_ws))
+        .route("/admin/events", get(live_events_sse));
+    let admin_router = if app_state.0.config.dashboard.enabled {
+        admin_router.route("/admin/requests/recent", get(get_recent_requests))
+    } else {
+        admin_router
+    };
+    let admin_token = app_state.0.config.admin.token.clone();
+    let admin_router = admin_router.layer(middleware::from_fn(
+        move |request: Request, next: Next| require_admin_token(admin_token.clone(), request, next),
+    ));
+
+    // 仪表盘页面本身是静态内容，数据由页面携带令牌从管理接口获取
+    let short_router = if app_state.0.config.dashboard.enabled {
+        short_router.route("/dashboard", get(get_dashboard))
+    } else {
+        short_router
+    };
+    let short_router = short_router.merge(admin_router);
+    let short_router = with_timeout(short_router, server_config.short_timeout_seconds);
+
+    // 对外接口的请求体大小限制（节点间复制接口使用单独的上限）
+    let public_router = with_body_limit(
+        completion_router.merge(short_router),
+        server_config.max_body_bytes,
+    );
+
+    // 节点间缓存复制，一批条目可能超过默认的请求体大小上限
+    let internal_router = Router::new().route(
+        "/internal/replicate",
+        post(receive_replication).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+    );
+
+    let router = Router::new()
+        .merge(public_router)
+        .merge(internal_router);
+
+    // 整体并发限制，上游请求另由 max_concurrent_requests 对应的信号量限制，缓存命中不受其影响
+    let max_inflight = app_state.0.config.max_inflight_requests;
+    let router = if max_inflight > 0 {
+        router.layer(tower::limit::ConcurrencyLimitLayer::new(max_inflight))
+    } else {
+        router
+    };
+
+    // 整体请求超时（包括等待并发许可的时间），流式响应只限制到返回响应头为止
+    let router = with_timeout(router, server_config.request_timeout_seconds);
+
+    router.with_state(app_state)
+}
+
+// 限制请求体大小：声明的 Content-Length 超限时在读取请求体之前直接返回 413，
+// 未声明长度的请求体在读取超过上限时同样被拒绝，0 表示不限制
+fn with_body_limit<S>(router: Router<S>, max_bytes: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if max_bytes == 0 {
+        return router.layer(DefaultBodyLimit::disable());
+    }
+    router
+        .layer(middleware::from_fn(
+            move |request: Request, next: Next| async move {
+                let too_large = request
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .is_some_and(|len| len > max_bytes);
+                if too_large {
+                    return AppError::PayloadTooLarge(tr!(
+                        "请求体超过 {} 字节的上限",
+                        "Request body exceeds the limit of {} bytes",
+                        max_bytes
+                    ))
+                    .into_response();
+                }
+                next.run(request).await
+            },
         ))
-        .with_state(app_state)
+        .layer(DefaultBodyLimit::max(max_bytes))
+}
+
+// 为一组路由加上超时层，超时返回 504，0 表示不限制
+fn with_timeout<S>(router: Router<S>, seconds: u64) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if seconds == 0 {
+        return router;
+    }
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(move |_: BoxError| async move {
+                AppError::GatewayTimeout(tr!(
+                    "请求处理超过 {} 秒",
+                    "Request processing took longer than {} seconds",
+                    seconds
+                ))
+            }))
+            .layer(TimeoutLayer::new(Duration::from_secs(seconds))),
+    )
+}
+
+/// 组合监听地址。IPv6 地址（`::`、`::1`，带不带方括号均可）会加上方括号；
+/// 监听 `::` 时多数系统（Linux 默认 `net.ipv6.bindv6only = 0`）同时接受 IPv4 连接
+pub fn listen_address(host: &str, port: u16) -> String {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{}:{}", host, port),
+    }
 }
 
 // 启动服务器函数
-pub async fn start_server(app: Router, config: &crate::utils::config::Config) -> Result<(), Box<dyn std::error::Error>> {
-    let bind_address = format!("{}:{}", config.server.host, config.server.port);
-    println!("正在启动服务器...");
-    let listener = TcpListener::bind(&bind_address).await?;
-    println!("服务器正在监听: {} 端口, 请访问 http://127.0.0.1:{}/v1/chat/completions", config.server.port, config.server.port);
+pub async fn start_server(app: Router, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    log_info!("正在启动服务器...", "Starting server...");
+    // 由 systemd 套接字激活启动时直接使用传入的套接字
+    let listener = match systemd::take_listener(0) {
+        Some(listener) => TcpListener::from_std(listener)?,
+        None => TcpListener::bind(listen_address(&config.server.host, config.server.port)).await?,
+    };
+    serve(listener, app, config, shutdown_signal()).await
+}
 
-    let server = axum::serve(listener, app.into_make_service());
+/// 在已绑定的套接字上提供服务，直到 shutdown 完成；之后停止接受新连接并等待进行中的请求处理完成，
+/// 超过 server.shutdown_timeout_seconds 仍未结束的连接被直接中断
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: &Config,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bind_address = listener.local_addr()?;
+    log_info!(
+        "服务器正在监听: {}, 请访问 http://127.0.0.1:{}/v1/chat/completions",
+        "Server listening on {}, visit http://127.0.0.1:{}/v1/chat/completions",
+        bind_address,
+        bind_address.port()
+    );
+
+    // 使用 hyper 的 HTTP/1 连接配置，以便设置请求头读取超时与长连接
+    let mut builder = http1::Builder::new();
+    builder.timer(TokioTimer::new()).keep_alive(config.server.keep_alive);
+    builder.header_read_timeout(
+        Some(config.server.header_read_timeout_seconds)
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs),
+    );
+
+    // 每个连接持有一个接收端，退出时通知连接优雅关闭；连接任务放在 JoinSet 中，以便等待或中断。
+    // 支持协议升级（/admin/ws）的连接不能交给 hyper-util 的 GracefulShutdown，因此自行跟踪
+    let (graceful_tx, graceful_rx) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
 
-    println!("服务器已就绪!");
+    log_info!("服务器已就绪!", "Server ready!");
+    systemd::notify("READY=1");
 
-    server.await?;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        // 文件描述符耗尽等错误，稍后重试
+                        log_warn!("接受连接失败: {}", "Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let conn = builder
+                    .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app.clone()))
+                    .with_upgrades();
+                let mut graceful = graceful_rx.clone();
+                connections.spawn(async move {
+                    tokio::pin!(conn);
+                    // 客户端断开、请求头读取超时等连接错误无需处理
+                    tokio::select! {
+                        _ = conn.as_mut() => {}
+                        _ = graceful.changed() => {
+                            conn.as_mut().graceful_shutdown();
+                            let _ = conn.await;
+                        }
+                    }
+                });
+            }
+            // 回收已结束的连接任务
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // 停止接受新连接，等待进行中的请求处理完成
+    systemd::notify("STOPPING=1");
+    drop(listener);
+    // SSE 事件流不会自行结束，先关闭订阅连接，否则优雅关闭会一直等待
+    live_events::close_subscribers();
+    let _ = graceful_tx.send(true);
+    let drain = async { while connections.join_next().await.is_some() {} };
+    match config.server.shutdown_timeout_seconds {
+        0 => drain.await,
+        secs => {
+            if tokio::time::timeout(Duration::from_secs(secs), drain).await.is_err() {
+                log_warn!(
+                    "等待进行中的请求超时，中断剩余的 {} 个连接",
+                    "Timed out waiting for in-flight requests, aborting {} remaining connections",
+                    connections.len()
+                );
+                connections.shutdown().await;
+            }
+        }
+    }
     Ok(())
 }
 
+// 等待退出信号（Ctrl-C 或 SIGTERM）
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log_error!("监听 Ctrl-C 信号失败: {}", "Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                log_error!("监听 SIGTERM 信号失败: {}", "Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    log_info!("收到退出信号，正在停止服务器...", "Shutdown signal received, stopping server...");
+}
+
 // 创建任务处理通道和运行时
 pub fn create_task_channels(
     cache_hit_pool_size: usize,
@@ -111,12 +528,14 @@ pub fn create_task_channels(
             .expect("无法创建缓存未命中处理运行时"),
     );
 
-    println!(
+    log_info!(
         "已创建专门处理缓存命中的线程池，线程数: {}",
+        "Created the cache hit thread pool, threads: {}",
         cache_hit_pool_size
     );
-    println!(
+    log_info!(
         "已创建专门处理缓存未命中的线程池，线程数: {}",
+        "Created the cache miss thread pool, threads: {}",
         cache_miss_pool_size
     );
 
@@ -125,19 +544,19 @@ pub fn create_task_channels(
     // 处理未命中缓存请求的通道
     let (tx_miss, mut rx_miss) = mpsc::channel(2048);
 
-    // 处理缓存命中的后台任务
-    let hit_runtime_clone = hit_runtime.clone();
+    // 处理缓存命中的后台任务（只持有句柄，运行时本身由调用方负责关闭）
+    let hit_handle = hit_runtime.handle().clone();
     tokio::spawn(async move {
         while let Some(task) = rx_hit.recv().await {
-            hit_runtime_clone.spawn(task);
+            hit_handle.spawn(task);
         }
     });
 
     // 处理缓存未命中的后台任务
-    let miss_runtime_clone = miss_runtime.clone();
+    let miss_handle = miss_runtime.handle().clone();
     tokio::spawn(async move {
         while let Some(task) = rx_miss.recv().await {
-            miss_runtime_clone.spawn(task);
+            miss_handle.spawn(task);
         }
     });
 
@@ -0,0 +1,374 @@
+//! 集成测试辅助：内嵌的 OpenAI 兼容模拟上游与完整的本地测试服务（启用 test-support 特性时编译）
+
+use crate::grpc_server::LlmCacheService;
+use crate::handlers::chat_completion_handler::TaskSender;
+use crate::models::api_model::AppState;
+use crate::server::{
+    build_app_state, create_router, create_task_channels, serve, start_background_tasks,
+};
+use crate::utils::config::Config;
+use crate::utils::db::{create_db_pool, init_db};
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 模拟上游的行为，可在测试过程中通过 `MockUpstream::set_behavior` 修改
+#[derive(Debug, Clone)]
+pub struct MockBehavior {
+    // 每个请求返回前的延迟
+    pub latency: Duration,
+    // 前 N 个请求返回错误，之后恢复正常
+    pub fail_first: usize,
+    pub fail_status: u16,
+    pub fail_body: String,
+    // 设置时错误响应附带该 Retry-After 头
+    pub fail_retry_after: Option<String>,
+    // 回答内容的前缀，完整回答为前缀加最后一条用户消息
+    pub reply_prefix: String,
+    // 设置时以 200 返回该响应体（如反向代理的 HTML 错误页），代替正常回答
+    pub raw_body: Option<String>,
+    // 流式响应中相邻两个 chunk 之间的间隔
+    pub chunk_delay: Duration,
+}
+
+impl Default for MockBehavior {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            fail_first: 0,
+            fail_status: 500,
+            fail_body: r#"{"error":{"message":"mock upstream error","type":"server_error"}}"#
+                .to_string(),
+            fail_retry_after: None,
+            reply_prefix: "mock reply: ".to_string(),
+            raw_body: None,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl MockBehavior {
+    /// 前 `count` 个请求以指定状态码与响应体失败
+    pub fn failing(count: usize, status: u16, body: &str) -> Self {
+        Self {
+            fail_first: count,
+            fail_status: status,
+            fail_body: body.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// 每个请求延迟 `latency` 后返回
+    pub fn slow(latency: Duration) -> Self {
+        Self {
+            latency,
+            ..Self::default()
+        }
+    }
+}
+
+struct MockState {
+    behavior: Mutex<MockBehavior>,
+    // 收到的请求体（按到达顺序）
+    requests: Mutex<Vec<Value>>,
+}
+
+/// 监听本地随机端口的 OpenAI 兼容模拟上游：
+/// 任意路径的 POST 都按 chat completion 处理，请求 `stream: true` 时以 SSE 分块返回
+pub struct MockUpstream {
+    pub url: String,
+    state: Arc<MockState>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MockUpstream {
+    pub async fn start(behavior: MockBehavior) -> Self {
+        let (state, app) = mock_app(behavior);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("模拟上游绑定端口失败");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Self { url, state, server }
+    }
+
+    /// 在 Unix 域套接字 socket_path 上监听的模拟上游，url 为 `unix://` 地址
+    #[cfg(unix)]
+    pub async fn start_unix(behavior: MockBehavior, socket_path: &std::path::Path) -> Self {
+        let (state, app) = mock_app(behavior);
+        let listener = tokio::net::UnixListener::bind(socket_path).expect("模拟上游绑定套接字失败");
+        let url = format!("unix://{}", socket_path.display());
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Self { url, state, server }
+    }
+
+    pub fn set_behavior(&self, behavior: MockBehavior) {
+        *self.state.behavior.lock().unwrap() = behavior;
+    }
+
+    /// 模拟上游收到的请求数
+    pub fn request_count(&self) -> usize {
+        self.state.requests.lock().unwrap().len()
+    }
+
+    /// 模拟上游收到的全部请求体
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockUpstream {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn mock_app(behavior: MockBehavior) -> (Arc<MockState>, Router) {
+    let state = Arc::new(MockState {
+        behavior: Mutex::new(behavior),
+        requests: Mutex::new(Vec::new()),
+    });
+    let app = Router::new()
+        .fallback(mock_handler)
+        .with_state(state.clone());
+    (state, app)
+}
+
+async fn mock_handler(State(state): State<Arc<MockState>>, body: Bytes) -> Response {
+    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let (behavior, index) = {
+        let mut requests = state.requests.lock().unwrap();
+        requests.push(request.clone());
+        (state.behavior.lock().unwrap().clone(), requests.len())
+    };
+
+    if !behavior.latency.is_zero() {
+        tokio::time::sleep(behavior.latency).await;
+    }
+
+    if index <= behavior.fail_first {
+        let status =
+            StatusCode::from_u16(behavior.fail_status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            behavior.fail_body,
+        )
+            .into_response();
+        if let Some(retry_after) = behavior.fail_retry_after
+            && let Ok(value) = HeaderValue::from_str(&retry_after)
+        {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        return response;
+    }
+
+    if let Some(body) = behavior.raw_body {
+        return ([(header::CONTENT_TYPE, "text/html")], body).into_response();
+    }
+
+    let model = request["model"].as_str().unwrap_or("mock-model").to_string();
+    let last_user = request["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().rev().find(|m| m["role"] == "user"))
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or_default();
+    let content = format!("{}{}", behavior.reply_prefix, last_user);
+
+    if request["stream"].as_bool().unwrap_or(false) {
+        return sse_response(&model, &content, behavior.chunk_delay);
+    }
+
+    let response = json!({
+        "id": format!("chatcmpl-mock-{}", index),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "system_fingerprint": "mock",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        }],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15},
+    });
+    // 附带常见的上游限流与耗时响应头，以及一个不应透传的自定义头
+    (
+        [
+            (header::CONTENT_TYPE.as_str(), "application/json"),
+            ("x-ratelimit-remaining-requests", "99"),
+            ("openai-processing-ms", "12"),
+            ("x-mock-internal", "1"),
+        ],
+        response.to_string(),
+    )
+        .into_response()
+}
+
+// 按空格拆分回答，每个词一个 chunk，最后发送 [DONE]；chunk 之间间隔 chunk_delay
+fn sse_response(model: &str, content: &str, chunk_delay: Duration) -> Response {
+    let mut events: Vec<String> = content
+        .split_inclusive(' ')
+        .map(|word| {
+            let chunk = json!({
+                "id": "chatcmpl-mock-stream",
+                "object": "chat.completion.chunk",
+                "model": model,
+                "choices": [{"index": 0, "delta": {"content": word}, "finish_reason": null}],
+            });
+            format!("data: {}\n\n", chunk)
+        })
+        .collect();
+    events.push("data: [DONE]\n\n".to_string());
+
+    let stream =
+        futures::stream::iter(events.into_iter().enumerate()).then(move |(i, event)| async move {
+            if i > 0 && !chunk_delay.is_zero() {
+                tokio::time::sleep(chunk_delay).await;
+            }
+            Ok::<_, std::io::Error>(event)
+        });
+    (
+        [(header::CONTENT_TYPE, "text/event-stream")],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// test_config 中配置的管理接口访问令牌
+pub const TEST_ADMIN_TOKEN: &str = "test-admin-token";
+
+/// 指向模拟上游的最小配置：直连模式、独立的临时数据库、管理接口令牌为 TEST_ADMIN_TOKEN，其余选项取默认值
+pub fn test_config(upstream_url: &str) -> Config {
+    let database_url = std::env::temp_dir()
+        .join(format!("llm_api_test_{}.db", uuid::Uuid::new_v4().simple()))
+        .to_string_lossy()
+        .into_owned();
+    let yaml = format!(
+        "database_url: \"{}\"\nuse_proxy: false\nadmin:\n  token: \"{}\"\napi_endpoints:\n  - url: \"{}\"\n    weight: 1\n",
+        database_url, TEST_ADMIN_TOKEN, upstream_url
+    );
+    serde_yaml::from_str(&yaml).expect("测试配置解析失败")
+}
+
+/// 按配置启动的完整服务（路由、线程池、数据库），监听本地随机端口；drop 时关闭线程池并删除临时数据库
+pub struct TestApp {
+    pub url: String,
+    pub state: Arc<AppState>,
+    shared: Arc<(Arc<AppState>, TaskSender, TaskSender)>,
+    client: reqwest::Client,
+    server: tokio::task::JoinHandle<()>,
+    runtimes: Vec<Arc<tokio::runtime::Runtime>>,
+}
+
+impl TestApp {
+    pub async fn spawn(config: Config) -> Self {
+        let pool = create_db_pool(&config.database_url, &config.database)
+            .await
+            .expect("创建测试数据库失败");
+        init_db(&pool).await.expect("初始化测试数据库失败");
+
+        let (tx_hit, tx_miss, hit_runtime, miss_runtime) =
+            create_task_channels(config.cache_hit_pool_size, config.cache_miss_pool_size);
+        // 与正式启动相同的应用状态与后台任务
+        let state = Arc::new(build_app_state(&config, Arc::new(pool)).expect("创建应用状态失败"));
+        start_background_tasks(&state).await;
+
+        let shared = Arc::new((state.clone(), tx_hit, tx_miss));
+        let app = create_router(shared.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("测试服务绑定端口失败");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            let _ = serve(listener, app, &server_state.config, std::future::pending()).await;
+        });
+
+        Self {
+            url,
+            state,
+            shared,
+            client: reqwest::Client::new(),
+            server,
+            runtimes: vec![hit_runtime, miss_runtime],
+        }
+    }
+
+    /// 与 HTTP 服务共享状态的 gRPC 服务，可直接调用其方法
+    pub fn grpc_service(&self) -> LlmCacheService {
+        LlmCacheService::new(self.shared.clone())
+    }
+
+    /// 向 /v1/chat/completions 发送请求
+    pub async fn chat(&self, body: &Value) -> reqwest::Response {
+        self.client
+            .post(format!("{}/v1/chat/completions", self.url))
+            .json(body)
+            .send()
+            .await
+            .expect("请求测试服务失败")
+    }
+
+    /// 携带管理接口令牌发送 GET 请求，path 以 / 开头
+    pub async fn admin_get(&self, path: &str) -> reqwest::Response {
+        self.client
+            .get(format!("{}{}", self.url, path))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .expect("请求测试服务失败")
+    }
+
+    /// 数据库中的缓存回答数
+    pub async fn db_answer_count(&self) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM answers")
+            .fetch_one(self.state.db.as_ref())
+            .await
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        self.server.abort();
+        // 运行时不能在异步上下文中直接 drop
+        for runtime in self.runtimes.drain(..) {
+            if let Ok(runtime) = Arc::try_unwrap(runtime) {
+                runtime.shutdown_background();
+            }
+        }
+        let database = PathBuf::from(&self.state.config.database_url);
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = database.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// 缓存写入是异步的：轮询直到条件成立，最多等待 5 秒
+pub async fn eventually<F, Fut>(mut condition: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    for _ in 0..100 {
+        if condition().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
@@ -1,9 +1,61 @@
+pub mod ab_test;
+pub mod adaptive_batch;
+pub mod admin_auth;
+pub mod analytics;
+pub mod answer_codec;
+pub mod audit;
+pub mod bench;
+pub mod cache_dry_run;
+pub mod cache_epoch;
+pub mod cache_key;
 pub mod cache_maintenance;
+pub mod compact;
 pub mod config;
+pub mod config_dump;
+pub mod config_include;
+pub mod config_validation;
+pub mod content_filter;
 pub mod context_trim;
+pub mod dashboard;
+pub mod daemon;
 pub mod db;
 pub mod db_writer;
+pub mod encryption;
+pub mod endpoint_stats;
+pub mod error;
+pub mod exit_flush;
+pub mod force_model;
+pub mod guardrails;
+pub mod hit_stats;
 pub mod http_client;
 pub mod idle_flush;
+pub mod inspect;
+pub mod json_mode;
+pub mod json_stream;
+pub mod live_events;
 pub mod logging;
-pub mod memory_cache;
\ No newline at end of file
+pub mod memory_cache;
+pub mod memory_pressure;
+pub mod plugin;
+pub mod prompt_cache;
+pub mod prompt_injection;
+pub mod prompt_template;
+pub mod prometheus;
+pub mod purge;
+pub mod redis_cache;
+pub mod rehash;
+pub mod replication;
+pub mod response_headers;
+pub mod response_parser;
+pub mod rewrite;
+pub mod statsd;
+pub mod systemd;
+pub mod unix_socket;
+pub mod upstream_queue;
+pub mod upstream_replay;
+pub mod warmup;
+pub mod wasm_plugin;
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm_runtime;
+pub mod webhook;
+pub mod websocket;
\ No newline at end of file
@@ -0,0 +1,137 @@
+use crate::models::api_model::{ApiEndpoint, select_api_endpoint};
+use crate::utils::db_writer::run_write;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AbTestConfig {
+    pub enabled: bool,
+    // 分配到 B 组的流量比例（0.0-1.0）
+    pub ratio_b: f64,
+}
+
+impl Default for AbTestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ratio_b: 0.5,
+        }
+    }
+}
+
+/// 按比例选择 A/B 组并在该组的端点中按权重选择。两组端点未同时配置时返回 None，按常规方式选择端点。
+pub fn select_ab_endpoint(
+    endpoints: &[ApiEndpoint],
+    config: &AbTestConfig,
+) -> Option<(ApiEndpoint, String)> {
+    if !config.enabled {
+        return None;
+    }
+
+    let arm_endpoints = |arm: &str| -> Vec<ApiEndpoint> {
+        endpoints
+            .iter()
+            .filter(|ep| ep.ab_arm.as_deref().is_some_and(|a| a.eq_ignore_ascii_case(arm)))
+            .cloned()
+            .collect()
+    };
+    let arm_a = arm_endpoints("a");
+    let arm_b = arm_endpoints("b");
+    if arm_a.is_empty() || arm_b.is_empty() {
+        return None;
+    }
+
+    let (arm, candidates) = if rand::rng().random_bool(config.ratio_b.clamp(0.0, 1.0)) {
+        ("b", arm_b)
+    } else {
+        ("a", arm_a)
+    };
+    select_api_endpoint(&candidates).map(|ep| (ep, arm.to_string()))
+}
+
+// 经由数据库写入任务记录一次由 A/B 组端点处理的请求
+pub async fn record_ab_result(
+    pool: &SqlitePool,
+    request_id: &str,
+    arm: &str,
+    endpoint: &str,
+    latency_ms: i64,
+    response_chars: i64,
+    success: bool,
+) -> Result<(), sqlx::Error> {
+    let request_id = request_id.to_string();
+    let arm = arm.to_string();
+    let endpoint = endpoint.to_string();
+    run_write(pool, move |pool| async move {
+        sqlx::query(
+            "INSERT INTO ab_results (request_id, arm, endpoint, latency_ms, response_chars, success)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(request_id)
+        .bind(arm)
+        .bind(endpoint)
+        .bind(latency_ms)
+        .bind(response_chars)
+        .bind(success)
+        .execute(&pool)
+        .await?;
+        Ok(())
+    })
+    .await
+}
+
+// 清理超过保留天数的 A/B 结果，返回删除的记录数
+pub async fn cleanup_ab_results(
+    pool: &SqlitePool,
+    retention_days: i64,
+) -> Result<u64, sqlx::Error> {
+    let cutoff = chrono::Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+    run_write(pool, move |pool| async move {
+        let deleted = sqlx::query("DELETE FROM ab_results WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&pool)
+            .await?;
+        Ok(deleted.rows_affected())
+    })
+    .await
+}
+
+// 按组汇总 A/B 对比结果
+pub async fn ab_report(pool: &SqlitePool) -> Result<serde_json::Value, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, i64, i64, Option<f64>, Option<i64>, Option<i64>, Option<f64>)>(
+        "SELECT arm,
+                COUNT(*),
+                SUM(success),
+                AVG(CASE WHEN success = 1 THEN latency_ms END),
+                MIN(CASE WHEN success = 1 THEN latency_ms END),
+                MAX(CASE WHEN success = 1 THEN latency_ms END),
+                AVG(CASE WHEN success = 1 THEN response_chars END)
+         FROM ab_results
+         GROUP BY arm
+         ORDER BY arm",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let arms: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(
+            |(arm, requests, successes, avg_latency, min_latency, max_latency, avg_chars)| {
+                json!({
+                    "arm": arm,
+                    "requests": requests,
+                    "successes": successes,
+                    "success_rate": if requests > 0 { successes as f64 / requests as f64 } else { 0.0 },
+                    "latency_ms_avg": avg_latency,
+                    "latency_ms_min": min_latency,
+                    "latency_ms_max": max_latency,
+                    "response_chars_avg": avg_chars,
+                })
+            },
+        )
+        .collect();
+
+    Ok(json!({ "arms": arms }))
+}
@@ -0,0 +1,262 @@
+use crate::{log_debug, log_info};
+use crate::utils::db_writer::DbWriter;
+use crate::utils::memory_cache::MemoryCache;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdaptiveBatchConfig {
+    pub enabled: bool,
+    // 批量写入阈值的下限，空闲时按该值尽快写入
+    pub min_size: usize,
+    // 批量写入阈值的上限，突发流量时最多累积到该值再写入
+    pub max_size: usize,
+    // 待写入项最长等待时间（毫秒），超过后不论数量多少都写入
+    pub max_delay_ms: u64,
+}
+
+impl Default for AdaptiveBatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: 1,
+            max_size: 200,
+            max_delay_ms: 2000,
+        }
+    }
+}
+
+// 统计新缓存项的到达速率（每秒），每秒滚动一次并做指数平滑
+struct ArrivalRate {
+    window_start: Instant,
+    count: u64,
+    rate: f64,
+}
+
+impl ArrivalRate {
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start).as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+        let current = self.count as f64 / elapsed;
+        self.rate = 0.5 * self.rate + 0.5 * current;
+        self.window_start = now;
+        self.count = 0;
+    }
+
+    // 平滑后的速率与当前窗口内的速率取较大值，使突发流量能立即提高阈值
+    fn current(&mut self, now: Instant) -> f64 {
+        self.roll(now);
+        let elapsed = now.duration_since(self.window_start).as_secs_f64();
+        if elapsed < 0.1 {
+            return self.rate;
+        }
+        self.rate.max(self.count as f64 / elapsed)
+    }
+}
+
+/// 批量写入触发器：按时间或数量触发，未启用自适应时使用固定的 batch_write_size
+pub struct BatchWriteTrigger {
+    config: AdaptiveBatchConfig,
+    static_size: usize,
+    arrivals: Mutex<ArrivalRate>,
+    last_flush: Mutex<Instant>,
+}
+
+impl BatchWriteTrigger {
+    pub fn new(config: AdaptiveBatchConfig, static_size: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            static_size,
+            arrivals: Mutex::new(ArrivalRate {
+                window_start: now,
+                count: 0,
+                rate: 0.0,
+            }),
+            last_flush: Mutex::new(now),
+        }
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.config.enabled
+    }
+
+    // 记录一次新缓存项写入内存缓存
+    pub fn record_arrival(&self) {
+        self.record_arrival_at(Instant::now());
+    }
+
+    fn record_arrival_at(&self, now: Instant) {
+        let mut arrivals = self.arrivals.lock().unwrap();
+        arrivals.roll(now);
+        arrivals.count += 1;
+    }
+
+    /// 当前的批量写入阈值：按到达速率估算最长等待时间内会累积的项数，限制在 [min_size, max_size] 之间
+    pub fn threshold(&self) -> usize {
+        self.threshold_at(Instant::now())
+    }
+
+    fn threshold_at(&self, now: Instant) -> usize {
+        if !self.config.enabled {
+            return self.static_size;
+        }
+
+        let rate = self.arrivals.lock().unwrap().current(now);
+        let expected = rate * self.config.max_delay_ms as f64 / 1000.0;
+        let min_size = self.config.min_size.max(1);
+        (expected.round() as usize).clamp(min_size, self.config.max_size.max(min_size))
+    }
+
+    // 记录一次批量写入
+    pub fn mark_flushed(&self) {
+        *self.last_flush.lock().unwrap() = Instant::now();
+    }
+
+    // 距上次批量写入是否已超过最长等待时间
+    fn flush_overdue(&self) -> bool {
+        self.flush_overdue_at(Instant::now())
+    }
+
+    fn flush_overdue_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(*self.last_flush.lock().unwrap())
+            >= Duration::from_millis(self.config.max_delay_ms)
+    }
+}
+
+// 启动按时间触发的批量写入任务：待写入项等待超过 max_delay_ms 时全部写入数据库
+pub fn start_adaptive_flush_task(
+    cache: Arc<MemoryCache>,
+    db: Arc<SqlitePool>,
+    cache_version: u8,
+    trigger: Arc<BatchWriteTrigger>,
+) {
+    if !trigger.is_adaptive() {
+        return;
+    }
+
+    let config = trigger.config.clone();
+    log_info!(
+        "启动自适应批量写入：阈值 {}~{} 条，最长等待 {} 毫秒",
+        "Adaptive batch writes enabled: threshold {}~{} items, max wait {} ms",
+        config.min_size,
+        config.max_size,
+        config.max_delay_ms
+    );
+
+    tokio::spawn(async move {
+        let db_writer = DbWriter::new(db, cache_version);
+        // 检查间隔取最长等待时间的一半，使待写入项的实际等待不超过 1.5 倍 max_delay_ms
+        let check_interval = Duration::from_millis((config.max_delay_ms / 2).max(50));
+        let mut interval = tokio::time::interval(check_interval);
+
+        loop {
+            interval.tick().await;
+
+            if cache.pending_count() == 0 || !trigger.flush_overdue() {
+                continue;
+            }
+
+            trigger.mark_flushed();
+            while cache.pending_count() > 0 {
+                let items = cache.take_pending_writes(config.max_size.max(1));
+                if items.is_empty() {
+                    break;
+                }
+                let (success, failed) = db_writer.batch_write(items).await;
+                log_debug!(
+                    "待写入项等待超过 {} 毫秒，批量写入完成，成功: {}，失败: {}",
+                    "Pending items waited longer than {} ms, batch write finished, succeeded: {}, failed: {}",
+                    config.max_delay_ms,
+                    success,
+                    failed
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive() -> BatchWriteTrigger {
+        BatchWriteTrigger::new(
+            AdaptiveBatchConfig {
+                enabled: true,
+                min_size: 1,
+                max_size: 200,
+                max_delay_ms: 2000,
+            },
+            50,
+        )
+    }
+
+    // 在 start 之后的 span 内均匀记录 count 次到达
+    fn arrive(trigger: &BatchWriteTrigger, start: Instant, count: u32, span: Duration) {
+        for i in 0..count {
+            trigger.record_arrival_at(start + span * i / count);
+        }
+    }
+
+    #[test]
+    fn static_size_is_used_when_not_adaptive() {
+        let trigger = BatchWriteTrigger::new(AdaptiveBatchConfig::default(), 50);
+        let start = Instant::now();
+        arrive(&trigger, start, 100, Duration::from_millis(500));
+        assert_eq!(trigger.threshold_at(start + Duration::from_millis(500)), 50);
+    }
+
+    #[test]
+    fn threshold_follows_the_arrival_rate() {
+        // 空闲时按下限尽快写入
+        let idle = adaptive();
+        let start = Instant::now();
+        assert_eq!(idle.threshold_at(start + Duration::from_secs(1)), 1);
+
+        // 每秒约 20 项，最长等待 2 秒内约累积 40 项
+        let moderate = adaptive();
+        let start = Instant::now();
+        arrive(&moderate, start, 10, Duration::from_millis(500));
+        let threshold = moderate.threshold_at(start + Duration::from_millis(500));
+        assert!((39..=41).contains(&threshold), "{}", threshold);
+
+        // 突发流量时不超过上限
+        let burst = adaptive();
+        let start = Instant::now();
+        arrive(&burst, start, 100, Duration::from_millis(500));
+        assert_eq!(burst.threshold_at(start + Duration::from_millis(500)), 200);
+    }
+
+    #[test]
+    fn threshold_backs_off_to_the_minimum_after_a_burst() {
+        let trigger = adaptive();
+        let start = Instant::now();
+        arrive(&trigger, start, 100, Duration::from_millis(500));
+
+        let mut previous = trigger.threshold_at(start + Duration::from_millis(500));
+        for second in 1..=10 {
+            let threshold = trigger.threshold_at(start + Duration::from_secs(second));
+            assert!(threshold <= previous, "{} > {}", threshold, previous);
+            previous = threshold;
+        }
+        assert_eq!(previous, 1);
+    }
+
+    #[test]
+    fn flushes_are_overdue_after_the_max_delay() {
+        let trigger = adaptive();
+        let start = Instant::now();
+        assert!(!trigger.flush_overdue_at(start + Duration::from_millis(1999)));
+        assert!(trigger.flush_overdue_at(start + Duration::from_millis(2000)));
+
+        trigger.mark_flushed();
+        let flushed = Instant::now();
+        assert!(!trigger.flush_overdue_at(flushed + Duration::from_millis(1000)));
+        assert!(trigger.flush_overdue_at(flushed + Duration::from_millis(2000)));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::tr;
+use crate::utils::error::AppError;
+use crate::utils::replication::secret_matches;
+use axum::extract::Request;
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+/// 浏览器的 WebSocket 与 EventSource 无法设置请求头，可改用该查询参数携带令牌
+pub const ADMIN_TOKEN_QUERY: &str = "access_token";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AdminConfig {
+    // 管理接口（/admin/*、/dashboard）的访问令牌，请求需携带 Authorization: Bearer <token>；
+    // 为空时拒绝所有管理请求
+    #[serde(default)]
+    pub token: String,
+}
+
+/// 校验管理接口的访问令牌：未配置令牌时返回 403，令牌缺失或不正确时返回 401
+pub fn authorize_admin(provided: Option<&str>, token: &str) -> Result<(), AppError> {
+    if token.is_empty() {
+        return Err(AppError::Forbidden(tr!(
+            "未配置 admin.token，管理接口已禁用",
+            "admin.token is not configured, the admin API is disabled"
+        )));
+    }
+    if !provided.is_some_and(|provided| secret_matches(provided, token)) {
+        return Err(AppError::Unauthorized(tr!(
+            "管理接口需要有效的访问令牌",
+            "The admin API requires a valid access token"
+        )));
+    }
+    Ok(())
+}
+
+/// 从 Authorization 请求头（或 gRPC 元数据）的取值中取出 Bearer 令牌
+pub fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// HTTP 管理接口的中间件，令牌取自 Authorization 请求头或 access_token 查询参数
+pub async fn require_admin_token(token: String, request: Request, next: Next) -> Response {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token)
+        .map(str::to_string)
+        .or_else(|| query_token(&request));
+    if let Err(e) = authorize_admin(provided.as_deref(), &token) {
+        return e.into_response();
+    }
+    next.run(request).await
+}
+
+fn query_token(request: &Request) -> Option<String> {
+    request.uri().query()?.split('&').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        (name == ADMIN_TOKEN_QUERY).then(|| percent_decode(value))
+    })
+}
+
+// 令牌通常只含 URL 安全字符，这里只处理 %XX 转义与 +
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
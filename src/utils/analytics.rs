@@ -0,0 +1,88 @@
+use crate::tr;
+use crate::utils::answer_codec::decode_answer;
+use crate::utils::encryption::decrypt_blob;
+use crate::utils::error::AppError;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// 排行最多返回的条数
+pub const MAX_TOP_LIMIT: i64 = 500;
+/// 回答预览最多截取的字符数，避免通过管理接口导出完整回答
+pub const MAX_PREVIEW_CHARS: usize = 1000;
+
+/// 命中次数排行中的一条缓存回答
+#[derive(Debug, Clone, Serialize)]
+pub struct TopQuestion {
+    pub answer_key: String,
+    // 解压后的回答预览，解密或解压失败时为空
+    pub preview: Option<String>,
+    pub hit_count: i64,
+    pub size: i64,
+    // 指向该回答的不同问题数量
+    pub question_count: i64,
+    pub created_at: i64,
+    pub last_hit_at: Option<i64>,
+}
+
+// 解密并解码缓存的回答，截取前 max_chars 个字符作为预览
+fn answer_preview(response: Vec<u8>, max_chars: usize) -> Option<String> {
+    let stored = decode_answer(&decrypt_blob(response).ok()?).ok()?;
+    let content = stored.content();
+    let mut preview: String = content.chars().take(max_chars).collect();
+    if content.chars().count() > max_chars {
+        preview.push('…');
+    }
+    Some(preview)
+}
+
+/// 按命中次数列出最常被命中的缓存回答。问题只以哈希形式保存，
+/// 因此预览展示的是回答内容，question_count 为指向该回答的不同问题数量。
+/// limit 与 preview_chars 分别限制在 MAX_TOP_LIMIT 与 MAX_PREVIEW_CHARS 以内，
+/// 回答的解密与解压在阻塞线程池中进行
+pub async fn top_questions(
+    pool: &SqlitePool,
+    limit: i64,
+    preview_chars: usize,
+) -> Result<Vec<TopQuestion>, AppError> {
+    let limit = limit.clamp(1, MAX_TOP_LIMIT);
+    let preview_chars = preview_chars.min(MAX_PREVIEW_CHARS);
+    let rows = sqlx::query_as::<_, (String, Vec<u8>, i64, i64, i64, Option<i64>, i64)>(
+        "SELECT a.key, a.response, a.hit_count, a.size, a.created_at, a.last_hit_at,
+                COUNT(q.key)
+         FROM answers a
+         LEFT JOIN questions q ON q.answer_key = a.key
+         WHERE a.hit_count > 0
+         GROUP BY a.key
+         ORDER BY a.hit_count DESC, a.last_hit_at DESC
+         LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    tokio::task::spawn_blocking(move || {
+        rows.into_iter()
+            .map(
+                |(key, response, hit_count, size, created_at, last_hit_at, question_count)| {
+                    TopQuestion {
+                        preview: answer_preview(response, preview_chars),
+                        answer_key: key,
+                        hit_count,
+                        size,
+                        question_count,
+                        created_at,
+                        last_hit_at,
+                    }
+                },
+            )
+            .collect()
+    })
+    .await
+    .map_err(|e| {
+        AppError::Internal(tr!(
+            "生成回答预览失败: {}",
+            "Failed to build answer previews: {}",
+            e
+        ))
+    })
+}
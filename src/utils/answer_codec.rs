@@ -0,0 +1,382 @@
+use crate::models::api_model::ChatResponseJson;
+use crate::proto::{CachedAnswer, CachedContent, ChatChoice, ChatMessage, Compression, Usage};
+use crate::tr;
+use crate::utils::encryption::content_digest;
+use brotli::CompressorWriter;
+use prost::Message;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// 当前写入的存储格式版本
+pub const ANSWER_FORMAT_VERSION: u32 = 1;
+
+// 存储时的 brotli 压缩等级：只在写入时压缩一次，取最高压缩率
+const STORAGE_QUALITY: u32 = 11;
+
+// CachedAnswer 格式的前缀，没有该前缀的数据是仅 brotli 压缩回答文本的旧格式
+const ANSWER_MAGIC: &[u8] = b"LLMANS1";
+
+/// 解码后的缓存回答
+#[derive(Debug, Clone)]
+pub struct StoredAnswer {
+    pub choices: Vec<ChatChoice>,
+    // 旧格式中为空
+    pub model: String,
+    // 旧格式中为 None
+    pub usage: Option<Usage>,
+    pub created_at: i64,
+}
+
+impl StoredAnswer {
+    /// 首个选项的回答内容
+    pub fn content(&self) -> &str {
+        self.choices
+            .first()
+            .and_then(|choice| choice.message.as_ref())
+            .map(|message| message.content.as_str())
+            .unwrap_or_default()
+    }
+}
+
+fn brotli_compress(data: &[u8], quality: u32) -> Result<Vec<u8>, String> {
+    let mut compressed = Vec::with_capacity(data.len() / 2); // 预分配大小
+    {
+        let mut compressor = CompressorWriter::new(&mut compressed, 4096, quality, 22);
+        compressor
+            .write_all(data)
+            .map_err(|e| tr!("压缩响应失败: {}", "Failed to compress the response: {}", e))?;
+        compressor.flush().map_err(|e| {
+            tr!(
+                "刷新压缩器失败: {}",
+                "Failed to flush the compressor: {}",
+                e
+            )
+        })?;
+    }
+    Ok(compressed)
+}
+
+fn brotli_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    brotli::Decompressor::new(data, data.len())
+        .read_to_end(&mut decompressed)
+        .map_err(|e| {
+            tr!(
+                "解压缩缓存数据失败: {}",
+                "Failed to decompress cache data: {}",
+                e
+            )
+        })?;
+    Ok(decompressed)
+}
+
+/// 将上游回答编码为缓存存储格式（CachedAnswer），正文使用 brotli 压缩
+pub fn encode_answer(
+    response: &ChatResponseJson,
+    cache_version: u8,
+    cache_epoch: u64,
+) -> Result<Vec<u8>, String> {
+    encode_answer_with_hit_body(response, cache_version, cache_epoch, None)
+}
+
+/// 同 encode_answer，并保存 brotli 压缩的命中响应 JSON（hit_body），供接受 br 的客户端命中时原样返回
+pub fn encode_answer_with_hit_body(
+    response: &ChatResponseJson,
+    cache_version: u8,
+    cache_epoch: u64,
+    hit_body: Option<&[u8]>,
+) -> Result<Vec<u8>, String> {
+    let content = CachedContent {
+        choices: response
+            .choices
+            .iter()
+            .map(|choice| ChatChoice {
+                index: choice.index,
+                finish_reason: choice.finish_reason.clone(),
+                message: Some(ChatMessage {
+                    role: choice.message.role.clone(),
+                    content: choice.message.content.clone(),
+                }),
+            })
+            .collect(),
+    };
+
+    let answer = CachedAnswer {
+        format_version: ANSWER_FORMAT_VERSION,
+        compression: Compression::Brotli as i32,
+        content: brotli_compress(&content.encode_to_vec(), STORAGE_QUALITY)?,
+        model: response.model.clone(),
+        usage: Some(Usage {
+            prompt_tokens: response.usage.prompt_tokens,
+            completion_tokens: response.usage.completion_tokens,
+            total_tokens: response.usage.total_tokens,
+        }),
+        created_at: chrono::Utc::now().timestamp(),
+        cache_version: cache_version as u32,
+        cache_epoch,
+        hit_body_br: match hit_body {
+            Some(body) => brotli_compress(body, STORAGE_QUALITY)?,
+            None => Vec::new(),
+        },
+    };
+    let mut encoded = Vec::with_capacity(ANSWER_MAGIC.len() + answer.encoded_len());
+    encoded.extend_from_slice(ANSWER_MAGIC);
+    answer.encode(&mut encoded).map_err(|e| {
+        tr!(
+            "编码缓存回答失败: {}",
+            "Failed to encode the cached answer: {}",
+            e
+        )
+    })?;
+    Ok(encoded)
+}
+
+/// 在阻塞线程池中编码回答，避免 brotli 压缩长回答时占用异步运行时的工作线程
+pub async fn encode_answer_async(
+    response: ChatResponseJson,
+    cache_version: u8,
+    cache_epoch: u64,
+    hit_body: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    tokio::task::spawn_blocking(move || {
+        encode_answer_with_hit_body(&response, cache_version, cache_epoch, hit_body.as_deref())
+    })
+    .await
+    .map_err(|e| tr!("压缩任务执行失败: {}", "Compression task failed: {}", e))?
+}
+
+// 按前缀区分格式：新格式返回解析出的 CachedAnswer，旧格式返回 None
+fn parse_envelope(data: &[u8]) -> Result<Option<CachedAnswer>, String> {
+    let Some(encoded) = data.strip_prefix(ANSWER_MAGIC) else {
+        return Ok(None);
+    };
+    CachedAnswer::decode(encoded).map(Some).map_err(|e| {
+        tr!(
+            "解析缓存回答失败: {}",
+            "Failed to parse the cached answer: {}",
+            e
+        )
+    })
+}
+
+/// 回答去重用的键（十六进制，启用缓存加密时为 HMAC，见 content_digest）。新格式按压缩后的正文、
+/// 缓存版本与纪元计算，不含写入时间、用量与模型等每次写入都可能不同的元数据，相同的回答只保存一份；
+/// 保存了命中响应体的回答还按响应体计算（其中的 id 与用量因请求而异，不能与其他问题共用）；
+/// 旧格式按整段数据计算
+pub fn answer_key(data: &[u8]) -> String {
+    let digest = match parse_envelope(data) {
+        Ok(Some(answer)) => content_digest(&[
+            &answer.content,
+            &answer.cache_version.to_le_bytes(),
+            &answer.cache_epoch.to_le_bytes(),
+            &answer.hit_body_br,
+        ]),
+        _ => content_digest(&[data]),
+    };
+    hex::encode(digest)
+}
+
+/// 回答写入时的缓存版本与全局缓存纪元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnswerStamp {
+    // 旧格式没有版本信息时为 None
+    pub cache_version: Option<u8>,
+    // 旧格式视为纪元 0
+    pub cache_epoch: u64,
+}
+
+/// 读取回答写入时的缓存版本与纪元（不解压正文）
+pub fn answer_stamp(data: &[u8]) -> AnswerStamp {
+    match parse_envelope(data) {
+        Ok(Some(answer)) => AnswerStamp {
+            cache_version: u8::try_from(answer.cache_version).ok(),
+            cache_epoch: answer.cache_epoch,
+        },
+        _ => AnswerStamp {
+            cache_version: None,
+            cache_epoch: 0,
+        },
+    }
+}
+
+/// 读取写入时保存的 brotli 压缩命中响应体（不解压），没有保存时返回 None
+pub fn answer_hit_body(data: &[u8]) -> Option<Vec<u8>> {
+    parse_envelope(data)
+        .ok()
+        .flatten()
+        .map(|answer| answer.hit_body_br)
+        .filter(|body| !body.is_empty())
+}
+
+/// 读取回答中记录的上游模型名（不解压正文），旧格式没有模型信息时返回 None
+pub fn answer_model(data: &[u8]) -> Option<String> {
+    parse_envelope(data).ok().flatten().map(|answer| answer.model)
+}
+
+// 旧格式：仅 brotli 压缩的回答文本
+fn decode_legacy(data: &[u8]) -> Result<StoredAnswer, String> {
+    let content = String::from_utf8(brotli_decompress(data)?).map_err(|e| {
+        tr!(
+            "解析缓存内容失败: {}",
+            "Failed to parse the cached content: {}",
+            e
+        )
+    })?;
+    Ok(StoredAnswer {
+        choices: vec![ChatChoice {
+            index: 0,
+            finish_reason: "stop".to_string(),
+            message: Some(ChatMessage {
+                role: "assistant".to_string(),
+                content,
+            }),
+        }],
+        model: String::new(),
+        usage: None,
+        created_at: 0,
+    })
+}
+
+/// 解码缓存中保存的回答，兼容旧格式
+pub fn decode_answer(data: &[u8]) -> Result<StoredAnswer, String> {
+    let Some(answer) = parse_envelope(data)? else {
+        return decode_legacy(data);
+    };
+    if answer.format_version > ANSWER_FORMAT_VERSION {
+        return Err(tr!(
+            "不支持的缓存存储格式版本: {}",
+            "Unsupported cache storage format version: {}",
+            answer.format_version
+        ));
+    }
+
+    let content = match Compression::try_from(answer.compression) {
+        Ok(Compression::Brotli) => brotli_decompress(&answer.content)?,
+        Ok(Compression::None) => answer.content,
+        Err(_) => {
+            return Err(tr!(
+                "未知的缓存压缩算法: {}",
+                "Unknown cache compression algorithm: {}",
+                answer.compression
+            ));
+        }
+    };
+    let content = CachedContent::decode(content.as_slice()).map_err(|e| {
+        tr!(
+            "解析缓存内容失败: {}",
+            "Failed to parse the cached content: {}",
+            e
+        )
+    })?;
+
+    Ok(StoredAnswer {
+        choices: content.choices,
+        model: answer.model,
+        usage: answer.usage,
+        created_at: answer.created_at,
+    })
+}
+
+/// 在阻塞线程池中解码回答，避免解压数 MB 的缓存数据时阻塞其他请求
+pub async fn decode_answer_async(data: Arc<Vec<u8>>) -> Result<StoredAnswer, String> {
+    tokio::task::spawn_blocking(move || decode_answer(&data))
+        .await
+        .map_err(|e| tr!("解压缩任务执行失败: {}", "Decompression task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(content: &str, prompt_tokens: i32) -> ChatResponseJson {
+        serde_json::from_value(json!({
+            "id": "chatcmpl-codec",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "codec-model",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop",
+            }],
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": 2,
+                "total_tokens": prompt_tokens + 2,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn answers_round_trip_with_their_metadata() {
+        let data = encode_answer(&response("round trip", 3), 2, 7).unwrap();
+        assert!(data.starts_with(ANSWER_MAGIC));
+
+        let stored = decode_answer(&data).unwrap();
+        assert_eq!(stored.content(), "round trip");
+        assert_eq!(stored.choices[0].finish_reason, "stop");
+        assert_eq!(stored.model, "codec-model");
+        assert_eq!(stored.usage.unwrap().prompt_tokens, 3);
+        assert_eq!(
+            answer_stamp(&data),
+            AnswerStamp {
+                cache_version: Some(2),
+                cache_epoch: 7,
+            }
+        );
+        assert_eq!(answer_model(&data).as_deref(), Some("codec-model"));
+    }
+
+    #[test]
+    fn legacy_brotli_answers_still_decode() {
+        let data =
+            brotli_compress("written by an old version".as_bytes(), STORAGE_QUALITY).unwrap();
+
+        let stored = decode_answer(&data).unwrap();
+        assert_eq!(stored.content(), "written by an old version");
+        assert_eq!(stored.model, "");
+        assert!(stored.usage.is_none());
+        assert_eq!(
+            answer_stamp(&data),
+            AnswerStamp {
+                cache_version: None,
+                cache_epoch: 0,
+            }
+        );
+        assert_eq!(answer_model(&data), None);
+    }
+
+    #[test]
+    fn corrupt_answers_are_errors_not_legacy_data() {
+        let mut data = encode_answer(&response("truncate me", 3), 1, 0).unwrap();
+        data.truncate(ANSWER_MAGIC.len() + 3);
+        assert!(decode_answer(&data).is_err());
+    }
+
+    #[test]
+    fn answer_key_ignores_per_write_metadata() {
+        let first = encode_answer(&response("same answer", 3), 1, 0).unwrap();
+        let second = encode_answer(&response("same answer", 30), 1, 0).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(answer_key(&first), answer_key(&second));
+
+        let bumped = encode_answer(&response("same answer", 3), 1, 1).unwrap();
+        assert_ne!(answer_key(&first), answer_key(&bumped));
+    }
+
+    #[test]
+    fn hit_bodies_are_stored_compressed_alongside_the_answer() {
+        let plain = encode_answer(&response("with hit body", 3), 1, 0).unwrap();
+        assert_eq!(answer_hit_body(&plain), None);
+
+        let hit_body = br#"{"id":"chatcmpl-hit"}"#;
+        let data = encode_answer_with_hit_body(&response("with hit body", 3), 1, 0, Some(hit_body))
+            .unwrap();
+        assert_eq!(decode_answer(&data).unwrap().content(), "with hit body");
+        let stored = answer_hit_body(&data).unwrap();
+        assert_eq!(brotli_decompress(&stored).unwrap(), hit_body);
+        assert_ne!(answer_key(&plain), answer_key(&data));
+    }
+}
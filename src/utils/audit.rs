@@ -0,0 +1,92 @@
+use crate::utils::db_writer::run_write;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    // 审计表最多保留的记录数，超出时删除最旧的记录
+    pub max_rows: i64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_rows: 100_000,
+        }
+    }
+}
+
+/// 单次请求的审计信息，在处理过程中逐步填充
+#[derive(Debug, Clone, Default)]
+pub struct AuditRecord {
+    pub request_id: String,
+    pub model: String,
+    // 问题哈希（缓存键）
+    pub key_hash: Option<String>,
+    // memory_hit / db_hit / miss / bypass
+    pub cache_status: Option<&'static str>,
+    pub endpoint: Option<String>,
+}
+
+// 经由数据库写入任务写入一条审计记录，并删除超出容量上限的旧记录
+pub async fn record_audit(
+    pool: &SqlitePool,
+    record: &AuditRecord,
+    latency_ms: i64,
+    status_code: u16,
+    max_rows: i64,
+) -> Result<(), sqlx::Error> {
+    let record = record.clone();
+    run_write(pool, move |pool| async move {
+        insert_audit(&pool, &record, latency_ms, status_code, max_rows).await
+    })
+    .await
+}
+
+async fn insert_audit(
+    pool: &SqlitePool,
+    record: &AuditRecord,
+    latency_ms: i64,
+    status_code: u16,
+    max_rows: i64,
+) -> Result<(), sqlx::Error> {
+    let id = sqlx::query(
+        "INSERT INTO audit_log (request_id, model, key_hash, cache_status, endpoint, latency_ms, status_code)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.request_id)
+    .bind(&record.model)
+    .bind(&record.key_hash)
+    .bind(record.cache_status)
+    .bind(&record.endpoint)
+    .bind(latency_ms)
+    .bind(status_code)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    // id 自增，按 id 范围删除即可保持容量上限
+    if max_rows > 0 && id > max_rows {
+        sqlx::query("DELETE FROM audit_log WHERE id <= ?")
+            .bind(id - max_rows)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// 清理超过保留天数的审计记录，返回删除的记录数
+pub async fn cleanup_audit_log(pool: &SqlitePool, retention_days: i64) -> Result<u64, sqlx::Error> {
+    let cutoff = chrono::Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+    run_write(pool, move |pool| async move {
+        let deleted = sqlx::query("DELETE FROM audit_log WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&pool)
+            .await?;
+        Ok(deleted.rows_affected())
+    })
+    .await
+}
@@ -0,0 +1,240 @@
+use crate::tr;
+use crate::utils::config::Config;
+use rand::Rng;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// 预热的热点问题数量，命中请求从中随机选取
+const HOT_PROMPTS: usize = 16;
+
+/// 压测参数：`llm_api bench --requests N --concurrency C --hit-ratio 0.8`
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub url: String,
+    pub model: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    // 预期命中缓存的请求比例（0.0-1.0）
+    pub hit_ratio: f64,
+}
+
+impl BenchOptions {
+    /// 解析 bench 子命令之后的参数，未指定的地址按配置中的服务端口生成
+    pub fn parse(args: &[String], config: &Config) -> Result<Self, String> {
+        let mut options = Self {
+            url: format!(
+                "http://127.0.0.1:{}/v1/chat/completions",
+                config.server.port
+            ),
+            model: "bench".to_string(),
+            requests: 1000,
+            concurrency: 10,
+            hit_ratio: 0.8,
+        };
+
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let value = iter
+                .next()
+                .ok_or_else(|| tr!("参数 {} 缺少取值", "Argument {} is missing a value", flag))?;
+            match flag.as_str() {
+                "--url" => options.url = value.clone(),
+                "--model" => options.model = value.clone(),
+                "--requests" => options.requests = parse_value(flag, value)?,
+                "--concurrency" => options.concurrency = parse_value(flag, value)?,
+                "--hit-ratio" => options.hit_ratio = parse_value(flag, value)?,
+                _ => return Err(tr!("未知参数: {}", "Unknown argument: {}", flag)),
+            }
+        }
+
+        if options.requests == 0 || options.concurrency == 0 {
+            return Err(tr!(
+                "--requests 与 --concurrency 必须大于 0",
+                "--requests and --concurrency must be greater than 0"
+            ));
+        }
+        if !(0.0..=1.0).contains(&options.hit_ratio) {
+            return Err(tr!(
+                "--hit-ratio 必须在 0.0 到 1.0 之间",
+                "--hit-ratio must be between 0.0 and 1.0"
+            ));
+        }
+        Ok(options)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| {
+        tr!(
+            "参数 {} 的取值无效: {}",
+            "Invalid value for argument {}: {}",
+            flag,
+            value
+        )
+    })
+}
+
+// 单个请求的结果
+struct Sample {
+    latency: Duration,
+    success: bool,
+    // 响应带有 x-cache-age 头，即由缓存返回
+    cache_hit: bool,
+}
+
+async fn send(client: &reqwest::Client, options: &BenchOptions, prompt: &str) -> Sample {
+    let body = serde_json::json!({
+        "model": options.model,
+        "messages": [{"role": "user", "content": prompt}],
+    });
+    let start = Instant::now();
+    let result = client.post(&options.url).json(&body).send().await;
+    let (success, cache_hit) = match result {
+        Ok(response) => {
+            let cache_hit = response.headers().contains_key("x-cache-age");
+            let success = response.status().is_success() && response.bytes().await.is_ok();
+            (success, cache_hit)
+        }
+        Err(_) => (false, false),
+    };
+    Sample {
+        latency: start.elapsed(),
+        success,
+        cache_hit,
+    }
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len()) - 1;
+    sorted[index]
+}
+
+/// 用合成问题压测本地服务，按比例混合热点问题（预热后应命中缓存）与从未出现的问题，输出延迟分位数与吞吐量
+pub async fn run_bench(options: BenchOptions) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(options.concurrency)
+        .build()
+        .map_err(|e| {
+            tr!(
+                "创建 HTTP 客户端失败: {}",
+                "Failed to create the HTTP client: {}",
+                e
+            )
+        })?;
+    let run_id = uuid::Uuid::new_v4().simple().to_string();
+    let hot_prompts: Vec<String> = (0..HOT_PROMPTS)
+        .map(|i| format!("bench hot prompt {} ({})", i, run_id))
+        .collect();
+
+    println!(
+        "{}",
+        tr!(
+            "压测目标: {}，请求数: {}，并发: {}，预期命中率: {:.0}%",
+            "Target: {}, requests: {}, concurrency: {}, expected hit ratio: {:.0}%",
+            options.url,
+            options.requests,
+            options.concurrency,
+            options.hit_ratio * 100.0
+        )
+    );
+
+    // 预热热点问题，使其写入缓存（写入缓存是异步的，稍等片刻）
+    if options.hit_ratio > 0.0 {
+        for prompt in &hot_prompts {
+            if !send(&client, &options, prompt).await.success {
+                return Err(tr!(
+                    "预热请求失败，请确认服务已在 {} 启动",
+                    "Warmup request failed, make sure the server is running at {}",
+                    options.url
+                ));
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    let options = Arc::new(options);
+    let hot_prompts = Arc::new(hot_prompts);
+    let next = Arc::new(AtomicUsize::new(0));
+    let samples = Arc::new(Mutex::new(Vec::with_capacity(options.requests)));
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|_| {
+            let client = client.clone();
+            let options = options.clone();
+            let hot_prompts = hot_prompts.clone();
+            let next = next.clone();
+            let samples = samples.clone();
+            let run_id = run_id.clone();
+            tokio::spawn(async move {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= options.requests {
+                        break;
+                    }
+                    let prompt = {
+                        let mut rng = rand::rng();
+                        if rng.random_bool(options.hit_ratio) {
+                            hot_prompts[rng.random_range(0..hot_prompts.len())].clone()
+                        } else {
+                            format!("bench miss prompt {} ({})", i, run_id)
+                        }
+                    };
+                    let sample = send(&client, &options, &prompt).await;
+                    samples.lock().await.push(sample);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.await;
+    }
+    let elapsed = start.elapsed();
+
+    let samples = samples.lock().await;
+    let succeeded = samples.iter().filter(|s| s.success).count();
+    let hits = samples.iter().filter(|s| s.cache_hit).count();
+    let mut latencies: Vec<Duration> = samples.iter().map(|s| s.latency).collect();
+    latencies.sort_unstable();
+    let total: Duration = latencies.iter().sum();
+
+    println!("{}", tr!("=== 压测结果 ===", "=== Benchmark results ==="));
+    println!(
+        "{}",
+        tr!(
+            "总耗时: {:.2} 秒，吞吐量: {:.1} 请求/秒",
+            "Elapsed: {:.2} s, throughput: {:.1} requests/s",
+            elapsed.as_secs_f64(),
+            samples.len() as f64 / elapsed.as_secs_f64()
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "成功: {}，失败: {}，缓存命中: {} ({:.1}%)",
+            "Succeeded: {}, failed: {}, cache hits: {} ({:.1}%)",
+            succeeded,
+            samples.len() - succeeded,
+            hits,
+            hits as f64 * 100.0 / samples.len().max(1) as f64
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "延迟 (毫秒): 平均 {:.1}，p50 {:.1}，p90 {:.1}，p99 {:.1}，最大 {:.1}",
+            "Latency (ms): mean {:.1}, p50 {:.1}, p90 {:.1}, p99 {:.1}, max {:.1}",
+            total.as_secs_f64() * 1000.0 / latencies.len().max(1) as f64,
+            percentile(&latencies, 0.50).as_secs_f64() * 1000.0,
+            percentile(&latencies, 0.90).as_secs_f64() * 1000.0,
+            percentile(&latencies, 0.99).as_secs_f64() * 1000.0,
+            latencies.last().copied().unwrap_or_default().as_secs_f64() * 1000.0
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use crate::{log_info, tr};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+// 演练模式下"本应写入"的回答：缓存键前 8 字节 → 编码后大小，同一问题重复出现时只计最后一次
+static WOULD_CACHE: OnceLock<Mutex<HashMap<u64, usize>>> = OnceLock::new();
+
+/// 缓存演练模式的累计结果
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DryRunTotals {
+    // 不同问题的条数
+    pub items: usize,
+    // 这些回答编码后的总字节数
+    pub bytes: usize,
+}
+
+/// 记录一条本应写入缓存的回答并输出日志（不写入内存缓存或数据库），返回累计结果。
+/// raw_size 为响应 JSON 的字节数，encoded_size 为编码压缩后的字节数
+pub fn record_would_cache(
+    question_key: &str,
+    raw_size: usize,
+    encoded_size: usize,
+    max_size: usize,
+) -> DryRunTotals {
+    let totals = {
+        let mut map = WOULD_CACHE
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+        if encoded_size <= max_size {
+            map.insert(short_key(question_key), encoded_size);
+        }
+        DryRunTotals {
+            items: map.len(),
+            bytes: map.values().sum(),
+        }
+    };
+
+    let ratio = if raw_size > 0 {
+        encoded_size as f64 / raw_size as f64 * 100.0
+    } else {
+        0.0
+    };
+    let key: String = question_key.chars().take(16).collect();
+    let skipped = if encoded_size > max_size {
+        tr!(
+            "，超过缓存大小上限，不会被缓存",
+            ", exceeds the cache size limit and would not be cached"
+        )
+    } else {
+        String::new()
+    };
+    log_info!(
+        "[缓存演练] 键: {}，原始 {} bytes，编码后 {} bytes（{:.1}%）{}；累计 {} 条，共 {} bytes",
+        "[cache dry run] key: {}, raw {} bytes, encoded {} bytes ({:.1}%){}; total {} items, {} bytes",
+        key,
+        raw_size,
+        encoded_size,
+        ratio,
+        skipped,
+        totals.items,
+        totals.bytes
+    );
+    totals
+}
+
+/// 当前的演练累计结果，未启用演练模式时为 0
+pub fn dry_run_totals() -> DryRunTotals {
+    let Some(map) = WOULD_CACHE.get() else {
+        return DryRunTotals::default();
+    };
+    let map = map.lock().unwrap();
+    DryRunTotals {
+        items: map.len(),
+        bytes: map.values().sum(),
+    }
+}
+
+// 缓存键为 SHA-256 十六进制，取前 16 位即可区分问题，节省长时间演练的内存
+fn short_key(question_key: &str) -> u64 {
+    question_key
+        .get(..16)
+        .and_then(|prefix| u64::from_str_radix(prefix, 16).ok())
+        .unwrap_or_else(|| {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            question_key.hash(&mut hasher);
+            hasher.finish()
+        })
+}
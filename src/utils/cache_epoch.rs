@@ -0,0 +1,112 @@
+use crate::{log_info, log_warn, tr};
+use crate::utils::db_writer::run_write;
+use crate::utils::error::AppError;
+use crate::utils::redis_cache::redis_cache;
+use crate::utils::replication;
+use sqlx::SqlitePool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// 当前的全局缓存纪元，启动时从数据库（或 Redis）读取
+static CURRENT_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// 当前的全局缓存纪元，写入时低于该纪元的缓存回答均视为未命中
+pub fn current_epoch() -> u64 {
+    CURRENT_EPOCH.load(Ordering::Relaxed)
+}
+
+/// 从数据库读取全局缓存纪元；使用 Redis 后端时以 Redis 中各节点共享的纪元为准
+pub async fn load_cache_epoch(pool: &SqlitePool) -> Result<u64, AppError> {
+    let epoch = sqlx::query_scalar::<_, i64>("SELECT value FROM cache_meta WHERE key = 'epoch'")
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or(0);
+    let mut epoch = epoch.max(0) as u64;
+    if let Some(redis) = redis_cache() {
+        epoch = redis.epoch().await.map_err(redis_error)?;
+    }
+    CURRENT_EPOCH.store(epoch, Ordering::Relaxed);
+    Ok(epoch)
+}
+
+/// 将全局缓存纪元加一，使此前写入的全部缓存立即失效（数据保留，由缓存维护按规则清理）。
+/// 使用 Redis 后端时纪元保存在 Redis 中，其他节点定期同步；启用缓存复制时同时通知对等节点
+pub async fn bump_cache_epoch(pool: &SqlitePool) -> Result<u64, AppError> {
+    let epoch = match redis_cache() {
+        Some(redis) => redis.bump_epoch().await.map_err(redis_error)?,
+        None => {
+            let epoch = run_write(pool, |pool| async move {
+                sqlx::query_scalar::<_, i64>(
+                    "INSERT INTO cache_meta (key, value) VALUES ('epoch', 1)
+                     ON CONFLICT(key) DO UPDATE SET value = value + 1
+                     RETURNING value",
+                )
+                .fetch_one(&pool)
+                .await
+            })
+            .await?;
+            epoch.max(0) as u64
+        }
+    };
+    CURRENT_EPOCH.fetch_max(epoch, Ordering::Relaxed);
+    replication::publish_epoch(epoch);
+    log_info!(
+        "全局缓存纪元已更新为 {}，此前的缓存全部失效",
+        "Global cache epoch bumped to {}, all earlier cache entries are invalidated",
+        epoch
+    );
+    Ok(epoch)
+}
+
+/// 采用其他节点的全局缓存纪元（只增不减）并保存到数据库，返回是否有变化
+pub async fn raise_cache_epoch(pool: &SqlitePool, epoch: u64) -> Result<bool, sqlx::Error> {
+    if epoch <= current_epoch() {
+        return Ok(false);
+    }
+    run_write(pool, move |pool| async move {
+        sqlx::query(
+            "INSERT INTO cache_meta (key, value) VALUES ('epoch', ?)
+             ON CONFLICT(key) DO UPDATE SET value = MAX(value, excluded.value)",
+        )
+        .bind(epoch as i64)
+        .execute(&pool)
+        .await
+    })
+    .await?;
+    let previous = CURRENT_EPOCH.fetch_max(epoch, Ordering::Relaxed);
+    if previous < epoch {
+        log_info!(
+            "已同步其他节点的全局缓存纪元 {}，此前的缓存全部失效",
+            "Synced global cache epoch {} from another node, all earlier cache entries are invalidated",
+            epoch
+        );
+    }
+    Ok(previous < epoch)
+}
+
+/// 使用 Redis 后端时定期读取共享的全局缓存纪元，使其他节点的缓存失效在本节点生效
+pub fn start_epoch_sync_task(interval_seconds: u64) {
+    let Some(redis) = redis_cache() else {
+        return;
+    };
+    let interval = Duration::from_secs(interval_seconds.max(1));
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            match redis.epoch().await {
+                Ok(epoch) => {
+                    CURRENT_EPOCH.fetch_max(epoch, Ordering::Relaxed);
+                }
+                Err(e) => log_warn!(
+                    "从 Redis 同步全局缓存纪元失败: {}",
+                    "Failed to sync the global cache epoch from Redis: {}",
+                    e
+                ),
+            }
+        }
+    });
+}
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::Database(tr!("Redis 查询错误: {}", "Redis query error: {}", e))
+}
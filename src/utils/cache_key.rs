@@ -0,0 +1,217 @@
+use crate::models::api_model::ChatRequestJson;
+use crate::tr;
+use crate::utils::config::CacheConfig;
+use crate::utils::db_writer::run_write;
+use crate::utils::encryption::{decrypt_blob, encrypt_blob};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+/// 计算缓存键所需的全部请求内容（插件处理之后）。开启 cache.store_key_source 时与问题键一起保存，
+/// 缓存键策略变更后 rehash 子命令据此重新计算问题键
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KeySource {
+    // (role, content)
+    pub messages: Vec<(String, String)>,
+    pub sampling: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    // 配置了 model_cache_versions 时请求的模型，各模型的缓存版本相互独立，回答不能跨模型共享
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // 匹配的改写规则对请求参数的修改（payload_rewrite_key），转发上游之前才应用，需单独计入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrites: Option<String>,
+}
+
+impl KeySource {
+    pub fn from_request(payload: &ChatRequestJson) -> Self {
+        Self {
+            messages: payload
+                .messages
+                .iter()
+                .map(|msg| (msg.role.clone(), msg.content.clone()))
+                .collect(),
+            sampling: payload.sampling_key(),
+            response_format: payload.response_format.clone(),
+            model: None,
+            rewrites: None,
+        }
+    }
+
+    /// 按缓存键配置计算问题键（十六进制 SHA-256），没有用户消息时返回 None。
+    /// 每个计入的字段都带标签与长度前缀，不同的请求内容不会拼接出相同的输入
+    pub fn question_key(&self, config: &CacheConfig) -> Option<String> {
+        // 按配置取第一条或最后一条用户消息；
+        // conversation 模式取最后一条，并把之前的整段对话计入缓存键，多轮对话的每一轮单独缓存
+        let is_user = |(role, _): &&(String, String)| role.eq_ignore_ascii_case("user");
+        let conversation_key = config.key_message == "conversation";
+        let user_index = if config.key_message == "last" || conversation_key {
+            self.messages.iter().rposition(|msg| is_user(&msg))
+        } else {
+            self.messages.iter().position(|msg| is_user(&msg))
+        }?;
+
+        let mut hasher = Sha256::new();
+        hash_field(&mut hasher, "user", &self.messages[user_index].1);
+        // 按需混入 system / prompt 消息；没有系统消息的请求缓存键保持不变
+        if config.key_include_system {
+            for (_, content) in self.messages.iter().filter(|(role, _)| {
+                role.eq_ignore_ascii_case("system") || role.eq_ignore_ascii_case("prompt")
+            }) {
+                hash_field(&mut hasher, "system", content);
+            }
+        }
+        // 按需混入该用户消息之前的若干条消息作为滚动上下文摘要
+        let context_messages = if conversation_key {
+            user_index
+        } else {
+            config.key_context_messages
+        };
+        if context_messages > 0 {
+            let start = user_index.saturating_sub(context_messages);
+            for (role, content) in &self.messages[start..user_index] {
+                hash_field(&mut hasher, "context_role", &role.to_lowercase());
+                hash_field(&mut hasher, "context", content);
+            }
+        }
+        // 按需混入采样参数，参数不同的请求不共享缓存
+        if config.key_include_sampling {
+            hash_field(&mut hasher, "sampling", &self.sampling);
+        }
+        // 结构化输出与普通文本回答不共享缓存
+        if let Some(format) = &self.response_format {
+            hash_field(&mut hasher, "response_format", &format.to_string());
+        }
+        if let Some(model) = &self.model {
+            hash_field(&mut hasher, "model", model);
+        }
+        // 改写规则修改过的请求得到的回答只供同样匹配这些规则的请求使用
+        if let Some(rewrites) = &self.rewrites {
+            hash_field(&mut hasher, "rewrites", rewrites);
+        }
+        Some(hex::encode(hasher.finalize()))
+    }
+}
+
+// 以“标签长度、标签、内容长度、内容”的形式写入一个字段，长度均为 8 字节小端序
+fn hash_field(hasher: &mut Sha256, tag: &str, value: &str) {
+    for part in [tag, value] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+}
+
+/// 经由数据库写入任务保存问题键对应的请求内容（启用缓存加密时同样加密）
+pub async fn store_key_source(
+    pool: &SqlitePool,
+    question_key: &str,
+    source: &KeySource,
+) -> Result<(), String> {
+    let json = serde_json::to_vec(source).map_err(|e| {
+        tr!(
+            "序列化缓存键来源失败: {}",
+            "Failed to serialize the cache key source: {}",
+            e
+        )
+    })?;
+    let stored = encrypt_blob(json)?;
+    let question_key = question_key.to_string();
+    run_write(pool, move |pool| async move {
+        sqlx::query("INSERT OR REPLACE INTO question_sources (key, source) VALUES (?, ?)")
+            .bind(question_key)
+            .bind(stored)
+            .execute(&pool)
+            .await?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| {
+        tr!(
+            "保存缓存键来源失败: {}",
+            "Failed to save the cache key source: {}",
+            e
+        )
+    })
+}
+
+/// 解码保存的请求内容
+pub fn decode_key_source(data: Vec<u8>) -> Result<KeySource, String> {
+    serde_json::from_slice(&decrypt_blob(data)?).map_err(|e| {
+        tr!(
+            "解析缓存键来源失败: {}",
+            "Failed to parse the cache key source: {}",
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(messages: &[(&str, &str)]) -> KeySource {
+        KeySource {
+            messages: messages
+                .iter()
+                .map(|(role, content)| (role.to_string(), content.to_string()))
+                .collect(),
+            sampling: String::new(),
+            response_format: None,
+            model: None,
+            rewrites: None,
+        }
+    }
+
+    fn key(source: &KeySource, config: &CacheConfig) -> String {
+        source.question_key(config).unwrap()
+    }
+
+    #[test]
+    fn delimiters_inside_messages_do_not_collide() {
+        let config = CacheConfig {
+            key_include_system: true,
+            key_context_messages: 2,
+            ..Default::default()
+        };
+
+        // 系统消息的内容被拼进用户消息
+        let split = source(&[("system", "b"), ("user", "a")]);
+        let merged = source(&[("user", "a\0system\0b")]);
+        assert_ne!(key(&split, &config), key(&merged, &config));
+
+        // 上下文消息的角色与内容之间的分隔
+        let first = source(&[("assistant", "x\0y"), ("user", "q")]);
+        let second = source(&[("assistant\0x", "y"), ("user", "q")]);
+        assert_ne!(key(&first, &config), key(&second, &config));
+
+        // 两条系统消息与合并后的一条系统消息
+        let two = source(&[("system", "a"), ("system", "b"), ("user", "q")]);
+        let one = source(&[("system", "a\0system\0b"), ("user", "q")]);
+        assert_ne!(key(&two, &config), key(&one, &config));
+    }
+
+    #[test]
+    fn optional_fields_are_distinguished_by_their_tags() {
+        let config = CacheConfig::default();
+        let mut with_model = source(&[("user", "q")]);
+        with_model.model = Some("m".to_string());
+        let mut with_rewrites = source(&[("user", "q")]);
+        with_rewrites.rewrites = Some("m".to_string());
+
+        assert_ne!(key(&with_model, &config), key(&with_rewrites, &config));
+        assert_ne!(
+            key(&with_model, &config),
+            key(&source(&[("user", "q")]), &config)
+        );
+    }
+
+    #[test]
+    fn messages_without_a_user_turn_have_no_key() {
+        assert!(
+            source(&[("system", "s")])
+                .question_key(&CacheConfig::default())
+                .is_none()
+        );
+    }
+}
@@ -1,7 +1,17 @@
+use crate::{log_error, log_info, log_warn, tr};
+use crate::utils::ab_test::cleanup_ab_results;
+use crate::utils::audit::cleanup_audit_log;
+use crate::utils::db::vacuum_if_needed;
+use crate::utils::db_writer::run_write;
+use crate::utils::live_events::{self, LiveEvent};
+use crate::utils::webhook::{WebhookEvent, notify};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// maintenance_log 表保留的最近维护次数
+const MAX_MAINTENANCE_RUNS: i64 = 1000;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CacheMaintenanceConfig {
@@ -10,6 +20,88 @@ pub struct CacheMaintenanceConfig {
     pub retention_days: i64,
     pub cleanup_on_startup: bool,
     pub min_hit_count: i64,
+    // 审计日志保留天数
+    #[serde(default = "default_audit_retention_days")]
+    pub audit_retention_days: i64,
+    // A/B 对比结果保留天数
+    #[serde(default = "default_ab_results_retention_days")]
+    pub ab_results_retention_days: i64,
+    // 每次维护时清理全部无引用的答案（不论命中次数与写入时间）
+    #[serde(default = "default_sweep_orphans")]
+    pub sweep_orphans: bool,
+    // 每次维护后执行 VACUUM（空闲页占比达到 database.vacuum_min_free_ratio 时）
+    #[serde(default)]
+    pub vacuum: bool,
+    // 按命中次数分级的保留规则，配置后代替 retention_days 与 min_hit_count
+    #[serde(default)]
+    pub retention_tiers: Vec<RetentionTier>,
+}
+
+/// 一级保留规则：回答命中次数不低于 min_hits（且低于更高一级的 min_hits）时，
+/// 指向它的问题与无引用的回答保留 retention_days 天
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetentionTier {
+    pub min_hits: i64,
+    pub retention_days: i64,
+}
+
+// 命中次数在 [min_hits, max_hits) 之间且写入时间早于 cutoff 的记录视为过期
+#[derive(Debug, Clone, Copy)]
+struct ExpiryRule {
+    min_hits: i64,
+    max_hits: i64,
+    cutoff: i64,
+}
+
+impl CacheMaintenanceConfig {
+    // 返回（无引用答案的过期规则，问题的过期规则）；now 为当前 Unix 秒
+    fn expiry_rules(&self, now: i64) -> (Vec<ExpiryRule>, Vec<ExpiryRule>) {
+        let cutoff = |days: i64| now - days * 24 * 60 * 60;
+        if self.retention_tiers.is_empty() {
+            // 未分级：问题不论命中次数按 retention_days 过期，
+            // 无引用答案另需命中次数低于 min_hit_count
+            let cutoff = cutoff(self.retention_days);
+            let answers = ExpiryRule {
+                min_hits: i64::MIN,
+                max_hits: self.min_hit_count,
+                cutoff,
+            };
+            let questions = ExpiryRule {
+                max_hits: i64::MAX,
+                ..answers
+            };
+            return (vec![answers], vec![questions]);
+        }
+
+        let mut tiers = self.retention_tiers.clone();
+        tiers.sort_by_key(|tier| std::cmp::Reverse(tier.min_hits));
+        let mut max_hits = i64::MAX;
+        let rules: Vec<ExpiryRule> = tiers
+            .iter()
+            .map(|tier| {
+                let rule = ExpiryRule {
+                    min_hits: tier.min_hits,
+                    max_hits,
+                    cutoff: cutoff(tier.retention_days),
+                };
+                max_hits = tier.min_hits;
+                rule
+            })
+            .collect();
+        (rules.clone(), rules)
+    }
+}
+
+fn default_audit_retention_days() -> i64 {
+    7
+}
+
+fn default_ab_results_retention_days() -> i64 {
+    30
+}
+
+fn default_sweep_orphans() -> bool {
+    true
 }
 
 impl Default for CacheMaintenanceConfig {
@@ -20,6 +112,11 @@ impl Default for CacheMaintenanceConfig {
             retention_days: 30,
             cleanup_on_startup: false,
             min_hit_count: 5,
+            audit_retention_days: default_audit_retention_days(),
+            ab_results_retention_days: default_ab_results_retention_days(),
+            sweep_orphans: default_sweep_orphans(),
+            vacuum: false,
+            retention_tiers: Vec::new(),
         }
     }
 }
@@ -56,21 +153,23 @@ pub async fn print_cache_stats(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     .fetch_all(pool)
     .await?;
 
-    println!("=== 缓存统计信息 ===");
-    println!("问题数量: {}", questions_count);
-    println!("答案数量: {}", answers_count);
-    println!("问答复用率: {:.2}", reuse_ratio);
-    println!(
+    log_info!("=== 缓存统计信息 ===", "=== Cache statistics ===");
+    log_info!("问题数量: {}", "Questions: {}", questions_count);
+    log_info!("答案数量: {}", "Answers: {}", answers_count);
+    log_info!("问答复用率: {:.2}", "Question/answer reuse ratio: {:.2}", reuse_ratio);
+    log_info!(
         "总缓存大小: {} 字节 ({:.2} MB)",
+        "Total cache size: {} bytes ({:.2} MB)",
         total_size,
         total_size as f64 / (1024.0 * 1024.0)
     );
 
     if !top_hits.is_empty() {
-        println!("命中率最高的答案:");
+        log_info!("命中率最高的答案:", "Most hit answers:");
         for (key, hits, size) in top_hits {
-            println!(
+            log_info!(
                 "  Key: {}... | 命中次数: {} | 大小: {} 字节",
+                "  Key: {}... | hits: {} | size: {} bytes",
                 key.chars().take(8).collect::<String>(),
                 hits,
                 size
@@ -91,102 +190,447 @@ pub async fn cleanup_backup_table(pool: &SqlitePool) -> Result<(), sqlx::Error>
     .await?;
 
     if exists_backup.is_some() {
-        println!("发现备份表cache_backup，正在删除...");
-        sqlx::query("DROP TABLE cache_backup").execute(pool).await?;
-        println!("备份表cache_backup已删除");
+        log_info!(
+            "发现备份表cache_backup，正在删除...",
+            "Found backup table cache_backup, dropping it..."
+        );
+        run_write(pool, |pool| async move {
+            sqlx::query("DROP TABLE cache_backup").execute(&pool).await
+        })
+        .await?;
+        log_info!("备份表cache_backup已删除", "Backup table cache_backup dropped");
     }
 
     Ok(())
 }
 
-// 清理过期缓存
+/// 缓存表之间失去关联的记录数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    // answer_key 指向的答案已不存在的问题，不会再被命中
+    pub dangling_questions: i64,
+    // 没有问题引用的答案
+    pub unreferenced_answers: i64,
+    // 问题已不存在的缓存键来源
+    pub orphaned_sources: i64,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 统计问题、答案与缓存键来源之间失去关联的记录，不做修改
+pub async fn check_consistency(pool: &SqlitePool) -> Result<ConsistencyReport, sqlx::Error> {
+    let count = |sql: &'static str| async move {
+        sqlx::query_scalar::<_, i64>(sql).fetch_one(pool).await
+    };
+    Ok(ConsistencyReport {
+        dangling_questions: count(
+            "SELECT COUNT(*) FROM questions
+             WHERE NOT EXISTS (SELECT 1 FROM answers a WHERE a.key = questions.answer_key)",
+        )
+        .await?,
+        unreferenced_answers: count(
+            "SELECT COUNT(*) FROM answers
+             WHERE NOT EXISTS (SELECT 1 FROM questions q WHERE q.answer_key = answers.key)",
+        )
+        .await?,
+        orphaned_sources: count(
+            "SELECT COUNT(*) FROM question_sources
+             WHERE NOT EXISTS (SELECT 1 FROM questions q WHERE q.key = question_sources.key)",
+        )
+        .await?,
+    })
+}
+
+/// 启动时输出缓存一致性报告，有失去关联的记录时给出警告
+pub async fn report_consistency(pool: &SqlitePool) {
+    match check_consistency(pool).await {
+        Ok(report) if report.is_consistent() => {
+            log_info!("缓存一致性检查通过", "Cache consistency check passed");
+        }
+        Ok(report) => log_warn!(
+            "缓存一致性检查: {} 条问题的答案缺失（缓存维护会清理），{} 条答案无引用，{} 条缓存键来源的问题已不存在",
+            "Cache consistency check: {} questions with missing answers (removed by cache maintenance), {} unreferenced answers, {} key sources without questions",
+            report.dangling_questions,
+            report.unreferenced_answers,
+            report.orphaned_sources
+        ),
+        Err(e) => log_error!(
+            "缓存一致性检查失败: {}",
+            "Cache consistency check failed: {}",
+            e
+        ),
+    }
+}
+
+/// 一次缓存清理删除的记录
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleanupStats {
+    pub deleted_answers: u64,
+    pub deleted_questions: u64,
+    // 答案表中回答数据（size 列）减少的字节数
+    pub reclaimed_bytes: i64,
+}
+
+// 答案表中回答数据的总字节数
+async fn answers_size(conn: &mut sqlx::SqliteConnection) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT COALESCE(SUM(size), 0) FROM answers")
+        .fetch_one(conn)
+        .await
+}
+
+// 清理过期缓存：经由数据库写入任务按保留规则删除过期的问题与无引用的答案
 pub async fn cleanup_old_entries(
     pool: &SqlitePool,
-    days: i64,
-    min_hit_count: i64,
-) -> Result<(), sqlx::Error> {
+    config: &CacheMaintenanceConfig,
+) -> Result<CleanupStats, sqlx::Error> {
+    let config = config.clone();
+    let stats = run_write(pool, move |pool| async move {
+        delete_expired_entries(&pool, &config).await
+    })
+    .await?;
+
+    // 打印缓存统计
+    print_cache_stats(pool).await?;
+
+    Ok(stats)
+}
+
+async fn delete_expired_entries(
+    pool: &SqlitePool,
+    config: &CacheMaintenanceConfig,
+) -> Result<CleanupStats, sqlx::Error> {
     let now = chrono::Utc::now().timestamp();
-    let cutoff = now - days * 24 * 60 * 60; // 转换天数为秒
+    let (answer_rules, question_rules) = config.expiry_rules(now);
 
     // 开始事务
     let mut tx = pool.begin().await?;
-
-    // 首先找出将要删除的答案
-    let orphaned_answers = sqlx::query_scalar::<_, String>(
-        "SELECT a.key FROM answers a 
-         LEFT JOIN questions q ON a.key = q.answer_key 
-         WHERE q.key IS NULL AND a.hit_count < ? AND a.created_at < ?",
-    )
-    .bind(min_hit_count)
-    .bind(cutoff)
-    .fetch_all(&mut *tx)
-    .await?;
-
-    let answers_count = orphaned_answers.len();
-
-    if answers_count > 0 {
-        // 删除过期且无引用的答案
-        let deleted = sqlx::query(
-            "DELETE FROM answers 
-             WHERE key IN (
-                SELECT a.key FROM answers a 
-                LEFT JOIN questions q ON a.key = q.answer_key 
-                WHERE q.key IS NULL AND a.hit_count < ? AND a.created_at < ?
-             )",
+    let size_before = answers_size(&mut tx).await?;
+
+    // 删除过期且无引用的答案
+    let mut deleted_answers = 0;
+    for rule in &answer_rules {
+        deleted_answers += sqlx::query(
+            "DELETE FROM answers
+             WHERE hit_count >= ? AND hit_count < ? AND created_at < ?
+               AND NOT EXISTS (SELECT 1 FROM questions q WHERE q.answer_key = answers.key)",
         )
-        .bind(min_hit_count)
-        .bind(cutoff)
+        .bind(rule.min_hits)
+        .bind(rule.max_hits)
+        .bind(rule.cutoff)
         .execute(&mut *tx)
-        .await?;
-
-        println!("已清理 {} 条过期答案记录", deleted.rows_affected());
+        .await?
+        .rows_affected();
+    }
+    if deleted_answers > 0 {
+        log_info!("已清理 {} 条过期答案记录", "Removed {} expired answers", deleted_answers);
     }
 
-    // 删除过期的问题（但保留引用的答案）
-    let deleted_questions = sqlx::query("DELETE FROM questions WHERE created_at < ?")
-        .bind(cutoff)
+    // 删除过期的问题（但保留引用的答案），答案缺失的问题按 0 次命中计
+    let mut deleted_questions = 0;
+    for rule in &question_rules {
+        deleted_questions += sqlx::query(
+            "DELETE FROM questions
+             WHERE created_at < ?
+               AND COALESCE(
+                   (SELECT a.hit_count FROM answers a WHERE a.key = questions.answer_key), 0
+               ) >= ?
+               AND COALESCE(
+                   (SELECT a.hit_count FROM answers a WHERE a.key = questions.answer_key), 0
+               ) < ?",
+        )
+        .bind(rule.cutoff)
+        .bind(rule.min_hits)
+        .bind(rule.max_hits)
         .execute(&mut *tx)
-        .await?;
+        .await?
+        .rows_affected();
+    }
 
-    println!(
+    log_info!(
         "已清理 {} 条过期问题记录",
-        deleted_questions.rows_affected()
+        "Removed {} expired questions",
+        deleted_questions
     );
 
+    if config.sweep_orphans {
+        // 无引用的答案无法再被命中，不论命中次数多高都应清理（包括上面删除问题后新产生的）
+        let swept_answers = sqlx::query(
+            "DELETE FROM answers
+             WHERE NOT EXISTS (SELECT 1 FROM questions q WHERE q.answer_key = answers.key)",
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if swept_answers > 0 {
+            log_info!(
+                "已清理 {} 条无引用答案",
+                "Removed {} unreferenced answers",
+                swept_answers
+            );
+        }
+        deleted_answers += swept_answers;
+    }
+
+    // 答案记录已不存在的问题（手动删除或异常退出后可能出现）无法再命中，总是清理
+    let dangling_questions = sqlx::query(
+        "DELETE FROM questions
+         WHERE NOT EXISTS (SELECT 1 FROM answers a WHERE a.key = questions.answer_key)",
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if dangling_questions > 0 {
+        log_info!(
+            "已清理 {} 条答案缺失的问题",
+            "Removed {} questions with missing answers",
+            dangling_questions
+        );
+    }
+    deleted_questions += dangling_questions;
+
+    // 问题已删除的缓存键来源（只清理同样过期的，刚保存来源而问题尚未批量写入的记录保留）
+    let sources_cutoff = question_rules.iter().map(|rule| rule.cutoff).max().unwrap_or(now);
+    sqlx::query(
+        "DELETE FROM question_sources
+         WHERE created_at < ?
+           AND NOT EXISTS (SELECT 1 FROM questions q WHERE q.key = question_sources.key)",
+    )
+    .bind(sources_cutoff)
+    .execute(&mut *tx)
+    .await?;
+
+    // 同一事务内比较，期间新写入的回答只会使结果偏小
+    let reclaimed_bytes = (size_before - answers_size(&mut tx).await?).max(0);
+
     // 提交事务
     tx.commit().await?;
 
-    // 打印缓存统计
-    print_cache_stats(pool).await?;
+    Ok(CleanupStats {
+        deleted_answers,
+        deleted_questions,
+        reclaimed_bytes,
+    })
+}
+
+/// 一次维护的记录，保存在 maintenance_log 表中
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceRun {
+    pub id: i64,
+    // 开始时间（Unix 秒）
+    pub started_at: i64,
+    pub duration_ms: i64,
+    // startup（启动时清理）或 scheduled（定期维护）
+    pub trigger: String,
+    pub deleted_answers: i64,
+    pub deleted_questions: i64,
+    pub deleted_audit_records: i64,
+    // 答案表中回答数据减少的字节数（数据库文件要在 VACUUM 后才会变小）
+    pub reclaimed_bytes: i64,
+    // 各步骤的错误信息，全部成功时为空
+    pub errors: Option<String>,
+}
+
+// 经由数据库写入任务写入维护记录，只保留最近的 MAX_MAINTENANCE_RUNS 条
+async fn record_maintenance_run(
+    pool: &SqlitePool,
+    run: &MaintenanceRun,
+) -> Result<(), sqlx::Error> {
+    let run = run.clone();
+    run_write(pool, move |pool| async move { insert_maintenance_run(&pool, &run).await }).await
+}
 
+async fn insert_maintenance_run(
+    pool: &SqlitePool,
+    run: &MaintenanceRun,
+) -> Result<(), sqlx::Error> {
+    let id = sqlx::query(
+        "INSERT INTO maintenance_log (started_at, duration_ms, trigger, deleted_answers,
+             deleted_questions, deleted_audit_records, reclaimed_bytes, errors)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(run.started_at)
+    .bind(run.duration_ms)
+    .bind(&run.trigger)
+    .bind(run.deleted_answers)
+    .bind(run.deleted_questions)
+    .bind(run.deleted_audit_records)
+    .bind(run.reclaimed_bytes)
+    .bind(&run.errors)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    if id > MAX_MAINTENANCE_RUNS {
+        sqlx::query("DELETE FROM maintenance_log WHERE id <= ?")
+            .bind(id - MAX_MAINTENANCE_RUNS)
+            .execute(pool)
+            .await?;
+    }
     Ok(())
 }
 
+/// 最近的 limit 次维护记录，最新的在前
+pub async fn maintenance_history(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<MaintenanceRun>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (i64, i64, i64, String, i64, i64, i64, i64, Option<String>)>(
+        "SELECT id, started_at, duration_ms, trigger, deleted_answers, deleted_questions,
+                deleted_audit_records, reclaimed_bytes, errors
+         FROM maintenance_log
+         ORDER BY id DESC
+         LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, started_at, duration_ms, trigger, answers, questions, audit, bytes, errors)| {
+                MaintenanceRun {
+                    id,
+                    started_at,
+                    duration_ms,
+                    trigger,
+                    deleted_answers: answers,
+                    deleted_questions: questions,
+                    deleted_audit_records: audit,
+                    reclaimed_bytes: bytes,
+                    errors,
+                }
+            },
+        )
+        .collect())
+}
+
+/// 执行一次缓存维护，成功后发送维护完成通知，并把结果写入 maintenance_log。
+/// trigger 为 startup 或 scheduled，返回缓存清理是否成功
+pub async fn run_maintenance(
+    pool: &SqlitePool,
+    config: &CacheMaintenanceConfig,
+    vacuum_min_free_ratio: f64,
+    trigger: &str,
+) -> bool {
+    let start = Instant::now();
+    let mut run = MaintenanceRun {
+        started_at: chrono::Utc::now().timestamp(),
+        trigger: trigger.to_string(),
+        ..Default::default()
+    };
+    let mut errors = Vec::new();
+
+    // 审计日志按自己的保留天数清理
+    match cleanup_audit_log(pool, config.audit_retention_days).await {
+        Ok(deleted) => {
+            run.deleted_audit_records = deleted as i64;
+            if deleted > 0 {
+                log_info!(
+                    "已清理 {} 条过期审计记录",
+                    "Removed {} expired audit records",
+                    deleted
+                );
+            }
+        }
+        Err(e) => {
+            log_error!("清理审计日志失败: {}", "Failed to clean up audit log: {}", e);
+            errors.push(tr!("清理审计日志失败: {}", "Audit log cleanup failed: {}", e));
+        }
+    }
+
+    // A/B 结果同样按保留天数清理
+    match cleanup_ab_results(pool, config.ab_results_retention_days).await {
+        Ok(deleted) if deleted > 0 => {
+            log_info!(
+                "已清理 {} 条过期 A/B 结果",
+                "Removed {} expired A/B results",
+                deleted
+            );
+        }
+        Ok(_) => {}
+        Err(e) => {
+            log_error!("清理 A/B 结果失败: {}", "Failed to clean up A/B results: {}", e);
+            errors.push(tr!("清理 A/B 结果失败: {}", "A/B result cleanup failed: {}", e));
+        }
+    }
+
+    let success = match cleanup_old_entries(pool, config).await {
+        Ok(stats) => {
+            let CleanupStats {
+                deleted_answers,
+                deleted_questions,
+                reclaimed_bytes,
+            } = stats;
+            run.deleted_answers = deleted_answers as i64;
+            run.deleted_questions = deleted_questions as i64;
+            run.reclaimed_bytes = reclaimed_bytes;
+            notify(WebhookEvent::MaintenanceCompleted {
+                deleted_answers,
+                deleted_questions,
+            });
+            live_events::publish(LiveEvent::Maintenance {
+                success: true,
+                deleted_answers,
+                deleted_questions,
+            });
+            // 清理后回收空闲页
+            if config.vacuum {
+                vacuum_if_needed(pool, vacuum_min_free_ratio).await;
+            }
+            true
+        }
+        Err(e) => {
+            log_error!("缓存清理失败: {}", "Cache cleanup failed: {}", e);
+            errors.push(tr!("缓存清理失败: {}", "Cache cleanup failed: {}", e));
+            live_events::publish(LiveEvent::Maintenance {
+                success: false,
+                deleted_answers: 0,
+                deleted_questions: 0,
+            });
+            false
+        }
+    };
+
+    run.duration_ms = start.elapsed().as_millis() as i64;
+    run.errors = (!errors.is_empty()).then(|| errors.join("; "));
+    if let Err(e) = record_maintenance_run(pool, &run).await {
+        log_error!("写入维护记录失败: {}", "Failed to record maintenance run: {}", e);
+    }
+    success
+}
+
 // 启动后台缓存维护任务
-pub fn start_maintenance_task(pool: Arc<SqlitePool>, config: CacheMaintenanceConfig) {
+pub fn start_maintenance_task(
+    pool: Arc<SqlitePool>,
+    config: CacheMaintenanceConfig,
+    vacuum_min_free_ratio: f64,
+) {
     if !config.enabled {
-        println!("缓存维护功能已禁用");
+        log_info!("缓存维护功能已禁用", "Cache maintenance disabled");
         return;
     }
 
     // 如果配置为启动时执行清理，则立即执行一次
     if config.cleanup_on_startup {
         let pool_clone = pool.clone();
-        let min_hit_count = config.min_hit_count;
-        let retention_days = config.retention_days;
+        let config = config.clone();
 
         tokio::spawn(async move {
-            println!("执行启动时缓存清理...");
-            if let Err(e) = cleanup_old_entries(&pool_clone, retention_days, min_hit_count).await {
-                eprintln!("启动时缓存清理失败: {}", e);
+            log_info!("执行启动时缓存清理...", "Running startup cache cleanup...");
+            if !run_maintenance(&pool_clone, &config, vacuum_min_free_ratio, "startup").await {
+                log_error!("启动时缓存清理失败", "Startup cache cleanup failed");
             }
         });
     }
 
     // 后台任务：定期清理和统计
     let interval_hours = config.interval_hours;
-    let retention_days = config.retention_days;
-    let min_hit_count = config.min_hit_count;
 
     tokio::spawn(async move {
         // 等待5秒，避免与启动清理同时执行
@@ -195,23 +639,27 @@ pub fn start_maintenance_task(pool: Arc<SqlitePool>, config: CacheMaintenanceCon
         // 先清理备份表
         tokio::time::sleep(Duration::from_secs(3600)).await; // 等待1小时
         if let Err(e) = cleanup_backup_table(&pool).await {
-            eprintln!("清理备份表失败: {}", e);
+            log_error!("清理备份表失败: {}", "Failed to drop backup table: {}", e);
         }
 
         // 定期执行清理任务
         let interval = Duration::from_secs(interval_hours * 60 * 60);
         let mut interval_timer = tokio::time::interval(interval);
 
-        println!("缓存维护任务已启动，间隔: {}小时", interval_hours);
+        log_info!(
+            "缓存维护任务已启动，间隔: {}小时",
+            "Cache maintenance task started, interval: {} hours",
+            interval_hours
+        );
 
         loop {
             interval_timer.tick().await;
 
-            println!("执行定期缓存维护...");
-            if let Err(e) = cleanup_old_entries(&pool, retention_days, min_hit_count).await {
-                eprintln!("缓存维护失败: {}", e);
+            log_info!("执行定期缓存维护...", "Running scheduled cache maintenance...");
+            if run_maintenance(&pool, &config, vacuum_min_free_ratio, "scheduled").await {
+                log_info!("缓存维护完成", "Cache maintenance finished");
             } else {
-                println!("缓存维护完成");
+                log_error!("缓存维护失败", "Cache maintenance failed");
             }
         }
     });
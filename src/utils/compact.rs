@@ -0,0 +1,114 @@
+use crate::tr;
+use crate::utils::config::Config;
+use crate::utils::db::create_db_pool;
+use sqlx::{Executor, SqlitePool};
+use std::path::Path;
+
+/// 数据库文件与 WAL 文件的大小，单位字节（文件不存在时为 0）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbFileSizes {
+    pub database: u64,
+    pub wal: u64,
+}
+
+impl DbFileSizes {
+    pub fn read(database_url: &str) -> Self {
+        let size = |path: &str| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        Self {
+            database: size(database_url),
+            wal: size(&format!("{}-wal", database_url)),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.database + self.wal
+    }
+}
+
+/// 整理前后的文件大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    pub before: DbFileSizes,
+    pub after: DbFileSizes,
+}
+
+/// 将 WAL 写回数据库并截断，然后执行 VACUUM 与 ANALYZE。
+/// VACUUM 在 WAL 模式下同样先写入 WAL，因此最后再截断一次
+pub async fn compact_database(
+    pool: &SqlitePool,
+    database_url: &str,
+) -> Result<CompactReport, sqlx::Error> {
+    let before = DbFileSizes::read(database_url);
+    for statement in [
+        "PRAGMA wal_checkpoint(TRUNCATE);",
+        "VACUUM;",
+        "ANALYZE;",
+        "PRAGMA wal_checkpoint(TRUNCATE);",
+    ] {
+        pool.execute(statement).await?;
+    }
+    Ok(CompactReport {
+        before,
+        after: DbFileSizes::read(database_url),
+    })
+}
+
+/// compact 子命令：整理数据库并输出整理前后的文件大小，可在低峰期代替启动时的 VACUUM
+pub async fn run_compact(args: &[String], config: &Config) -> Result<(), String> {
+    if !args.is_empty() {
+        return Err(tr!("用法: llm_api compact", "Usage: llm_api compact"));
+    }
+    // 连接池会自动创建缺失的数据库文件，先检查以免误整理空数据库
+    if !Path::new(&config.database_url).exists() {
+        return Err(tr!(
+            "数据库文件 {} 不存在",
+            "Database file {} does not exist",
+            config.database_url
+        ));
+    }
+
+    let pool = create_db_pool(&config.database_url, &config.database)
+        .await
+        .map_err(|e| tr!("打开数据库失败: {}", "Failed to open the database: {}", e))?;
+    let report = compact_database(&pool, &config.database_url)
+        .await
+        .map_err(|e| {
+            tr!(
+                "整理数据库失败: {}",
+                "Failed to compact the database: {}",
+                e
+            )
+        })?;
+    pool.close().await;
+
+    let CompactReport { before, after } = report;
+    println!("{}", config.database_url);
+    println!(
+        "{}",
+        tr!(
+            "  数据库:   {} -> {} 字节",
+            "  Database: {} -> {} bytes",
+            before.database,
+            after.database
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "  WAL:      {} -> {} 字节",
+            "  WAL:      {} -> {} bytes",
+            before.wal,
+            after.wal
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "  合计:     {} -> {} 字节",
+            "  Total:    {} -> {} bytes",
+            before.total(),
+            after.total()
+        )
+    );
+    Ok(())
+}
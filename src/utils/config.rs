@@ -1,12 +1,83 @@
+use crate::{log_info, log_warn, tr};
+use crate::grpc_server::GrpcConfig;
+use crate::utils::ab_test::AbTestConfig;
+use crate::utils::adaptive_batch::AdaptiveBatchConfig;
+use crate::utils::admin_auth::AdminConfig;
+use crate::utils::audit::AuditConfig;
 use crate::utils::cache_maintenance::CacheMaintenanceConfig;
+use crate::utils::config_include::load_merged_yaml;
+use crate::utils::config_validation::{applied_defaults, validate_config};
+use crate::utils::content_filter::ContentFilterConfig;
+use crate::utils::dashboard::DashboardConfig;
+use crate::utils::encryption::EncryptionConfig;
+use crate::utils::force_model::ForceModelConfig;
+use crate::utils::guardrails::GuardrailsConfig;
+use crate::utils::hit_stats::HitStatsConfig;
+use crate::utils::logging::{LoggingConfig, init_logging};
+use crate::utils::memory_pressure::MemoryPressureConfig;
+use crate::utils::prompt_cache::PromptCacheConfig;
+use crate::utils::prompt_injection::PromptInjectionConfig;
+use crate::utils::prompt_template::PromptTemplate;
+use crate::utils::redis_cache::RedisCacheConfig;
+use crate::utils::replication::ReplicationConfig;
+use crate::utils::response_headers::ResponseHeadersConfig;
+use crate::utils::rewrite::RewriteRule;
+use crate::utils::statsd::StatsdConfig;
+use crate::utils::upstream_queue::UpstreamQueueConfig;
+use crate::utils::upstream_replay::UpstreamReplayConfig;
+use crate::utils::warmup::WarmupConfig;
+use crate::utils::wasm_plugin::WasmPluginConfig;
+use crate::utils::webhook::WebhookConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CacheConfig {
     pub enabled: bool,
     pub max_items: usize,
     pub batch_write_size: usize,
+    #[serde(default = "default_max_pending_writes")]
+    pub max_pending_writes: usize,
+    #[serde(default = "default_pending_overflow_policy")]
+    pub pending_overflow_policy: String,
+    // 内存缓存项的最长保留时间（秒），超过后移出内存，之后经数据库查询命中，0 表示不过期
+    #[serde(default)]
+    pub memory_ttl_seconds: u64,
+    // 缓存回答超过该字节数时流式输出响应体，0 表示不流式输出
+    #[serde(default = "default_stream_threshold_bytes")]
+    pub stream_threshold_bytes: usize,
+    // 写入回答时同时保存 brotli 压缩的命中响应体，客户端的 Accept-Encoding 接受 br 时命中直接返回该响应体
+    #[serde(default = "default_brotli_hit_responses")]
+    pub brotli_hit_responses: bool,
+    // 缓存存储后端：sqlite（默认）或 redis
+    #[serde(default = "default_cache_backend")]
+    pub backend: String,
+    #[serde(default)]
+    pub redis: RedisCacheConfig,
+    // 自适应批量写入：按负载调整批量写入阈值，并按时间触发写入
+    #[serde(default)]
+    pub adaptive_batch: AdaptiveBatchConfig,
+    // 计算缓存键时是否同时包含 system / prompt 消息，使不同系统提示词的请求不共享缓存
+    #[serde(default)]
+    pub key_include_system: bool,
+    // 用哪条用户消息计算缓存键：first（第一条，默认）、last（最后一条，适合多轮对话）
+    // 或 conversation（最后一条及之前的整段对话，多轮对话的每一轮单独缓存，忽略 key_context_messages）
+    #[serde(default = "default_key_message")]
+    pub key_message: String,
+    // 同时计入缓存键的、该用户消息之前的消息条数（滚动上下文摘要），0 表示不计入
+    #[serde(default)]
+    pub key_context_messages: usize,
+    // 计算缓存键时是否包含采样参数（temperature、top_p、惩罚系数、seed、max_tokens、stop），参数不同的请求不共享缓存
+    #[serde(default)]
+    pub key_include_sampling: bool,
+    // 保存计算缓存键所用的请求内容（消息、采样参数与 response_format），
+    // 缓存键策略变更后可用 rehash 子命令迁移；问题原文会因此写入数据库（启用缓存加密时同样加密）
+    #[serde(default)]
+    pub store_key_source: bool,
+    // 缓存演练：只记录本应缓存的回答（键、大小、压缩率），不写入内存缓存或数据库，用于上线前估算缓存容量
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 impl Default for CacheConfig {
@@ -15,10 +86,55 @@ impl Default for CacheConfig {
             enabled: true,
             max_items: 100,
             batch_write_size: 20,
+            max_pending_writes: default_max_pending_writes(),
+            pending_overflow_policy: default_pending_overflow_policy(),
+            memory_ttl_seconds: 0,
+            stream_threshold_bytes: default_stream_threshold_bytes(),
+            brotli_hit_responses: default_brotli_hit_responses(),
+            backend: default_cache_backend(),
+            redis: RedisCacheConfig::default(),
+            adaptive_batch: AdaptiveBatchConfig::default(),
+            key_include_system: false,
+            key_message: default_key_message(),
+            key_context_messages: 0,
+            key_include_sampling: false,
+            store_key_source: false,
+            dry_run: false,
         }
     }
 }
 
+impl CacheConfig {
+    /// 是否使用 Redis 缓存后端。backend 的取值不区分大小写（与配置校验一致），统一在此判断
+    pub fn uses_redis(&self) -> bool {
+        self.backend.eq_ignore_ascii_case("redis")
+    }
+}
+
+pub fn default_max_pending_writes() -> usize {
+    1000
+}
+
+pub fn default_pending_overflow_policy() -> String {
+    "flush".to_string()
+}
+
+pub fn default_stream_threshold_bytes() -> usize {
+    1024 * 1024
+}
+
+pub fn default_brotli_hit_responses() -> bool {
+    false
+}
+
+pub fn default_key_message() -> String {
+    "first".to_string()
+}
+
+pub fn default_cache_backend() -> String {
+    "sqlite".to_string()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct IdleFlushConfig {
     pub enabled: bool,
@@ -70,6 +186,16 @@ pub struct ContextTrimConfig {
     pub summary_aggressiveness: usize,
     pub summary_mode: String,
     pub summary_api: SummaryApiConfig,
+    #[serde(default = "default_tokenizer")]
+    pub tokenizer: String,
+    #[serde(default)]
+    pub model_tokenizers: HashMap<String, String>,
+    #[serde(default = "default_trim_strategy")]
+    pub strategy: String,
+    #[serde(default = "default_overflow_retry")]
+    pub overflow_retry: bool,
+    #[serde(default = "default_overflow_retry_ratio")]
+    pub overflow_retry_ratio: f32,
 }
 
 impl Default for ContextTrimConfig {
@@ -84,10 +210,31 @@ impl Default for ContextTrimConfig {
             summary_aggressiveness: 1,
             summary_mode: "local".to_string(),
             summary_api: SummaryApiConfig::default(),
+            tokenizer: default_tokenizer(),
+            model_tokenizers: HashMap::new(),
+            strategy: default_trim_strategy(),
+            overflow_retry: default_overflow_retry(),
+            overflow_retry_ratio: default_overflow_retry_ratio(),
         }
     }
 }
 
+pub fn default_tokenizer() -> String {
+    "heuristic".to_string()
+}
+
+pub fn default_trim_strategy() -> String {
+    "auto".to_string()
+}
+
+pub fn default_overflow_retry() -> bool {
+    true
+}
+
+pub fn default_overflow_retry_ratio() -> f32 {
+    0.7
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProxyConfig {
     pub request_timeout_seconds: u64,
@@ -109,6 +256,27 @@ impl Default for ProxyConfig {
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    // 单个请求从收到到返回响应头的最长时间（秒），超时返回 504，0 表示不限制
+    #[serde(default)]
+    pub request_timeout_seconds: u64,
+    // 对话补全与嵌入接口的超时（秒），0 表示不限制
+    #[serde(default = "default_completion_timeout_seconds")]
+    pub completion_timeout_seconds: u64,
+    // 模型列表、管理接口与 /metrics 的超时（秒），0 表示不限制
+    #[serde(default = "default_short_timeout_seconds")]
+    pub short_timeout_seconds: u64,
+    // 请求体大小上限（字节），超过时返回 413，0 表示不限制
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    // 读取请求头的最长时间（秒），包括长连接上等待下一个请求的时间，0 表示不限制
+    #[serde(default = "default_header_read_timeout_seconds")]
+    pub header_read_timeout_seconds: u64,
+    // 是否允许 HTTP/1.1 长连接
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: bool,
+    // 收到退出信号后等待进行中请求完成的最长时间（秒），超时后中断剩余连接，0 表示不限制
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
 }
 
 impl Default for ServerConfig {
@@ -116,6 +284,92 @@ impl Default for ServerConfig {
         Self {
             host: "0.0.0.0".to_string(),
             port: 4321,
+            request_timeout_seconds: 0,
+            completion_timeout_seconds: default_completion_timeout_seconds(),
+            short_timeout_seconds: default_short_timeout_seconds(),
+            max_body_bytes: default_max_body_bytes(),
+            header_read_timeout_seconds: default_header_read_timeout_seconds(),
+            keep_alive: default_keep_alive(),
+            shutdown_timeout_seconds: default_shutdown_timeout_seconds(),
+        }
+    }
+}
+
+pub fn default_completion_timeout_seconds() -> u64 {
+    600
+}
+
+pub fn default_short_timeout_seconds() -> u64 {
+    10
+}
+
+pub fn default_max_body_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+pub fn default_header_read_timeout_seconds() -> u64 {
+    30
+}
+
+pub fn default_keep_alive() -> bool {
+    true
+}
+
+pub fn default_shutdown_timeout_seconds() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutboundProxyConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub no_proxy: String,
+}
+
+impl Default for OutboundProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(), // 例如 http://127.0.0.1:7890 或 socks5://127.0.0.1:1080
+            username: String::new(),
+            password: String::new(),
+            no_proxy: "localhost,127.0.0.1".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TlsConfig {
+    // 双向 TLS 客户端证书（PEM 格式）
+    #[serde(default)]
+    pub client_cert_path: String,
+    // 客户端证书对应的私钥（PKCS#8 PEM 格式）
+    #[serde(default)]
+    pub client_key_path: String,
+    // 额外信任的根证书（PEM，可包含多个证书）
+    #[serde(default)]
+    pub ca_bundle_path: String,
+    // 是否接受无效证书（仅用于自签名的测试环境）
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    /// 以端点专用配置逐字段覆盖全局配置：设置了的字段覆盖全局值，未设置的沿用全局值。
+    /// 客户端证书与私钥成对覆盖；accept_invalid_certs 在任一处启用即生效
+    pub fn merged_over(&self, base: &TlsConfig) -> TlsConfig {
+        let pick = |value: &String, fallback: &String| {
+            if value.is_empty() { fallback } else { value }.clone()
+        };
+        let has_identity = !self.client_cert_path.is_empty() || !self.client_key_path.is_empty();
+        let identity = if has_identity { self } else { base };
+        TlsConfig {
+            client_cert_path: identity.client_cert_path.clone(),
+            client_key_path: identity.client_key_path.clone(),
+            ca_bundle_path: pick(&self.ca_bundle_path, &base.ca_bundle_path),
+            accept_invalid_certs: self.accept_invalid_certs || base.accept_invalid_certs,
         }
     }
 }
@@ -131,6 +385,95 @@ pub struct HttpClientConfig {
     pub http2_keep_alive_interval_seconds: u64,
     pub http2_keep_alive_timeout_seconds: u64,
     pub http2_initial_stream_window_size: usize,
+    #[serde(default)]
+    pub outbound_proxy: OutboundProxyConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    // HTTP 版本：auto（按 ALPN 协商，默认）、http1（只用 HTTP/1.1）或 http2（直接使用 HTTP/2）
+    #[serde(default = "default_http_version")]
+    pub http_version: String,
+    // 上游地址同时解析出 IPv4 与 IPv6 时优先连接的协议族：auto（按系统解析顺序）、ipv4 或 ipv6，
+    // 优先的地址族连接失败或较慢时仍会回退到另一族（happy eyeballs）
+    #[serde(default = "default_ip_preference")]
+    pub ip_preference: String,
+}
+
+pub fn default_http_version() -> String {
+    "auto".to_string()
+}
+
+pub fn default_ip_preference() -> String {
+    "auto".to_string()
+}
+
+/// 端点专用的 HTTP 客户端参数，设置的字段覆盖全局 http_client，未设置的沿用全局值
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HttpClientOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redirects: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_preference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outbound_proxy: Option<OutboundProxyConfig>,
+}
+
+impl HttpClientOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.timeout_seconds.is_none()
+            && self.connect_timeout_seconds.is_none()
+            && self.tcp_keepalive_seconds.is_none()
+            && self.pool_idle_timeout_seconds.is_none()
+            && self.pool_max_idle_per_host.is_none()
+            && self.max_redirects.is_none()
+            && self.http_version.is_none()
+            && self.ip_preference.is_none()
+            && self.outbound_proxy.is_none()
+    }
+
+    /// 在全局配置的基础上应用覆盖，得到端点实际使用的客户端配置
+    pub fn apply(&self, base: &HttpClientConfig) -> HttpClientConfig {
+        let mut config = base.clone();
+        if let Some(value) = self.timeout_seconds {
+            config.timeout_seconds = value;
+        }
+        if let Some(value) = self.connect_timeout_seconds {
+            config.connect_timeout_seconds = value;
+        }
+        if let Some(value) = self.tcp_keepalive_seconds {
+            config.tcp_keepalive_seconds = value;
+        }
+        if let Some(value) = self.pool_idle_timeout_seconds {
+            config.pool_idle_timeout_seconds = value;
+        }
+        if let Some(value) = self.pool_max_idle_per_host {
+            config.pool_max_idle_per_host = value;
+        }
+        if let Some(value) = self.max_redirects {
+            config.max_redirects = value;
+        }
+        if let Some(value) = &self.http_version {
+            config.http_version = value.clone();
+        }
+        if let Some(value) = &self.ip_preference {
+            config.ip_preference = value.clone();
+        }
+        if let Some(value) = &self.outbound_proxy {
+            config.outbound_proxy = value.clone();
+        }
+        config
+    }
 }
 
 impl Default for HttpClientConfig {
@@ -145,6 +488,10 @@ impl Default for HttpClientConfig {
             http2_keep_alive_interval_seconds: 30,
             http2_keep_alive_timeout_seconds: 30,
             http2_initial_stream_window_size: 1024 * 1024, // 1MB
+            outbound_proxy: OutboundProxyConfig::default(),
+            tls: TlsConfig::default(),
+            http_version: default_http_version(),
+            ip_preference: default_ip_preference(),
         }
     }
 }
@@ -155,6 +502,30 @@ pub struct DatabaseConfig {
     pub min_connections: u32,
     pub max_lifetime_seconds: u64,
     pub idle_timeout_seconds: u64,
+    // 启动时是否执行 VACUUM（数据库较大时会阻塞启动）
+    #[serde(default = "default_vacuum_on_startup")]
+    pub vacuum_on_startup: bool,
+    // 空闲页占比达到该值时才执行 VACUUM，0 表示总是执行
+    #[serde(default = "default_vacuum_min_free_ratio")]
+    pub vacuum_min_free_ratio: f64,
+    // 内存映射大小（字节），内存较小的设备可调低或设为 0 关闭
+    #[serde(default = "default_mmap_size")]
+    pub mmap_size: u64,
+    // 页缓存大小，正数为页数，负数为 KiB（与 SQLite 的 cache_size 一致）
+    #[serde(default = "default_cache_size")]
+    pub cache_size: i64,
+    // 同步级别：OFF / NORMAL / FULL / EXTRA
+    #[serde(default = "default_synchronous")]
+    pub synchronous: String,
+    // WAL 自动检查点的页数阈值
+    #[serde(default = "default_wal_autocheckpoint")]
+    pub wal_autocheckpoint: u32,
+    // 写入缓存遇到数据库忙（SQLITE_BUSY / SQLITE_LOCKED）时的最大重试次数
+    #[serde(default = "default_busy_retries")]
+    pub busy_retries: u32,
+    // 重试的基础间隔（毫秒），每次翻倍并附加随机抖动
+    #[serde(default = "default_busy_retry_base_ms")]
+    pub busy_retry_base_ms: u64,
 }
 
 impl Default for DatabaseConfig {
@@ -164,10 +535,50 @@ impl Default for DatabaseConfig {
             min_connections: 10,
             max_lifetime_seconds: 1800, // 30 minutes
             idle_timeout_seconds: 600,  // 10 minutes
+            vacuum_on_startup: default_vacuum_on_startup(),
+            vacuum_min_free_ratio: default_vacuum_min_free_ratio(),
+            mmap_size: default_mmap_size(),
+            cache_size: default_cache_size(),
+            synchronous: default_synchronous(),
+            wal_autocheckpoint: default_wal_autocheckpoint(),
+            busy_retries: default_busy_retries(),
+            busy_retry_base_ms: default_busy_retry_base_ms(),
         }
     }
 }
 
+pub fn default_vacuum_on_startup() -> bool {
+    true
+}
+
+pub fn default_vacuum_min_free_ratio() -> f64 {
+    0.1
+}
+
+pub fn default_mmap_size() -> u64 {
+    30_000_000_000
+}
+
+pub fn default_cache_size() -> i64 {
+    20000
+}
+
+pub fn default_synchronous() -> String {
+    "NORMAL".to_string()
+}
+
+pub fn default_wal_autocheckpoint() -> u32 {
+    1000
+}
+
+pub fn default_busy_retries() -> u32 {
+    3
+}
+
+pub fn default_busy_retry_base_ms() -> u64 {
+    50
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ApiDefaultsConfig {
     pub default_role: String,
@@ -176,6 +587,13 @@ pub struct ApiDefaultsConfig {
     pub default_system_fingerprint: String,
     pub cache_system_fingerprint: String,
     pub cache_max_size_bytes: usize,
+    // 严格模式：上游响应不符合标准结构时直接返回 502，不再尽力从通用 JSON 中构造回答
+    #[serde(default)]
+    pub strict_response_parsing: bool,
+    // 兼容模式：缓存命中时 finish_reason 返回 "stop"、系统指纹使用 default_system_fingerprint，
+    // 避免严格校验枚举值的 SDK 解析失败；是否命中缓存只通过响应头区分
+    #[serde(default)]
+    pub compatible_cache_hits: bool,
 }
 
 impl Default for ApiDefaultsConfig {
@@ -187,6 +605,8 @@ impl Default for ApiDefaultsConfig {
             default_system_fingerprint: "unknown".to_string(),
             cache_system_fingerprint: "cached".to_string(),
             cache_max_size_bytes: 5 * 1024 * 1024, // 5MB
+            strict_response_parsing: false,
+            compatible_cache_hits: false,
         }
     }
 }
@@ -200,18 +620,28 @@ pub struct Config {
     pub use_curl: bool,
     #[serde(default = "default_use_proxy")]
     pub use_proxy: bool,
+    // 对所有端点生效的 enable_thinking，仅作为未在端点上设置时的默认值
     #[serde(default)]
     pub enable_thinking: Option<bool>,
     #[serde(default = "default_cache_hit_pool_size")]
     pub cache_hit_pool_size: usize,
     #[serde(default = "default_cache_miss_pool_size")]
     pub cache_miss_pool_size: usize,
+    // 同时发往上游的请求数上限（缓存命中不占用）
     #[serde(default = "default_max_concurrent_requests")]
     pub max_concurrent_requests: usize,
-    #[serde(default = "default_cache_override_mode")]
-    pub cache_override_mode: bool,
+    // 服务同时处理的请求数上限（包括缓存命中），应高于 max_concurrent_requests，使缓存命中不必排在上游请求之后，0 表示不限制
+    #[serde(default = "default_max_inflight_requests")]
+    pub max_inflight_requests: usize,
+    // 未在 model_cache_versions 中配置的模型使用的缓存版本
     #[serde(default = "default_cache_version")]
     pub cache_version: u8,
+    // 按模型配置的缓存版本，提高某个模型的版本会使该模型的旧缓存失效
+    #[serde(default)]
+    pub model_cache_versions: HashMap<String, u8>,
+    // 客户端请求未指定 model 时使用的模型，在路由与计算缓存键之前填入
+    #[serde(default)]
+    pub default_model: Option<String>,
     #[serde(default = "default_api_headers")]
     pub api_headers: HashMap<String, String>,
     #[serde(default)]
@@ -232,6 +662,57 @@ pub struct Config {
     pub database: DatabaseConfig,
     #[serde(default)]
     pub api_defaults: ApiDefaultsConfig,
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    #[serde(default)]
+    pub memory_pressure: MemoryPressureConfig,
+    #[serde(default)]
+    pub prompt_injection: PromptInjectionConfig,
+    #[serde(default)]
+    pub prompt_templates: HashMap<String, PromptTemplate>,
+    #[serde(default)]
+    pub ab_test: AbTestConfig,
+    #[serde(default)]
+    pub hit_stats: HitStatsConfig,
+    #[serde(default)]
+    pub statsd: StatsdConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub content_filter: ContentFilterConfig,
+    #[serde(default)]
+    pub guardrails: GuardrailsConfig,
+    #[serde(default)]
+    pub wasm_plugins: Vec<WasmPluginConfig>,
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub upstream_replay: UpstreamReplayConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub force_model: ForceModelConfig,
+    #[serde(default)]
+    pub upstream_queue: UpstreamQueueConfig,
+    #[serde(default)]
+    pub response_headers: ResponseHeadersConfig,
+    // 上游响应无法解析时换一个端点重试一次
+    #[serde(default = "default_parse_failure_fallback")]
+    pub parse_failure_fallback: bool,
+    #[serde(default)]
+    pub prompt_cache: PromptCacheConfig,
+    #[serde(default)]
+    pub dashboard: DashboardConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
 }
 
 pub fn default_database_url() -> String {
@@ -258,14 +739,28 @@ pub fn default_max_concurrent_requests() -> usize {
     100
 }
 
-pub fn default_cache_override_mode() -> bool {
-    false
+pub fn default_max_inflight_requests() -> usize {
+    1000
+}
+
+pub fn default_parse_failure_fallback() -> bool {
+    true
 }
 
 pub fn default_cache_version() -> u8 {
     0
 }
 
+impl Config {
+    /// 模型当前的缓存版本，低于该版本的缓存回答对该模型视为未命中
+    pub fn cache_version_for(&self, model: &str) -> u8 {
+        self.model_cache_versions
+            .get(model)
+            .copied()
+            .unwrap_or(self.cache_version)
+    }
+}
+
 pub fn default_api_headers() -> HashMap<String, String> {
     let mut headers = HashMap::new();
     headers.insert("Content-Type".to_string(), "application/json".to_string());
@@ -273,11 +768,51 @@ pub fn default_api_headers() -> HashMap<String, String> {
     headers
 }
 
-pub fn load_config() -> Result<Config, String> {
-    let mut file =
-        std::fs::File::open("config.yaml").map_err(|e| format!("无法打开配置文件: {}", e))?;
-    let mut contents = String::new();
-    std::io::Read::read_to_string(&mut file, &mut contents)
-        .map_err(|e| format!("无法读取配置文件: {}", e))?;
-    serde_yaml::from_str(&contents).map_err(|e| format!("解析配置文件失败: {}", e))
+/// 读取 config.yaml，合并 include、config.d/ 与选中的配置档后解析并校验
+pub fn load_config(profile: Option<&str>) -> Result<Config, String> {
+    let merged = load_merged_yaml(Path::new("config.yaml"), profile)?;
+    // 先单独读取 logging 节，使整份配置的解析错误也按配置的语言输出
+    if let Some(logging) = merged
+        .get("logging")
+        .and_then(|v| serde_yaml::from_value::<LoggingConfig>(v.clone()).ok())
+    {
+        init_logging(&logging);
+    }
+    let config: Config = serde_yaml::from_value(merged.clone()).map_err(|e| {
+        tr!(
+            "解析配置文件失败: {}",
+            "Failed to parse the config file: {}",
+            e
+        )
+    })?;
+    // 尽早应用日志级别与语言，之后的校验提示即按配置输出
+    init_logging(&config.logging);
+
+    // 反序列化之外的取值校验：有错误时拒绝启动，警告只输出提示
+    let issues = validate_config(&config);
+    for warning in &issues.warnings {
+        log_warn!("配置警告: {}", "Config warning: {}", warning);
+    }
+    if !issues.errors.is_empty() {
+        return Err(tr!(
+            "配置校验未通过，共 {} 处错误:\n  - {}",
+            "Config validation failed with {} error(s):\n  - {}",
+            issues.errors.len(),
+            issues.errors.join("\n  - ")
+        ));
+    }
+
+    // 输出未在配置文件中设置、使用默认值的配置项
+    let raw = serde_json::to_value(&merged).unwrap_or_default();
+    let defaults = applied_defaults(&raw, &config);
+    if !defaults.is_empty() {
+        log_info!(
+            "以下配置项未设置，使用默认值:",
+            "The following options are not set and use their defaults:"
+        );
+        for line in defaults {
+            log_info!("  {}", "  {}", line);
+        }
+    }
+    Ok(config)
 }
@@ -0,0 +1,126 @@
+use crate::utils::config::Config;
+use serde_json::Value;
+
+const MASK: &str = "***";
+
+/// 生效的运行配置（已填充默认值），其中的密钥、口令、认证请求头与 URL 中的凭据替换为 "***"。
+/// 空值保持为空，便于确认某项密钥是否已配置
+pub fn sanitized_config(config: &Config) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+    sanitize(&mut value, None);
+    value
+}
+
+fn sanitize(value: &mut Value, parent: Option<&str>) {
+    match value {
+        Value::Object(map) => {
+            let in_webhooks = parent == Some("targets");
+            for (name, field) in map.iter_mut() {
+                if is_secret_name(name) {
+                    mask(field);
+                } else if let Value::String(text) = field
+                    && is_url_name(name)
+                {
+                    // webhook 地址（如 Slack、Discord）的路径本身就是凭据
+                    *text = if in_webhooks {
+                        url_origin(text)
+                    } else {
+                        mask_url_credentials(text)
+                    };
+                } else {
+                    sanitize(field, Some(name));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                sanitize(item, parent);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn mask(value: &mut Value) {
+    let empty = match value {
+        Value::Null => true,
+        Value::String(text) => text.is_empty(),
+        _ => false,
+    };
+    if !empty {
+        *value = Value::String(MASK.to_string());
+    }
+}
+
+// 字段名或请求头名按 _ - . 分词后判断：password、secret、token、authorization、cookie、api key 等。
+// 保存密钥的环境变量名（*_env）与文件路径（*_path、key_file）不是密钥本身，不做处理
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let parts: Vec<&str> = name.split(['_', '-', '.']).collect();
+    if matches!(parts.last(), Some(&("env" | "path" | "file"))) {
+        return false;
+    }
+    parts.iter().any(|part| {
+        matches!(
+            *part,
+            "password" | "passwd" | "secret" | "token" | "authorization" | "cookie" | "apikey"
+                | "credentials"
+        )
+    }) || parts.windows(2).any(|w| w == ["api", "key"])
+        || name == "key"
+}
+
+fn is_url_name(name: &str) -> bool {
+    name == "url" || name.ends_with("_url")
+}
+
+// 隐藏 URL 中的口令（user:pass@）以及查询参数中与密钥同名的参数值
+fn mask_url_credentials(url: &str) -> String {
+    let (base, query) = match url.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (url, None),
+    };
+    let mut masked = match base.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            let authority = match authority.rsplit_once('@') {
+                Some((userinfo, host)) => match userinfo.split_once(':') {
+                    Some((user, _)) => format!("{}:{}@{}", user, MASK, host),
+                    None => format!("{}@{}", MASK, host),
+                },
+                None => authority.to_string(),
+            };
+            format!("{}://{}{}", scheme, authority, path)
+        }
+        None => base.to_string(),
+    };
+    if let Some(query) = query {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((name, _)) if is_secret_name(name) || name.eq_ignore_ascii_case("sig") => {
+                    format!("{}={}", name, MASK)
+                }
+                _ => param.to_string(),
+            })
+            .collect();
+        masked.push('?');
+        masked.push_str(&params.join("&"));
+    }
+    masked
+}
+
+// 只保留协议与主机部分
+fn url_origin(url: &str) -> String {
+    if url.is_empty() {
+        return String::new();
+    }
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let authority = rest.split(['/', '?']).next().unwrap_or_default();
+            let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+            format!("{}://{}/{}", scheme, host, MASK)
+        }
+        None => MASK.to_string(),
+    }
+}
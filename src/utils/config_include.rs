@@ -0,0 +1,193 @@
+use crate::{log_info, tr};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+// 未通过 --profile 指定配置档时读取的环境变量
+pub const PROFILE_ENV: &str = "LLM_CACHE_PROFILE";
+
+/// 从命令行参数中取出 `--profile <名称>` 或 `--profile=<名称>`（其余参数保持原顺序），
+/// 未指定时读取环境变量 LLM_CACHE_PROFILE
+pub fn take_profile_arg(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let mut profile = None;
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--profile" {
+            if i + 1 >= args.len() {
+                return Err(tr!(
+                    "参数 --profile 缺少配置档名称",
+                    "Argument --profile is missing a profile name"
+                ));
+            }
+            profile = Some(args.remove(i + 1));
+            args.remove(i);
+        } else if let Some(name) = args[i].strip_prefix("--profile=") {
+            profile = Some(name.to_string());
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(profile.or_else(|| {
+        std::env::var(PROFILE_ENV)
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+    }))
+}
+
+/// 读取主配置文件并按顺序合并：
+/// 1. 主配置中 `include:` 列出的文件（相对路径相对于主配置所在目录）；
+/// 2. 主配置同目录下 `config.d/` 中的 .yaml / .yml 文件（按文件名排序）；
+/// 3. `profiles:` 中选中的配置档。
+///
+/// 映射逐键递归合并，列表与标量整体替换。合并结果不含 include 与 profiles 键
+pub fn load_merged_yaml(path: &Path, profile: Option<&str>) -> Result<Value, String> {
+    let mut merged = read_yaml(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    let includes = take_key(&mut merged, "include");
+    let profiles = take_key(&mut merged, "profiles");
+
+    let mut sources = Vec::new();
+    for include in include_paths(includes)? {
+        sources.push(base_dir.join(include));
+    }
+    sources.extend(config_dir_files(&base_dir.join("config.d"))?);
+
+    for source in sources {
+        let mut overlay = read_yaml(&source)?;
+        if take_key(&mut overlay, "include").is_some() || take_key(&mut overlay, "profiles").is_some()
+        {
+            return Err(tr!(
+                "{}: include 与 profiles 只能写在主配置文件中",
+                "{}: include and profiles are only allowed in the main config file",
+                source.display()
+            ));
+        }
+        log_info!("合并配置文件: {}", "Merging config file: {}", source.display());
+        merge(&mut merged, overlay);
+    }
+
+    if let Some(name) = profile {
+        let Some(overlay) = profiles.as_ref().and_then(|p| p.get(name)).cloned() else {
+            let available: Vec<String> = profiles
+                .as_ref()
+                .and_then(Value::as_mapping)
+                .map(|p| p.keys().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default();
+            return Err(tr!(
+                "未找到配置档 \"{}\"，已定义的配置档: {}",
+                "Profile \"{}\" not found, defined profiles: {}",
+                name,
+                if available.is_empty() {
+                    tr!("（无）", "(none)")
+                } else {
+                    available.join(", ")
+                }
+            ));
+        };
+        log_info!("使用配置档: {}", "Using profile: {}", name);
+        merge(&mut merged, overlay);
+    }
+
+    Ok(merged)
+}
+
+fn read_yaml(path: &Path) -> Result<Value, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        tr!(
+            "无法读取配置文件 {}: {}",
+            "Failed to read config file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let value: Value = serde_yaml::from_str(&contents).map_err(|e| {
+        tr!(
+            "解析配置文件 {} 失败: {}",
+            "Failed to parse config file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    match value {
+        // 空文件视为空映射
+        Value::Null => Ok(Value::Mapping(Mapping::new())),
+        Value::Mapping(_) => Ok(value),
+        _ => Err(tr!(
+            "配置文件 {} 的顶层应为映射",
+            "The top level of config file {} must be a mapping",
+            path.display()
+        )),
+    }
+}
+
+fn take_key(value: &mut Value, key: &str) -> Option<Value> {
+    value.as_mapping_mut()?.remove(key)
+}
+
+// include 可以是单个路径或路径列表
+fn include_paths(includes: Option<Value>) -> Result<Vec<String>, String> {
+    match includes {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(path)) => Ok(vec![path]),
+        Some(Value::Sequence(paths)) => paths
+            .into_iter()
+            .map(|path| match path {
+                Value::String(path) => Ok(path),
+                other => Err(tr!(
+                    "include 中的路径应为字符串: {:?}",
+                    "Paths in include must be strings: {:?}",
+                    other
+                )),
+            })
+            .collect(),
+        Some(other) => Err(tr!(
+            "include 应为路径或路径列表: {:?}",
+            "include must be a path or a list of paths: {:?}",
+            other
+        )),
+    }
+}
+
+fn config_dir_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        tr!(
+            "无法读取目录 {}: {}",
+            "Failed to read directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// 将 overlay 合并到 base：两边都是映射时逐键递归合并，否则 overlay 整体替换 base
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
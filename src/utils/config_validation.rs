@@ -0,0 +1,660 @@
+use crate::models::api_model::ApiEndpoint;
+use crate::tr;
+use crate::utils::config::Config;
+use crate::utils::context_trim::TrimStrategy;
+use crate::utils::unix_socket::{is_unix_url, unix_url_target};
+use serde_json::Value;
+
+/// 配置校验结果：errors 会阻止启动，warnings 只输出提示。
+/// 提示按 logging.language 输出中文或英文，load_config 在校验之前已初始化日志语言
+#[derive(Debug, Default)]
+pub struct ConfigIssues {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ConfigIssues {
+    fn error(&mut self, path: &str, message: impl std::fmt::Display) {
+        self.errors.push(format!("{}: {}", path, message));
+    }
+
+    fn warn(&mut self, path: &str, message: impl std::fmt::Display) {
+        self.warnings.push(format!("{}: {}", path, message));
+    }
+
+    // 取值不在可选范围内时报错，并列出可选值
+    fn one_of(&mut self, path: &str, value: &str, allowed: &[&str]) {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(value)) {
+            self.error(
+                path,
+                tr!(
+                    "未知的取值 \"{}\"，可选值: {}",
+                    "unknown value \"{}\", allowed: {}",
+                    value,
+                    allowed.join(" / ")
+                ),
+            );
+        }
+    }
+}
+
+const TOKENIZERS: &[&str] = &[
+    "heuristic",
+    "auto",
+    "o200k_base",
+    "o200k_harmony",
+    "cl100k_base",
+    "p50k_base",
+    "p50k_edit",
+    "r50k_base",
+    "gpt2",
+];
+
+/// 在反序列化之外检查配置的取值：端点与 URL、权重、超时、枚举取值、数值范围与相互矛盾的组合。
+/// 每条问题以配置路径开头，并说明应如何修改
+pub fn validate_config(config: &Config) -> ConfigIssues {
+    let mut issues = ConfigIssues::default();
+
+    validate_endpoints(&mut issues, config);
+    validate_limits(&mut issues, config);
+    validate_timeouts(&mut issues, config);
+    validate_cache(&mut issues, config);
+    validate_context_trim(&mut issues, config);
+    validate_integrations(&mut issues, config);
+
+    issues
+}
+
+fn validate_endpoints(issues: &mut ConfigIssues, config: &Config) {
+    if config.api_endpoints.is_empty() {
+        issues.error(
+            "api_endpoints",
+            tr!(
+                "未配置任何上游端点，至少需要一个（如 url: \"http://127.0.0.1:8080\"）",
+                "no upstream endpoint is configured, at least one is required (e.g. url: \"http://127.0.0.1:8080\")"
+            ),
+        );
+        return;
+    }
+    check_endpoint_list(issues, "api_endpoints", &config.api_endpoints);
+
+    for (i, endpoint) in config.api_endpoints.iter().enumerate() {
+        if let Some(arm) = &endpoint.ab_arm {
+            issues.one_of(&format!("api_endpoints[{}].ab_arm", i), arm, &["a", "b"]);
+        }
+        let overrides = &endpoint.overrides;
+        if let Some(temperature) = overrides.temperature
+            && !(0.0..=2.0).contains(&temperature)
+        {
+            issues.error(
+                &format!("api_endpoints[{}].overrides.temperature", i),
+                tr!("应在 0.0 到 2.0 之间", "must be between 0.0 and 2.0"),
+            );
+        }
+        for (name, value, min, max) in [
+            ("top_p", overrides.top_p, 0.0, 1.0),
+            ("frequency_penalty", overrides.frequency_penalty, -2.0, 2.0),
+            ("presence_penalty", overrides.presence_penalty, -2.0, 2.0),
+        ] {
+            if let Some(value) = value
+                && !(min..=max).contains(&value)
+            {
+                issues.error(
+                    &format!("api_endpoints[{}].overrides.{}", i, name),
+                    tr!("应在 {} 到 {} 之间", "must be between {} and {}", min, max),
+                );
+            }
+        }
+        if overrides.max_tokens.is_some_and(|max_tokens| max_tokens <= 0) {
+            issues.error(
+                &format!("api_endpoints[{}].overrides.max_tokens", i),
+                tr!("应大于 0", "must be greater than 0"),
+            );
+        }
+
+        let client = &endpoint.http_client;
+        for (name, value) in [
+            ("timeout_seconds", client.timeout_seconds),
+            ("connect_timeout_seconds", client.connect_timeout_seconds),
+        ] {
+            if value == Some(0) {
+                issues.error(
+                    &format!("api_endpoints[{}].http_client.{}", i, name),
+                    tr!(
+                        "为 0 时每个上游请求都会立即超时，应设为正数（秒）",
+                        "0 makes every upstream request time out immediately, use a positive number of seconds"
+                    ),
+                );
+            }
+        }
+        if let Some(version) = &client.http_version {
+            issues.one_of(
+                &format!("api_endpoints[{}].http_client.http_version", i),
+                version,
+                &["auto", "http1", "http2"],
+            );
+        }
+        if let Some(preference) = &client.ip_preference {
+            issues.one_of(
+                &format!("api_endpoints[{}].http_client.ip_preference", i),
+                preference,
+                &["auto", "ipv4", "ipv6"],
+            );
+        }
+    }
+    if config.enable_thinking.is_some()
+        && config.api_endpoints.iter().any(|ep| ep.enable_thinking.is_none())
+    {
+        issues.warn(
+            "enable_thinking",
+            tr!(
+                "全局设置会发送给所有未单独配置的端点，不支持该字段的后端可能拒绝请求；建议改为在 api_endpoints 中按端点设置 enable_thinking",
+                "the global setting is sent to every endpoint without its own value, and backends that do not support the field may reject requests; set enable_thinking per endpoint in api_endpoints instead"
+            ),
+        );
+    }
+    if config.force_model.enabled {
+        let without_model = config
+            .api_endpoints
+            .iter()
+            .filter(|ep| ep.model.is_none())
+            .count();
+        if config.force_model.reject_other_models && without_model == config.api_endpoints.len() {
+            issues.error(
+                "force_model.reject_other_models",
+                tr!(
+                    "没有端点配置 model，所有请求都会被拒绝；请为端点设置 model 或关闭该选项",
+                    "no endpoint sets model, so every request would be rejected; set model on an endpoint or turn this option off"
+                ),
+            );
+        } else if without_model > 0 {
+            issues.warn(
+                "force_model.enabled",
+                tr!(
+                    "{} 个端点未配置 model，转发到这些端点时仍使用客户端指定的模型",
+                    "{} endpoint(s) have no model, requests forwarded to them keep the model the client asked for",
+                    without_model
+                ),
+            );
+        }
+    }
+    if config.ab_test.enabled {
+        let has_arm = |arm: &str| {
+            config.api_endpoints.iter().any(|ep| {
+                ep.weight > 0 && ep.ab_arm.as_deref().is_some_and(|a| a.eq_ignore_ascii_case(arm))
+            })
+        };
+        if !has_arm("a") || !has_arm("b") {
+            issues.warn(
+                "ab_test.enabled",
+                tr!(
+                    "已启用 A/B 对比，但没有同时配置 ab_arm 为 a 与 b（且权重大于 0）的端点，将按常规方式选择端点",
+                    "A/B comparison is enabled, but there are no endpoints with ab_arm a and b (and a weight above 0); endpoints are selected as usual"
+                ),
+            );
+        }
+        if !(0.0..=1.0).contains(&config.ab_test.ratio_b) {
+            issues.error(
+                "ab_test.ratio_b",
+                tr!("应在 0.0 到 1.0 之间", "must be between 0.0 and 1.0"),
+            );
+        }
+    }
+}
+
+// 端点列表：URL 可解析，且权重不全为 0
+fn check_endpoint_list(issues: &mut ConfigIssues, path: &str, endpoints: &[ApiEndpoint]) {
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        if let Err(e) = check_endpoint_url(&endpoint.url) {
+            issues.error(&format!("{}[{}].url", path, i), e);
+        } else if endpoint.url.trim_end_matches('/').ends_with("/v1/chat/completions") {
+            issues.warn(
+                &format!("{}[{}].url", path, i),
+                tr!(
+                    "转发时会追加 /v1/chat/completions，实际请求 {}/v1/chat/completions；应只填写基础地址，如 \"{}\"",
+                    "/v1/chat/completions is appended when forwarding, so requests go to {}/v1/chat/completions; configure only the base address, e.g. \"{}\"",
+                    endpoint.url.trim_end_matches('/'),
+                    endpoint.url.trim_end_matches('/').trim_end_matches("/v1/chat/completions")
+                ),
+            );
+        }
+    }
+    if !endpoints.is_empty() && endpoints.iter().all(|ep| ep.weight == 0) {
+        issues.error(
+            path,
+            tr!(
+                "所有端点的 weight 都为 0，没有端点会被选中，至少一个端点的 weight 应大于 0",
+                "every endpoint has weight 0, so none is ever selected; at least one endpoint needs a weight above 0"
+            ),
+        );
+    }
+}
+
+fn check_endpoint_url(url: &str) -> Result<(), String> {
+    if is_unix_url(url) {
+        return match unix_url_target(url) {
+            Some(_) => Ok(()),
+            None => Err(tr!(
+                "无效的 Unix 套接字地址 \"{}\"，格式应为 unix:///套接字路径/请求路径",
+                "invalid Unix socket address \"{}\", expected unix:///socket/path/request/path",
+                url
+            )),
+        };
+    }
+    check_url(url, &["http", "https"])
+}
+
+fn check_url(url: &str, schemes: &[&str]) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| {
+        tr!(
+            "无法解析 \"{}\" ({})，应为完整地址，如 {}://127.0.0.1:8080",
+            "cannot parse \"{}\" ({}), expected a full address such as {}://127.0.0.1:8080",
+            url,
+            e,
+            schemes[0]
+        )
+    })?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(tr!(
+            "不支持的协议 \"{}\"，可选: {}",
+            "unsupported scheme \"{}\", allowed: {}",
+            parsed.scheme(),
+            schemes.join(" / ")
+        ));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(tr!("\"{}\" 缺少主机名", "\"{}\" has no host name", url));
+    }
+    Ok(())
+}
+
+fn validate_limits(issues: &mut ConfigIssues, config: &Config) {
+    for (path, value) in [
+        ("max_concurrent_requests", config.max_concurrent_requests),
+        ("cache_hit_pool_size", config.cache_hit_pool_size),
+        ("cache_miss_pool_size", config.cache_miss_pool_size),
+    ] {
+        if value == 0 {
+            issues.error(path, tr!("不能为 0", "must not be 0"));
+        }
+    }
+    if config.max_inflight_requests > 0
+        && config.max_inflight_requests < config.max_concurrent_requests
+    {
+        issues.warn(
+            "max_inflight_requests",
+            tr!(
+                "小于 max_concurrent_requests ({})，缓存命中会排在上游请求之后，建议调高或设为 0（不限制）",
+                "is below max_concurrent_requests ({}), so cache hits queue behind upstream requests; raise it or set 0 (unlimited)",
+                config.max_concurrent_requests
+            ),
+        );
+    }
+    let queue = &config.upstream_queue;
+    if queue.max_queue_depth > 0 && queue.acquire_timeout_seconds == 0 {
+        issues.warn(
+            "upstream_queue.max_queue_depth",
+            tr!(
+                "acquire_timeout_seconds 为 0 时请求不会排队，该项不起作用",
+                "has no effect while acquire_timeout_seconds is 0, because requests never queue"
+            ),
+        );
+    }
+
+    let database = &config.database;
+    if database.max_connections == 0 {
+        issues.error("database.max_connections", tr!("不能为 0", "must not be 0"));
+    } else if database.min_connections > database.max_connections {
+        issues.error(
+            "database.min_connections",
+            tr!(
+                "大于 max_connections ({})，应不超过 max_connections",
+                "is greater than max_connections ({}), it must not exceed max_connections",
+                database.max_connections
+            ),
+        );
+    }
+    issues.one_of(
+        "database.synchronous",
+        &database.synchronous,
+        &["OFF", "NORMAL", "FULL", "EXTRA"],
+    );
+    if !(0.0..=1.0).contains(&database.vacuum_min_free_ratio) {
+        issues.error(
+            "database.vacuum_min_free_ratio",
+            tr!("应在 0.0 到 1.0 之间", "must be between 0.0 and 1.0"),
+        );
+    }
+}
+
+fn validate_timeouts(issues: &mut ConfigIssues, config: &Config) {
+    // 这些超时直接传给 HTTP 客户端或 curl，为 0 时每个请求都会立即超时
+    for (path, value) in [
+        ("http_client.timeout_seconds", config.http_client.timeout_seconds),
+        (
+            "http_client.connect_timeout_seconds",
+            config.http_client.connect_timeout_seconds,
+        ),
+        ("proxy.request_timeout_seconds", config.proxy.request_timeout_seconds),
+        ("proxy.connect_timeout_seconds", config.proxy.connect_timeout_seconds),
+    ] {
+        if value == 0 {
+            issues.error(
+                path,
+                tr!(
+                    "为 0 时每个上游请求都会立即超时，应设为正数（秒）",
+                    "0 makes every upstream request time out immediately, use a positive number of seconds"
+                ),
+            );
+        }
+    }
+
+    let client = &config.http_client;
+    issues.one_of("http_client.http_version", &client.http_version, &["auto", "http1", "http2"]);
+    issues.one_of("http_client.ip_preference", &client.ip_preference, &["auto", "ipv4", "ipv6"]);
+    if client.connect_timeout_seconds > client.timeout_seconds {
+        issues.warn(
+            "http_client.connect_timeout_seconds",
+            tr!(
+                "大于 timeout_seconds ({})，连接超时不会生效",
+                "is greater than timeout_seconds ({}), so the connect timeout never applies",
+                client.timeout_seconds
+            ),
+        );
+    }
+
+    let server = &config.server;
+    let upstream_timeout = if config.use_proxy || config.use_curl {
+        ("proxy.request_timeout_seconds", config.proxy.request_timeout_seconds)
+    } else {
+        ("http_client.timeout_seconds", client.timeout_seconds)
+    };
+    if server.completion_timeout_seconds > 0 && server.completion_timeout_seconds < upstream_timeout.1
+    {
+        issues.warn(
+            "server.completion_timeout_seconds",
+            tr!(
+                "小于 {} ({})，慢请求会在上游返回之前以 504 结束，建议不低于上游超时",
+                "is below {} ({}), so slow requests end with 504 before the upstream answers; use at least the upstream timeout",
+                upstream_timeout.0,
+                upstream_timeout.1
+            ),
+        );
+    }
+    if server.request_timeout_seconds > 0
+        && server.completion_timeout_seconds > 0
+        && server.request_timeout_seconds < server.completion_timeout_seconds
+    {
+        issues.warn(
+            "server.request_timeout_seconds",
+            tr!(
+                "小于 completion_timeout_seconds ({})，对话补全实际以前者为准",
+                "is below completion_timeout_seconds ({}), so it is the limit that actually applies to chat completions",
+                server.completion_timeout_seconds
+            ),
+        );
+    }
+}
+
+fn validate_cache(issues: &mut ConfigIssues, config: &Config) {
+    let cache = &config.cache;
+    issues.one_of("cache.backend", &cache.backend, &["sqlite", "redis"]);
+    issues.one_of("cache.key_message", &cache.key_message, &["first", "last", "conversation"]);
+    issues.one_of(
+        "cache.pending_overflow_policy",
+        &cache.pending_overflow_policy,
+        &["flush", "drop"],
+    );
+    if cache.uses_redis()
+        && let Err(e) = check_url(&cache.redis.url, &["redis", "rediss"])
+    {
+        issues.error("cache.redis.url", e);
+    }
+    if cache.batch_write_size == 0 && !cache.adaptive_batch.enabled {
+        issues.error("cache.batch_write_size", tr!("不能为 0", "must not be 0"));
+    }
+    if config.api_defaults.cache_max_size_bytes == 0 && cache.enabled {
+        issues.warn(
+            "api_defaults.cache_max_size_bytes",
+            tr!(
+                "为 0 时任何回答都超过大小上限，不会被缓存",
+                "0 makes every answer exceed the size limit, so nothing is cached"
+            ),
+        );
+    }
+    issues.one_of(
+        "upstream_replay.mode",
+        &config.upstream_replay.mode,
+        &["off", "record", "replay"],
+    );
+    if config.encryption.enabled && config.upstream_replay.mode.eq_ignore_ascii_case("record") {
+        issues.error(
+            "upstream_replay.mode",
+            tr!(
+                "启用缓存加密（encryption.enabled）时不能使用 record，记录文件会以明文保存完整的问题与回答",
+                "record cannot be used with cache encryption (encryption.enabled), the record file would keep full prompts and answers in plaintext"
+            ),
+        );
+    }
+    issues.one_of(
+        "logging.level",
+        &config.logging.level,
+        &["error", "warn", "info", "debug", "trace"],
+    );
+    issues.one_of("logging.language", &config.logging.language, &["zh", "en"]);
+
+    let tiers = &config.cache_maintenance.retention_tiers;
+    for (i, tier) in tiers.iter().enumerate() {
+        let path = format!("cache_maintenance.retention_tiers[{}]", i);
+        if tier.min_hits < 0 {
+            issues.error(
+                &format!("{}.min_hits", path),
+                tr!("不能为负数", "must not be negative"),
+            );
+        }
+        if tier.retention_days <= 0 {
+            issues.error(
+                &format!("{}.retention_days", path),
+                tr!("应为正数（天）", "must be a positive number of days"),
+            );
+        }
+        if tiers[..i].iter().any(|other| other.min_hits == tier.min_hits) {
+            issues.error(
+                &format!("{}.min_hits", path),
+                tr!(
+                    "与前面的规则重复（{}），该规则不会生效",
+                    "duplicates an earlier tier ({}) and never applies",
+                    tier.min_hits
+                ),
+            );
+        }
+    }
+    if !tiers.is_empty() && tiers.iter().all(|tier| tier.min_hits > 0) {
+        issues.warn(
+            "cache_maintenance.retention_tiers",
+            tr!(
+                "没有 min_hits 为 0 的规则，命中次数低于最低一级的缓存不会过期",
+                "no tier has min_hits 0, so entries with fewer hits than the lowest tier never expire"
+            ),
+        );
+    }
+}
+
+fn validate_context_trim(issues: &mut ConfigIssues, config: &Config) {
+    let trim = &config.context_trim;
+    issues.one_of("context_trim.summary_mode", &trim.summary_mode, &["local", "ai"]);
+    if !trim.strategy.eq_ignore_ascii_case("auto") && TrimStrategy::parse(&trim.strategy).is_none() {
+        issues.error(
+            "context_trim.strategy",
+            tr!(
+                "未知的裁切策略 \"{}\"，可选: auto / pairs / sliding_window / middle_out / importance",
+                "unknown trim strategy \"{}\", allowed: auto / pairs / sliding_window / middle_out / importance",
+                trim.strategy
+            ),
+        );
+    }
+    issues.one_of("context_trim.tokenizer", &trim.tokenizer, TOKENIZERS);
+    for (model, tokenizer) in &trim.model_tokenizers {
+        issues.one_of(
+            &format!("context_trim.model_tokenizers.{}", model),
+            tokenizer,
+            TOKENIZERS,
+        );
+    }
+    if trim.overflow_retry && !(trim.overflow_retry_ratio > 0.0 && trim.overflow_retry_ratio <= 1.0)
+    {
+        issues.error(
+            "context_trim.overflow_retry_ratio",
+            tr!("应在 0.0（不含）到 1.0 之间", "must be above 0.0 and at most 1.0"),
+        );
+    }
+    if trim.enabled && trim.max_context_tokens == 0 {
+        issues.warn(
+            "context_trim.max_context_tokens",
+            tr!(
+                "为 0 时除 system 消息与当前这一轮外的历史都会被裁掉",
+                "0 trims all history except system messages and the current turn"
+            ),
+        );
+    }
+
+    let summary_api = &trim.summary_api;
+    if summary_api.enabled {
+        if summary_api.endpoints.is_empty() {
+            issues.error(
+                "context_trim.summary_api.endpoints",
+                tr!(
+                    "已启用摘要 API，但没有配置摘要端点",
+                    "the summary API is enabled but no summary endpoint is configured"
+                ),
+            );
+        }
+        check_endpoint_list(issues, "context_trim.summary_api.endpoints", &summary_api.endpoints);
+    }
+    if trim.summary_mode.eq_ignore_ascii_case("ai") && !summary_api.enabled {
+        issues.warn(
+            "context_trim.summary_mode",
+            tr!(
+                "为 ai，但 summary_api.enabled 为 false，摘要会回退到本地压缩",
+                "is ai but summary_api.enabled is false, so summaries fall back to local compression"
+            ),
+        );
+    }
+}
+
+fn validate_integrations(issues: &mut ConfigIssues, config: &Config) {
+    let proxy = &config.http_client.outbound_proxy;
+    if proxy.enabled
+        && let Err(e) = check_url(&proxy.url, &["http", "https", "socks5", "socks5h"])
+    {
+        issues.error("http_client.outbound_proxy.url", e);
+    }
+
+    if config.replication.enabled {
+        if config.replication.peers.is_empty() {
+            issues.warn(
+                "replication.peers",
+                tr!(
+                    "已启用缓存复制，但没有配置对等节点",
+                    "replication is enabled but no peers are configured"
+                ),
+            );
+        }
+        if config.replication.shared_secret.is_empty() {
+            issues.error(
+                "replication.shared_secret",
+                tr!(
+                    "启用缓存复制时必须配置，否则任何客户端都可以通过 /internal/replicate 写入缓存",
+                    "required when replication is enabled, otherwise any client can write to the cache through /internal/replicate"
+                ),
+            );
+        }
+        for (i, peer) in config.replication.peers.iter().enumerate() {
+            if let Err(e) = check_url(peer, &["http", "https"]) {
+                issues.error(&format!("replication.peers[{}]", i), e);
+            }
+        }
+    }
+
+    if config.admin.token.is_empty() {
+        issues.warn(
+            "admin.token",
+            tr!(
+                "未配置，/admin/* 管理接口（含仪表盘数据）将拒绝所有请求",
+                "not configured, the /admin/* API (including dashboard data) rejects every request"
+            ),
+        );
+    }
+
+    if config.webhooks.enabled {
+        for (i, target) in config.webhooks.targets.iter().enumerate() {
+            if let Err(e) = check_url(&target.url, &["http", "https"]) {
+                issues.error(&format!("webhooks.targets[{}].url", i), e);
+            }
+            issues.one_of(
+                &format!("webhooks.targets[{}].format", i),
+                &target.format,
+                &["generic", "slack", "discord"],
+            );
+        }
+    }
+}
+
+/// 列出配置文件中未设置、因而使用默认值的配置项，按顶层配置分组，每组一行。
+/// 整节未设置的只输出节名；raw 为配置文件原始解析结果
+pub fn applied_defaults(raw: &Value, config: &Config) -> Vec<String> {
+    let Ok(Value::Object(effective)) = serde_json::to_value(config) else {
+        return Vec::new();
+    };
+    let empty = serde_json::Map::new();
+    let raw = raw.as_object().unwrap_or(&empty);
+
+    let mut lines = Vec::new();
+    let mut whole_sections = Vec::new();
+    for (name, value) in &effective {
+        match (raw.get(name), value) {
+            (None, Value::Object(map)) if !map.is_empty() => whole_sections.push(name.clone()),
+            (None, value) => lines.push(format!("{} = {}", name, value)),
+            (Some(Value::Object(set)), Value::Object(_)) => {
+                let mut defaults = Vec::new();
+                collect_defaults(set, value, "", &mut defaults);
+                if !defaults.is_empty() {
+                    lines.push(format!("{}: {}", name, defaults.join(", ")));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !whole_sections.is_empty() {
+        lines.push(tr!(
+            "整节使用默认值: {}",
+            "Whole sections using defaults: {}",
+            whole_sections.join(", ")
+        ));
+    }
+    lines
+}
+
+fn collect_defaults(
+    set: &serde_json::Map<String, Value>,
+    effective: &Value,
+    prefix: &str,
+    out: &mut Vec<String>,
+) {
+    let Value::Object(effective) = effective else {
+        return;
+    };
+    for (name, value) in effective {
+        let path = format!("{}{}", prefix, name);
+        match (set.get(name), value) {
+            (None, Value::Object(map)) if !map.is_empty() => {
+                out.push(tr!("{}（整节默认）", "{} (whole section default)", path))
+            }
+            (None, value) => out.push(format!("{}={}", path, value)),
+            (Some(Value::Object(inner)), Value::Object(_)) => {
+                collect_defaults(inner, value, &format!("{}.", path), out)
+            }
+            _ => {}
+        }
+    }
+}
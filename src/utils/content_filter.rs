@@ -0,0 +1,260 @@
+use crate::{log_info, tr};
+use crate::models::api_model::ChatResponseJson;
+use crate::utils::error::AppError;
+use crate::utils::logging::english;
+use crate::utils::plugin::{ResponseContext, ResponsePlugin};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ContentFilterConfig {
+    pub enabled: bool,
+    pub rules: Vec<ContentFilterRule>,
+    // 被过滤的回答使用的 finish_reason
+    pub finish_reason: String,
+    // 回答被拦截时替换成的内容
+    pub blocked_message: String,
+}
+
+impl Default for ContentFilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rules: Vec::new(),
+            finish_reason: "filtered".to_string(),
+            blocked_message: "该回答包含被禁止的内容，已被过滤".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ContentFilterRule {
+    // 规则名称，仅用于日志
+    #[serde(default)]
+    pub name: String,
+    // 正则表达式
+    pub pattern: String,
+    // redact：替换匹配的内容；block：拦截整个回答
+    #[serde(default = "default_filter_action")]
+    pub action: String,
+    #[serde(default = "default_filter_replacement")]
+    pub replacement: String,
+}
+
+fn default_filter_action() -> String {
+    "redact".to_string()
+}
+
+fn default_filter_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// 过滤结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOutcome {
+    Passed,
+    // 部分内容被替换
+    Redacted,
+    // 整个回答被拦截，不应写入缓存
+    Blocked,
+}
+
+#[derive(Clone)]
+struct CompiledRule {
+    name: String,
+    regex: Regex,
+    block: bool,
+    replacement: String,
+}
+
+/// 启动时编译好的回答内容过滤器
+#[derive(Clone)]
+pub struct ContentFilter {
+    rules: Vec<CompiledRule>,
+    finish_reason: String,
+    blocked_message: String,
+}
+
+impl ContentFilter {
+    /// 编译配置中的规则，未启用或没有规则时返回 None
+    pub fn from_config(config: &ContentFilterConfig) -> Result<Option<Self>, String> {
+        if !config.enabled || config.rules.is_empty() {
+            return Ok(None);
+        }
+
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            let regex = Regex::new(&rule.pattern).map_err(|e| {
+                tr!(
+                    "内容过滤规则 {} 的正则表达式无效: {}",
+                    "Invalid regex in content filter rule {}: {}",
+                    rule.pattern,
+                    e
+                )
+            })?;
+            let block = match rule.action.as_str() {
+                "block" => true,
+                "redact" => false,
+                other => {
+                    return Err(tr!(
+                        "未知的内容过滤动作: {}",
+                        "Unknown content filter action: {}",
+                        other
+                    ));
+                }
+            };
+            rules.push(CompiledRule {
+                name: if rule.name.is_empty() {
+                    rule.pattern.clone()
+                } else {
+                    rule.name.clone()
+                },
+                regex,
+                block,
+                replacement: rule.replacement.clone(),
+            });
+        }
+
+        Ok(Some(Self {
+            rules,
+            finish_reason: config.finish_reason.clone(),
+            blocked_message: config.blocked_message.clone(),
+        }))
+    }
+
+    // 检查单段文本，返回过滤结果、处理后的文本与命中的规则名称
+    fn check(&self, text: &str) -> (FilterOutcome, String, Vec<&str>) {
+        let mut outcome = FilterOutcome::Passed;
+        let mut output = text.to_string();
+        let mut matched = Vec::new();
+
+        for rule in &self.rules {
+            if !rule.regex.is_match(&output) {
+                continue;
+            }
+            matched.push(rule.name.as_str());
+            if rule.block {
+                return (FilterOutcome::Blocked, self.blocked_message.clone(), matched);
+            }
+            output = rule
+                .regex
+                .replace_all(&output, rule.replacement.as_str())
+                .into_owned();
+            outcome = FilterOutcome::Redacted;
+        }
+
+        (outcome, output, matched)
+    }
+
+    /// 在返回或写入缓存之前过滤回答内容，被处理的选项 finish_reason 设置为配置的值
+    pub fn apply(&self, response: &mut ChatResponseJson, request_id: &str) -> FilterOutcome {
+        let mut result = FilterOutcome::Passed;
+
+        for choice in &mut response.choices {
+            let (outcome, content, matched) = self.check(&choice.message.content);
+            if outcome == FilterOutcome::Passed {
+                continue;
+            }
+
+            let action = match (outcome == FilterOutcome::Blocked, english()) {
+                (true, false) => "拦截",
+                (false, false) => "替换",
+                (true, true) => "blocked",
+                (false, true) => "replaced",
+            };
+            log_info!(
+                "[{}] 回答命中内容过滤规则: {}，处理方式: {}",
+                "[{}] Answer matched content filter rules: {}, action: {}",
+                request_id,
+                matched.join(", "),
+                action
+            );
+            choice.message.content = content;
+            choice.finish_reason = self.finish_reason.clone();
+            if outcome == FilterOutcome::Blocked || result == FilterOutcome::Passed {
+                result = outcome;
+            }
+        }
+
+        result
+    }
+}
+
+/// 流式响应的过滤状态。已发送的内容无法撤回，因此累积各 chunk 的增量内容，
+/// 命中任一规则（替换或拦截）时以 finish_reason 为配置值的结束 chunk 截断流
+pub struct StreamFilter {
+    filter: Arc<ContentFilter>,
+    request_id: String,
+    content: String,
+}
+
+impl StreamFilter {
+    pub fn new(filter: Arc<ContentFilter>, request_id: &str) -> Self {
+        Self {
+            filter,
+            request_id: request_id.to_string(),
+            content: String::new(),
+        }
+    }
+
+    /// 在转发前检查一行 SSE 输出；命中规则时返回替代该行的结束事件（含 [DONE]），之后应结束流
+    pub fn check_line(&mut self, line: &str) -> Option<String> {
+        let data = line.strip_prefix("data:")?.trim();
+        let chunk: serde_json::Value = serde_json::from_str(data).ok()?;
+        let choices = chunk["choices"].as_array()?;
+        for choice in choices {
+            if let Some(delta) = choice["delta"]["content"].as_str() {
+                self.content.push_str(delta);
+            }
+        }
+
+        let (outcome, _, matched) = self.filter.check(&self.content);
+        if outcome == FilterOutcome::Passed {
+            return None;
+        }
+        log_info!(
+            "[{}] 流式回答命中内容过滤规则: {}，截断输出",
+            "[{}] Streamed answer matched content filter rules: {}, truncating the stream",
+            self.request_id,
+            matched.join(", ")
+        );
+        let end = serde_json::json!({
+            "id": chunk["id"],
+            "object": "chat.completion.chunk",
+            "created": chunk["created"],
+            "model": chunk["model"],
+            "choices": [{
+                "index": 0,
+                "delta": {},
+                "finish_reason": self.filter.finish_reason,
+            }],
+        });
+        Some(format!("data: {}\n\ndata: [DONE]\n\n", end))
+    }
+}
+
+impl ResponsePlugin for ContentFilter {
+    fn name(&self) -> &str {
+        "content_filter"
+    }
+
+    // 被拦截的回答不写入缓存
+    fn pre_cache_store(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        Ok(self.apply(response, ctx.request_id) != FilterOutcome::Blocked)
+    }
+
+    // 缓存写入后可能新增了过滤规则，命中时同样检查
+    fn post_cache_hit(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<(), AppError> {
+        self.apply(response, ctx.request_id);
+        Ok(())
+    }
+}
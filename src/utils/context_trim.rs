@@ -1,8 +1,12 @@
+use crate::{log_debug, log_trace, log_warn};
 use crate::models::api_model::select_api_endpoint;
 use crate::models::api_model::{ApiEndpoint, ChatMessageJson, ChatRequestJson, ChatResponseJson};
+use crate::utils::config::ContextTrimConfig;
 use reqwest::Client;
 use std::collections::HashMap;
 use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
 use tokio::task;
 use uuid::Uuid;
 
@@ -106,17 +110,120 @@ fn is_cjk_char(ch: char) -> bool {
     (0xAC00..=0xD7AF).contains(&code) // 韩文音节
 }
 
+/// token 计数器：使用真实分词器（tiktoken BPE）或启发式估算
+#[derive(Clone, Copy)]
+pub enum TokenCounter {
+    Heuristic,
+    Bpe(&'static CoreBPE),
+}
+
+impl TokenCounter {
+    /// 根据配置为请求模型选择分词器：优先 model_tokenizers 中的精确匹配，其次最长前缀匹配，
+    /// 最后使用全局 tokenizer。`auto` 按模型名推断 OpenAI 分词器，无法识别时回退启发式估算。
+    pub fn for_model(config: &ContextTrimConfig, model: &str) -> Self {
+        let name = config
+            .model_tokenizers
+            .get(model)
+            .or_else(|| {
+                config
+                    .model_tokenizers
+                    .iter()
+                    .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+                    .max_by_key(|(prefix, _)| prefix.len())
+                    .map(|(_, name)| name)
+            })
+            .unwrap_or(&config.tokenizer);
+
+        let tokenizer = match name.to_lowercase().as_str() {
+            "auto" => get_tokenizer(model),
+            "o200k_base" => Some(Tokenizer::O200kBase),
+            "o200k_harmony" => Some(Tokenizer::O200kHarmony),
+            "cl100k_base" => Some(Tokenizer::Cl100kBase),
+            "p50k_base" => Some(Tokenizer::P50kBase),
+            "p50k_edit" => Some(Tokenizer::P50kEdit),
+            "r50k_base" | "gpt2" => Some(Tokenizer::R50kBase),
+            "heuristic" => None,
+            other => {
+                log_warn!(
+                    "未知的分词器 {}，回退到启发式估算",
+                    "Unknown tokenizer {}, falling back to the heuristic estimate",
+                    other
+                );
+                None
+            }
+        };
+
+        match tokenizer.and_then(|t| tiktoken_rs::bpe_for_tokenizer(t).ok()) {
+            Some(bpe) => TokenCounter::Bpe(bpe),
+            None => TokenCounter::Heuristic,
+        }
+    }
+
+    /// 计算单条消息的 token 数（与启发式估算一致，包含每条消息 3 个 token 的固定开销）
+    pub fn count(&self, message: &str) -> usize {
+        match self {
+            TokenCounter::Heuristic => estimate_tokens(message),
+            TokenCounter::Bpe(_) if message.is_empty() => 0,
+            TokenCounter::Bpe(bpe) => bpe.encode_ordinary(message).len() + 3,
+        }
+    }
+}
+
 /// 计算消息列表的总token数量
-pub fn calculate_total_tokens(messages: &[ChatMessageJson]) -> usize {
+pub fn calculate_total_tokens(messages: &[ChatMessageJson], counter: TokenCounter) -> usize {
     if messages.is_empty() {
         return 0;
     }
 
     // 缓存每条消息的估算，避免重复计算
-    messages
-        .iter()
-        .map(|msg| estimate_tokens(&msg.content))
-        .sum()
+    messages.iter().map(|msg| message_tokens(msg, counter)).sum()
+}
+
+/// 计算单条消息的 token 数，工具调用参数同样计入
+fn message_tokens(message: &ChatMessageJson, counter: TokenCounter) -> usize {
+    let mut tokens = counter.count(&message.content);
+    for calls in [&message.tool_calls, &message.function_call]
+        .into_iter()
+        .flatten()
+    {
+        tokens += counter.count(&calls.to_string());
+    }
+    tokens
+}
+
+/// 查找工具调用单元：携带 tool_calls 的 assistant 消息及其后紧随的 tool/function 结果消息。
+/// 返回每条消息所属单元的 [start, end) 区间，不属于任何单元的消息为 None。
+/// 裁切时单元内的消息必须整体保留或整体丢弃，否则上游会因调用与结果不匹配而拒绝请求。
+fn tool_call_units(messages: &[ChatMessageJson]) -> Vec<Option<(usize, usize)>> {
+    let n = messages.len();
+    let mut units = vec![None; n];
+    let mut i = 0usize;
+    while i < n {
+        if messages[i].has_tool_calls() {
+            let start = i;
+            i += 1;
+            while i < n && messages[i].is_tool_result() {
+                i += 1;
+            }
+            for unit in units.iter_mut().take(i).skip(start) {
+                *unit = Some((start, i));
+            }
+        } else {
+            i += 1;
+        }
+    }
+    units
+}
+
+/// 若某个工具调用单元中有任一消息被标记，则将整个单元标记
+fn expand_to_units(marks: &mut [bool], units: &[Option<(usize, usize)>]) {
+    for i in 0..marks.len() {
+        if marks[i]
+            && let Some((start, end)) = units[i]
+        {
+            marks[start..end].iter_mut().for_each(|m| *m = true);
+        }
+    }
 }
 
 /// 改进的摘要函数，按语义边界截断
@@ -327,6 +434,7 @@ async fn summarize_message_with_ai(
     api_endpoints: &[ApiEndpoint],
     api_headers: &HashMap<String, String>,
     summary_api_endpoints: &[ApiEndpoint],
+    summary_api_key: Option<&str>,
     summary_api_max_tokens: i32,
     summary_api_temperature: f32,
     summary_api_timeout_seconds: u64,
@@ -338,7 +446,8 @@ async fn summarize_message_with_ai(
     );
 
     // 优先使用摘要专用的端点，如果没有则使用通用端点
-    let endpoint = if !summary_api_endpoints.is_empty() {
+    let use_summary_endpoints = !summary_api_endpoints.is_empty();
+    let endpoint = if use_summary_endpoints {
         select_api_endpoint(summary_api_endpoints)
     } else {
         select_api_endpoint(api_endpoints)
@@ -366,11 +475,22 @@ async fn summarize_message_with_ai(
         messages: vec![ChatMessageJson {
             role: "user".to_string(),
             content: prompt,
+            ..Default::default()
         }],
         temperature: summary_api_temperature,
         max_tokens: summary_api_max_tokens,
         stream: false,
         enable_thinking: None,
+        stop: None,
+        top_p: None,
+        frequency_penalty: None,
+        presence_penalty: None,
+        seed: None,
+        response_format: None,
+        x_trim: None,
+        template: None,
+        variables: None,
+        extra: serde_json::Map::new(),
     };
 
     if let Ok(payload_json) = serde_json::to_string(&req_payload) {
@@ -383,6 +503,16 @@ async fn summarize_message_with_ai(
         if !api_headers.contains_key("Content-Type") {
             request_builder = request_builder.header("Content-Type", "application/json");
         }
+        // 摘要专用端点使用独立的 API Key；若已显式配置授权头则不覆盖
+        let has_auth = api_headers
+            .keys()
+            .any(|h| h.eq_ignore_ascii_case("authorization"));
+        if use_summary_endpoints
+            && !has_auth
+            && let Some(key) = summary_api_key
+        {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
+        }
         // 便于上游/日志识别该请求为摘要
         request_builder = request_builder.header("X-Summary-Request", "true");
 
@@ -406,7 +536,11 @@ async fn summarize_message_with_ai(
                 }
             }
             _ => {
-                println!("[summary:{}] 请求失败/超时，回退本地摘要", summary_req_id);
+                log_warn!(
+                    "[summary:{}] 请求失败/超时，回退本地摘要",
+                    "[summary:{}] Request failed or timed out, falling back to local summary",
+                    summary_req_id
+                );
             }
         }
     }
@@ -422,6 +556,7 @@ async fn summarize_messages_concurrent(
     api_endpoints: &[ApiEndpoint],
     api_headers: &HashMap<String, String>,
     summary_api_endpoints: &[ApiEndpoint],
+    summary_api_key: Option<&str>,
     summary_api_max_tokens: i32,
     summary_api_temperature: f32,
     summary_api_timeout_seconds: u64,
@@ -447,6 +582,7 @@ async fn summarize_messages_concurrent(
             let api_endpoints = api_endpoints.to_vec();
             let api_headers = api_headers.clone();
             let summary_api_endpoints = summary_api_endpoints.to_vec();
+            let summary_api_key = summary_api_key.map(str::to_string);
 
             task::spawn(async move {
                 let result = summarize_message_with_ai(
@@ -456,6 +592,7 @@ async fn summarize_messages_concurrent(
                     &api_endpoints,
                     &api_headers,
                     &summary_api_endpoints,
+                    summary_api_key.as_deref(),
                     summary_api_max_tokens,
                     summary_api_temperature,
                     summary_api_timeout_seconds,
@@ -481,8 +618,9 @@ async fn summarize_messages_concurrent(
     }
 
     if failed_count > 0 {
-        println!(
+        log_warn!(
             "[WARNING] {} AI摘要任务失败，已回退到本地摘要",
+            "[WARNING] {} AI summary tasks failed, fell back to local summary",
             failed_count
         );
     }
@@ -492,168 +630,304 @@ async fn summarize_messages_concurrent(
     results
 }
 
-/// 默认裁切：保留最后一条消息、所有 prompt 消息，以及第一轮用户对话及其对应的第一句 AI 回复。
-pub fn trim_context(messages: &[ChatMessageJson], max_tokens: usize) -> Vec<ChatMessageJson> {
-    if messages.is_empty() {
-        return Vec::new();
-    }
-    let request_id: String = Uuid::new_v4().to_string().chars().take(8).collect();
-
-    let total_tokens = calculate_total_tokens(messages);
-    println!(
-        "[request_id:{}] trim_context: total_tokens={}",
-        request_id, total_tokens
-    );
-
-    if total_tokens <= max_tokens {
-        println!(
-            "[request_id:{}] trim_context: early return (total_tokens <= max_tokens)",
-            request_id
-        );
-        return messages.to_vec();
-    }
+/// 上下文裁切策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// 默认裁切：预算允许时保留首轮对话，其余按轮次从新到旧填充
+    Pairs,
+    /// 滑动窗口：只保留最近且连续的若干消息
+    SlidingWindow,
+    /// 中间截断：保留对话开头和结尾，丢弃中间部分
+    MiddleOut,
+    /// 智能裁切：按重要性对旧消息进行摘要压缩
+    Importance,
+}
 
-    // 如果历史记录为空但还是超了配置项，则允许本次请求发送
-    if messages.len() <= 2 {
-        println!(
-            "[request_id:{}] trim_context: history length <= 2, returning as-is",
-            request_id
-        );
-        return messages.to_vec();
+impl TrimStrategy {
+    /// 解析策略名称，`simple`/`smart` 为旧版 mode 的别名
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "pairs" | "simple" => Some(TrimStrategy::Pairs),
+            "sliding_window" | "window" => Some(TrimStrategy::SlidingWindow),
+            "middle_out" => Some(TrimStrategy::MiddleOut),
+            "importance" | "smart" => Some(TrimStrategy::Importance),
+            _ => None,
+        }
     }
 
-    let n = messages.len();
-    let mut keep = vec![false; n];
-    // 始终保留最后一条
-    keep[n - 1] = true;
-    // 其次，保留所有 prompt 消息
-    for (i, m) in messages.iter().enumerate() {
-        let role = m.role.as_str();
-        if role.eq_ignore_ascii_case("prompt") || role.eq_ignore_ascii_case("system") {
-            keep[i] = true;
+    /// 根据配置确定默认策略：`auto` 时沿用 smart_enabled 开关
+    pub fn from_config(strategy: &str, smart_enabled: bool) -> Self {
+        let fallback = if smart_enabled {
+            TrimStrategy::Importance
+        } else {
+            TrimStrategy::Pairs
+        };
+        if strategy.eq_ignore_ascii_case("auto") {
+            return fallback;
         }
+        TrimStrategy::parse(strategy).unwrap_or_else(|| {
+            log_warn!(
+                "未知的裁切策略 {}，按 smart_enabled 选择",
+                "Unknown trim strategy {}, choosing by smart_enabled",
+                strategy
+            );
+            fallback
+        })
     }
+}
 
-    // 改为按对(pair)保留：尽量保留最近的完整 user->assistant 对，避免出现孤立的单侧消息
-    // 找到最后一条 user 的索引，然后与其后最近的 assistant 配对
-    let mut pairs: Vec<(usize, usize)> = Vec::new();
+/// 将消息划分为裁切的最小单位：system/prompt 消息始终保留不参与划分，工具调用单元视为一个块
+fn trim_blocks(messages: &[ChatMessageJson]) -> (Vec<bool>, Vec<(usize, usize)>) {
+    let units = tool_call_units(messages);
+    let mut pinned = vec![false; messages.len()];
+    let mut blocks = Vec::new();
     let mut i = 0usize;
-    while i < n {
-        if messages[i].role.eq_ignore_ascii_case("user") {
-            // 找到对应的 assistant
-            let mut j = i + 1;
-            while j < n {
-                if messages[j].role.eq_ignore_ascii_case("assistant") {
-                    pairs.push((i, j));
-                    break;
-                }
-                j += 1;
-            }
-            i = j;
-        } else {
+    while i < messages.len() {
+        let role = messages[i].role.as_str();
+        if role.eq_ignore_ascii_case("system") || role.eq_ignore_ascii_case("prompt") {
+            pinned[i] = true;
             i += 1;
+            continue;
         }
+        let (start, end) = units[i].unwrap_or((i, i + 1));
+        blocks.push((start, end));
+        i = end;
     }
+    (pinned, blocks)
+}
 
-    // 保证至少保留第一轮（如果存在）
-    if !pairs.is_empty() {
-        let (u, a) = pairs[0];
-        keep[u] = true;
-        keep[a] = true;
-    }
+/// 按标记组装结果，保持原有顺序
+fn collect_kept(messages: &[ChatMessageJson], keep: &[bool]) -> Vec<ChatMessageJson> {
+    messages
+        .iter()
+        .zip(keep)
+        .filter(|(_, k)| **k)
+        .map(|(m, _)| m.clone())
+        .collect()
+}
 
-    // 计算当前保留的 token 总数，并缓存每条消息的估算值以便复用
-    let mut token_cache: Vec<usize> = Vec::with_capacity(n);
-    for m in messages.iter() {
-        token_cache.push(estimate_tokens(&m.content));
+/// 滑动窗口裁切：保留所有 system 消息，再从最后一条消息向前连续保留，直到超出 token 上限。
+pub fn trim_sliding_window(
+    messages: &[ChatMessageJson],
+    max_tokens: usize,
+    counter: TokenCounter,
+) -> Vec<ChatMessageJson> {
+    let total_tokens = calculate_total_tokens(messages, counter);
+    if total_tokens <= max_tokens {
+        return messages.to_vec();
     }
+    let request_id: String = Uuid::new_v4().to_string().chars().take(8).collect();
 
-    let mut current_tokens = 0usize;
-    for i in 0..n {
-        if keep[i] {
-            current_tokens += token_cache[i];
+    let (mut keep, blocks) = trim_blocks(messages);
+    let block_cost = |(start, end): (usize, usize)| -> usize {
+        messages[start..end]
+            .iter()
+            .map(|m| message_tokens(m, counter))
+            .sum()
+    };
+    let mut current_tokens: usize = messages
+        .iter()
+        .zip(&keep)
+        .filter(|(_, k)| **k)
+        .map(|(m, _)| message_tokens(m, counter))
+        .sum();
+
+    for (pos, &(start, end)) in blocks.iter().enumerate().rev() {
+        let cost = block_cost((start, end));
+        // 最后一个块（当前输入）始终保留
+        if pos + 1 != blocks.len() && current_tokens + cost > max_tokens {
+            break;
         }
+        keep[start..end].iter_mut().for_each(|k| *k = true);
+        current_tokens += cost;
     }
 
-    // 反向按消息对（若是 assistant 则尝试连同其前面的 user 一起保留）尝试添加更多消息
-    let mut idx = n as isize - 1;
-    while idx >= 0 {
-        let i = idx as usize;
-        if keep[i] {
-            idx -= 1;
-            continue;
-        }
+    let result = collect_kept(messages, &keep);
+    log_trace!(
+        "[request_id:{}] trim_sliding_window: total_tokens={}, final_tokens={}, final_result_len={}",
+        "[request_id:{}] trim_sliding_window: total_tokens={}, final_tokens={}, final_result_len={}",
+        request_id,
+        total_tokens,
+        current_tokens,
+        result.len()
+    );
+    result
+}
 
-        let role = messages[i].role.as_str();
-        if role.eq_ignore_ascii_case("assistant") {
-            // 尝试连带前面的 user
-            if i >= 1 && messages[i - 1].role.eq_ignore_ascii_case("user") {
-                let pair_cost = token_cache[i] + token_cache[i - 1];
-                if current_tokens + pair_cost <= max_tokens {
-                    keep[i] = true;
-                    keep[i - 1] = true;
-                    current_tokens += pair_cost;
-                }
-                idx = idx - 2;
-                continue;
+/// 中间截断裁切：保留所有 system 消息和最后一条消息，再交替从结尾和开头向中间填充，丢弃中间部分。
+pub fn trim_middle_out(
+    messages: &[ChatMessageJson],
+    max_tokens: usize,
+    counter: TokenCounter,
+) -> Vec<ChatMessageJson> {
+    let total_tokens = calculate_total_tokens(messages, counter);
+    if total_tokens <= max_tokens {
+        return messages.to_vec();
+    }
+    let request_id: String = Uuid::new_v4().to_string().chars().take(8).collect();
+
+    let (mut keep, blocks) = trim_blocks(messages);
+    let block_cost = |(start, end): (usize, usize)| -> usize {
+        messages[start..end]
+            .iter()
+            .map(|m| message_tokens(m, counter))
+            .sum()
+    };
+    let mut current_tokens: usize = messages
+        .iter()
+        .zip(&keep)
+        .filter(|(_, k)| **k)
+        .map(|(m, _)| message_tokens(m, counter))
+        .sum();
+
+    if let Some(&last) = blocks.last() {
+        keep[last.0..last.1].iter_mut().for_each(|k| *k = true);
+        current_tokens += block_cost(last);
+    }
+
+    // head/tail 为尚未处理的块区间 [head, tail)
+    let mut head = 0usize;
+    let mut tail = blocks.len().saturating_sub(1);
+    let mut head_open = true;
+    let mut tail_open = true;
+    while head < tail && (head_open || tail_open) {
+        if tail_open {
+            let block = blocks[tail - 1];
+            let cost = block_cost(block);
+            if current_tokens + cost <= max_tokens {
+                keep[block.0..block.1].iter_mut().for_each(|k| *k = true);
+                current_tokens += cost;
+                tail -= 1;
             } else {
-                if current_tokens + token_cache[i] <= max_tokens {
-                    keep[i] = true;
-                    current_tokens += token_cache[i];
-                }
-                idx -= 1;
-                continue;
+                tail_open = false;
             }
-        } else if role.eq_ignore_ascii_case("user") {
-            // 优先保留 user 与其后 assistant 一起（如果存在）
-            if i + 1 < n && messages[i + 1].role.eq_ignore_ascii_case("assistant") {
-                let pair_cost = token_cache[i] + token_cache[i + 1];
-                if current_tokens + pair_cost <= max_tokens {
-                    keep[i] = true;
-                    keep[i + 1] = true;
-                    current_tokens += pair_cost;
-                }
-                idx -= 1;
-                continue;
+        }
+        if head_open && head < tail {
+            let block = blocks[head];
+            let cost = block_cost(block);
+            if current_tokens + cost <= max_tokens {
+                keep[block.0..block.1].iter_mut().for_each(|k| *k = true);
+                current_tokens += cost;
+                head += 1;
             } else {
-                if current_tokens + token_cache[i] <= max_tokens {
-                    keep[i] = true;
-                    current_tokens += token_cache[i];
-                }
-                idx -= 1;
-                continue;
-            }
-        } else {
-            // 其他 role（如 prompt/system/function）按单条尝试
-            if current_tokens + token_cache[i] <= max_tokens {
-                keep[i] = true;
-                current_tokens += token_cache[i];
+                head_open = false;
             }
-            idx -= 1;
         }
     }
 
-    // 组装最终结果，保持原有顺序；对于被裁掉但未删除的消息，尽量做标注或摘要（这里先直接丢弃）
-    let mut result = Vec::with_capacity(n);
-    for i in 0..n {
-        if keep[i] {
-            result.push(messages[i].clone());
+    let result = collect_kept(messages, &keep);
+    log_trace!(
+        "[request_id:{}] trim_middle_out: total_tokens={}, final_tokens={}, dropped_blocks={}, final_result_len={}",
+        "[request_id:{}] trim_middle_out: total_tokens={}, final_tokens={}, dropped_blocks={}, final_result_len={}",
+        request_id,
+        total_tokens,
+        current_tokens,
+        tail - head,
+        result.len()
+    );
+    result
+}
+
+/// 将消息划分为对话轮次：每轮以 user 消息开始，包含其后的回答、工具调用与结果，直到下一条 user 消息。
+/// system/prompt 消息始终保留（返回的标记为 true），不计入任何一轮的 token；第一条 user 之前的其他消息单独成为一轮。
+/// 按轮次整体保留或丢弃，不会留下缺少提问的回答或缺少调用的工具结果。
+fn conversation_turns(messages: &[ChatMessageJson]) -> (Vec<bool>, Vec<(usize, usize)>) {
+    let pinned: Vec<bool> = messages
+        .iter()
+        .map(|m| m.role.eq_ignore_ascii_case("system") || m.role.eq_ignore_ascii_case("prompt"))
+        .collect();
+    let mut turns: Vec<(usize, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, m) in messages.iter().enumerate() {
+        if m.role.eq_ignore_ascii_case("user") {
+            if let Some(s) = start {
+                turns.push((s, i));
+            }
+            start = Some(i);
+        } else if start.is_none() && !pinned[i] {
+            start = Some(i);
         }
     }
+    if let Some(s) = start {
+        turns.push((s, messages.len()));
+    }
+    (pinned, turns)
+}
+
+/// 默认裁切：保留所有 system/prompt 消息与最后一轮对话（当前输入及本轮的工具调用与结果），
+/// 预算允许时保留第一轮对话，其余轮次从新到旧填充，放不下的整轮丢弃。
+pub fn trim_context(
+    messages: &[ChatMessageJson],
+    max_tokens: usize,
+    counter: TokenCounter,
+) -> Vec<ChatMessageJson> {
+    if messages.is_empty() {
+        return Vec::new();
+    }
+    let request_id: String = Uuid::new_v4().to_string().chars().take(8).collect();
+
+    let total_tokens = calculate_total_tokens(messages, counter);
+    log_trace!(
+        "[request_id:{}] trim_context: total_tokens={}",
+        "[request_id:{}] trim_context: total_tokens={}",
+        request_id,
+        total_tokens
+    );
 
-    if result.len() < 2 {
-        let start = if n >= 2 { n - 2 } else { 0 };
-        println!(
-            "[request_id:{}] trim_context: final_result_len=0, returning last {} messages",
-            request_id,
-            n - start
+    if total_tokens <= max_tokens {
+        log_trace!(
+            "[request_id:{}] trim_context: early return (total_tokens <= max_tokens)",
+            "[request_id:{}] trim_context: early return (total_tokens <= max_tokens)",
+            request_id
         );
-        return messages[start..].to_vec();
+        return messages.to_vec();
     }
 
-    println!(
-        "[request_id:{}] trim_context: final_result_len={}",
+    let (pinned, turns) = conversation_turns(messages);
+    let mut keep = pinned.clone();
+    let token_cache: Vec<usize> = messages.iter().map(|m| message_tokens(m, counter)).collect();
+    // 一轮的 token 数（其中的 system/prompt 消息已计入固定保留部分）
+    let turn_cost = |&(start, end): &(usize, usize)| -> usize {
+        (start..end)
+            .filter(|&i| !pinned[i])
+            .map(|i| token_cache[i])
+            .sum()
+    };
+    let mut current_tokens: usize = (0..messages.len())
+        .filter(|&i| keep[i])
+        .map(|i| token_cache[i])
+        .sum();
+
+    // 最后一轮为当前输入，即使超出预算也始终保留
+    if let Some(last) = turns.last() {
+        keep[last.0..last.1].iter_mut().for_each(|k| *k = true);
+        current_tokens += turn_cost(last);
+    }
+
+    // 其次在预算内保留第一轮，再从新到旧尝试其余轮次（放不下的跳过，继续尝试更早的轮次）
+    let rest = turns.len().saturating_sub(1);
+    let candidates = turns[..rest]
+        .first()
+        .into_iter()
+        .chain(turns[..rest].iter().skip(1).rev());
+    let mut dropped_turns = 0usize;
+    for turn in candidates {
+        let cost = turn_cost(turn);
+        if current_tokens + cost <= max_tokens {
+            keep[turn.0..turn.1].iter_mut().for_each(|k| *k = true);
+            current_tokens += cost;
+        } else {
+            dropped_turns += 1;
+        }
+    }
+
+    let result = collect_kept(messages, &keep);
+    log_trace!(
+        "[request_id:{}] trim_context: final_tokens={}, dropped_turns={}, final_result_len={}",
+        "[request_id:{}] trim_context: final_tokens={}, dropped_turns={}, final_result_len={}",
         request_id,
+        current_tokens,
+        dropped_turns,
         result.len()
     );
     result
@@ -669,18 +943,21 @@ pub async fn trim_context_smart(
     summary_mode: &str,
     summary_api_enabled: bool,
     summary_api_endpoints: &[ApiEndpoint],
+    summary_api_key: Option<&str>,
     summary_api_max_tokens: i32,
     summary_api_temperature: f32,
     summary_api_timeout_seconds: u64,
     client: &Client,
     api_endpoints: &[ApiEndpoint],
     api_headers: &HashMap<String, String>,
+    counter: TokenCounter,
 ) -> Vec<ChatMessageJson> {
     if messages.is_empty() {
         return Vec::new();
     }
     let request_id: String = Uuid::new_v4().to_string().chars().take(8).collect();
-    println!(
+    log_trace!(
+        "[request_id:{}] trim_context_smart: start, n={}",
         "[request_id:{}] trim_context_smart: start, n={}",
         request_id,
         messages.len()
@@ -692,18 +969,25 @@ pub async fn trim_context_smart(
     // 计算每条消息的初始 token 数
     let mut token_cache: Vec<usize> = messages
         .iter()
-        .map(|m| estimate_tokens(&m.content) + per_message_overhead)
+        .map(|m| message_tokens(m, counter) + per_message_overhead)
         .collect();
 
     let total_tokens: usize = token_cache.iter().sum();
-    println!(
+    log_debug!(
         "[request_id:{}] 初始总token数: {}, 目标限制: {}",
-        request_id, total_tokens, max_tokens
+        "[request_id:{}] Initial total tokens: {}, target limit: {}",
+        request_id,
+        total_tokens,
+        max_tokens
     );
 
     // 如果已经在限制内，直接返回
     if total_tokens <= max_tokens {
-        println!("[request_id:{}] token数已在限制内，无需裁切", request_id);
+        log_debug!(
+            "[request_id:{}] token数已在限制内，无需裁切",
+            "[request_id:{}] Token count already within the limit, no trimming needed",
+            request_id
+        );
         return output;
     }
 
@@ -732,7 +1016,12 @@ pub async fn trim_context_smart(
         }
     }
 
-    println!("[request_id:{}] 发现 {} 个对话对", request_id, pairs.len());
+    log_trace!(
+        "[request_id:{}] 发现 {} 个对话对",
+        "[request_id:{}] Found {} conversation pairs",
+        request_id,
+        pairs.len()
+    );
 
     // 标记需要保护的消息（不进行摘要）
     let mut protected = vec![false; n];
@@ -760,6 +1049,14 @@ pub async fn trim_context_smart(
         protected[n - 1] = true;
     }
 
+    // 4. 受保护消息所在的工具调用单元整体保护，避免调用参数与结果只被压缩一部分
+    let units = tool_call_units(messages);
+    expand_to_units(&mut protected, &units);
+    // 最后一轮（当前输入及本轮的工具调用与结果）的起点，极限压缩时同样跳过
+    let (_, turns) = conversation_turns(messages);
+    let tail_start = turns.last().map_or(n - 1, |&(start, _)| start.min(n - 1));
+    protected[tail_start..].iter_mut().for_each(|p| *p = true);
+
     // 计算需要摘要的消息，使用改进的重要性评分
     let mut messages_to_summarize = Vec::new();
     let mut protected_tokens = 0usize;
@@ -767,6 +1064,9 @@ pub async fn trim_context_smart(
     for (idx, &is_protected) in protected.iter().enumerate() {
         if is_protected {
             protected_tokens += token_cache[idx];
+        } else if messages[idx].content.is_empty() {
+            // 仅含 tool_calls 的 assistant 消息没有可摘要的文本
+            continue;
         } else {
             let importance_score = calculate_message_importance(&messages[idx], idx, n, &pairs);
             let content_length = messages[idx].content.len();
@@ -783,8 +1083,9 @@ pub async fn trim_context_smart(
         }
     }
 
-    println!(
+    log_trace!(
         "[request_id:{}] 保护消息token: {}, 需摘要消息: {}",
+        "[request_id:{}] Protected message tokens: {}, messages to summarize: {}",
         request_id,
         protected_tokens,
         messages_to_summarize.len()
@@ -811,6 +1112,7 @@ pub async fn trim_context_smart(
             api_endpoints,
             api_headers,
             summary_api_endpoints,
+            summary_api_key,
             summary_api_max_tokens,
             summary_api_temperature,
             summary_api_timeout_seconds,
@@ -823,21 +1125,27 @@ pub async fn trim_context_smart(
         for (idx, summarized_content) in summary_results {
             if !protected[idx] {
                 output[idx].content = summarized_content;
-                token_cache[idx] = estimate_tokens(&output[idx].content) + per_message_overhead;
+                token_cache[idx] = message_tokens(&output[idx], counter) + per_message_overhead;
             }
         }
     }
 
     // 重新计算总token数
     let current_tokens: usize = token_cache.iter().sum();
-    println!(
+    log_trace!(
         "[request_id:{}] 摘要后总token: {}",
-        request_id, current_tokens
+        "[request_id:{}] Total tokens after summarizing: {}",
+        request_id,
+        current_tokens
     );
 
     // 如果仍然超限，进行渐进式压缩
     if current_tokens > max_tokens {
-        println!("[request_id:{}] 仍超限，进行渐进式压缩", request_id);
+        log_debug!(
+            "[request_id:{}] 仍超限，进行渐进式压缩",
+            "[request_id:{}] Still over the limit, compressing progressively",
+            request_id
+        );
 
         // 按时间顺序（从早到晚）对未保护的消息进行更激进的压缩
         let mut remaining_tokens = current_tokens;
@@ -860,7 +1168,7 @@ pub async fn trim_context_smart(
             );
 
             output[idx].content = summarize_content(&output[idx].content, target_chars);
-            let new_tokens = estimate_tokens(&output[idx].content) + per_message_overhead;
+            let new_tokens = message_tokens(&output[idx], counter) + per_message_overhead;
 
             reduced_tokens += original_tokens.saturating_sub(new_tokens);
             token_cache[idx] = new_tokens;
@@ -876,11 +1184,18 @@ pub async fn trim_context_smart(
     // 最终检查：如果还是超限，对所有非关键消息进行极限压缩
     let final_tokens: usize = token_cache.iter().sum();
     if final_tokens > max_tokens {
-        println!("[request_id:{}] 执行极限压缩", request_id);
+        log_debug!(
+            "[request_id:{}] 执行极限压缩",
+            "[request_id:{}] Applying extreme compression",
+            request_id
+        );
 
         for idx in 0..n {
-            // 保护最后一条消息和所有 system 消息
-            if idx == n - 1 || messages[idx].role.eq_ignore_ascii_case("system") {
+            // 保护最后一轮和所有 system/prompt 消息
+            if idx >= tail_start
+                || messages[idx].role.eq_ignore_ascii_case("system")
+                || messages[idx].role.eq_ignore_ascii_case("prompt")
+            {
                 continue;
             }
 
@@ -891,7 +1206,7 @@ pub async fn trim_context_smart(
                 5
             };
             output[idx].content = summarize_content(&output[idx].content, min_chars);
-            token_cache[idx] = estimate_tokens(&output[idx].content) + per_message_overhead;
+            token_cache[idx] = message_tokens(&output[idx], counter) + per_message_overhead;
 
             let current_total: usize = token_cache.iter().sum();
             if current_total <= max_tokens {
@@ -900,9 +1215,10 @@ pub async fn trim_context_smart(
         }
     }
 
-    let final_total_tokens = calculate_total_tokens(&output);
-    println!(
+    let final_total_tokens = calculate_total_tokens(&output, counter);
+    log_debug!(
         "[request_id:{}] 智能裁切完成 - 消息数: {}, 最终token: {}, 压缩率: {:.1}%",
+        "[request_id:{}] Smart trimming done - messages: {}, final tokens: {}, compression: {:.1}%",
         request_id,
         output.len(),
         final_total_tokens,
@@ -0,0 +1,191 @@
+use crate::tr;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// 后台运行时标准输出与标准错误默认重定向到的文件
+const DEFAULT_LOG_FILE: &str = "llm_api.log";
+
+/// 后台运行相关的命令行参数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonOptions {
+    // 是否转入后台运行（--daemon）
+    pub daemon: bool,
+    // 写入进程号的文件（--pidfile），不使用 --daemon 时同样生效
+    pub pidfile: Option<PathBuf>,
+    // 后台运行时标准输出与标准错误的重定向目标（--log-file）
+    pub log_file: Option<PathBuf>,
+}
+
+impl DaemonOptions {
+    pub fn log_file(&self) -> PathBuf {
+        self.log_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LOG_FILE))
+    }
+}
+
+fn take_value(args: &mut Vec<String>, i: usize, name: &str) -> Result<PathBuf, String> {
+    if i + 1 >= args.len() {
+        return Err(tr!(
+            "参数 {} 缺少文件路径",
+            "Argument {} is missing a file path",
+            name
+        ));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(PathBuf::from(value))
+}
+
+/// 从命令行参数中取出 `--daemon`、`--pidfile <路径>` 与 `--log-file <路径>`
+/// （也可写为 `--pidfile=<路径>`），其余参数保持原顺序
+pub fn take_daemon_args(args: &mut Vec<String>) -> Result<DaemonOptions, String> {
+    let mut options = DaemonOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--daemon" => {
+                options.daemon = true;
+                args.remove(i);
+            }
+            "--pidfile" => options.pidfile = Some(take_value(args, i, "--pidfile")?),
+            "--log-file" => options.log_file = Some(take_value(args, i, "--log-file")?),
+            arg => {
+                if let Some(path) = arg.strip_prefix("--pidfile=") {
+                    options.pidfile = Some(PathBuf::from(path));
+                    args.remove(i);
+                } else if let Some(path) = arg.strip_prefix("--log-file=") {
+                    options.log_file = Some(PathBuf::from(path));
+                    args.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+// 无法可靠判断时视为已退出，由用户自行确认不会重复启动
+#[cfg(not(target_os = "linux"))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
+
+/// 检查进程号文件：其中记录的进程仍在运行时返回错误，文件不存在或进程已退出时通过
+pub fn check_pidfile(path: &Path) -> Result<(), String> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Ok(());
+    };
+    match contents.trim().parse::<u32>() {
+        Ok(pid) if pid != std::process::id() && process_running(pid) => Err(tr!(
+            "进程号文件 {} 记录的进程 {} 仍在运行",
+            "Pidfile {} records process {}, which is still running",
+            path.display(),
+            pid
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 进程号文件，创建时写入当前进程号，离开作用域（正常退出）时删除
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        check_pidfile(path)?;
+        std::fs::write(path, format!("{}\n", std::process::id())).map_err(|e| {
+            tr!(
+                "写入进程号文件 {} 失败: {}",
+                "Failed to write pidfile {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // 只删除仍属于本进程的文件
+        let ours = std::fs::read_to_string(&self.path)
+            .is_ok_and(|contents| contents.trim() == std::process::id().to_string());
+        if ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// 以相同的参数（去掉 --daemon）在后台重新启动本程序：标准输入接到 /dev/null，
+/// 标准输出与标准错误追加写入日志文件，并脱离当前终端的进程组。返回后台进程的进程号
+pub fn spawn_background(args: &[String], options: &DaemonOptions) -> Result<u32, String> {
+    if cfg!(not(unix)) {
+        return Err(tr!(
+            "当前平台不支持 --daemon，请使用系统服务管理器",
+            "--daemon is not supported on this platform, use a service manager instead"
+        ));
+    }
+    if let Some(pidfile) = &options.pidfile {
+        check_pidfile(pidfile)?;
+    }
+    let log_path = options.log_file();
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| {
+            tr!(
+                "打开日志文件 {} 失败: {}",
+                "Failed to open log file {}: {}",
+                log_path.display(),
+                e
+            )
+        })?;
+    let stderr = log.try_clone().map_err(|e| {
+        tr!(
+            "打开日志文件 {} 失败: {}",
+            "Failed to open log file {}: {}",
+            log_path.display(),
+            e
+        )
+    })?;
+    let exe = std::env::current_exe().map_err(|e| {
+        tr!(
+            "无法获取程序路径: {}",
+            "Failed to get the executable path: {}",
+            e
+        )
+    })?;
+
+    let mut command = Command::new(exe);
+    command
+        .args(args.iter().filter(|arg| arg.as_str() != "--daemon"))
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(stderr);
+    #[cfg(unix)]
+    {
+        // 独立的进程组：终端的 Ctrl-C 与挂断不会传到后台进程
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    command.spawn().map(|child| child.id()).map_err(|e| {
+        tr!(
+            "启动后台进程失败: {}",
+            "Failed to start the background process: {}",
+            e
+        )
+    })
+}
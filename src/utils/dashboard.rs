@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// 内置仪表盘页面（静态 HTML，数据由页面定时请求管理接口获取）
+pub const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DashboardConfig {
+    // 是否提供 /dashboard 页面与 /admin/requests/recent 接口
+    pub enabled: bool,
+    // 内存中保留的最近请求条数，0 表示不记录
+    pub recent_requests: usize,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            recent_requests: 100,
+        }
+    }
+}
+
+/// 最近一次请求的摘要（不含问题与回答内容）
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentRequest {
+    // 请求完成时间（Unix 秒）
+    pub timestamp: i64,
+    pub request_id: String,
+    pub model: String,
+    // memory_hit / db_hit / miss / bypass，请求在查询缓存之前被拒绝时为空
+    pub cache_status: Option<&'static str>,
+    pub endpoint: Option<String>,
+    pub latency_ms: u64,
+    pub status_code: u16,
+}
+
+// 最近的请求，最新的在队尾
+static RECENT_REQUESTS: Mutex<VecDeque<RecentRequest>> = Mutex::new(VecDeque::new());
+
+/// 记录一次请求，超出 capacity 时丢弃最旧的记录
+pub fn record_request(request: RecentRequest, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+    let Ok(mut recent) = RECENT_REQUESTS.lock() else {
+        return;
+    };
+    while recent.len() >= capacity {
+        recent.pop_front();
+    }
+    recent.push_back(request);
+}
+
+/// 最近的 limit 条请求，最新的在前
+pub fn recent_requests(limit: usize) -> Vec<RecentRequest> {
+    RECENT_REQUESTS
+        .lock()
+        .map(|recent| recent.iter().rev().take(limit).cloned().collect())
+        .unwrap_or_default()
+}
@@ -1,5 +1,6 @@
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteSynchronous};
 use sqlx::{Executor, SqlitePool};
+use crate::{log_error, log_info, log_warn};
 use crate::utils::config::DatabaseConfig;
 
 // 初始化数据库和表结构
@@ -12,7 +13,24 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
             size INTEGER NOT NULL,
             hit_count INTEGER NOT NULL DEFAULT 0,
             version INTEGER NOT NULL DEFAULT 0,
-            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            last_hit_at INTEGER,
+            epoch INTEGER NOT NULL DEFAULT 0
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    // 旧版本数据库的答案表缺少最近命中时间列
+    add_column_if_missing(pool, "answers", "last_hit_at", "INTEGER").await?;
+    // 旧版本数据库的答案表缺少全局缓存纪元列
+    add_column_if_missing(pool, "answers", "epoch", "INTEGER NOT NULL DEFAULT 0").await?;
+
+    // 创建缓存元数据表（如全局缓存纪元）
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS cache_meta (
+            key TEXT PRIMARY KEY,
+            value INTEGER NOT NULL
         )",
     )
     .execute(pool)
@@ -30,6 +48,67 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await?;
 
+    // 创建缓存键来源表（cache.store_key_source），供缓存键策略变更后重新计算问题键
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS question_sources (
+            key TEXT PRIMARY KEY,
+            source BLOB NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    // 创建请求审计表
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            request_id TEXT NOT NULL,
+            model TEXT NOT NULL,
+            key_hash TEXT,
+            cache_status TEXT,
+            endpoint TEXT,
+            latency_ms INTEGER NOT NULL,
+            status_code INTEGER NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    // 创建 A/B 对比结果表
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ab_results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            request_id TEXT NOT NULL,
+            arm TEXT NOT NULL,
+            endpoint TEXT NOT NULL,
+            latency_ms INTEGER NOT NULL,
+            response_chars INTEGER NOT NULL,
+            success INTEGER NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    // 创建缓存维护记录表
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS maintenance_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            started_at INTEGER NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            trigger TEXT NOT NULL,
+            deleted_answers INTEGER NOT NULL,
+            deleted_questions INTEGER NOT NULL,
+            deleted_audit_records INTEGER NOT NULL,
+            reclaimed_bytes INTEGER NOT NULL,
+            errors TEXT
+        )",
+    )
+    .execute(pool)
+    .await?;
+
     // 创建索引以提高查询速度
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_answers_key ON answers(key)")
         .execute(pool)
@@ -47,6 +126,18 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await?;
 
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ab_results_arm ON ab_results(arm)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ab_results_created_at ON ab_results(created_at)")
+        .execute(pool)
+        .await?;
+
     // 如果存在旧的cache表，迁移数据到新表
     let exists_cache = sqlx::query_scalar::<_, i32>(
         "SELECT 1 FROM sqlite_master WHERE type='table' AND name='cache'",
@@ -55,7 +146,10 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     .await?;
 
     if exists_cache.is_some() {
-        println!("检测到旧的cache表，开始数据迁移...");
+        log_info!(
+            "检测到旧的cache表，开始数据迁移...",
+            "Found the legacy cache table, migrating data..."
+        );
 
         // 从cache表中复制数据到answers表和questions表
         sqlx::query(
@@ -72,53 +166,139 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await?;
 
-        println!("数据迁移完成");
+        log_info!("数据迁移完成", "Data migration finished");
 
         // 重命名旧表而不是删除，以保留数据
-        println!("重命名旧的cache表为cache_backup...");
+        log_info!(
+            "重命名旧的cache表为cache_backup...",
+            "Renaming the legacy cache table to cache_backup..."
+        );
         sqlx::query("ALTER TABLE cache RENAME TO cache_backup")
             .execute(pool)
             .await?;
-        println!("旧表已重命名为cache_backup");
+        log_info!("旧表已重命名为cache_backup", "Legacy table renamed to cache_backup");
     }
 
     Ok(())
 }
 
-pub async fn optimize_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    // 数据库优化参数
+// 表中不存在指定列时添加该列
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query_scalar::<_, i32>(
+        "SELECT 1 FROM pragma_table_info(?) WHERE name = ?",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+
+    if exists.is_none() {
+        log_info!("为表 {} 添加列 {}", "Adding column {1} to table {0}", table, column);
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn optimize_db(pool: &SqlitePool, config: &DatabaseConfig) -> Result<(), sqlx::Error> {
+    // 数据库优化参数，内存与同步相关的参数来自配置
     let pragmas = [
-        "PRAGMA journal_mode=WAL;",
-        "PRAGMA wal_autocheckpoint=1000;", // 增加检查点间隔以提高写入性能
-        "PRAGMA wal_checkpoint(PASSIVE);", // 使用被动检查点避免阻塞
-        "PRAGMA read_uncommitted=true;",
-        "PRAGMA synchronous=NORMAL;",
-        "PRAGMA cache_size=20000;", // 增加缓存大小
-        "PRAGMA temp_store=MEMORY;",
-        "PRAGMA mmap_size=30000000000;",
-        "PRAGMA page_size=4096;",    // 使用更高效的页大小
-        "PRAGMA busy_timeout=5000;", // 设置忙等待超时
-        "PRAGMA foreign_keys=OFF;",  // 关闭外键约束检查以提高性能
+        "PRAGMA journal_mode=WAL;".to_string(),
+        format!("PRAGMA wal_autocheckpoint={};", config.wal_autocheckpoint), // 增加检查点间隔以提高写入性能
+        "PRAGMA wal_checkpoint(PASSIVE);".to_string(), // 使用被动检查点避免阻塞
+        "PRAGMA read_uncommitted=true;".to_string(),
+        // synchronous、cache_size、mmap_size 等按连接生效的参数已在 create_db_pool 中设置到每个连接
+        "PRAGMA page_size=4096;".to_string(), // 使用更高效的页大小
     ];
 
     for pragma in pragmas.iter() {
-        match pool.execute(*pragma).await {
+        match pool.execute(pragma.as_str()).await {
             Ok(_) => {}
             Err(e) => {
-                eprintln!("设置SQLite参数失败 ({}): {}", pragma, e);
+                log_error!(
+                    "设置SQLite参数失败 ({}): {}",
+                    "Failed to set SQLite parameter ({}): {}",
+                    pragma,
+                    e
+                );
             }
         }
     }
 
     // 运行一次VACUUM来整理数据库
-    match pool.execute("VACUUM;").await {
-        Ok(_) => println!("数据库VACUUM成功"),
-        Err(e) => eprintln!("数据库VACUUM失败: {}", e),
+    if config.vacuum_on_startup {
+        vacuum_if_needed(pool, config.vacuum_min_free_ratio).await;
+    } else {
+        log_info!("已跳过启动时的数据库VACUUM", "Skipped database VACUUM at startup");
     }
 
     Ok(())
 }
 
+// 空闲页占数据库总页数的比例
+async fn free_page_ratio(pool: &SqlitePool) -> Result<f64, sqlx::Error> {
+    let page_count = sqlx::query_scalar::<_, i64>("PRAGMA page_count")
+        .fetch_one(pool)
+        .await?;
+    let freelist_count = sqlx::query_scalar::<_, i64>("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await?;
+    if page_count == 0 {
+        return Ok(0.0);
+    }
+    Ok(freelist_count as f64 / page_count as f64)
+}
+
+/// 空闲页占比达到 min_free_ratio 时执行 VACUUM 整理数据库
+pub async fn vacuum_if_needed(pool: &SqlitePool, min_free_ratio: f64) {
+    if min_free_ratio > 0.0 {
+        match free_page_ratio(pool).await {
+            Ok(ratio) if ratio < min_free_ratio => {
+                log_info!(
+                    "数据库空闲页占比 {:.1}% 低于阈值 {:.1}%，跳过VACUUM",
+                    "Database free page ratio {:.1}% is below the threshold {:.1}%, skipping VACUUM",
+                    ratio * 100.0,
+                    min_free_ratio * 100.0
+                );
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => log_error!(
+                "查询数据库空闲页失败: {}",
+                "Failed to query database free pages: {}",
+                e
+            ),
+        }
+    }
+
+    match pool.execute("VACUUM;").await {
+        Ok(_) => log_info!("数据库VACUUM成功", "Database VACUUM succeeded"),
+        Err(e) => log_error!("数据库VACUUM失败: {}", "Database VACUUM failed: {}", e),
+    }
+}
+
+// 解析配置中的同步级别，无法识别时使用 NORMAL
+fn synchronous_mode(config: &DatabaseConfig) -> SqliteSynchronous {
+    config.synchronous.parse().unwrap_or_else(|_| {
+        log_warn!(
+            "无效的 synchronous 配置: {}，使用 NORMAL",
+            "Invalid synchronous setting: {}, using NORMAL",
+            config.synchronous
+        );
+        SqliteSynchronous::Normal
+    })
+}
+
 // 创建数据库连接池
 pub async fn create_db_pool(database_url: &str, config: &DatabaseConfig) -> Result<SqlitePool, sqlx::Error> {
     SqlitePoolOptions::new()
@@ -132,7 +312,11 @@ pub async fn create_db_pool(database_url: &str, config: &DatabaseConfig) -> Resu
                 .create_if_missing(true)
                 .foreign_keys(false) // 禁用外键约束检查以提高性能
                 .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal) // 使用WAL模式
-                .synchronous(sqlx::sqlite::SqliteSynchronous::Normal), // 降低同步级别
+                .synchronous(synchronous_mode(config)) // 降低同步级别
+                .busy_timeout(std::time::Duration::from_secs(5)) // 设置忙等待超时
+                .pragma("cache_size", config.cache_size.to_string()) // 增加缓存大小
+                .pragma("mmap_size", config.mmap_size.to_string())
+                .pragma("temp_store", "MEMORY"),
         )
         .await
 }
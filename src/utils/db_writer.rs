@@ -1,153 +1,522 @@
-use sha2::{Digest, Sha256};
+use crate::{log_debug, log_error, log_info, log_warn};
+use crate::utils::answer_codec::{answer_key, answer_stamp};
+use crate::utils::config::DatabaseConfig;
+use crate::utils::encryption::encrypt_blob;
+use crate::utils::live_events::{self, LiveEvent};
+use crate::utils::redis_cache::redis_cache;
+use crate::utils::webhook;
+use rand::Rng;
+use serde::Serialize;
 use sqlx::SqlitePool;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
 
-/// 数据库写入工具，用于将缓存数据写入到数据库
-pub struct DbWriter {
-    db: Arc<SqlitePool>,
-    cache_version: u8,
+// 写入队列容量，队列满时写入方等待
+const WRITE_QUEUE_SIZE: usize = 4096;
+// 单个事务中合并的命中计数更新的最大条数
+const MAX_HIT_BATCH: usize = 512;
+
+/// 数据库忙（SQLITE_BUSY / SQLITE_LOCKED）时的重试策略
+struct BusyRetryPolicy {
+    max_retries: u32,
+    base_delay_ms: u64,
 }
 
-impl DbWriter {
-    /// 创建新的数据库写入工具
-    pub fn new(db: Arc<SqlitePool>, cache_version: u8) -> Self {
-        Self { db, cache_version }
+static RETRY_POLICY: OnceLock<BusyRetryPolicy> = OnceLock::new();
+// 唯一写入任务的命令队列，未初始化时各调用方直接写入数据库
+static WRITER: OnceLock<WriterHandle> = OnceLock::new();
+// 因数据库忙而重试的次数
+static BUSY_RETRIES: AtomicU64 = AtomicU64::new(0);
+// 重试耗尽后仍因数据库忙而失败的写入次数
+static BUSY_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+struct WriterHandle {
+    // 写入任务所写的数据库文件，写入其他数据库的调用方直接写入
+    filename: PathBuf,
+    tx: mpsc::Sender<WriteCommand>,
+}
+
+/// 按配置设置数据库忙时的写入重试策略，并启动唯一的数据库写入任务。
+/// SQLite 同一时间只允许一个写入者，缓存写入、命中计数以及审计、A/B 结果、缓存键来源、
+/// 缓存纪元与缓存维护等写操作都经由该任务串行执行，避免多个任务争抢写锁
+pub fn init_db_writer(db: Arc<SqlitePool>, config: &DatabaseConfig) {
+    let _ = RETRY_POLICY.set(BusyRetryPolicy {
+        max_retries: config.busy_retries,
+        base_delay_ms: config.busy_retry_base_ms,
+    });
+
+    let (tx, rx) = mpsc::channel(WRITE_QUEUE_SIZE);
+    let handle = WriterHandle {
+        filename: db.connect_options().get_filename().to_path_buf(),
+        tx,
+    };
+    if WRITER.set(handle).is_ok() {
+        tokio::spawn(run_writer(db, rx));
+        log_info!("数据库写入任务已启动", "Database writer task started");
     }
+}
 
-    /// 批量写入数据到数据库
-    pub async fn batch_write(&self, items: Vec<(String, Vec<u8>)>) -> (usize, usize) {
-        let items_len = items.len();
-        if items_len == 0 {
-            return (0, 0);
+// 写入同一个数据库时返回写入任务的命令队列
+fn writer_for(db: &SqlitePool) -> Option<&'static mpsc::Sender<WriteCommand>> {
+    WRITER
+        .get()
+        .filter(|writer| writer.filename == db.connect_options().get_filename())
+        .map(|writer| &writer.tx)
+}
+
+fn retry_policy() -> &'static BusyRetryPolicy {
+    RETRY_POLICY.get_or_init(|| {
+        let config = DatabaseConfig::default();
+        BusyRetryPolicy {
+            max_retries: config.busy_retries,
+            base_delay_ms: config.busy_retry_base_ms,
         }
+    })
+}
+
+/// 数据库写入统计
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DbWriteStats {
+    pub busy_retries: u64,
+    pub busy_failures: u64,
+    // 写入队列中等待执行的命令数
+    pub queued: usize,
+}
 
-        println!("开始批量写入 {} 条缓存数据到数据库", items_len);
+pub fn db_write_stats() -> DbWriteStats {
+    DbWriteStats {
+        busy_retries: BUSY_RETRIES.load(Ordering::Relaxed),
+        busy_failures: BUSY_FAILURES.load(Ordering::Relaxed),
+        queued: WRITER
+            .get()
+            .map(|writer| writer.tx.max_capacity() - writer.tx.capacity())
+            .unwrap_or(0),
+    }
+}
 
-        // 使用事务进行批量写入
-        let tx_result = self.db.begin().await;
-        if let Err(e) = tx_result {
-            eprintln!("开始数据库事务失败: {}", e);
-            return (0, items_len);
-        }
+/// 需要增加命中计数的缓存项
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HitKey {
+    // 按答案 key 更新
+    Answer(String),
+    // 按问题 key 找到对应答案后更新（内存缓存命中时使用）
+    Question(String),
+}
 
-        let mut tx = tx_result.unwrap();
-        let mut success_count = 0;
-
-        for (question_key, compressed) in items {
-            let data_size = compressed.len() as i64;
-
-            // 计算答案的哈希作为key
-            let mut hasher = Sha256::new();
-            hasher.update(&compressed);
-            let answer_key = hex::encode(hasher.finalize());
-
-            // 1. 插入答案表
-            let answer_result = sqlx::query(
-                "INSERT OR IGNORE INTO answers (key, response, size, hit_count, version) 
-                 VALUES (?, ?, ?, 0, ?)",
-            )
-            .bind(&answer_key)
-            .bind(&compressed)
-            .bind(data_size)
-            .bind(self.cache_version)
-            .execute(&mut *tx)
-            .await;
-
-            if let Err(e) = answer_result {
-                eprintln!("批量写入: 插入答案记录失败: {}", e);
-                continue;
+// 在写入任务中执行的一次写操作，结果由其自行回传给调用方
+type WriteJob = Box<dyn FnOnce(SqlitePool) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+enum WriteCommand {
+    Run(WriteJob),
+    Hit(HitKey),
+}
+
+// 写入任务：按顺序执行队列中的命令，连续的命中计数更新合并到同一个事务中
+async fn run_writer(db: Arc<SqlitePool>, mut rx: mpsc::Receiver<WriteCommand>) {
+    let mut next = None;
+
+    loop {
+        let command = match next.take() {
+            Some(command) => command,
+            None => match rx.recv().await {
+                Some(command) => command,
+                None => break,
+            },
+        };
+
+        match command {
+            WriteCommand::Run(job) => job(SqlitePool::clone(&db)).await,
+            WriteCommand::Hit(hit) => {
+                let mut hits = HashMap::new();
+                *hits.entry(hit).or_insert(0) += 1;
+                let mut merged = 1;
+                while merged < MAX_HIT_BATCH {
+                    match rx.try_recv() {
+                        Ok(WriteCommand::Hit(hit)) => {
+                            *hits.entry(hit).or_insert(0) += 1;
+                            merged += 1;
+                        }
+                        Ok(other) => {
+                            next = Some(other);
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+
+                if let Err(e) = with_busy_retry(|| apply_hits(&db, &hits)).await {
+                    log_warn!(
+                        "更新缓存命中计数失败: {}",
+                        "Failed to update cache hit count: {}",
+                        e
+                    );
+                }
             }
+        }
+    }
+}
 
-            // 2. 插入问题表
-            let question_result = sqlx::query(
-                "INSERT OR REPLACE INTO questions (key, answer_key) 
-                 VALUES (?, ?)",
-            )
-            .bind(&question_key)
-            .bind(&answer_key)
-            .execute(&mut *tx)
-            .await;
-
-            if let Err(e) = question_result {
-                eprintln!("批量写入: 插入问题记录失败: {}", e);
-                continue;
+/// 在写入任务中执行一次写操作并返回其结果。写入任务未启动、写入的是其他数据库或写入任务已退出
+/// （如进程退出时）则在当前任务中直接执行。op 中不能再调用 run_write，否则写入任务会等待自身
+pub async fn run_write<T, F, Fut>(db: &SqlitePool, op: F) -> Result<T, sqlx::Error>
+where
+    T: Send + 'static,
+    F: FnOnce(SqlitePool) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T, sqlx::Error>> + Send + 'static,
+{
+    let (reply, result) = oneshot::channel();
+    let job: WriteJob = Box::new(move |db| {
+        Box::pin(async move {
+            let _ = reply.send(op(db).await);
+        })
+    });
+
+    // 先预留队列位置，写入任务已退出时作业仍在手中，可以直接执行
+    match writer_for(db) {
+        Some(writer) => match writer.reserve().await {
+            Ok(permit) => permit.send(WriteCommand::Run(job)),
+            Err(_) => job(db.clone()).await,
+        },
+        None => job(db.clone()).await,
+    }
+    result.await.unwrap_or(Err(sqlx::Error::WorkerCrashed))
+}
+
+/// 增加缓存命中次数并更新最近命中时间。写入任务已启动时交由其合并执行，否则直接更新
+pub async fn record_hit(db: &SqlitePool, hit: HitKey) {
+    if let Some(writer) = writer_for(db)
+        && let Ok(permit) = writer.reserve().await
+    {
+        permit.send(WriteCommand::Hit(hit));
+        return;
+    }
+
+    let hits = HashMap::from([(hit, 1)]);
+    if let Err(e) = with_busy_retry(|| apply_hits(db, &hits)).await {
+        log_warn!("更新缓存命中计数失败: {}", "Failed to update cache hit count: {}", e);
+    }
+}
+
+// 在同一个事务中按次数累加命中计数
+async fn apply_hits(db: &SqlitePool, hits: &HashMap<HitKey, i64>) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    for (hit, count) in hits {
+        let result = match hit {
+            HitKey::Answer(key) => {
+                sqlx::query(
+                    "UPDATE answers SET hit_count = hit_count + ?, last_hit_at = strftime('%s', 'now')
+                     WHERE key = ?",
+                )
+                .bind(count)
+                .bind(key)
+                .execute(&mut *tx)
+                .await
+            }
+            HitKey::Question(key) => {
+                sqlx::query(
+                    "UPDATE answers SET hit_count = hit_count + ?, last_hit_at = strftime('%s', 'now')
+                     WHERE key = (SELECT answer_key FROM questions WHERE key = ?)",
+                )
+                .bind(count)
+                .bind(key)
+                .execute(&mut *tx)
+                .await
             }
+        };
 
-            success_count += 1;
+        match result {
+            Err(e) if is_busy_error(&e) => return Err(e),
+            Err(e) => log_warn!(
+                "更新缓存命中计数失败: {}",
+                "Failed to update cache hit count: {}",
+                e
+            ),
+            Ok(_) => {}
         }
+    }
 
-        // 提交事务
-        if let Err(e) = tx.commit().await {
-            eprintln!("批量写入: 提交事务失败: {}", e);
-            return (success_count, items_len - success_count);
-        }
+    tx.commit().await
+}
 
-        println!("批量写入完成，成功: {}/{}", success_count, items_len);
-        (success_count, items_len - success_count)
-    }
+// 数据库被其他连接锁定（SQLITE_BUSY = 5 / SQLITE_LOCKED = 6，含扩展错误码）
+fn is_busy_error(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|db_err| db_err.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
 
-    /// 写入单个缓存项到数据库
-    pub async fn write_single(&self, question_key: String, compressed: Vec<u8>) -> bool {
-        let data_size = compressed.len() as i64;
+// 数据库忙时按指数退避加随机抖动重试整个事务
+async fn with_busy_retry<T, F, Fut>(mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let policy = retry_policy();
+    let mut attempt = 0;
 
-        // 计算答案的哈希作为key
-        let mut hasher = Sha256::new();
-        hasher.update(&compressed);
-        let answer_key = hex::encode(hasher.finalize());
+    loop {
+        match op().await {
+            Err(e) if is_busy_error(&e) => {
+                if attempt >= policy.max_retries {
+                    BUSY_FAILURES.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
+                }
+                attempt += 1;
+                BUSY_RETRIES.fetch_add(1, Ordering::Relaxed);
 
-        // 使用事务确保数据一致性
-        let tx_result = self.db.begin().await;
-        if let Err(e) = tx_result {
-            eprintln!("开始数据库事务失败: {}", e);
-            return false;
+                let backoff = policy.base_delay_ms.saturating_mul(1 << (attempt - 1).min(10));
+                let jitter = rand::rng().random_range(0..=policy.base_delay_ms);
+                let delay = backoff + jitter;
+                log_warn!(
+                    "数据库忙，{} 毫秒后重试写入 ({}/{}): {}",
+                    "Database busy, retrying the write in {} ms ({}/{}): {}",
+                    delay,
+                    attempt,
+                    policy.max_retries,
+                    e
+                );
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+            }
+            result => return result,
         }
+    }
+}
 
-        let mut tx = tx_result.unwrap();
+// 在同一个事务中写入缓存项。单条记录出错时跳过该条，数据库忙时整个事务回滚并返回错误
+async fn insert_items(db: &SqlitePool, items: &[PreparedItem]) -> Result<usize, sqlx::Error> {
+    // 使用事务进行批量写入
+    let mut tx = db.begin().await?;
+    let mut success_count = 0;
 
-        // 1. 插入或更新答案表
+    for item in items {
+        // 1. 插入答案表
         let answer_result = sqlx::query(
-            "INSERT OR IGNORE INTO answers (key, response, size, hit_count, version) 
-             VALUES (?, ?, ?, 0, ?)",
+            "INSERT OR IGNORE INTO answers (key, response, size, hit_count, version, epoch)
+             VALUES (?, ?, ?, 0, ?, ?)",
         )
-        .bind(&answer_key)
-        .bind(&compressed)
-        .bind(data_size)
-        .bind(self.cache_version)
+        .bind(&item.answer_key)
+        .bind(&item.stored)
+        .bind(item.size)
+        .bind(item.version)
+        .bind(item.epoch as i64)
         .execute(&mut *tx)
         .await;
 
-        if let Err(e) = answer_result {
-            eprintln!("插入答案记录失败: {}", e);
-            let _ = tx.rollback().await;
-            return false;
+        match answer_result {
+            Err(e) if is_busy_error(&e) => return Err(e),
+            Err(e) => {
+                log_error!("插入答案记录失败: {}", "Failed to insert answer: {}", e);
+                continue;
+            }
+            Ok(_) => {}
         }
 
-        // 2. 插入或更新问题表
+        // 2. 插入问题表
         let question_result = sqlx::query(
-            "INSERT OR REPLACE INTO questions (key, answer_key) 
+            "INSERT OR REPLACE INTO questions (key, answer_key)
              VALUES (?, ?)",
         )
-        .bind(&question_key)
-        .bind(&answer_key)
+        .bind(&item.question_key)
+        .bind(&item.answer_key)
         .execute(&mut *tx)
         .await;
 
-        if let Err(e) = question_result {
-            eprintln!("插入问题记录失败: {}", e);
-            let _ = tx.rollback().await;
-            return false;
+        match question_result {
+            Err(e) if is_busy_error(&e) => return Err(e),
+            Err(e) => {
+                log_error!("插入问题记录失败: {}", "Failed to insert question: {}", e);
+                continue;
+            }
+            Ok(_) => {}
+        }
+
+        success_count += 1;
+    }
+
+    // 提交事务
+    tx.commit().await?;
+    Ok(success_count)
+}
+
+// 待写入数据库的缓存项（已计算答案 key 并按需加密）
+struct PreparedItem {
+    question_key: String,
+    answer_key: String,
+    stored: Vec<u8>,
+    size: i64,
+    version: u8,
+    epoch: u64,
+}
+
+/// 数据库写入工具，用于将缓存数据写入到数据库（使用 Redis 缓存后端时写入 Redis）
+pub struct DbWriter {
+    db: Arc<SqlitePool>,
+    // 回答中没有记录缓存版本（旧格式）时使用的版本
+    cache_version: u8,
+}
+
+impl DbWriter {
+    /// 创建新的数据库写入工具
+    pub fn new(db: Arc<SqlitePool>, cache_version: u8) -> Self {
+        Self { db, cache_version }
+    }
+
+    /// 批量写入数据到数据库，返回（成功数, 失败数）
+    pub async fn batch_write(&self, items: Vec<(String, Vec<u8>)>) -> (usize, usize) {
+        let (success, failed) = self.batch_write_inner(items).await;
+        webhook::batch_write_result(failed);
+        if success + failed > 0 {
+            live_events::publish(LiveEvent::Flush {
+                written: success,
+                failed,
+            });
+        }
+        (success, failed)
+    }
+
+    async fn batch_write_inner(&self, items: Vec<(String, Vec<u8>)>) -> (usize, usize) {
+        let items_len = items.len();
+        if items_len == 0 {
+            return (0, 0);
         }
 
-        // 提交事务
-        if let Err(e) = tx.commit().await {
-            eprintln!("提交事务失败: {}", e);
-            return false;
+        // 使用 Redis 缓存后端时，整批在同一个事务中写入
+        if let Some(redis) = redis_cache() {
+            log_debug!(
+                "开始批量写入 {} 条缓存数据到 Redis",
+                "Writing a batch of {} cache entries to Redis",
+                items_len
+            );
+            return match redis.insert_batch(&items, self.cache_version).await {
+                Ok(()) => {
+                    log_debug!(
+                        "批量写入完成，成功: {}/{}",
+                        "Batch write finished, succeeded: {}/{}",
+                        items_len,
+                        items_len
+                    );
+                    (items_len, 0)
+                }
+                Err(e) => {
+                    log_error!("批量写入 Redis 失败: {}", "Batch write to Redis failed: {}", e);
+                    (0, items_len)
+                }
+            };
         }
 
-        println!(
-            "成功缓存响应 Size: {}, Answer Key: {}",
-            data_size, answer_key
+        log_debug!(
+            "开始批量写入 {} 条缓存数据到数据库",
+            "Writing a batch of {} cache entries to the database",
+            items_len
         );
-        true
+
+        let prepared: Vec<PreparedItem> = items
+            .into_iter()
+            .filter_map(|(question_key, compressed)| {
+                self.prepare(question_key, compressed)
+                    .map_err(|e| log_error!("批量写入: {}", "Batch write: {}", e))
+                    .ok()
+            })
+            .collect();
+
+        match self.insert(prepared).await {
+            Ok(success_count) => {
+                log_debug!(
+                    "批量写入完成，成功: {}/{}",
+                    "Batch write finished, succeeded: {}/{}",
+                    success_count,
+                    items_len
+                );
+                (success_count, items_len - success_count)
+            }
+            Err(e) => {
+                log_error!("批量写入失败: {}", "Batch write failed: {}", e);
+                (0, items_len)
+            }
+        }
+    }
+
+    // 按回答正文计算答案 key，启用缓存加密时转为密文，答案 key 仍按明文计算以便去重
+    fn prepare(&self, question_key: String, compressed: Vec<u8>) -> Result<PreparedItem, String> {
+        let size = compressed.len() as i64;
+        let answer_key = answer_key(&compressed);
+        let stamp = answer_stamp(&compressed);
+        let version = stamp.cache_version.unwrap_or(self.cache_version);
+        let epoch = stamp.cache_epoch;
+        let stored = encrypt_blob(compressed)?;
+
+        Ok(PreparedItem {
+            question_key,
+            answer_key,
+            stored,
+            size,
+            version,
+            epoch,
+        })
+    }
+
+    // 交由写入任务执行并等待结果
+    async fn insert(&self, items: Vec<PreparedItem>) -> Result<usize, sqlx::Error> {
+        run_write(&self.db, move |db| async move {
+            with_busy_retry(|| insert_items(&db, &items)).await
+        })
+        .await
+    }
+
+    /// 写入单个缓存项到数据库
+    pub async fn write_single(&self, question_key: String, compressed: Vec<u8>) -> bool {
+        let data_size = compressed.len() as i64;
+
+        if let Some(redis) = redis_cache() {
+            let items = [(question_key, compressed)];
+            return match redis.insert_batch(&items, self.cache_version).await {
+                Ok(()) => {
+                    log_debug!(
+                        "成功缓存响应到 Redis Size: {}",
+                        "Response cached to Redis, size: {}",
+                        data_size
+                    );
+                    true
+                }
+                Err(e) => {
+                    log_error!("写入 Redis 失败: {}", "Failed to write to Redis: {}", e);
+                    false
+                }
+            };
+        }
+
+        let item = match self.prepare(question_key, compressed) {
+            Ok(item) => item,
+            Err(e) => {
+                log_error!("{}", "{}", e);
+                return false;
+            }
+        };
+        let answer_key = item.answer_key.clone();
+
+        match self.insert(vec![item]).await {
+            Ok(1) => {
+                log_debug!(
+                    "成功缓存响应 Size: {}, Answer Key: {}",
+                    "Response cached, size: {}, answer key: {}",
+                    data_size,
+                    answer_key
+                );
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                log_error!("写入缓存失败: {}", "Failed to write cache entry: {}", e);
+                false
+            }
+        }
     }
 }
@@ -0,0 +1,266 @@
+use crate::{log_info, tr};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// 加密数据的前缀，用于区分加密数据与启用加密前写入的未加密数据
+const ENCRYPTED_MAGIC: &[u8] = b"LLMENC1";
+const NONCE_LEN: usize = 12;
+// SHA-256 的分组长度，HMAC 按该长度填充密钥
+const HMAC_BLOCK_LEN: usize = 64;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    // 保存密钥的环境变量，密钥为 64 位十六进制字符串（32 字节）
+    pub key_env: String,
+    // 保存密钥的文件路径，非空时优先于环境变量
+    pub key_file: String,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_env: "LLM_CACHE_ENCRYPTION_KEY".to_string(),
+            key_file: String::new(),
+        }
+    }
+}
+
+/// 缓存加密所用的密钥：AES-256-GCM 加密缓存数据，并由同一密钥派生回答去重键的 HMAC 密钥
+pub struct CacheCipher {
+    cipher: Aes256Gcm,
+    mac_key: [u8; 32],
+}
+
+impl CacheCipher {
+    pub fn new(key: &[u8]) -> Result<Self, String> {
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| {
+            tr!(
+                "加密密钥应为 32 字节，实际为 {} 字节",
+                "The encryption key must be 32 bytes, got {} bytes",
+                key.len()
+            )
+        })?;
+        // 去重键与加密使用不同的子密钥
+        let mac_key = hmac_sha256(key, &[b"llm-cache answer key"]);
+        Ok(Self { cipher, mac_key })
+    }
+
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, data).map_err(|_| {
+            tr!("加密缓存数据失败", "Failed to encrypt cache data")
+        })?;
+
+        let mut encrypted =
+            Vec::with_capacity(ENCRYPTED_MAGIC.len() + NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(ENCRYPTED_MAGIC);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(encrypted)
+    }
+
+    // payload 为去掉前缀后的 nonce + 密文
+    fn decrypt(&self, payload: &[u8]) -> Result<Vec<u8>, String> {
+        if payload.len() < NONCE_LEN {
+            return Err(tr!(
+                "加密缓存数据不完整",
+                "Encrypted cache data is truncated"
+            ));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                tr!(
+                    "解密缓存数据失败（密钥不匹配或数据已损坏）",
+                    "Failed to decrypt cache data (wrong key or corrupted data)"
+                )
+            })
+    }
+}
+
+static CIPHER: OnceLock<CacheCipher> = OnceLock::new();
+
+/// 启动时读取密钥并启用缓存加密，密钥缺失或无效时返回错误
+pub fn init_encryption(config: &EncryptionConfig) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let key_hex = if config.key_file.is_empty() {
+        std::env::var(&config.key_env).map_err(|_| {
+            tr!(
+                "未设置加密密钥环境变量 {}",
+                "The encryption key environment variable {} is not set",
+                config.key_env
+            )
+        })?
+    } else {
+        std::fs::read_to_string(&config.key_file).map_err(|e| {
+            tr!(
+                "读取加密密钥文件 {} 失败: {}",
+                "Failed to read the encryption key file {}: {}",
+                config.key_file,
+                e
+            )
+        })?
+    };
+    let key = hex::decode(key_hex.trim()).map_err(|e| {
+        tr!(
+            "加密密钥不是有效的十六进制: {}",
+            "The encryption key is not valid hex: {}",
+            e
+        )
+    })?;
+
+    let _ = CIPHER.set(CacheCipher::new(&key)?);
+    log_info!("已启用缓存加密 (AES-256-GCM)", "Cache encryption enabled (AES-256-GCM)");
+    Ok(())
+}
+
+/// 加密写入存储的缓存数据，未启用加密时原样返回
+pub fn encrypt_blob(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    match CIPHER.get() {
+        Some(cipher) => cipher.encrypt(&data),
+        None => Ok(data),
+    }
+}
+
+/// 解密从存储读取的缓存数据，未加密的数据原样返回
+pub fn decrypt_blob(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    decrypt_with(CIPHER.get(), data)
+}
+
+fn decrypt_with(cipher: Option<&CacheCipher>, data: Vec<u8>) -> Result<Vec<u8>, String> {
+    let Some(payload) = data.strip_prefix(ENCRYPTED_MAGIC) else {
+        return Ok(data);
+    };
+    let Some(cipher) = cipher else {
+        return Err(tr!(
+            "缓存数据已加密，但未启用缓存加密",
+            "Cache data is encrypted but cache encryption is not enabled"
+        ));
+    };
+    cipher.decrypt(payload)
+}
+
+/// 按顺序拼接各部分计算摘要。启用缓存加密时使用以密钥派生的 HMAC-SHA256，
+/// 使无密钥者无法通过对候选明文求哈希来确认缓存中是否存在某个回答；未启用时为普通 SHA-256
+pub fn content_digest(parts: &[&[u8]]) -> [u8; 32] {
+    digest_with(CIPHER.get(), parts)
+}
+
+fn digest_with(cipher: Option<&CacheCipher>, parts: &[&[u8]]) -> [u8; 32] {
+    match cipher {
+        Some(cipher) => hmac_sha256(&cipher.mac_key, parts),
+        None => {
+            let mut hasher = Sha256::new();
+            for part in parts {
+                hasher.update(part);
+            }
+            hasher.finalize().into()
+        }
+    }
+}
+
+// HMAC-SHA256（RFC 2104）
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK_LEN];
+    if key.len() > HMAC_BLOCK_LEN {
+        let hashed: [u8; 32] = Sha256::digest(key).into();
+        block[..hashed.len()].copy_from_slice(&hashed);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    for part in parts {
+        inner.update(part);
+    }
+    let inner: [u8; 32] = inner.finalize().into();
+
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner);
+    outer.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(byte: u8) -> CacheCipher {
+        CacheCipher::new(&[byte; 32]).unwrap()
+    }
+
+    #[test]
+    fn blobs_round_trip() {
+        let cipher = cipher(1);
+        let encrypted = cipher.encrypt(b"secret answer").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_MAGIC));
+        assert!(!encrypted.windows(6).any(|w| w == b"secret"));
+
+        let decrypted = decrypt_with(Some(&cipher), encrypted).unwrap();
+        assert_eq!(decrypted, b"secret answer");
+    }
+
+    #[test]
+    fn plaintext_written_before_encryption_is_returned_as_is() {
+        let legacy = b"written before encryption".to_vec();
+        assert_eq!(decrypt_with(Some(&cipher(1)), legacy.clone()).unwrap(), legacy);
+        assert_eq!(decrypt_with(None, legacy.clone()).unwrap(), legacy);
+    }
+
+    #[test]
+    fn wrong_or_missing_keys_are_rejected() {
+        let encrypted = cipher(1).encrypt(b"secret answer").unwrap();
+        assert!(decrypt_with(Some(&cipher(2)), encrypted.clone()).is_err());
+        assert!(decrypt_with(None, encrypted).is_err());
+    }
+
+    #[test]
+    fn truncated_ciphertext_is_rejected() {
+        let cipher = cipher(1);
+        let encrypted = cipher.encrypt(b"secret answer").unwrap();
+
+        let mut short_tag = encrypted.clone();
+        short_tag.pop();
+        assert!(decrypt_with(Some(&cipher), short_tag).is_err());
+
+        let short_nonce = encrypted[..ENCRYPTED_MAGIC.len() + NONCE_LEN - 1].to_vec();
+        assert!(decrypt_with(Some(&cipher), short_nonce).is_err());
+    }
+
+    #[test]
+    fn keys_must_be_32_bytes() {
+        assert!(CacheCipher::new(&[0u8; 16]).is_err());
+    }
+
+    #[test]
+    fn hmac_matches_the_rfc_4231_vector() {
+        let mac = hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]);
+        assert_eq!(
+            hex::encode(mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn digests_depend_on_the_key_when_encryption_is_enabled() {
+        let parts: [&[u8]; 2] = [b"same ", b"answer"];
+        let plain = digest_with(None, &parts);
+        assert_eq!(plain, <[u8; 32]>::from(Sha256::digest(b"same answer")));
+        assert_ne!(digest_with(Some(&cipher(1)), &parts), plain);
+        assert_ne!(
+            digest_with(Some(&cipher(1)), &parts),
+            digest_with(Some(&cipher(2)), &parts)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::utils::webhook;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Instant;
+
+// 每个端点保留的最近延迟样本数量，百分位按该窗口计算
+const LATENCY_WINDOW: usize = 1000;
+
+#[derive(Default)]
+struct EndpointCounters {
+    success: AtomicU64,
+    errors: AtomicU64,
+    // 未完成即被丢弃（如客户端断开）的请求，不计入成功或失败
+    cancelled: AtomicU64,
+    // 连续失败次数，成功一次即清零
+    consecutive_errors: AtomicU64,
+    in_flight: AtomicI64,
+    // 最近请求的耗时（毫秒）
+    latencies_ms: Mutex<VecDeque<u64>>,
+    // 最近一次错误信息及其发生时间（Unix 秒）
+    last_error: Mutex<Option<(i64, String)>>,
+}
+
+/// 单个端点的统计快照
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatsSnapshot {
+    pub url: String,
+    pub success: u64,
+    pub errors: u64,
+    pub cancelled: u64,
+    pub error_rate: f64,
+    pub consecutive_errors: u64,
+    pub in_flight: i64,
+    pub samples: usize,
+    pub latency_ms_avg: Option<f64>,
+    pub latency_ms_p50: Option<u64>,
+    pub latency_ms_p90: Option<u64>,
+    pub latency_ms_p99: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<i64>,
+}
+
+/// 按端点统计请求延迟、成功/失败次数与进行中的请求数
+#[derive(Default)]
+pub struct EndpointStats {
+    endpoints: DashMap<String, Arc<EndpointCounters>>,
+}
+
+/// 进行中的上游请求，结束时通过 `finish` 记录结果；未调用 `finish` 即被丢弃时记为取消
+pub struct RequestTracker {
+    url: String,
+    counters: Arc<EndpointCounters>,
+    started: Instant,
+    finished: bool,
+}
+
+impl EndpointStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn counters(&self, url: &str) -> Arc<EndpointCounters> {
+        self.endpoints.entry(url.to_string()).or_default().clone()
+    }
+
+    // 开始一次发往端点的请求
+    pub fn start(&self, url: &str) -> RequestTracker {
+        let counters = self.counters(url);
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestTracker {
+            url: url.to_string(),
+            counters,
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+
+    // 获取端点的统计快照，尚无请求的端点返回全零统计
+    pub fn snapshot(&self, url: &str) -> EndpointStatsSnapshot {
+        let counters = self
+            .endpoints
+            .get(url)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_default();
+
+        let success = counters.success.load(Ordering::Relaxed);
+        let errors = counters.errors.load(Ordering::Relaxed);
+        let total = success + errors;
+
+        let mut latencies: Vec<u64> = counters
+            .latencies_ms
+            .lock()
+            .map(|samples| samples.iter().copied().collect())
+            .unwrap_or_default();
+        latencies.sort_unstable();
+        let percentile = |p: f64| -> Option<u64> {
+            if latencies.is_empty() {
+                return None;
+            }
+            let rank = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len());
+            Some(latencies[rank - 1])
+        };
+        let latency_ms_avg = if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().sum::<u64>() as f64 / latencies.len() as f64)
+        };
+
+        let last_error = counters.last_error.lock().ok().and_then(|e| e.clone());
+
+        EndpointStatsSnapshot {
+            url: url.to_string(),
+            success,
+            errors,
+            cancelled: counters.cancelled.load(Ordering::Relaxed),
+            error_rate: if total == 0 {
+                0.0
+            } else {
+                errors as f64 / total as f64
+            },
+            consecutive_errors: counters.consecutive_errors.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            samples: latencies.len(),
+            latency_ms_avg,
+            latency_ms_p50: percentile(0.5),
+            latency_ms_p90: percentile(0.9),
+            latency_ms_p99: percentile(0.99),
+            last_error_at: last_error.as_ref().map(|(at, _)| *at),
+            last_error: last_error.map(|(_, message)| message),
+        }
+    }
+}
+
+impl RequestTracker {
+    // 记录请求结果与耗时，error 为 None 表示成功
+    pub fn finish(mut self, error: Option<&str>) {
+        self.record(error);
+    }
+
+    fn record(&mut self, error: Option<&str>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        let counters = &self.counters;
+        counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+        match error {
+            None => {
+                counters.success.fetch_add(1, Ordering::Relaxed);
+                let previous = counters.consecutive_errors.swap(0, Ordering::Relaxed);
+                webhook::endpoint_recovered(&self.url, previous);
+            }
+            Some(message) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                let consecutive = counters.consecutive_errors.fetch_add(1, Ordering::Relaxed) + 1;
+                let message: String = message.chars().take(200).collect();
+                webhook::endpoint_failed(&self.url, consecutive, &message);
+                if let Ok(mut last_error) = counters.last_error.lock() {
+                    *last_error = Some((chrono::Utc::now().timestamp(), message));
+                }
+            }
+        }
+
+        if let Ok(mut samples) = counters.latencies_ms.lock() {
+            if samples.len() >= LATENCY_WINDOW {
+                samples.pop_front();
+            }
+            samples.push_back(self.started.elapsed().as_millis() as u64);
+        }
+    }
+}
+
+impl Drop for RequestTracker {
+    fn drop(&mut self) {
+        // 请求被取消（如客户端断开）时只释放进行中计数，端点本身没有失败，
+        // 不影响错误率、连续失败次数与延迟样本
+        if !self.finished {
+            self.finished = true;
+            self.counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+            self.counters.cancelled.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// 用于展示的端点地址，去掉其中可能包含的认证信息
+pub fn endpoint_label(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) if !parsed.username().is_empty() || parsed.password().is_some() => {
+            let _ = parsed.set_username("");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
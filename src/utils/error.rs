@@ -0,0 +1,250 @@
+use crate::tr;
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+
+/// 转发给客户端的上游错误响应体的最大字节数，超出部分丢弃
+pub const MAX_UPSTREAM_ERROR_BODY: usize = 64 * 1024;
+
+/// 统一的服务错误类型，响应体采用 OpenAI 兼容格式：
+/// `{"error": {"message": "...", "type": "...", "code": "..."}}`
+#[derive(Debug, Clone)]
+pub enum AppError {
+    /// 客户端请求不合法（400）
+    BadRequest(String),
+    /// 请求缺少有效的访问令牌（401）
+    Unauthorized(String),
+    /// 请求未通过鉴权（403）
+    Forbidden(String),
+    /// 请求体超过大小上限（413）
+    PayloadTooLarge(String),
+    /// 服务暂不可用：没有可用端点等（503）
+    ServiceUnavailable(String),
+    /// 上游并发许可已耗尽，客户端应在 retry_after_secs 秒后重试（429）
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
+    /// 无法连接上游或上游请求失败（502）
+    BadGateway(String),
+    /// 上游请求或读取响应超时（504）
+    GatewayTimeout(String),
+    /// 上游响应体无法解析：严格模式下为 502，否则为 500
+    InvalidUpstreamResponse { message: String, strict: bool },
+    /// 上游返回了非成功状态码，原样保留状态码、响应体与 Retry-After
+    Upstream {
+        status: StatusCode,
+        body: String,
+        retry_after: Option<String>,
+    },
+    /// 数据库操作失败（500）
+    Database(String),
+    /// 其他内部错误（500）
+    Internal(String),
+}
+
+impl AppError {
+    /// 由上游的非成功响应构造错误，以便原样转发给客户端；
+    /// 响应体最多读取 MAX_UPSTREAM_ERROR_BODY 字节，且须在 read_timeout 内读完，超出部分丢弃
+    pub async fn from_upstream_response(
+        mut response: reqwest::Response,
+        read_timeout: Duration,
+    ) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let deadline = tokio::time::Instant::now() + read_timeout;
+        let mut body = Vec::new();
+        while body.len() < MAX_UPSTREAM_ERROR_BODY {
+            match tokio::time::timeout_at(deadline, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => body.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        body.truncate(MAX_UPSTREAM_ERROR_BODY);
+        let body = String::from_utf8_lossy(&body).into_owned();
+        AppError::Upstream {
+            status,
+            body,
+            retry_after,
+        }
+    }
+
+    /// 上游是否因上下文（输入 token）超长而拒绝了请求
+    pub fn is_context_overflow(&self) -> bool {
+        let AppError::Upstream { status, body, .. } = self else {
+            return false;
+        };
+        if !matches!(status.as_u16(), 400 | 413 | 422) {
+            return false;
+        }
+        let body = body.to_lowercase();
+        [
+            "context_length_exceeded",
+            "maximum context length",
+            "context length",
+            "context window",
+            "prompt is too long",
+            "too many tokens",
+        ]
+        .iter()
+        .any(|pattern| body.contains(pattern))
+    }
+
+    /// 上游返回了成功状态码，但响应体无法解析（如反向代理返回的 HTML 错误页）
+    pub fn is_invalid_upstream_response(&self) -> bool {
+        matches!(self, AppError::InvalidUpstreamResponse { .. })
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            AppError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::InvalidUpstreamResponse { strict: true, .. } => StatusCode::BAD_GATEWAY,
+            AppError::InvalidUpstreamResponse { strict: false, .. } => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Upstream { status, .. } => *status,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::PayloadTooLarge(message)
+            | AppError::ServiceUnavailable(message)
+            | AppError::TooManyRequests { message, .. }
+            | AppError::BadGateway(message)
+            | AppError::GatewayTimeout(message)
+            | AppError::InvalidUpstreamResponse { message, .. }
+            | AppError::Upstream { body: message, .. }
+            | AppError::Database(message)
+            | AppError::Internal(message) => message,
+        }
+    }
+
+    /// OpenAI 错误体中的 `type` 字段
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) | AppError::PayloadTooLarge(_) => "invalid_request_error",
+            AppError::Unauthorized(_) | AppError::Forbidden(_) => "authentication_error",
+            AppError::ServiceUnavailable(_) => "service_unavailable_error",
+            AppError::TooManyRequests { .. } => "rate_limit_error",
+            AppError::BadGateway(_)
+            | AppError::GatewayTimeout(_)
+            | AppError::InvalidUpstreamResponse { strict: true, .. } => "upstream_error",
+            AppError::Upstream { status, .. } => match status.as_u16() {
+                401 | 403 => "authentication_error",
+                429 => "rate_limit_error",
+                400..=499 => "invalid_request_error",
+                _ => "upstream_error",
+            },
+            AppError::Database(_)
+            | AppError::Internal(_)
+            | AppError::InvalidUpstreamResponse { strict: false, .. } => "server_error",
+        }
+    }
+
+    /// OpenAI 错误体中的 `code` 字段
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::ServiceUnavailable(_) => "service_unavailable",
+            AppError::TooManyRequests { .. } => "rate_limit_exceeded",
+            AppError::BadGateway(_) | AppError::InvalidUpstreamResponse { strict: true, .. } => {
+                "bad_gateway"
+            }
+            AppError::GatewayTimeout(_) => "gateway_timeout",
+            AppError::Upstream { .. } => "upstream_error",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) | AppError::InvalidUpstreamResponse { strict: false, .. } => {
+                "internal_error"
+            }
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.status(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Database(tr!("数据库查询错误: {}", "Database query error: {}", e))
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut response = match &self {
+            // 上游返回的 JSON 错误体原样透传，便于客户端按上游语义处理（如 429 限流）
+            AppError::Upstream { status, body, .. }
+                if serde_json::from_str::<serde_json::Value>(body).is_ok() =>
+            {
+                (
+                    *status,
+                    [(header::CONTENT_TYPE, "application/json")],
+                    body.clone(),
+                )
+                    .into_response()
+            }
+            _ => {
+                let body = json!({
+                    "error": {
+                        "message": self.message(),
+                        "type": self.error_type(),
+                        "code": self.code(),
+                    }
+                });
+                (self.status(), Json(body)).into_response()
+            }
+        };
+
+        if let AppError::Upstream {
+            retry_after: Some(value),
+            ..
+        } = &self
+            && let Ok(value) = HeaderValue::from_str(value)
+        {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        if let AppError::Unauthorized(_) = &self {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        if let AppError::TooManyRequests {
+            retry_after_secs, ..
+        } = &self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(*retry_after_secs));
+        }
+        response
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{log_error, log_info};
+use crate::utils::db_writer::DbWriter;
+use crate::utils::memory_cache::MemoryCache;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// 进程退出时将内存缓存与待写入队列写入数据库的保护器。
+/// 正常退出路径调用 `flush`；若因 panic 等原因提前析构，则在析构时尽力写入。
+pub struct PendingFlushGuard {
+    cache: Arc<MemoryCache>,
+    db: Arc<SqlitePool>,
+    cache_version: u8,
+    flushed: AtomicBool,
+}
+
+impl PendingFlushGuard {
+    pub fn new(cache: Arc<MemoryCache>, db: Arc<SqlitePool>, cache_version: u8) -> Self {
+        Self {
+            cache,
+            db,
+            cache_version,
+            flushed: AtomicBool::new(false),
+        }
+    }
+
+    // 将全部内存缓存项与待写入项写入数据库
+    pub async fn flush(&self) {
+        self.flushed.store(true, Ordering::SeqCst);
+        flush_all(&self.cache, self.db.clone(), self.cache_version).await;
+    }
+}
+
+async fn flush_all(cache: &MemoryCache, db: Arc<SqlitePool>, cache_version: u8) {
+    let items = cache.drain_all();
+    if items.is_empty() {
+        return;
+    }
+
+    log_info!(
+        "退出前刷新: 开始将 {} 个缓存项写入数据库",
+        "Flush on exit: writing {} cache entries to the database",
+        items.len()
+    );
+    let (success, failed) = DbWriter::new(db, cache_version).batch_write(items).await;
+    log_info!(
+        "退出前刷新: 写入完成，成功: {}，失败: {}",
+        "Flush on exit: write finished, succeeded: {}, failed: {}",
+        success,
+        failed
+    );
+}
+
+impl Drop for PendingFlushGuard {
+    fn drop(&mut self) {
+        if self.flushed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // 析构可能发生在异步运行时内部，因此在独立线程中创建临时运行时执行写入
+        let cache = self.cache.clone();
+        let db = self.db.clone();
+        let cache_version = self.cache_version;
+        let handle = std::thread::spawn(move || {
+            match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime.block_on(async {
+                    let flush = flush_all(&cache, db, cache_version);
+                    if tokio::time::timeout(Duration::from_secs(10), flush).await.is_err() {
+                        log_error!(
+                            "退出前刷新: 写入超时，放弃剩余缓存项",
+                            "Flush on exit: write timed out, abandoning the remaining entries"
+                        );
+                    }
+                }),
+                Err(e) => log_error!(
+                    "退出前刷新: 创建运行时失败: {}",
+                    "Flush on exit: failed to create runtime: {}",
+                    e
+                ),
+            }
+        });
+        if handle.join().is_err() {
+            log_error!(
+                "退出前刷新: 写入线程异常退出",
+                "Flush on exit: writer thread exited abnormally"
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::models::api_model::{ApiEndpoint, ChatRequestJson};
+use crate::utils::error::AppError;
+use crate::utils::plugin::{RequestContext, RequestPlugin};
+use crate::{log_debug, tr};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ForceModelConfig {
+    // 启用后忽略客户端指定的模型，始终使用端点配置的 model
+    pub enabled: bool,
+    // 客户端指定的模型不是任何端点配置的 model 时拒绝请求（400）
+    pub reject_other_models: bool,
+}
+
+/// 强制使用端点模型的请求插件：拒绝未配置的模型，所有端点使用同一模型时在路由前替换请求中的模型，
+/// 使缓存版本、按模型的注入规则与审计记录都以实际使用的模型为准
+pub struct ForceModelPlugin {
+    config: ForceModelConfig,
+    // 各端点配置的 model（去重，保持配置顺序）
+    models: Vec<String>,
+    // 所有端点都配置了同一个 model 时为该模型
+    single_model: Option<String>,
+}
+
+impl ForceModelPlugin {
+    pub fn new(config: ForceModelConfig, endpoints: &[ApiEndpoint]) -> Self {
+        let mut models: Vec<String> = Vec::new();
+        for model in endpoints.iter().filter_map(|ep| ep.model.as_ref()) {
+            if !models.contains(model) {
+                models.push(model.clone());
+            }
+        }
+        let single_model = match models.as_slice() {
+            [model] if endpoints.iter().all(|ep| ep.model.is_some()) => Some(model.clone()),
+            _ => None,
+        };
+        Self {
+            config,
+            models,
+            single_model,
+        }
+    }
+}
+
+impl RequestPlugin for ForceModelPlugin {
+    fn name(&self) -> &str {
+        "force_model"
+    }
+
+    fn pre_routing(
+        &self,
+        ctx: &RequestContext,
+        payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        if self.config.reject_other_models && !self.models.contains(&payload.model) {
+            return Err(AppError::BadRequest(tr!(
+                "模型 \"{}\" 不可用，可用的模型: {}",
+                "Model \"{}\" is not available, available models: {}",
+                payload.model,
+                self.models.join(", ")
+            )));
+        }
+        if let Some(model) = &self.single_model
+            && &payload.model != model
+        {
+            log_debug!(
+                "[{}] 忽略客户端指定的模型 {}，使用 {}",
+                "[{}] Ignoring the client model {}, using {}",
+                ctx.request_id,
+                payload.model,
+                model
+            );
+            payload.model = model.clone();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::models::api_model::ChatResponseJson;
+use crate::utils::config::ContextTrimConfig;
+use crate::utils::context_trim::TokenCounter;
+use crate::utils::error::AppError;
+use crate::utils::plugin::{ResponseContext, ResponsePlugin};
+use crate::{log_warn, tr};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuardrailsConfig {
+    // 单个回答的最大字符数，0 表示不限制
+    pub max_completion_chars: usize,
+    // 单个回答的最大 token 数（按请求模型对应的分词器计算），0 表示不限制
+    pub max_completion_tokens: usize,
+    // 超出上限时的处理方式：truncate（截断并追加标记）、reject（返回错误）
+    pub action: String,
+    // 截断后追加在回答末尾的标记
+    pub truncation_marker: String,
+}
+
+impl Default for GuardrailsConfig {
+    fn default() -> Self {
+        Self {
+            max_completion_chars: 0,
+            max_completion_tokens: 0,
+            action: "truncate".to_string(),
+            truncation_marker: "\n\n[回答超过长度上限，已截断]".to_string(),
+        }
+    }
+}
+
+impl GuardrailsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_completion_chars > 0 || self.max_completion_tokens > 0
+    }
+}
+
+// 回答的 token 数（计数结果包含 3 个 token 的消息格式开销，此处扣除）
+fn completion_tokens(counter: TokenCounter, text: &str) -> usize {
+    counter.count(text).saturating_sub(3)
+}
+
+// 在不超过字符与 token 上限的前提下保留的最大字符数，未超出时返回 None
+fn allowed_chars(text: &str, config: &GuardrailsConfig, counter: TokenCounter) -> Option<usize> {
+    let total_chars = text.chars().count();
+    let mut limit = total_chars;
+    if config.max_completion_chars > 0 {
+        limit = limit.min(config.max_completion_chars);
+    }
+
+    let prefix = |chars: usize| -> &str {
+        match text.char_indices().nth(chars) {
+            Some((index, _)) => &text[..index],
+            None => text,
+        }
+    };
+
+    if config.max_completion_tokens > 0
+        && completion_tokens(counter, prefix(limit)) > config.max_completion_tokens
+    {
+        // 二分查找 token 数不超过上限的最长前缀
+        let (mut low, mut high) = (0, limit);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if completion_tokens(counter, prefix(mid)) <= config.max_completion_tokens {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        limit = low;
+    }
+
+    (limit < total_chars).then_some(limit)
+}
+
+/// 对上游回答执行长度上限。超出时按配置截断（finish_reason 设置为 length）或返回错误，
+/// 返回是否进行了截断。
+pub fn enforce_completion_limit(
+    response: &mut ChatResponseJson,
+    config: &GuardrailsConfig,
+    counter: TokenCounter,
+    request_id: &str,
+) -> Result<bool, AppError> {
+    if !config.is_enabled() {
+        return Ok(false);
+    }
+
+    let mut truncated = false;
+    for choice in &mut response.choices {
+        let content = &choice.message.content;
+        let Some(keep) = allowed_chars(content, config, counter) else {
+            continue;
+        };
+
+        let total_chars = content.chars().count();
+        if config.action == "reject" {
+            log_warn!(
+                "[{}] 回答长度 {} 字符超过上限，拒绝返回",
+                "[{}] Answer length of {} characters exceeds the limit, rejecting",
+                request_id,
+                total_chars
+            );
+            return Err(AppError::BadGateway(tr!(
+                "上游回答超过长度上限（{} 字符）",
+                "The upstream answer exceeds the length limit ({} characters)",
+                total_chars
+            )));
+        }
+
+        log_warn!(
+            "[{}] 回答长度 {} 字符超过上限，截断为 {} 字符",
+            "[{}] Answer length of {} characters exceeds the limit, truncated to {} characters",
+            request_id,
+            total_chars,
+            keep
+        );
+        let mut content: String = content.chars().take(keep).collect();
+        content.push_str(&config.truncation_marker);
+        choice.message.content = content;
+        choice.finish_reason = "length".to_string();
+        truncated = true;
+    }
+
+    Ok(truncated)
+}
+
+/// 执行回答长度上限的响应插件，被截断的回答不写入缓存
+pub struct GuardrailsPlugin {
+    config: GuardrailsConfig,
+    // 用于按模型选择分词器
+    context_trim: ContextTrimConfig,
+}
+
+impl GuardrailsPlugin {
+    pub fn new(config: GuardrailsConfig, context_trim: ContextTrimConfig) -> Self {
+        Self {
+            config,
+            context_trim,
+        }
+    }
+}
+
+impl ResponsePlugin for GuardrailsPlugin {
+    fn name(&self) -> &str {
+        "guardrails"
+    }
+
+    fn pre_cache_store(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        let counter = TokenCounter::for_model(&self.context_trim, ctx.model);
+        let truncated = enforce_completion_limit(response, &self.config, counter, ctx.request_id)?;
+        Ok(!truncated)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::log_info;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HitStatsConfig {
+    // 统计窗口（分钟），命中率按最近这段时间计算
+    pub window_minutes: u64,
+    // 日志汇总间隔（分钟），0 表示不输出
+    pub report_interval_minutes: u64,
+}
+
+impl Default for HitStatsConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: 60,
+            report_interval_minutes: 5,
+        }
+    }
+}
+
+/// 单次请求的缓存结果
+#[derive(Debug, Clone, Copy)]
+pub enum CacheOutcome {
+    MemoryHit,
+    DbHit,
+    Miss,
+    // 未查询缓存（如流式请求）
+    Bypass,
+}
+
+impl CacheOutcome {
+    fn index(self) -> usize {
+        match self {
+            CacheOutcome::MemoryHit => 0,
+            CacheOutcome::DbHit => 1,
+            CacheOutcome::Miss => 2,
+            CacheOutcome::Bypass => 3,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheOutcome::MemoryHit => "memory_hit",
+            CacheOutcome::DbHit => "db_hit",
+            CacheOutcome::Miss => "miss",
+            CacheOutcome::Bypass => "bypass",
+        }
+    }
+
+    // 上报监控指标时使用的名称
+    pub fn metric_name(self) -> &'static str {
+        match self {
+            CacheOutcome::MemoryHit => "cache.memory_hit",
+            CacheOutcome::DbHit => "cache.db_hit",
+            CacheOutcome::Miss => "cache.miss",
+            CacheOutcome::Bypass => "cache.bypass",
+        }
+    }
+}
+
+/// 命中率统计快照
+#[derive(Debug, Clone, Serialize)]
+pub struct HitRateSnapshot {
+    pub window_minutes: u64,
+    pub memory_hits: u64,
+    pub db_hits: u64,
+    pub misses: u64,
+    pub bypass: u64,
+    // 命中数 / (命中数 + 未命中数)，不含未查询缓存的请求
+    pub hit_rate: f64,
+}
+
+impl HitRateSnapshot {
+    fn from_counts(window_minutes: u64, counts: [u64; 4]) -> Self {
+        let hits = counts[0] + counts[1];
+        let lookups = hits + counts[2];
+        Self {
+            window_minutes,
+            memory_hits: counts[0],
+            db_hits: counts[1],
+            misses: counts[2],
+            bypass: counts[3],
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+}
+
+/// 按分钟分桶的缓存命中统计，同时保留启动以来的累计值
+pub struct HitRateStats {
+    window_minutes: u64,
+    // (分钟时间戳, 各结果计数)
+    buckets: Mutex<VecDeque<(i64, [u64; 4])>>,
+    totals: [AtomicU64; 4],
+}
+
+impl HitRateStats {
+    pub fn new(window_minutes: u64) -> Self {
+        Self {
+            window_minutes: window_minutes.max(1),
+            buckets: Mutex::new(VecDeque::new()),
+            totals: Default::default(),
+        }
+    }
+
+    fn current_minute() -> i64 {
+        chrono::Utc::now().timestamp() / 60
+    }
+
+    // 丢弃超出统计窗口的分桶
+    fn prune(&self, buckets: &mut VecDeque<(i64, [u64; 4])>, now: i64) {
+        let oldest = now - self.window_minutes as i64 + 1;
+        while buckets.front().is_some_and(|(minute, _)| *minute < oldest) {
+            buckets.pop_front();
+        }
+    }
+
+    pub fn record(&self, outcome: CacheOutcome) {
+        self.totals[outcome.index()].fetch_add(1, Ordering::Relaxed);
+
+        let now = Self::current_minute();
+        if let Ok(mut buckets) = self.buckets.lock() {
+            match buckets.back_mut() {
+                Some((minute, counts)) if *minute == now => counts[outcome.index()] += 1,
+                _ => {
+                    let mut counts = [0u64; 4];
+                    counts[outcome.index()] = 1;
+                    buckets.push_back((now, counts));
+                }
+            }
+            self.prune(&mut buckets, now);
+        }
+    }
+
+    // 统计窗口内的命中情况
+    pub fn window(&self) -> HitRateSnapshot {
+        let mut counts = [0u64; 4];
+        if let Ok(mut buckets) = self.buckets.lock() {
+            self.prune(&mut buckets, Self::current_minute());
+            for (_, bucket) in buckets.iter() {
+                for (total, value) in counts.iter_mut().zip(bucket) {
+                    *total += value;
+                }
+            }
+        }
+        HitRateSnapshot::from_counts(self.window_minutes, counts)
+    }
+
+    // 启动以来的累计命中情况
+    pub fn lifetime(&self) -> HitRateSnapshot {
+        let counts = [0, 1, 2, 3].map(|i| self.totals[i].load(Ordering::Relaxed));
+        HitRateSnapshot::from_counts(0, counts)
+    }
+}
+
+// 启动命中率定期汇总任务
+pub fn start_hit_rate_report_task(stats: Arc<HitRateStats>, config: HitStatsConfig) {
+    if config.report_interval_minutes == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.report_interval_minutes * 60));
+        // 跳过启动时立即触发的第一次
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let window = stats.window();
+            log_info!(
+                "=== 缓存命中率（最近 {} 分钟）: {:.1}%，内存命中: {}，数据库命中: {}，未命中: {}，未查询缓存: {} ===",
+                "=== Cache hit rate (last {} minutes): {:.1}%, memory hits: {}, database hits: {}, misses: {}, not looked up: {} ===",
+                window.window_minutes,
+                window.hit_rate * 100.0,
+                window.memory_hits,
+                window.db_hits,
+                window.misses,
+                window.bypass
+            );
+        }
+    });
+}
@@ -1,23 +1,232 @@
 use reqwest;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
-use crate::utils::config::HttpClientConfig;
+use crate::tr;
+use crate::models::api_model::ApiEndpoint;
+use crate::utils::config::{HttpClientConfig, OutboundProxyConfig, TlsConfig};
 
-pub fn create_http_client(config: &HttpClientConfig) -> Result<reqwest::Client, reqwest::Error> {
+pub type ClientBuildError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 按协议族偏好排序解析结果的 DNS 解析器。hyper 以第一个地址的协议族作为首选，
+/// 另一族作为 happy eyeballs 的回退地址，因此排序即可决定优先连接 IPv4 还是 IPv6
+struct PreferredFamilyResolver {
+    prefer_ipv6: bool,
+}
+
+impl Resolve for PreferredFamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let prefer_ipv6 = self.prefer_ipv6;
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            // 端口由连接器按 URL 重新设置
+            let mut addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            addrs.sort_by_key(|addr| addr.is_ipv6() != prefer_ipv6);
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+// 根据 ip_preference 设置上游连接优先使用的协议族，auto 时沿用系统解析顺序
+pub fn apply_ip_preference(
+    builder: reqwest::ClientBuilder,
+    preference: &str,
+) -> reqwest::ClientBuilder {
+    let prefer_ipv6 = match preference.to_lowercase().as_str() {
+        "ipv4" => false,
+        "ipv6" => true,
+        _ => return builder,
+    };
+    builder.dns_resolver(Arc::new(PreferredFamilyResolver { prefer_ipv6 }))
+}
+
+// 根据出站代理配置（HTTP CONNECT 或 SOCKS5）设置客户端代理，未启用时禁用代理
+pub fn apply_outbound_proxy(
+    builder: reqwest::ClientBuilder,
+    config: &OutboundProxyConfig,
+) -> Result<reqwest::ClientBuilder, reqwest::Error> {
+    if !config.enabled || config.url.is_empty() {
+        return Ok(builder.no_proxy());
+    }
+
+    let mut proxy = reqwest::Proxy::all(&config.url)?;
+    if !config.username.is_empty() {
+        proxy = proxy.basic_auth(&config.username, &config.password);
+    }
+    if !config.no_proxy.is_empty() {
+        proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&config.no_proxy));
+    }
+
+    Ok(builder.proxy(proxy))
+}
+
+// 根据 TLS 配置设置证书校验策略、额外根证书与客户端证书（双向 TLS）
+pub fn apply_tls(
+    builder: reqwest::ClientBuilder,
+    config: &TlsConfig,
+) -> Result<reqwest::ClientBuilder, ClientBuildError> {
+    let mut builder = builder.danger_accept_invalid_certs(config.accept_invalid_certs);
+
+    if !config.ca_bundle_path.is_empty() {
+        let pem = std::fs::read(&config.ca_bundle_path).map_err(|e| {
+            tr!(
+                "读取CA证书失败 ({}): {}",
+                "Failed to read the CA bundle ({}): {}",
+                config.ca_bundle_path,
+                e
+            )
+        })?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if config.client_cert_path.is_empty() && config.client_key_path.is_empty() {
+        return Ok(builder);
+    }
+    if config.client_cert_path.is_empty() || config.client_key_path.is_empty() {
+        return Err(tr!(
+            "双向 TLS 需要同时配置 client_cert_path 和 client_key_path",
+            "Mutual TLS requires both client_cert_path and client_key_path"
+        )
+        .into());
+    }
+
+    let cert = std::fs::read(&config.client_cert_path).map_err(|e| {
+        tr!(
+            "读取客户端证书失败 ({}): {}",
+            "Failed to read the client certificate ({}): {}",
+            config.client_cert_path,
+            e
+        )
+    })?;
+    let key = std::fs::read(&config.client_key_path).map_err(|e| {
+        tr!(
+            "读取客户端私钥失败 ({}): {}",
+            "Failed to read the client private key ({}): {}",
+            config.client_key_path,
+            e
+        )
+    })?;
+    let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key)?;
+
+    Ok(builder.identity(identity))
+}
+
+// 应用出站代理与 TLS 等与连接相关的通用配置
+pub fn apply_connection_options(
+    builder: reqwest::ClientBuilder,
+    config: &HttpClientConfig,
+) -> Result<reqwest::ClientBuilder, ClientBuildError> {
+    let builder = apply_ip_preference(builder, &config.ip_preference);
+    let builder = apply_outbound_proxy(builder, &config.outbound_proxy)?;
+    apply_tls(builder, &config.tls)
+}
+
+pub fn create_http_client(config: &HttpClientConfig) -> Result<reqwest::Client, ClientBuildError> {
     // HTTP客户端配置
-    reqwest::Client::builder()
+    let builder = reqwest::Client::builder()
         .timeout(Duration::from_secs(config.timeout_seconds))
         .connect_timeout(Duration::from_secs(config.connect_timeout_seconds))
         .tcp_nodelay(true)
         .tcp_keepalive(Some(Duration::from_secs(config.tcp_keepalive_seconds)))
         .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_seconds)) // 空闲连接超时
         .pool_max_idle_per_host(config.pool_max_idle_per_host) // 每个主机最大空闲连接数
-        .danger_accept_invalid_certs(true)
         .redirect(reqwest::redirect::Policy::limited(config.max_redirects))
         .http1_title_case_headers()
         .http2_adaptive_window(true) // HTTP/2自适应窗口大小
         .http2_keep_alive_interval(Some(Duration::from_secs(config.http2_keep_alive_interval_seconds)))
         .http2_keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout_seconds))
-        .http2_initial_stream_window_size(config.http2_initial_stream_window_size as u32) // 1MB窗口大小
-        .no_proxy() // 禁用代理
-        .build()
+        .http2_initial_stream_window_size(config.http2_initial_stream_window_size as u32); // 1MB窗口大小
+    let builder = match config.http_version.to_lowercase().as_str() {
+        "http1" => builder.http1_only(),
+        "http2" => builder.http2_prior_knowledge(),
+        _ => builder,
+    };
+
+    Ok(apply_connection_options(builder, config)?.build()?)
+}
+
+// 端点独立客户端的配置：专用 HTTP 客户端参数与 TLS 配置分别覆盖全局配置
+fn endpoint_client_config(endpoint: &ApiEndpoint, config: &HttpClientConfig) -> HttpClientConfig {
+    let mut endpoint_config = endpoint.http_client.apply(config);
+    endpoint_config.tls = endpoint.effective_tls(&config.tls);
+    endpoint_config
+}
+
+// 为配置了专用 TLS 或 HTTP 客户端参数的端点创建独立客户端，按 ApiEndpoint::client_key 索引
+pub fn create_endpoint_clients(
+    endpoints: &[ApiEndpoint],
+    config: &HttpClientConfig,
+) -> Result<HashMap<String, reqwest::Client>, ClientBuildError> {
+    let mut clients = HashMap::new();
+
+    for endpoint in endpoints {
+        if endpoint.tls.is_some() || !endpoint.http_client.is_empty() {
+            let endpoint_config = endpoint_client_config(endpoint, config);
+            let client = create_http_client(&endpoint_config).map_err(|e| {
+                tr!(
+                    "创建端点 {} 的HTTP客户端失败: {}",
+                    "Failed to create the HTTP client for endpoint {}: {}",
+                    endpoint.url,
+                    e
+                )
+            })?;
+            clients.insert(endpoint.client_key(), client);
+        }
+    }
+
+    Ok(clients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn endpoint(tls: serde_json::Value) -> ApiEndpoint {
+        serde_json::from_value(json!({
+            "url": "https://upstream.internal",
+            "weight": 1,
+            "model": null,
+            "tls": tls,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn endpoint_identity_keeps_the_global_trust_settings() {
+        let mut config = HttpClientConfig::default();
+        config.tls.ca_bundle_path = "/etc/llm/ca.pem".to_string();
+        config.tls.accept_invalid_certs = true;
+
+        let endpoint = endpoint(json!({
+            "client_cert_path": "/etc/llm/client.pem",
+            "client_key_path": "/etc/llm/client.key",
+        }));
+        let tls = endpoint_client_config(&endpoint, &config).tls;
+        assert_eq!(tls.client_cert_path, "/etc/llm/client.pem");
+        assert_eq!(tls.client_key_path, "/etc/llm/client.key");
+        assert_eq!(tls.ca_bundle_path, "/etc/llm/ca.pem");
+        assert!(tls.accept_invalid_certs);
+    }
+
+    #[test]
+    fn endpoint_trust_settings_override_the_global_ones() {
+        let mut config = HttpClientConfig::default();
+        config.tls.ca_bundle_path = "/etc/llm/ca.pem".to_string();
+        config.tls.client_cert_path = "/etc/llm/global.pem".to_string();
+        config.tls.client_key_path = "/etc/llm/global.key".to_string();
+
+        let endpoint = endpoint(json!({ "ca_bundle_path": "/etc/llm/private-ca.pem" }));
+        let tls = endpoint_client_config(&endpoint, &config).tls;
+        assert_eq!(tls.ca_bundle_path, "/etc/llm/private-ca.pem");
+        assert_eq!(tls.client_cert_path, "/etc/llm/global.pem");
+        assert_eq!(tls.client_key_path, "/etc/llm/global.key");
+        assert!(!tls.accept_invalid_certs);
+    }
 }
@@ -4,6 +4,7 @@ use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::time;
 
+use crate::{log_info, log_warn};
 use crate::utils::db_writer::DbWriter;
 use crate::utils::memory_cache::MemoryCache;
 
@@ -63,13 +64,15 @@ impl IdleFlushManager {
 
     pub async fn start_flush_task(self: Arc<Self>) {
         if !self.config.enabled {
-            println!("空闲刷新功能已禁用");
+            log_info!("空闲刷新功能已禁用", "Idle flush disabled");
             return;
         }
 
-        println!(
+        log_info!(
             "启动空闲刷新任务：空闲超时 {:?}，检查间隔 {:?}",
-            self.config.idle_timeout, self.config.check_interval
+            "Starting idle flush task: idle timeout {:?}, check interval {:?}",
+            self.config.idle_timeout,
+            self.config.check_interval
         );
 
         tokio::spawn(async move {
@@ -89,17 +92,23 @@ impl IdleFlushManager {
                     let cache_count = self.cache.cache_count();
 
                     if pending_count > 0 || cache_count > 0 {
-                        println!("系统空闲超过 {:?}，开始刷新缓存", self.config.idle_timeout);
-                        println!(
+                        log_info!(
+                            "系统空闲超过 {:?}，开始刷新缓存",
+                            "Idle for more than {:?}, flushing the cache",
+                            self.config.idle_timeout
+                        );
+                        log_info!(
                             "当前缓存项数量: {}, 待写入项数量: {}",
-                            cache_count, pending_count
+                            "Cache entries: {}, pending writes: {}",
+                            cache_count,
+                            pending_count
                         );
 
                         // 将所有待写入的项取出
                         let pending_items = self.cache.take_pending_writes(pending_count);
 
                         // 将当前缓存中的所有项移到待写入状态并取出
-                        let cache_items = self.cache.flush_all_to_pending().await;
+                        let cache_items = self.cache.flush_all_to_pending();
 
                         // 合并所有需要写入的项
                         let mut all_items =
@@ -111,15 +120,24 @@ impl IdleFlushManager {
                         if let Some(writer) = &self.db_writer {
                             let total_items = all_items.len();
                             if total_items > 0 {
-                                println!("空闲刷新: 开始将 {} 个缓存项写入数据库", total_items);
+                                log_info!(
+                                    "空闲刷新: 开始将 {} 个缓存项写入数据库",
+                                    "Idle flush: writing {} cache entries to the database",
+                                    total_items
+                                );
                                 let (success, failed) = writer.batch_write(all_items).await;
-                                println!(
+                                log_info!(
                                     "空闲刷新: 数据库写入完成，成功: {}，失败: {}",
-                                    success, failed
+                                    "Idle flush: database write finished, succeeded: {}, failed: {}",
+                                    success,
+                                    failed
                                 );
                             }
                         } else {
-                            println!("空闲刷新: 未配置数据库连接，跳过写入操作");
+                            log_warn!(
+                                "空闲刷新: 未配置数据库连接，跳过写入操作",
+                                "Idle flush: no database connection configured, skipping the write"
+                            );
                         }
 
                         // 重置活动时间
@@ -0,0 +1,205 @@
+use crate::tr;
+use crate::utils::answer_codec::{StoredAnswer, decode_answer};
+use crate::utils::config::Config;
+use crate::utils::db::{create_db_pool, init_db};
+use crate::utils::encryption::{decrypt_blob, init_encryption};
+use chrono::DateTime;
+use sqlx::SqlitePool;
+use std::fmt::Write;
+
+/// 一条缓存记录：问题到回答的对应关系、回答表中的元数据与解码后的回答
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub question_key: String,
+    pub answer_key: String,
+    pub question_created_at: i64,
+    // 存储的（压缩、加密后的）回答大小，单位字节
+    pub size: i64,
+    pub hit_count: i64,
+    pub version: i64,
+    pub epoch: i64,
+    pub created_at: i64,
+    pub last_hit_at: Option<i64>,
+    pub answer: StoredAnswer,
+}
+
+// 回答键、问题写入时间、回答数据、大小、命中次数、版本、纪元、回答写入时间、最近命中时间
+type EntryRow = (String, i64, Vec<u8>, i64, i64, i64, i64, i64, Option<i64>);
+
+/// 按问题键查找缓存记录并解码回答，问题不存在时返回 None
+pub async fn inspect_entry(
+    pool: &SqlitePool,
+    question_key: &str,
+) -> Result<Option<CacheEntry>, String> {
+    let row = sqlx::query_as::<_, EntryRow>(
+        "SELECT a.key, q.created_at, a.response, a.size, a.hit_count, a.version, a.epoch,
+                a.created_at, a.last_hit_at
+         FROM questions q
+         JOIN answers a ON q.answer_key = a.key
+         WHERE q.key = ?",
+    )
+    .bind(question_key)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| tr!("数据库查询错误: {}", "Database query error: {}", e))?;
+    let Some((
+        answer_key,
+        question_created_at,
+        data,
+        size,
+        hit_count,
+        version,
+        epoch,
+        created_at,
+        last_hit_at,
+    )) = row
+    else {
+        return Ok(None);
+    };
+
+    let answer = decode_answer(&decrypt_blob(data)?)?;
+    Ok(Some(CacheEntry {
+        question_key: question_key.to_string(),
+        answer_key,
+        question_created_at,
+        size,
+        hit_count,
+        version,
+        epoch,
+        created_at,
+        last_hit_at,
+        answer,
+    }))
+}
+
+// Unix 秒转为 UTC 时间，0 表示未知（旧格式的回答没有上游生成时间）
+fn format_time(timestamp: i64) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) if timestamp > 0 => {
+            format!("{} ({})", time.format("%Y-%m-%d %H:%M:%S UTC"), timestamp)
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// 以文本形式输出缓存记录，供 inspect 子命令打印
+pub fn format_entry(entry: &CacheEntry) -> String {
+    let answer = &entry.answer;
+    let mut out = String::new();
+    let model = if answer.model.is_empty() {
+        "-"
+    } else {
+        &answer.model
+    };
+    let last_hit = entry
+        .last_hit_at
+        .map(format_time)
+        .unwrap_or_else(|| "-".to_string());
+    let lines = [
+        tr!("问题键:   {}", "Question key: {}", entry.question_key),
+        tr!("回答键:   {}", "Answer key:   {}", entry.answer_key),
+        tr!("模型:     {}", "Model:        {}", model),
+        tr!("存储大小: {} 字节", "Size:         {} bytes", entry.size),
+        tr!("命中次数: {}", "Hit count:    {}", entry.hit_count),
+        tr!("缓存版本: {}", "Version:      {}", entry.version),
+        tr!("缓存纪元: {}", "Epoch:        {}", entry.epoch),
+        tr!(
+            "问题写入: {}",
+            "Question:     {}",
+            format_time(entry.question_created_at)
+        ),
+        tr!(
+            "回答写入: {}",
+            "Created:      {}",
+            format_time(entry.created_at)
+        ),
+        tr!(
+            "上游生成: {}",
+            "Generated:    {}",
+            format_time(answer.created_at)
+        ),
+        tr!("最近命中: {}", "Last hit:     {}", last_hit),
+    ];
+    for line in lines {
+        let _ = writeln!(out, "{}", line);
+    }
+    if let Some(usage) = &answer.usage {
+        let _ = writeln!(
+            out,
+            "{}",
+            tr!(
+                "用量:     prompt {} / completion {} / total {}",
+                "Usage:        prompt {} / completion {} / total {}",
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.total_tokens
+            )
+        );
+    }
+    for choice in &answer.choices {
+        let content = choice
+            .message
+            .as_ref()
+            .map(|message| message.content.as_str())
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "{}",
+            tr!(
+                "\n--- 选项 {} (finish_reason: {}, {} 字节) ---\n{}",
+                "\n--- Choice {} (finish_reason: {}, {} bytes) ---\n{}",
+                choice.index,
+                choice.finish_reason,
+                content.len(),
+                content
+            )
+        );
+    }
+    out
+}
+
+/// 供命令行子命令使用：打开配置中的缓存数据库并启用缓存加密（加密的缓存需要相同的密钥才能解码），
+/// 缓存存放在 Redis 时返回错误
+pub async fn open_cache_db(config: &Config, command: &str) -> Result<SqlitePool, String> {
+    if config.cache.uses_redis() {
+        return Err(tr!(
+            "{} 仅支持 SQLite 缓存后端",
+            "{} only supports the SQLite cache backend",
+            command
+        ));
+    }
+    let pool = create_db_pool(&config.database_url, &config.database)
+        .await
+        .map_err(|e| tr!("打开数据库失败: {}", "Failed to open the database: {}", e))?;
+    init_db(&pool).await.map_err(|e| {
+        tr!(
+            "初始化数据库失败: {}",
+            "Failed to initialize the database: {}",
+            e
+        )
+    })?;
+    init_encryption(&config.encryption)?;
+    Ok(pool)
+}
+
+/// inspect 子命令：`llm_api inspect <question_key>`，打印数据库中对应的缓存记录
+pub async fn run_inspect(args: &[String], config: &Config) -> Result<(), String> {
+    let [question_key] = args else {
+        return Err(tr!(
+            "用法: llm_api inspect <question_key>",
+            "Usage: llm_api inspect <question_key>"
+        ));
+    };
+    let pool = open_cache_db(config, "inspect").await?;
+    match inspect_entry(&pool, question_key).await? {
+        Some(entry) => {
+            print!("{}", format_entry(&entry));
+            Ok(())
+        }
+        None => Err(tr!(
+            "未找到问题键 {} 对应的缓存记录",
+            "No cache entry found for question key {}",
+            question_key
+        )),
+    }
+}
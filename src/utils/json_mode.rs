@@ -0,0 +1,65 @@
+use crate::models::api_model::ChatResponseJson;
+use crate::utils::error::AppError;
+use crate::utils::plugin::{ResponseContext, ResponsePlugin};
+use crate::{log_warn, tr};
+
+/// 请求了结构化输出（response_format 为 json_object 或 json_schema）时校验回答内容是否为有效 JSON：
+/// 上游回答无效时返回 502 且不写入缓存，缓存命中的回答无效时返回 500
+pub struct JsonModePlugin;
+
+// 返回第一条内容不是有效 JSON 的回答序号
+fn invalid_choice(response: &ChatResponseJson) -> Option<i32> {
+    response
+        .choices
+        .iter()
+        .find(|choice| serde_json::from_str::<serde_json::Value>(&choice.message.content).is_err())
+        .map(|choice| choice.index)
+}
+
+impl ResponsePlugin for JsonModePlugin {
+    fn name(&self) -> &str {
+        "json_mode"
+    }
+
+    fn pre_cache_store(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        if !ctx.structured_output {
+            return Ok(true);
+        }
+        if let Some(index) = invalid_choice(response) {
+            log_warn!(
+                "[{}] 请求了 JSON 输出，但上游回答 {} 不是有效的 JSON，不写入缓存",
+                "[{}] JSON output was requested but upstream choice {} is not valid JSON, not caching",
+                ctx.request_id,
+                index
+            );
+            return Err(AppError::BadGateway(tr!(
+                "上游返回的内容不是有效的 JSON（请求指定了 response_format）",
+                "The upstream content is not valid JSON (the request specified response_format)"
+            )));
+        }
+        Ok(true)
+    }
+
+    fn post_cache_hit(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<(), AppError> {
+        if ctx.structured_output && invalid_choice(response).is_some() {
+            return Err(AppError::Internal(tr!(
+                "缓存的回答不是有效的 JSON（请求指定了 response_format）",
+                "The cached answer is not valid JSON (the request specified response_format)"
+            )));
+        }
+        Ok(())
+    }
+
+    // 只校验请求了 JSON 输出的命中
+    fn handles_cache_hit(&self, ctx: &ResponseContext) -> bool {
+        ctx.structured_output
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{log_warn, tr};
+use axum::body::{Body, Bytes};
+use serde::Serialize;
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+
+// 响应体每个分块的大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// 将序列化输出按块通过通道交给响应体
+struct ChunkWriter {
+    buf: Vec<u8>,
+    tx: mpsc::Sender<Bytes>,
+}
+
+impl ChunkWriter {
+    fn send_buf(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.tx.blocking_send(Bytes::from(chunk)).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                tr!("客户端已断开连接", "The client disconnected"),
+            )
+        })
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send_buf()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buf()
+    }
+}
+
+/// 在阻塞线程池中边序列化边输出 JSON 响应体，不在内存中生成完整的 JSON 字符串
+pub fn stream_json<T: Serialize + Send + 'static>(value: T) -> Body {
+    let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            tx,
+        };
+        let result = serde_json::to_writer(&mut writer, &value)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.flush());
+        if let Err(e) = result {
+            log_warn!("流式输出响应失败: {}", "Failed to stream response: {}", e);
+        }
+    });
+
+    Body::from_stream(futures::stream::poll_fn(move |cx| {
+        rx.poll_recv(cx).map(|chunk| chunk.map(Ok::<_, io::Error>))
+    }))
+}
@@ -0,0 +1,238 @@
+use crate::log_debug;
+use crate::utils::websocket::{Frame, OP_CLOSE, OP_PONG, OP_TEXT, read_frame, write_frame};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, watch};
+
+// 每个订阅者最多积压的事件数，消费过慢时跳过旧事件
+const EVENT_BUFFER: usize = 1024;
+// SSE 连接空闲时发送注释行的间隔，避免被代理当作空闲连接断开
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// 推送给 /admin/ws 与 /admin/events 订阅者的实时事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    RequestStarted {
+        request_id: String,
+        model: String,
+        stream: bool,
+    },
+    // source: memory / db
+    CacheHit {
+        request_id: String,
+        source: &'static str,
+    },
+    CacheMiss {
+        request_id: String,
+        endpoint: String,
+    },
+    RequestFinished {
+        request_id: String,
+        status_code: u16,
+        latency_ms: u64,
+    },
+    // 一批缓存写入数据库
+    Flush {
+        written: usize,
+        failed: usize,
+    },
+    Maintenance {
+        success: bool,
+        deleted_answers: u64,
+        deleted_questions: u64,
+    },
+}
+
+impl LiveEvent {
+    /// 事件类型，与序列化后的 type 字段一致
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LiveEvent::RequestStarted { .. } => "request_started",
+            LiveEvent::CacheHit { .. } => "cache_hit",
+            LiveEvent::CacheMiss { .. } => "cache_miss",
+            LiveEvent::RequestFinished { .. } => "request_finished",
+            LiveEvent::Flush { .. } => "flush",
+            LiveEvent::Maintenance { .. } => "maintenance",
+        }
+    }
+
+    /// 是否表示出错：请求以 4xx/5xx 结束、批量写入有失败、维护失败
+    pub fn is_error(&self) -> bool {
+        match self {
+            LiveEvent::RequestFinished { status_code, .. } => *status_code >= 400,
+            LiveEvent::Flush { failed, .. } => *failed > 0,
+            LiveEvent::Maintenance { success, .. } => !success,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    timestamp: i64,
+    #[serde(flatten)]
+    event: &'a LiveEvent,
+}
+
+/// 已序列化的事件，所有订阅者共享同一份
+pub struct PublishedEvent {
+    pub kind: &'static str,
+    pub error: bool,
+    pub json: String,
+}
+
+/// 订阅时的事件过滤条件（查询参数）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EventFilter {
+    // 逗号分隔的事件类型，如 cache_miss,request_finished，未设置时推送所有类型
+    #[serde(default)]
+    pub types: Option<String>,
+    // 只推送表示出错的事件
+    #[serde(default)]
+    pub errors: bool,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &PublishedEvent) -> bool {
+        if self.errors && !event.error {
+            return false;
+        }
+        match &self.types {
+            Some(types) => types.split(',').any(|kind| kind.trim() == event.kind),
+            None => true,
+        }
+    }
+}
+
+static EVENTS: LazyLock<broadcast::Sender<Arc<PublishedEvent>>> =
+    LazyLock::new(|| broadcast::channel(EVENT_BUFFER).0);
+// 服务器退出时通知，结束所有订阅连接，使优雅关闭不必等待长连接
+static CLOSING: LazyLock<watch::Sender<()>> = LazyLock::new(|| watch::channel(()).0);
+
+/// 发布一个事件；没有订阅者时直接丢弃，不做序列化
+pub fn publish(event: LiveEvent) {
+    if EVENTS.receiver_count() == 0 {
+        return;
+    }
+    let envelope = Envelope {
+        timestamp: chrono::Utc::now().timestamp(),
+        event: &event,
+    };
+    if let Ok(json) = serde_json::to_string(&envelope) {
+        let _ = EVENTS.send(Arc::new(PublishedEvent {
+            kind: event.kind(),
+            error: event.is_error(),
+            json,
+        }));
+    }
+}
+
+pub fn subscribe() -> broadcast::Receiver<Arc<PublishedEvent>> {
+    EVENTS.subscribe()
+}
+
+/// 结束所有订阅连接（服务器退出时调用）
+pub fn close_subscribers() {
+    CLOSING.send_replace(());
+}
+
+// 订阅时立即登记，此后的 close_subscribers 调用都会唤醒
+fn closing() -> impl Future<Output = ()> + Send + 'static {
+    let mut closing = CLOSING.subscribe();
+    async move {
+        let _ = closing.changed().await;
+    }
+}
+
+// 订阅者积压过多、跳过旧事件时发送的提示
+fn lagged_message(skipped: u64) -> String {
+    format!("{{\"type\":\"lagged\",\"skipped\":{}}}", skipped)
+}
+
+/// 在已完成握手的 WebSocket 连接上推送事件，直到客户端关闭连接、连接出错或服务器退出
+pub async fn serve_subscriber<S>(
+    socket: S,
+    mut events: broadcast::Receiver<Arc<PublishedEvent>>,
+    filter: EventFilter,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(socket);
+
+    // 单独的任务读取客户端帧，避免读到一半时被事件推送打断
+    let (control_tx, mut control_rx) = mpsc::channel::<Frame>(8);
+    let reader_task = tokio::spawn(async move {
+        while let Ok(frame) = read_frame(&mut reader).await {
+            let closing = matches!(frame, Frame::Close);
+            if control_tx.send(frame).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let closing = closing();
+    tokio::pin!(closing);
+    loop {
+        let result = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if filter.matches(&event) => {
+                    write_frame(&mut writer, OP_TEXT, event.json.as_bytes()).await
+                }
+                Ok(_) => Ok(()),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    write_frame(&mut writer, OP_TEXT, lagged_message(skipped).as_bytes()).await
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            frame = control_rx.recv() => match frame {
+                Some(Frame::Ping(payload)) => write_frame(&mut writer, OP_PONG, &payload).await,
+                Some(Frame::Close) | None => {
+                    let _ = write_frame(&mut writer, OP_CLOSE, &[]).await;
+                    break;
+                }
+                Some(_) => Ok(()),
+            },
+            _ = &mut closing => {
+                let _ = write_frame(&mut writer, OP_CLOSE, &[]).await;
+                break;
+            }
+        };
+        if let Err(e) = result {
+            log_debug!("实时事件连接已断开: {}", "Live event connection closed: {}", e);
+            break;
+        }
+    }
+    reader_task.abort();
+}
+
+/// SSE 格式的事件流（每个事件一行 data），空闲时定期发送注释行保活，服务器退出时结束
+pub fn sse_stream(
+    events: broadcast::Receiver<Arc<PublishedEvent>>,
+    filter: EventFilter,
+) -> impl Stream<Item = Result<String, std::io::Error>> {
+    futures::stream::unfold(
+        (events, filter, Box::pin(closing())),
+        |(mut events, filter, mut closing)| async move {
+            loop {
+                let next = tokio::select! {
+                    _ = &mut closing => return None,
+                    next = tokio::time::timeout(SSE_KEEP_ALIVE, events.recv()) => next,
+                };
+                let chunk = match next {
+                    Err(_) => ": keep-alive\n\n".to_string(),
+                    Ok(Ok(event)) if filter.matches(&event) => format!("data: {}\n\n", event.json),
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                        format!("data: {}\n\n", lagged_message(skipped))
+                    }
+                    Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+                };
+                return Some((Ok(chunk), (events, filter, closing)));
+            }
+        },
+    )
+}
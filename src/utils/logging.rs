@@ -1,7 +1,137 @@
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
-// 统一带请求ID的日志输出
-pub fn log_with_id<T: Display>(id: &str, message: T) {
-    println!("[{}] {}", id, message);
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    // 日志级别：error / warn / info / debug / trace，低于该级别的日志不输出
+    pub level: String,
+    // 日志语言：zh（中文）或 en（英文）
+    pub language: String,
 }
 
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            language: "zh".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+// 加载配置之前使用默认值（info、中文）
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+static ENGLISH: AtomicBool = AtomicBool::new(false);
+
+/// 按配置设置日志级别与语言，可重复调用；无效的取值保持原设置
+pub fn init_logging(config: &LoggingConfig) {
+    if let Some(level) = LogLevel::parse(&config.level) {
+        MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    }
+    ENGLISH.store(config.language.eq_ignore_ascii_case("en"), Ordering::Relaxed);
+}
+
+/// 该级别的日志是否输出
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// 是否输出英文日志
+pub fn english() -> bool {
+    ENGLISH.load(Ordering::Relaxed)
+}
+
+/// 输出一条已格式化的日志：error / warn 写入标准错误，其余写入标准输出
+pub fn write_log(level: LogLevel, message: &str) {
+    if level <= LogLevel::Warn {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// 按 logging.language 选择中文或英文格式字符串并格式化，两种语言使用相同的参数。
+/// 用于配置校验提示与返回给客户端的错误信息：`tr!("中文格式", "English format", 参数...)`
+#[macro_export]
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        if $crate::utils::logging::english() {
+            format!($en $(, $arg)*)
+        } else {
+            format!($zh $(, $arg)*)
+        }
+    };
+}
+
+// 按级别过滤并按语言选择格式字符串
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:expr, $zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        if $crate::utils::logging::enabled($level) {
+            let message = $crate::tr!($zh, $en $(, $arg)*);
+            $crate::utils::logging::write_log($level, &message);
+        }
+    };
+}
+
+/// 错误日志：`log_error!("中文格式", "English format", 参数...)`
+#[macro_export]
+macro_rules! log_error {
+    ($($args:tt)*) => {
+        $crate::__log!($crate::utils::logging::LogLevel::Error, $($args)*)
+    };
+}
+
+/// 警告日志，参数同 log_error!
+#[macro_export]
+macro_rules! log_warn {
+    ($($args:tt)*) => {
+        $crate::__log!($crate::utils::logging::LogLevel::Warn, $($args)*)
+    };
+}
+
+/// 常规信息日志，参数同 log_error!
+#[macro_export]
+macro_rules! log_info {
+    ($($args:tt)*) => {
+        $crate::__log!($crate::utils::logging::LogLevel::Info, $($args)*)
+    };
+}
+
+/// 调试日志（单个请求的处理过程等），参数同 log_error!
+#[macro_export]
+macro_rules! log_debug {
+    ($($args:tt)*) => {
+        $crate::__log!($crate::utils::logging::LogLevel::Debug, $($args)*)
+    };
+}
+
+/// 最详细的跟踪日志，参数同 log_error!
+#[macro_export]
+macro_rules! log_trace {
+    ($($args:tt)*) => {
+        $crate::__log!($crate::utils::logging::LogLevel::Trace, $($args)*)
+    };
+}
@@ -1,53 +1,220 @@
+use crate::{log_debug, log_info};
 use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use serde::Serialize;
 use std::collections::VecDeque;
-use tokio::sync::Mutex;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 
+// 淘汰队列的分片数，插入时只锁定键所在的分片
+const QUEUE_SHARDS: usize = 16;
+
+// 缓存值以 Arc 共享：命中时只复制指针，淘汰到待写入队列或取出写库时移动而不复制数据
 pub struct MemoryCache {
-    cache: DashMap<String, Vec<u8>>,
-    queue: Mutex<VecDeque<String>>,
+    cache: DashMap<String, Arc<Vec<u8>>>,
+    // 按键哈希分片的插入顺序队列，淘汰时轮流从各分片取最早的项（近似全局 FIFO）
+    queues: Vec<Mutex<VecDeque<String>>>,
+    hasher: RandomState,
+    // 各分片队列中的键总数
+    queued: AtomicUsize,
+    // 下一次淘汰的分片
+    evict_cursor: AtomicUsize,
     max_items: usize,
-    pending_writes: DashMap<String, Vec<u8>>,
+    // 缓存项在内存中的最长保留时间（秒），0 表示不过期
+    ttl_seconds: u64,
+    pending_writes: DashMap<String, Arc<Vec<u8>>>,
+    // 缓存项写入内存的时间（Unix 秒），用于计算缓存年龄
+    inserted_at: DashMap<String, i64>,
+    // 缓存项在内存中的命中次数，移入待写入队列后保留，写库或丢弃时移除
+    hit_counts: DashMap<String, u64>,
+    // 缓存项与待写入项占用的字节数（仅统计压缩后的数据）
+    tracked_bytes: AtomicUsize,
+    // 待写入队列超限后触发的同步刷新次数与丢弃的项数
+    overflow_flushes: AtomicU64,
+    overflow_dropped: AtomicU64,
+    // 查询命中与未命中次数
+    hits: AtomicU64,
+    misses: AtomicU64,
+    // 因达到容量上限被移入待写入队列的项数
+    evictions: AtomicU64,
+    // 因超过保留时间被移出内存的项数
+    expirations: AtomicU64,
+}
+
+/// 内存缓存统计快照
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryCacheStats {
+    pub items: usize,
+    pub max_items: usize,
+    pub bytes: usize,
+    pub pending_writes: usize,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub hits: u64,
+    pub misses: u64,
+    // 命中数 / 查询数
+    pub hit_rate: f64,
+    pub overflow_flushes: u64,
+    pub overflow_dropped: u64,
 }
 
 impl MemoryCache {
-    pub fn new(max_items: usize) -> Self {
+    pub fn new(max_items: usize, ttl_seconds: u64) -> Self {
         Self {
             cache: DashMap::new(),
-            queue: Mutex::new(VecDeque::with_capacity(max_items)),
+            queues: (0..QUEUE_SHARDS)
+                .map(|_| Mutex::new(VecDeque::with_capacity(max_items / QUEUE_SHARDS + 1)))
+                .collect(),
+            hasher: RandomState::new(),
+            queued: AtomicUsize::new(0),
+            evict_cursor: AtomicUsize::new(0),
             max_items,
+            ttl_seconds,
             pending_writes: DashMap::new(),
+            inserted_at: DashMap::new(),
+            hit_counts: DashMap::new(),
+            tracked_bytes: AtomicUsize::new(0),
+            overflow_flushes: AtomicU64::new(0),
+            overflow_dropped: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
         }
     }
 
     // 获取缓存项
-    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
-        self.cache.get(key).map(|value| value.clone())
+    pub fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        // 超过保留时间的项移出内存，按未命中处理，由调用方继续查询数据库
+        if self.ttl_seconds > 0 {
+            self.expire(key, chrono::Utc::now().timestamp());
+        }
+        let value = self.cache.get(key).map(|value| value.clone());
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            *self.hit_counts.entry(key.to_string()).or_insert(0) += 1;
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    // 获取缓存项写入内存的时间（Unix 秒）
+    pub fn inserted_at(&self, key: &str) -> Option<i64> {
+        self.inserted_at.get(key).map(|ts| *ts)
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<VecDeque<String>> {
+        &self.queues[self.hasher.hash_one(key) as usize % QUEUE_SHARDS]
     }
 
     // 添加缓存项
-    pub async fn insert(&self, key: String, value: Vec<u8>) {
-        // 如果已经存在，只更新值
-        if self.cache.contains_key(&key) {
-            self.cache.insert(key, value);
-            return;
+    pub fn insert(&self, key: String, value: Vec<u8>) {
+        self.inserted_at.insert(key.clone(), chrono::Utc::now().timestamp());
+        self.tracked_bytes.fetch_add(value.len(), Ordering::Relaxed);
+
+        // 如果已经存在，只更新值（entry 持有该键的分片锁，同一个键并发插入时不会重复入队）
+        match self.cache.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let old = entry.insert(Arc::new(value));
+                self.tracked_bytes.fetch_sub(old.len(), Ordering::Relaxed);
+                return;
+            }
+            Entry::Vacant(entry) => {
+                let key = entry.key().clone();
+                entry.insert(Arc::new(value));
+                self.shard(&key).lock().unwrap().push_back(key);
+            }
         }
 
-        // 获取锁进行队列操作
-        let mut queue = self.queue.lock().await;
+        // 如果超过容量上限，需要移除最早的项
+        if self.queued.fetch_add(1, Ordering::Relaxed) >= self.max_items {
+            self.evict_one();
+        }
+    }
+
+    // 从下一个非空分片中移除最早的项并放入待写入队列。
+    // 过期移出的项在队列中留有过时的键，取出时跳过；跳过后不再超过容量上限则无需淘汰
+    fn evict_one(&self) {
+        let start = self.evict_cursor.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..QUEUE_SHARDS {
+            let shard = &self.queues[(start + offset) % QUEUE_SHARDS];
+            loop {
+                let Some(oldest_key) = shard.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let queued = self
+                    .queued
+                    .fetch_sub(1, Ordering::Relaxed)
+                    .saturating_sub(1);
 
-        // 如果达到容量上限，需要移除最早的项
-        if queue.len() >= self.max_items {
-            if let Some(oldest_key) = queue.pop_front() {
-                // 将被移除的项放入待写入队列
+                // 将被移除的项放入待写入队列（该键可能已过期或被整体刷新移走）
                 if let Some((_, value)) = self.cache.remove(&oldest_key) {
-                    self.pending_writes.insert(oldest_key, value);
+                    self.inserted_at.remove(&oldest_key);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                    if let Some(old) = self.pending_writes.insert(oldest_key, value) {
+                        self.tracked_bytes.fetch_sub(old.len(), Ordering::Relaxed);
+                    }
+                    return;
+                }
+                if queued <= self.max_items {
+                    return;
                 }
             }
         }
+    }
+
+    // 缓存项写入内存已超过保留时间时将其移入待写入队列（尚未写入数据库的项仍会写入），返回是否移出。
+    // 淘汰队列中的键留待 evict_one 取出时跳过，避免逐个扫描队列
+    fn expire(&self, key: &str, now: i64) -> bool {
+        let ttl = self.ttl_seconds as i64;
+        if self
+            .inserted_at
+            .remove_if(key, |_, inserted_at| now - *inserted_at >= ttl)
+            .is_none()
+        {
+            return false;
+        }
+
+        if let Some((key, value)) = self.cache.remove(key) {
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            if let Some(old) = self.pending_writes.insert(key, value) {
+                self.tracked_bytes.fetch_sub(old.len(), Ordering::Relaxed);
+            }
+        }
+        true
+    }
+
+    /// 将所有超过保留时间的缓存项移出内存，返回移出的项数
+    pub fn expire_stale(&self) -> usize {
+        self.expire_stale_at(chrono::Utc::now().timestamp())
+    }
+
+    // 以 now（Unix 秒）为当前时间移出超过保留时间的缓存项
+    fn expire_stale_at(&self, now: i64) -> usize {
+        if self.ttl_seconds == 0 {
+            return 0;
+        }
+        let ttl = self.ttl_seconds as i64;
+        let stale: Vec<String> = self
+            .inserted_at
+            .iter()
+            .filter(|entry| now - *entry.value() >= ttl)
+            .map(|entry| entry.key().clone())
+            .collect();
+        stale.iter().filter(|key| self.expire(key, now)).count()
+    }
 
-        // 插入新项
-        queue.push_back(key.clone());
-        self.cache.insert(key, value);
+    // 清空全部分片队列
+    fn clear_queues(&self) {
+        for shard in &self.queues {
+            let mut shard = shard.lock().unwrap();
+            self.queued.fetch_sub(shard.len(), Ordering::Relaxed);
+            shard.clear();
+        }
     }
 
     // 获取待写入的项
@@ -65,7 +232,9 @@ impl MemoryCache {
 
         for key in pending_keys {
             if let Some((k, v)) = self.pending_writes.remove(&key) {
-                result.push((k, v));
+                self.tracked_bytes.fetch_sub(v.len(), Ordering::Relaxed);
+                self.hit_counts.remove(&k);
+                result.push((k, Arc::unwrap_or_clone(v)));
                 count += 1;
                 if count >= batch_size {
                     break;
@@ -76,22 +245,23 @@ impl MemoryCache {
         result
     }
 
-    // 将所有缓存项移动到待写入状态并返回这些项
-    pub async fn flush_all_to_pending(&self) -> Vec<(String, Vec<u8>)> {
+    // 取出所有缓存项并从内存缓存中移除，由调用方负责写入数据库
+    pub fn flush_all_to_pending(&self) -> Vec<(String, Vec<u8>)> {
         // 获取所有缓存键
         let cache_keys: Vec<String> = self.cache.iter().map(|entry| entry.key().clone()).collect();
 
         let mut result = Vec::with_capacity(cache_keys.len());
 
         // 清空队列
-        let mut queue = self.queue.lock().await;
-        queue.clear();
+        self.clear_queues();
+        self.inserted_at.clear();
 
-        // 将所有缓存项移到待写入状态
+        // 直接移出缓存项，不再在待写入队列中保留一份副本（否则会被重复写入）
         for key in cache_keys {
             if let Some((k, v)) = self.cache.remove(&key) {
-                self.pending_writes.insert(k.clone(), v.clone());
-                result.push((k, v));
+                self.tracked_bytes.fetch_sub(v.len(), Ordering::Relaxed);
+                self.hit_counts.remove(&k);
+                result.push((k, Arc::unwrap_or_clone(v)));
             }
         }
 
@@ -107,4 +277,217 @@ impl MemoryCache {
     pub fn cache_count(&self) -> usize {
         self.cache.len()
     }
+
+    // 取出全部缓存项与待写入项并从内存中移除（内存压力时使用，避免额外复制）
+    pub fn drain_all(&self) -> Vec<(String, Vec<u8>)> {
+        self.clear_queues();
+        self.inserted_at.clear();
+        self.hit_counts.clear();
+
+        let keys: Vec<String> = self
+            .pending_writes
+            .iter()
+            .map(|entry| entry.key().clone())
+            .chain(self.cache.iter().map(|entry| entry.key().clone()))
+            .collect();
+
+        let mut result = Vec::with_capacity(keys.len());
+        for key in keys {
+            // 同一个键可能同时存在于两处，以缓存中的新值为准
+            let pending = self.pending_writes.remove(&key);
+            let cached = self.cache.remove(&key);
+            for (_, v) in pending.iter().chain(cached.iter()) {
+                self.tracked_bytes.fetch_sub(v.len(), Ordering::Relaxed);
+            }
+            if let Some((k, v)) = cached.or(pending) {
+                result.push((k, Arc::unwrap_or_clone(v)));
+            }
+        }
+
+        result
+    }
+
+    // 获取缓存项与待写入项占用的字节数
+    pub fn memory_bytes(&self) -> usize {
+        self.tracked_bytes.load(Ordering::Relaxed)
+    }
+
+    // 丢弃超出上限的待写入项，返回丢弃数量。优先丢弃在内存中命中次数最少的项，
+    // 次数相同时先丢弃压缩后体积较大的项
+    pub fn drop_excess_pending(&self, max_pending: usize) -> usize {
+        let excess = self.pending_writes.len().saturating_sub(max_pending);
+        if excess == 0 {
+            return 0;
+        }
+
+        let mut ranked: Vec<(String, u64, usize)> = self
+            .pending_writes
+            .iter()
+            .map(|entry| {
+                let hits = self.hit_counts.get(entry.key()).map_or(0, |hits| *hits);
+                (entry.key().clone(), hits, entry.value().len())
+            })
+            .collect();
+        ranked.sort_unstable_by_key(|(_, hits, size)| (*hits, std::cmp::Reverse(*size)));
+
+        let mut dropped = 0;
+        for (key, _, _) in ranked.into_iter().take(excess) {
+            if let Some((_, v)) = self.pending_writes.remove(&key) {
+                self.tracked_bytes.fetch_sub(v.len(), Ordering::Relaxed);
+                self.hit_counts.remove(&key);
+                dropped += 1;
+            }
+        }
+
+        self.overflow_dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+        dropped
+    }
+
+    // 记录一次因待写入队列超限触发的同步刷新
+    pub fn record_overflow_flush(&self) {
+        self.overflow_flushes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 获取内存缓存统计快照
+    pub fn stats(&self) -> MemoryCacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let (overflow_flushes, overflow_dropped) = self.overflow_stats();
+        MemoryCacheStats {
+            items: self.cache_count(),
+            max_items: self.max_items,
+            bytes: self.memory_bytes(),
+            pending_writes: self.pending_count(),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            hits,
+            misses,
+            hit_rate: if hits + misses == 0 {
+                0.0
+            } else {
+                hits as f64 / (hits + misses) as f64
+            },
+            overflow_flushes,
+            overflow_dropped,
+        }
+    }
+
+    // 获取待写入队列超限统计：(同步刷新次数, 丢弃项数)
+    pub fn overflow_stats(&self) -> (u64, u64) {
+        (
+            self.overflow_flushes.load(Ordering::Relaxed),
+            self.overflow_dropped.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// 启动过期清理任务：定期将超过保留时间的缓存项移出内存（查询时也会按需移出）
+pub fn start_expiry_task(cache: Arc<MemoryCache>) {
+    if cache.ttl_seconds == 0 {
+        return;
+    }
+
+    log_info!(
+        "内存缓存项保留时间: {} 秒",
+        "Memory cache entry retention: {} seconds",
+        cache.ttl_seconds
+    );
+    let check_interval = Duration::from_secs((cache.ttl_seconds / 2).clamp(1, 60));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(check_interval);
+        loop {
+            interval.tick().await;
+            let expired = cache.expire_stale();
+            if expired > 0 {
+                log_debug!(
+                    "{} 个内存缓存项超过保留时间，已移入待写入队列",
+                    "{} memory cache entries exceeded their retention and moved to the pending write queue",
+                    expired
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    #[test]
+    fn expired_entries_move_to_the_pending_writes() {
+        let cache = MemoryCache::new(10, 60);
+        cache.insert("fresh".to_string(), b"fresh".to_vec());
+        cache.insert("stale".to_string(), b"stale".to_vec());
+
+        assert!(!cache.expire("stale", now() + 59));
+        assert!(cache.expire("stale", now() + 60));
+        assert!(!cache.expire("stale", now() + 60));
+
+        assert!(cache.get("stale").is_none());
+        assert!(cache.get("fresh").is_some());
+        assert_eq!(cache.cache_count(), 1);
+        assert_eq!(
+            cache.take_pending_writes(10),
+            vec![("stale".to_string(), b"stale".to_vec())]
+        );
+        assert_eq!(cache.stats().expirations, 1);
+    }
+
+    #[test]
+    fn expired_keys_left_in_the_queue_are_skipped_on_eviction() {
+        let cache = MemoryCache::new(2, 60);
+        cache.insert("a".to_string(), b"a".to_vec());
+        cache.insert("b".to_string(), b"b".to_vec());
+        assert!(cache.expire("a", now() + 60));
+
+        // 各分片轮流淘汰，取出哪个键取决于分片，这里只检查容量与数据不丢失
+        for key in ["c", "d", "e"] {
+            cache.insert(key.to_string(), key.as_bytes().to_vec());
+            assert!(cache.cache_count() <= 2);
+            assert!(cache.queued.load(Ordering::Relaxed) <= 2);
+        }
+        assert_eq!(cache.cache_count() + cache.pending_count(), 5);
+        assert_eq!(cache.stats().evictions as usize, cache.pending_count() - 1);
+    }
+
+    #[test]
+    fn reinserted_keys_are_cached_again_after_expiring() {
+        let cache = MemoryCache::new(2, 60);
+        cache.insert("a".to_string(), b"old".to_vec());
+        assert!(cache.expire("a", now() + 60));
+        cache.insert("a".to_string(), b"new".to_vec());
+
+        assert_eq!(cache.get("a").as_deref(), Some(&b"new".to_vec()));
+        cache.insert("b".to_string(), b"b".to_vec());
+        cache.insert("c".to_string(), b"c".to_vec());
+        assert!(cache.cache_count() <= 2);
+    }
+
+    #[test]
+    fn pending_overflow_drops_the_least_hit_entries_first() {
+        // 过期后三项都移入待写入队列，并带着在内存中的命中次数
+        let cache = MemoryCache::new(10, 60);
+        cache.insert("hot".to_string(), vec![0; 100]);
+        cache.insert("cold_small".to_string(), vec![0; 10]);
+        cache.insert("cold_large".to_string(), vec![0; 50]);
+        assert!(cache.get("hot").is_some());
+        assert!(cache.get("hot").is_some());
+        assert_eq!(cache.expire_stale_at(now() + 60), 3);
+
+        // 命中次数相同时先丢弃体积较大的项，命中过的项即使最大也保留
+        assert_eq!(cache.drop_excess_pending(2), 1);
+        let mut kept: Vec<String> = cache
+            .take_pending_writes(10)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        kept.sort();
+        assert_eq!(kept, ["cold_small", "hot"]);
+        assert_eq!(cache.stats().overflow_dropped, 1);
+    }
 }
@@ -0,0 +1,147 @@
+use crate::{log_info, log_warn, tr};
+use crate::utils::db_writer::DbWriter;
+use crate::utils::memory_cache::MemoryCache;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MemoryPressureConfig {
+    pub enabled: bool,
+    pub max_rss_mb: u64,
+    pub max_cache_mb: u64,
+    pub check_interval_seconds: u64,
+}
+
+impl Default for MemoryPressureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_rss_mb: 0,
+            max_cache_mb: 0,
+            check_interval_seconds: 10,
+        }
+    }
+}
+
+// 读取当前进程的常驻内存（字节），仅 Linux 下可用
+fn current_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+// 判断是否超过任一内存阈值，返回超限原因
+fn pressure_reason(cache: &MemoryCache, config: &MemoryPressureConfig) -> Option<String> {
+    if config.max_cache_mb > 0 {
+        let cache_bytes = cache.memory_bytes() as u64;
+        if cache_bytes > config.max_cache_mb * 1024 * 1024 {
+            return Some(tr!(
+                "内存缓存占用 {} bytes 超过阈值 {} MB",
+                "Memory cache uses {} bytes, above the {} MB threshold",
+                cache_bytes, config.max_cache_mb
+            ));
+        }
+    }
+
+    if config.max_rss_mb > 0
+        && let Some(rss) = current_rss_bytes()
+        && rss > config.max_rss_mb * 1024 * 1024
+    {
+        return Some(tr!(
+            "进程常驻内存 {} bytes 超过阈值 {} MB",
+            "Process RSS is {} bytes, above the {} MB threshold",
+            rss, config.max_rss_mb
+        ));
+    }
+
+    None
+}
+
+// 启动内存压力监控任务：超过阈值时先写出待写入队列，仍超限则清空内存缓存并写入数据库
+pub fn start_memory_pressure_task(
+    cache: Arc<MemoryCache>,
+    db: Arc<SqlitePool>,
+    cache_version: u8,
+    config: MemoryPressureConfig,
+) {
+    if config.max_rss_mb == 0 && config.max_cache_mb == 0 {
+        log_info!(
+            "内存压力监控未配置任何阈值，跳过启动",
+            "Memory pressure monitoring has no thresholds configured, not starting"
+        );
+        return;
+    }
+
+    if config.max_rss_mb > 0 && current_rss_bytes().is_none() {
+        log_warn!(
+            "当前平台无法读取进程常驻内存，仅按内存缓存占用判断",
+            "Process resident memory cannot be read on this platform, judging by memory cache usage only"
+        );
+    }
+
+    log_info!(
+        "启动内存压力监控：RSS 阈值 {} MB，缓存阈值 {} MB，检查间隔 {} 秒",
+        "Starting memory pressure monitoring: RSS threshold {} MB, cache threshold {} MB, check interval {} s",
+        config.max_rss_mb,
+        config.max_cache_mb,
+        config.check_interval_seconds
+    );
+
+    let writer = DbWriter::new(db, cache_version);
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.check_interval_seconds.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            // 内存缓存已为空时无可释放内容（RSS 可能因分配器未归还内存而保持高位）
+            if cache.cache_count() == 0 && cache.pending_count() == 0 {
+                continue;
+            }
+
+            let Some(reason) = pressure_reason(&cache, &config) else {
+                continue;
+            };
+            log_warn!(
+                "检测到内存压力: {}，开始刷新缓存",
+                "Memory pressure detected: {}, flushing the cache",
+                reason
+            );
+
+            // 先写出待写入队列
+            let pending_items = cache.take_pending_writes(cache.pending_count());
+            if !pending_items.is_empty() {
+                let (success, failed) = writer.batch_write(pending_items).await;
+                log_info!(
+                    "内存压力刷新: 待写入项写入完成，成功: {}，失败: {}",
+                    "Memory pressure flush: pending writes finished, succeeded: {}, failed: {}",
+                    success,
+                    failed
+                );
+            }
+
+            // 仍然超限时清空内存缓存
+            if let Some(reason) = pressure_reason(&cache, &config) {
+                let items = cache.drain_all();
+                log_warn!(
+                    "内存压力仍未解除 ({})，驱逐全部 {} 个内存缓存项",
+                    "Memory pressure persists ({}), evicting all {} memory cache entries",
+                    reason,
+                    items.len()
+                );
+                let (success, failed) = writer.batch_write(items).await;
+                log_info!(
+                    "内存压力刷新: 缓存项写入完成，成功: {}，失败: {}",
+                    "Memory pressure flush: cache entries written, succeeded: {}, failed: {}",
+                    success,
+                    failed
+                );
+            }
+        }
+    });
+}
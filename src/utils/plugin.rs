@@ -0,0 +1,219 @@
+use crate::{log_debug, log_info};
+use crate::models::api_model::{ApiEndpoint, ChatRequestJson, ChatResponseJson};
+use crate::utils::config::Config;
+use crate::utils::content_filter::ContentFilter;
+use crate::utils::endpoint_stats::endpoint_label;
+use crate::utils::error::AppError;
+use crate::utils::force_model::ForceModelPlugin;
+use crate::utils::guardrails::GuardrailsPlugin;
+use crate::utils::json_mode::JsonModePlugin;
+use crate::utils::prompt_injection::PromptInjectionPlugin;
+use crate::utils::prompt_template::PromptTemplatePlugin;
+use crate::utils::wasm_plugin::register_wasm_plugins;
+use axum::http::HeaderMap;
+use std::sync::Arc;
+
+/// 请求插件可见的上下文
+pub struct RequestContext<'a> {
+    pub request_id: &'a str,
+    pub headers: &'a HeaderMap,
+}
+
+/// 响应插件可见的上下文
+pub struct ResponseContext<'a> {
+    pub request_id: &'a str,
+    // 实际发送给上游的模型（缓存命中时为请求中的模型）
+    pub model: &'a str,
+    // 请求是否要求 JSON 结构化输出
+    pub structured_output: bool,
+}
+
+/// 请求插件：在路由与计算缓存键之前修改或拒绝请求，也可以为请求指定上游端点
+pub trait RequestPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    // 路由前调用，返回错误时直接拒绝请求
+    fn pre_routing(
+        &self,
+        _ctx: &RequestContext,
+        _payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    // 为请求选择上游端点，返回 None 时交给后续插件或默认的加权选择
+    fn select_endpoint(
+        &self,
+        _ctx: &RequestContext,
+        _payload: &ChatRequestJson,
+        _endpoints: &[ApiEndpoint],
+    ) -> Option<ApiEndpoint> {
+        None
+    }
+}
+
+/// 响应插件：处理上游回答（写入缓存前）与缓存命中的回答
+pub trait ResponsePlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    // 上游回答返回客户端与写入缓存之前调用。返回 Ok(false) 表示该回答不写入缓存，
+    // 返回错误时以该错误代替回答
+    fn pre_cache_store(
+        &self,
+        _ctx: &ResponseContext,
+        _response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        Ok(true)
+    }
+
+    // 缓存命中的回答返回客户端之前调用
+    fn post_cache_hit(
+        &self,
+        _ctx: &ResponseContext,
+        _response: &mut ChatResponseJson,
+    ) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    // 本次命中是否需要执行 post_cache_hit。返回 false 时命中可以直接返回写入时保存的响应体
+    fn handles_cache_hit(&self, _ctx: &ResponseContext) -> bool {
+        true
+    }
+}
+
+/// 按注册顺序依次执行的插件列表
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    request_plugins: Vec<Arc<dyn RequestPlugin>>,
+    response_plugins: Vec<Arc<dyn ResponsePlugin>>,
+    // 内容过滤同时作用于不经过响应插件的流式转发
+    content_filter: Option<Arc<ContentFilter>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 按配置注册内置插件（强制模型、提示词模板、system prompt 注入、回答长度上限、内容过滤与 JSON 输出校验）与 WASM 插件
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut registry = Self::new();
+
+        // 强制模型最先执行，后续插件看到的都是实际使用的模型
+        if config.force_model.enabled {
+            registry.register_request(Arc::new(ForceModelPlugin::new(
+                config.force_model.clone(),
+                &config.api_endpoints,
+            )));
+        }
+        registry.register_request(Arc::new(PromptTemplatePlugin::new(
+            config.prompt_templates.clone(),
+        )));
+        if config.prompt_injection.enabled {
+            registry.register_request(Arc::new(PromptInjectionPlugin::new(
+                config.prompt_injection.clone(),
+            )));
+        }
+
+        if config.guardrails.is_enabled() {
+            registry.register_response(Arc::new(GuardrailsPlugin::new(
+                config.guardrails.clone(),
+                config.context_trim.clone(),
+            )));
+        }
+        if let Some(filter) = ContentFilter::from_config(&config.content_filter)? {
+            let filter = Arc::new(filter);
+            registry.register_response(filter.clone());
+            registry.content_filter = Some(filter);
+        }
+        // 在其他内置插件修改回答之后校验 JSON 输出
+        registry.register_response(Arc::new(JsonModePlugin));
+
+        // 用户提供的 WASM 插件在内置插件之后执行
+        register_wasm_plugins(&mut registry, &config.wasm_plugins)?;
+
+        Ok(registry)
+    }
+
+    pub fn register_request(&mut self, plugin: Arc<dyn RequestPlugin>) {
+        log_info!("注册请求插件: {}", "Registered request plugin: {}", plugin.name());
+        self.request_plugins.push(plugin);
+    }
+
+    pub fn register_response(&mut self, plugin: Arc<dyn ResponsePlugin>) {
+        log_info!("注册响应插件: {}", "Registered response plugin: {}", plugin.name());
+        self.response_plugins.push(plugin);
+    }
+
+    pub fn content_filter(&self) -> Option<&Arc<ContentFilter>> {
+        self.content_filter.as_ref()
+    }
+
+    pub fn pre_routing(
+        &self,
+        ctx: &RequestContext,
+        payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        for plugin in &self.request_plugins {
+            plugin.pre_routing(ctx, payload).inspect_err(|e| {
+                log_info!(
+                    "[{}] 插件 {} 拒绝请求: {}",
+                    "[{}] Plugin {} rejected the request: {}",
+                    ctx.request_id,
+                    plugin.name(),
+                    e
+                );
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn select_endpoint(
+        &self,
+        ctx: &RequestContext,
+        payload: &ChatRequestJson,
+        endpoints: &[ApiEndpoint],
+    ) -> Option<ApiEndpoint> {
+        self.request_plugins.iter().find_map(|plugin| {
+            let endpoint = plugin.select_endpoint(ctx, payload, endpoints)?;
+            log_debug!(
+                "[{}] 插件 {} 选择了端点: {}",
+                "[{}] Plugin {} selected endpoint: {}",
+                ctx.request_id,
+                plugin.name(),
+                endpoint_label(&endpoint.url)
+            );
+            Some(endpoint)
+        })
+    }
+
+    // 任一插件返回 false 时回答不写入缓存
+    pub fn pre_cache_store(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        let mut cacheable = true;
+        for plugin in &self.response_plugins {
+            cacheable &= plugin.pre_cache_store(ctx, response)?;
+        }
+        Ok(cacheable)
+    }
+
+    pub fn post_cache_hit(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<(), AppError> {
+        for plugin in &self.response_plugins {
+            plugin.post_cache_hit(ctx, response)?;
+        }
+        Ok(())
+    }
+
+    pub fn handles_cache_hit(&self, ctx: &ResponseContext) -> bool {
+        self.response_plugins
+            .iter()
+            .any(|plugin| plugin.handles_cache_hit(ctx))
+    }
+}
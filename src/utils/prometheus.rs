@@ -0,0 +1,140 @@
+use crate::models::api_model::AppState;
+use crate::utils::db_writer::db_write_stats;
+use std::fmt::Write;
+
+// Prometheus 文本格式中单个指标的一组样本
+struct Metric<'a> {
+    name: &'a str,
+    help: &'a str,
+    kind: &'a str,
+    // (标签, 值)，标签为空时不输出大括号
+    samples: Vec<(String, f64)>,
+}
+
+impl Metric<'_> {
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (labels, value) in &self.samples {
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {}", self.name, value);
+            } else {
+                let _ = writeln!(out, "{}{{{}}} {}", self.name, labels, value);
+            }
+        }
+    }
+}
+
+fn single<'a>(name: &'a str, help: &'a str, kind: &'a str, value: f64) -> Metric<'a> {
+    Metric {
+        name,
+        help,
+        kind,
+        samples: vec![(String::new(), value)],
+    }
+}
+
+/// 以 Prometheus 文本格式（0.0.4）输出内存缓存、缓存命中与数据库写入指标
+pub fn render_metrics(state: &AppState) -> String {
+    let mut metrics = Vec::new();
+
+    let lifetime = state.hit_stats.lifetime();
+    metrics.push(Metric {
+        name: "llm_cache_requests_total",
+        help: "启动以来按缓存结果统计的请求数",
+        kind: "counter",
+        samples: vec![
+            ("outcome=\"memory_hit\"".to_string(), lifetime.memory_hits as f64),
+            ("outcome=\"db_hit\"".to_string(), lifetime.db_hits as f64),
+            ("outcome=\"miss\"".to_string(), lifetime.misses as f64),
+            ("outcome=\"bypass\"".to_string(), lifetime.bypass as f64),
+        ],
+    });
+
+    if let Some(cache) = &state.memory_cache {
+        let stats = cache.stats();
+        metrics.push(single(
+            "llm_cache_memory_items",
+            "内存缓存当前条目数",
+            "gauge",
+            stats.items as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_max_items",
+            "内存缓存容量上限",
+            "gauge",
+            stats.max_items as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_bytes",
+            "内存缓存与待写入队列占用的字节数（压缩后）",
+            "gauge",
+            stats.bytes as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_pending_writes",
+            "待写入数据库的条目数",
+            "gauge",
+            stats.pending_writes as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_evictions_total",
+            "因达到容量上限被移入待写入队列的条目数",
+            "counter",
+            stats.evictions as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_expirations_total",
+            "因超过保留时间被移出内存的条目数",
+            "counter",
+            stats.expirations as f64,
+        ));
+        metrics.push(Metric {
+            name: "llm_cache_memory_lookups_total",
+            help: "内存缓存查询次数（按结果）",
+            kind: "counter",
+            samples: vec![
+                ("result=\"hit\"".to_string(), stats.hits as f64),
+                ("result=\"miss\"".to_string(), stats.misses as f64),
+            ],
+        });
+        metrics.push(single(
+            "llm_cache_memory_overflow_flushes_total",
+            "待写入队列超限触发的同步写入次数",
+            "counter",
+            stats.overflow_flushes as f64,
+        ));
+        metrics.push(single(
+            "llm_cache_memory_overflow_dropped_total",
+            "待写入队列超限丢弃的条目数",
+            "counter",
+            stats.overflow_dropped as f64,
+        ));
+    }
+
+    let db_writes = db_write_stats();
+    metrics.push(single(
+        "llm_cache_db_write_queue",
+        "数据库写入队列中等待执行的命令数",
+        "gauge",
+        db_writes.queued as f64,
+    ));
+    metrics.push(single(
+        "llm_cache_db_write_busy_retries_total",
+        "因数据库忙而重试的写入次数",
+        "counter",
+        db_writes.busy_retries as f64,
+    ));
+    metrics.push(single(
+        "llm_cache_db_write_busy_failures_total",
+        "重试耗尽后仍因数据库忙而失败的写入次数",
+        "counter",
+        db_writes.busy_failures as f64,
+    ));
+
+    let mut out = String::new();
+    for metric in &metrics {
+        metric.render(&mut out);
+    }
+    out
+}
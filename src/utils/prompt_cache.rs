@@ -0,0 +1,70 @@
+use crate::models::api_model::ChatMessageJson;
+use crate::utils::context_trim::{TokenCounter, calculate_total_tokens};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PromptCacheConfig {
+    // 是否为所有端点添加提示词前缀缓存标记（cache_control），端点可用 prompt_cache_hints 单独设置
+    pub enabled: bool,
+    // 前缀（从第一条消息到标记位置）至少达到该 token 数才添加标记，过短的前缀缓存收益低于写入开销
+    pub min_prefix_tokens: usize,
+}
+
+impl Default for PromptCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_prefix_tokens: 1024,
+        }
+    }
+}
+
+/// 选出应添加缓存标记的消息序号：开头的系统提示词末尾，以及最后一条用户消息之前的对话历史末尾。
+/// 这两段前缀在多轮对话中保持不变；累计 token 数不足 `min_prefix_tokens` 或内容为空的位置不标记
+pub fn cache_breakpoints(
+    messages: &[ChatMessageJson],
+    counter: TokenCounter,
+    min_prefix_tokens: usize,
+) -> Vec<usize> {
+    let Some(last_user) = messages
+        .iter()
+        .rposition(|msg| msg.role.eq_ignore_ascii_case("user"))
+    else {
+        return Vec::new();
+    };
+    let system_end = messages
+        .iter()
+        .take_while(|msg| msg.role.eq_ignore_ascii_case("system"))
+        .count()
+        .checked_sub(1);
+    let history_end = last_user.checked_sub(1);
+
+    let mut breakpoints: Vec<usize> = [system_end, history_end].into_iter().flatten().collect();
+    breakpoints.dedup();
+    breakpoints.retain(|&index| {
+        !messages[index].content.is_empty()
+            && calculate_total_tokens(&messages[..=index], counter) >= min_prefix_tokens
+    });
+    breakpoints
+}
+
+/// 把序列化后请求中指定消息的内容改写为带 cache_control 的文本块（Anthropic 格式，
+/// OpenRouter、LiteLLM 等 OpenAI 兼容网关同样支持）
+pub fn annotate_payload(payload: &mut Value, breakpoints: &[usize]) {
+    let Some(messages) = payload.get_mut("messages").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for &index in breakpoints {
+        let Some(message) = messages.get_mut(index) else {
+            continue;
+        };
+        if let Some(text) = message.get("content").and_then(Value::as_str) {
+            message["content"] = json!([{
+                "type": "text",
+                "text": text,
+                "cache_control": {"type": "ephemeral"},
+            }]);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{log_debug, log_warn};
+use crate::models::api_model::{ChatMessageJson, ChatRequestJson};
+use crate::utils::error::AppError;
+use crate::utils::plugin::{RequestContext, RequestPlugin};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PromptInjectionRule {
+    // prepend：在最前面插入配置的 system 消息；replace：移除客户端的 system 消息后再插入
+    #[serde(default = "default_injection_mode")]
+    pub mode: String,
+    pub system_prompt: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PromptInjectionConfig {
+    pub enabled: bool,
+    #[serde(default = "default_injection_mode")]
+    pub mode: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    // 按模型名（精确匹配优先，其次最长前缀匹配）单独配置，优先于全局配置
+    #[serde(default)]
+    pub models: HashMap<String, PromptInjectionRule>,
+}
+
+impl Default for PromptInjectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: default_injection_mode(),
+            system_prompt: String::new(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+fn default_injection_mode() -> String {
+    "prepend".to_string()
+}
+
+impl PromptInjectionConfig {
+    // 查找请求模型对应的注入规则，未配置模型规则时使用全局规则
+    fn rule_for_model(&self, model: &str) -> Option<(&str, &str)> {
+        let rule = self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map(|(_, rule)| rule)
+        });
+
+        match rule {
+            Some(rule) => Some((rule.mode.as_str(), rule.system_prompt.as_str())),
+            None if !self.system_prompt.is_empty() => {
+                Some((self.mode.as_str(), self.system_prompt.as_str()))
+            }
+            None => None,
+        }
+    }
+}
+
+/// 按配置向消息列表注入 system 消息，需在裁切与计算缓存键之前调用。返回是否进行了注入。
+pub fn apply_prompt_injection(
+    messages: &mut Vec<ChatMessageJson>,
+    config: &PromptInjectionConfig,
+    model: &str,
+) -> bool {
+    if !config.enabled {
+        return false;
+    }
+    let Some((mode, system_prompt)) = config.rule_for_model(model) else {
+        return false;
+    };
+    if system_prompt.is_empty() {
+        return false;
+    }
+
+    if mode.eq_ignore_ascii_case("replace") {
+        messages.retain(|m| !m.role.eq_ignore_ascii_case("system"));
+    } else if !mode.eq_ignore_ascii_case("prepend") {
+        log_warn!(
+            "未知的 system prompt 注入模式 {}，按 prepend 处理",
+            "Unknown system prompt injection mode {}, treating it as prepend",
+            mode
+        );
+    }
+
+    messages.insert(
+        0,
+        ChatMessageJson {
+            role: "system".to_string(),
+            content: system_prompt.to_string(),
+            ..Default::default()
+        },
+    );
+    true
+}
+
+/// 按配置注入 system prompt 的请求插件
+pub struct PromptInjectionPlugin {
+    config: PromptInjectionConfig,
+}
+
+impl PromptInjectionPlugin {
+    pub fn new(config: PromptInjectionConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl RequestPlugin for PromptInjectionPlugin {
+    fn name(&self) -> &str {
+        "prompt_injection"
+    }
+
+    fn pre_routing(
+        &self,
+        ctx: &RequestContext,
+        payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        if apply_prompt_injection(&mut payload.messages, &self.config, &payload.model) {
+            log_debug!(
+                "[{}] 已注入配置的 system prompt",
+                "[{}] Injected the configured system prompt",
+                ctx.request_id
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use crate::models::api_model::{ChatMessageJson, ChatRequestJson};
+use crate::utils::error::AppError;
+use crate::utils::plugin::{RequestContext, RequestPlugin};
+use crate::{log_debug, tr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PromptTemplate {
+    // 模板消息，content 中可使用 {{变量名}} 占位符
+    pub messages: Vec<ChatMessageJson>,
+}
+
+// 将文本中的 {{name}} 占位符替换为变量值，未提供的变量返回其名称
+fn render(text: &str, variables: &HashMap<String, serde_json::Value>) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+
+        let name = rest[start + 2..start + 2 + len].trim();
+        match variables.get(name) {
+            Some(serde_json::Value::String(value)) => output.push_str(value),
+            Some(value) => output.push_str(&value.to_string()),
+            None => return Err(name.to_string()),
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// 若请求指定了 `template`，按配置的模板展开为消息并放在客户端消息之前。
+/// 需在注入 system prompt、路由与计算缓存键之前调用。返回是否进行了展开。
+pub fn expand_prompt_template(
+    payload: &mut ChatRequestJson,
+    templates: &HashMap<String, PromptTemplate>,
+) -> Result<bool, AppError> {
+    let Some(name) = payload.template.take() else {
+        return Ok(false);
+    };
+    let template = templates.get(&name).ok_or_else(|| {
+        AppError::BadRequest(tr!(
+            "未找到提示词模板: {}",
+            "Prompt template not found: {}",
+            name
+        ))
+    })?;
+    let variables = payload.variables.take().unwrap_or_default();
+
+    let mut messages = Vec::with_capacity(template.messages.len() + payload.messages.len());
+    for message in &template.messages {
+        let content = render(&message.content, &variables).map_err(|missing| {
+            AppError::BadRequest(tr!(
+                "提示词模板 {} 缺少变量: {}",
+                "Prompt template {} is missing variables: {}",
+                name,
+                missing
+            ))
+        })?;
+        messages.push(ChatMessageJson {
+            content,
+            ..message.clone()
+        });
+    }
+    messages.append(&mut payload.messages);
+    payload.messages = messages;
+
+    Ok(true)
+}
+
+/// 按请求中的 `template` 字段展开提示词模板的请求插件
+pub struct PromptTemplatePlugin {
+    templates: HashMap<String, PromptTemplate>,
+}
+
+impl PromptTemplatePlugin {
+    pub fn new(templates: HashMap<String, PromptTemplate>) -> Self {
+        Self { templates }
+    }
+}
+
+impl RequestPlugin for PromptTemplatePlugin {
+    fn name(&self) -> &str {
+        "prompt_template"
+    }
+
+    fn pre_routing(
+        &self,
+        ctx: &RequestContext,
+        payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        if expand_prompt_template(payload, &self.templates)? {
+            log_debug!(
+                "[{}] 已展开提示词模板",
+                "[{}] Expanded the prompt template",
+                ctx.request_id
+            );
+        }
+        Ok(())
+    }
+}
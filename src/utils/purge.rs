@@ -0,0 +1,201 @@
+use crate::tr;
+use crate::utils::answer_codec::answer_model;
+use crate::utils::config::Config;
+use crate::utils::encryption::decrypt_blob;
+use crate::utils::inspect::open_cache_db;
+use sqlx::SqlitePool;
+
+/// 删除条件：`llm_api purge --model X --older-than 30d --max-hits 2`，各条件同时满足的回答才会删除
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PurgeOptions {
+    // 回答中记录的上游模型名
+    pub model: Option<String>,
+    // 回答写入时间早于多少秒之前
+    pub older_than: Option<i64>,
+    // 命中次数不超过该值
+    pub max_hits: Option<i64>,
+    // 只统计将要删除的记录，不实际删除
+    pub dry_run: bool,
+}
+
+impl PurgeOptions {
+    /// 解析 purge 子命令之后的参数，至少需要一个删除条件
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            if flag == "--dry-run" {
+                options.dry_run = true;
+                continue;
+            }
+            let value = iter
+                .next()
+                .ok_or_else(|| tr!("参数 {} 缺少取值", "Argument {} is missing a value", flag))?;
+            match flag.as_str() {
+                "--model" => options.model = Some(value.clone()),
+                "--older-than" => options.older_than = Some(parse_age(value)?),
+                "--max-hits" => {
+                    let max_hits = value.parse().map_err(|_| {
+                        tr!(
+                            "参数 {} 的取值无效: {}",
+                            "Invalid value for argument {}: {}",
+                            flag,
+                            value
+                        )
+                    })?;
+                    options.max_hits = Some(max_hits);
+                }
+                _ => return Err(tr!("未知参数: {}", "Unknown argument: {}", flag)),
+            }
+        }
+
+        if options.model.is_none() && options.older_than.is_none() && options.max_hits.is_none() {
+            return Err(tr!(
+                "至少需要指定 --model、--older-than 或 --max-hits 中的一个",
+                "At least one of --model, --older-than or --max-hits is required"
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// 解析时长，如 `90s`、`30m`、`12h`、`30d`、`2w`，返回秒数
+pub fn parse_age(value: &str) -> Result<i64, String> {
+    let invalid = || {
+        tr!(
+            "无效的时长: {}（示例: 30d、12h、45m）",
+            "Invalid duration: {} (examples: 30d, 12h, 45m)",
+            value
+        )
+    };
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at_checked(split).ok_or_else(invalid)?;
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    if number < 0 {
+        return Err(invalid());
+    }
+    number.checked_mul(unit_seconds).ok_or_else(invalid)
+}
+
+/// 删除（或预演时将要删除）的记录统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PurgeReport {
+    pub answers: u64,
+    pub questions: u64,
+    // 删除的回答的存储大小之和，单位字节
+    pub bytes: i64,
+}
+
+// 回答的模型名，无法解密或旧格式没有模型信息时为 None（不会被 --model 匹配）
+fn stored_model(response: Vec<u8>) -> Option<String> {
+    answer_model(&decrypt_blob(response).ok()?)
+}
+
+/// 在一个事务中删除满足条件的回答及指向它们的问题，now 为当前 Unix 秒
+pub async fn purge_entries(
+    pool: &SqlitePool,
+    options: &PurgeOptions,
+    now: i64,
+) -> Result<PurgeReport, sqlx::Error> {
+    let cutoff = options.older_than.map_or(i64::MAX, |age| now - age);
+    let max_hits = options.max_hits.unwrap_or(i64::MAX);
+    // 不按模型过滤时无需读取回答数据
+    let query = if options.model.is_some() {
+        "SELECT key, size, response FROM answers WHERE created_at < ? AND hit_count <= ?"
+    } else {
+        "SELECT key, size, X'' FROM answers WHERE created_at < ? AND hit_count <= ?"
+    };
+
+    let mut tx = pool.begin().await?;
+    let candidates = sqlx::query_as::<_, (String, i64, Vec<u8>)>(query)
+        .bind(cutoff)
+        .bind(max_hits)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let mut report = PurgeReport::default();
+    for (key, size, response) in candidates {
+        if let Some(model) = &options.model
+            && stored_model(response).as_ref() != Some(model)
+        {
+            continue;
+        }
+
+        if options.dry_run {
+            let questions: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM questions WHERE answer_key = ?")
+                    .bind(&key)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            report.questions += questions as u64;
+            report.answers += 1;
+        } else {
+            sqlx::query(
+                "DELETE FROM question_sources
+                 WHERE key IN (SELECT key FROM questions WHERE answer_key = ?)",
+            )
+            .bind(&key)
+            .execute(&mut *tx)
+            .await?;
+            report.questions += sqlx::query("DELETE FROM questions WHERE answer_key = ?")
+                .bind(&key)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            report.answers += sqlx::query("DELETE FROM answers WHERE key = ?")
+                .bind(&key)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        report.bytes += size;
+    }
+
+    if !options.dry_run {
+        tx.commit().await?;
+    }
+    Ok(report)
+}
+
+/// purge 子命令：按条件删除数据库中的缓存记录并输出删除的数量
+pub async fn run_purge(args: &[String], config: &Config) -> Result<(), String> {
+    let options = PurgeOptions::parse(args)?;
+    let pool = open_cache_db(config, "purge").await?;
+    let report = purge_entries(&pool, &options, chrono::Utc::now().timestamp())
+        .await
+        .map_err(|e| {
+            tr!(
+                "删除缓存记录失败: {}",
+                "Failed to delete cache entries: {}",
+                e
+            )
+        })?;
+
+    let summary = if options.dry_run {
+        tr!(
+            "将删除: {} 个回答，{} 个问题，{} 字节",
+            "Would remove: {} answers, {} questions, {} bytes",
+            report.answers,
+            report.questions,
+            report.bytes
+        )
+    } else {
+        tr!(
+            "已删除: {} 个回答，{} 个问题，{} 字节",
+            "Removed: {} answers, {} questions, {} bytes",
+            report.answers,
+            report.questions,
+            report.bytes
+        )
+    };
+    println!("{}", summary);
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use crate::{log_error, log_info};
+use crate::utils::answer_codec::{answer_key, answer_stamp};
+use crate::utils::encryption::encrypt_blob;
+use crate::utils::endpoint_stats::endpoint_label;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RedisCacheConfig {
+    // 连接地址，如 redis://:password@127.0.0.1:6379/0
+    pub url: String,
+    // 键前缀，多套缓存共用一个 Redis 时用于区分
+    pub key_prefix: String,
+    // 缓存条目的过期时间（秒），0 表示不过期
+    pub ttl_seconds: u64,
+    // 从 Redis 同步全局缓存纪元的间隔（秒），其他节点使缓存失效后最迟经过该时间生效
+    #[serde(default = "default_epoch_refresh_seconds")]
+    pub epoch_refresh_seconds: u64,
+}
+
+impl Default for RedisCacheConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379".to_string(),
+            key_prefix: "llm_cache:".to_string(),
+            ttl_seconds: 0,
+            epoch_refresh_seconds: default_epoch_refresh_seconds(),
+        }
+    }
+}
+
+pub fn default_epoch_refresh_seconds() -> u64 {
+    5
+}
+
+/// 从 Redis 读取的缓存回答
+pub struct RedisAnswer {
+    pub answer_key: String,
+    pub response: Vec<u8>,
+    pub created_at: i64,
+    pub version: i64,
+}
+
+/// Redis 缓存后端，与 SQLite 的 answers / questions 表结构对应：
+/// 答案保存为 `{prefix}answer:{key}` 哈希，问题保存为指向答案键的 `{prefix}question:{key}`
+pub struct RedisCache {
+    conn: ConnectionManager,
+    key_prefix: String,
+    ttl_seconds: u64,
+}
+
+static REDIS_CACHE: OnceLock<RedisCache> = OnceLock::new();
+
+/// 连接 Redis 并将其设为缓存存储后端
+pub async fn init_redis_cache(config: &RedisCacheConfig) -> Result<(), redis::RedisError> {
+    let _ = REDIS_CACHE.set(RedisCache::connect(config).await?);
+    log_info!(
+        "缓存存储后端: Redis ({})",
+        "Cache storage backend: Redis ({})",
+        endpoint_label(&config.url)
+    );
+    Ok(())
+}
+
+/// 当前使用的 Redis 缓存后端，使用 SQLite 时返回 None
+pub fn redis_cache() -> Option<&'static RedisCache> {
+    REDIS_CACHE.get()
+}
+
+impl RedisCache {
+    /// 按配置连接 Redis
+    pub async fn connect(config: &RedisCacheConfig) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(config.url.as_str())?;
+        Ok(Self {
+            conn: client.get_connection_manager().await?,
+            key_prefix: config.key_prefix.clone(),
+            ttl_seconds: config.ttl_seconds,
+        })
+    }
+
+    fn question_key(&self, question_key: &str) -> String {
+        format!("{}question:{}", self.key_prefix, question_key)
+    }
+
+    fn answer_key(&self, answer_key: &str) -> String {
+        format!("{}answer:{}", self.key_prefix, answer_key)
+    }
+
+    fn epoch_key(&self) -> String {
+        format!("{}meta:epoch", self.key_prefix)
+    }
+
+    /// 所有节点共享的全局缓存纪元
+    pub async fn epoch(&self) -> Result<u64, redis::RedisError> {
+        let mut conn = self.conn.clone();
+        let epoch: Option<u64> = conn.get(self.epoch_key()).await?;
+        Ok(epoch.unwrap_or_default())
+    }
+
+    /// 将共享的全局缓存纪元加一，返回新的纪元
+    pub async fn bump_epoch(&self) -> Result<u64, redis::RedisError> {
+        let mut conn = self.conn.clone();
+        conn.incr(self.epoch_key(), 1u64).await
+    }
+
+    /// 按问题键查询缓存回答，只返回版本不低于 min_version、全局缓存纪元不低于 min_epoch 的回答
+    pub async fn get(
+        &self,
+        question_key: &str,
+        min_version: u8,
+        min_epoch: u64,
+    ) -> Result<Option<RedisAnswer>, redis::RedisError> {
+        let mut conn = self.conn.clone();
+        let Some(answer_key) = conn
+            .get::<_, Option<String>>(self.question_key(question_key))
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let (response, created_at, version, epoch): (
+            Option<Vec<u8>>,
+            Option<i64>,
+            Option<i64>,
+            Option<u64>,
+        ) = redis::cmd("HMGET")
+            .arg(self.answer_key(&answer_key))
+            .arg(&["response", "created_at", "version", "epoch"])
+            .query_async(&mut conn)
+            .await?;
+        // 答案可能已过期或被删除
+        let Some(response) = response else {
+            return Ok(None);
+        };
+        let version = version.unwrap_or_default();
+        if version < min_version as i64 || epoch.unwrap_or_default() < min_epoch {
+            return Ok(None);
+        }
+
+        Ok(Some(RedisAnswer {
+            answer_key,
+            response,
+            created_at: created_at.unwrap_or_default(),
+            version,
+        }))
+    }
+
+    /// 更新答案的命中次数与最近命中时间，答案不存在时忽略
+    pub async fn record_hit(&self, answer_key: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.conn.clone();
+        let key = self.answer_key(answer_key);
+        if !conn.exists::<_, bool>(&key).await? {
+            return Ok(());
+        }
+        redis::pipe()
+            .hincr(&key, "hit_count", 1)
+            .ignore()
+            .hset(&key, "last_hit_at", chrono::Utc::now().timestamp())
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await
+    }
+
+    /// 按问题键更新命中统计（内存缓存命中时使用）
+    pub async fn record_question_hit(&self, question_key: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.conn.clone();
+        match conn
+            .get::<_, Option<String>>(self.question_key(question_key))
+            .await?
+        {
+            Some(answer_key) => self.record_hit(&answer_key).await,
+            None => Ok(()),
+        }
+    }
+
+    /// 在同一个事务中写入多条缓存，已存在的答案保持不变（与 SQLite 的 INSERT OR IGNORE 一致）。
+    /// default_version 用于没有记录缓存版本的旧格式回答
+    pub async fn insert_batch(
+        &self,
+        items: &[(String, Vec<u8>)],
+        default_version: u8,
+    ) -> Result<(), redis::RedisError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for (question_key, compressed) in items {
+            // 按回答正文计算答案 key
+            let answer_key = answer_key(compressed);
+            let stamp = answer_stamp(compressed);
+            let version = stamp.cache_version.unwrap_or(default_version);
+            let epoch = stamp.cache_epoch;
+
+            // 启用缓存加密时写入密文，答案 key 仍按明文计算以便去重
+            let stored = match encrypt_blob(compressed.clone()) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    log_error!("批量写入: {}", "Batch write: {}", e);
+                    continue;
+                }
+            };
+
+            let answer = self.answer_key(&answer_key);
+            let question = self.question_key(question_key);
+            pipe.hset_nx(&answer, "response", stored)
+                .ignore()
+                .hset_nx(&answer, "size", compressed.len())
+                .ignore()
+                .hset_nx(&answer, "hit_count", 0)
+                .ignore()
+                .hset_nx(&answer, "version", version)
+                .ignore()
+                .hset_nx(&answer, "epoch", epoch)
+                .ignore()
+                .hset_nx(&answer, "created_at", now)
+                .ignore()
+                .set(&question, &answer_key)
+                .ignore();
+            if self.ttl_seconds > 0 {
+                let ttl = self.ttl_seconds as i64;
+                pipe.expire(&answer, ttl).ignore().expire(&question, ttl).ignore();
+            }
+        }
+
+        let mut conn = self.conn.clone();
+        pipe.query_async::<()>(&mut conn).await
+    }
+}
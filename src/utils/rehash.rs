@@ -0,0 +1,135 @@
+use crate::tr;
+use crate::utils::cache_key::decode_key_source;
+use crate::utils::config::{CacheConfig, Config};
+use crate::utils::inspect::open_cache_db;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+// 输出中最多列出的无法迁移的问题键
+const MAX_LISTED_KEYS: usize = 20;
+
+/// 按当前缓存键配置重新计算问题键的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RehashReport {
+    // 新旧键相同，无需迁移
+    pub unchanged: u64,
+    // 已改为新键
+    pub migrated: u64,
+    // 新键已有对应的问题（多个旧键在新策略下是同一个问题），旧键被删除
+    pub merged: u64,
+    // 没有保存缓存键来源（或来源无法解码）的问题，保持原样，在新策略下不会再被命中
+    pub unmigratable: u64,
+    pub unmigratable_keys: Vec<String>,
+}
+
+/// 在一个事务中按 config 重新计算所有保存了来源的问题键；dry_run 时只统计不修改
+pub async fn rehash_keys(
+    pool: &SqlitePool,
+    config: &CacheConfig,
+    dry_run: bool,
+) -> Result<RehashReport, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let rows = sqlx::query_as::<_, (String, Option<Vec<u8>>)>(
+        "SELECT q.key, s.source
+         FROM questions q
+         LEFT JOIN question_sources s ON s.key = q.key",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut existing: HashSet<String> = rows.iter().map(|(key, _)| key.clone()).collect();
+    let mut report = RehashReport::default();
+    for (old_key, source) in rows {
+        let new_key = source
+            .and_then(|data| decode_key_source(data).ok())
+            .and_then(|source| source.question_key(config));
+        let Some(new_key) = new_key else {
+            report.unmigratable += 1;
+            report.unmigratable_keys.push(old_key);
+            continue;
+        };
+        if new_key == old_key {
+            report.unchanged += 1;
+            continue;
+        }
+
+        existing.remove(&old_key);
+        if !existing.insert(new_key.clone()) {
+            report.merged += 1;
+            if !dry_run {
+                for statement in [
+                    "DELETE FROM questions WHERE key = ?",
+                    "DELETE FROM question_sources WHERE key = ?",
+                ] {
+                    sqlx::query(statement).bind(&old_key).execute(&mut *tx).await?;
+                }
+            }
+            continue;
+        }
+
+        report.migrated += 1;
+        if !dry_run {
+            for statement in [
+                "UPDATE questions SET key = ? WHERE key = ?",
+                "UPDATE question_sources SET key = ? WHERE key = ?",
+            ] {
+                sqlx::query(statement)
+                    .bind(&new_key)
+                    .bind(&old_key)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+
+    if !dry_run {
+        tx.commit().await?;
+    }
+    Ok(report)
+}
+
+/// rehash 子命令：缓存键策略变更后按当前配置迁移问题键，输出迁移结果与无法迁移的问题
+pub async fn run_rehash(args: &[String], config: &Config) -> Result<(), String> {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" => true,
+        _ => {
+            return Err(tr!(
+                "用法: llm_api rehash [--dry-run]",
+                "Usage: llm_api rehash [--dry-run]"
+            ));
+        }
+    };
+    let pool = open_cache_db(config, "rehash").await?;
+    let report = rehash_keys(&pool, &config.cache, dry_run)
+        .await
+        .map_err(|e| {
+            tr!(
+                "迁移问题键失败: {}",
+                "Failed to migrate question keys: {}",
+                e
+            )
+        })?;
+
+    if dry_run {
+        println!(
+            "{}",
+            tr!("将迁移: {}", "Would migrate: {}", report.migrated)
+        );
+    } else {
+        println!("{}", tr!("已迁移: {}", "Migrated: {}", report.migrated));
+    }
+    println!("{}", tr!("合并: {}", "Merged: {}", report.merged));
+    println!("{}", tr!("无需迁移: {}", "Unchanged: {}", report.unchanged));
+    println!(
+        "{}",
+        tr!("无法迁移: {}", "Unmigratable: {}", report.unmigratable)
+    );
+    for key in report.unmigratable_keys.iter().take(MAX_LISTED_KEYS) {
+        println!("  {}", key);
+    }
+    if report.unmigratable_keys.len() > MAX_LISTED_KEYS {
+        println!("  ...");
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use crate::{log_error, log_info, log_warn, tr};
+use crate::proto::{ReplicatedEntry, ReplicationBatch};
+use crate::utils::cache_epoch::current_epoch;
+use crate::utils::endpoint_stats::endpoint_label;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 节点间校验共享密钥的请求头
+pub const REPLICATION_SECRET_HEADER: &str = "x-replication-secret";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+    // 本节点标识，用于忽略自己发出的数据，为空时启动时随机生成
+    pub node_id: String,
+    // 对等节点的基础地址（如 http://10.0.0.2:4321）
+    pub peers: Vec<String>,
+    // 节点间共享的密钥，通过 X-Replication-Secret 请求头校验；启用复制时必须配置
+    pub shared_secret: String,
+    // 每批最多推送的条目数
+    pub batch_size: usize,
+    // 未凑满一批时最多等待的时间（毫秒）
+    pub flush_interval_ms: u64,
+    // 待推送队列容量，队列已满时丢弃新条目
+    pub queue_size: usize,
+    pub timeout_seconds: u64,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_id: String::new(),
+            peers: Vec::new(),
+            shared_secret: String::new(),
+            batch_size: 50,
+            flush_interval_ms: 500,
+            queue_size: 10000,
+            timeout_seconds: 5,
+        }
+    }
+}
+
+struct Replicator {
+    node_id: String,
+    sender: mpsc::Sender<ReplicatedEntry>,
+    client: reqwest::Client,
+    config: ReplicationConfig,
+}
+
+static REPLICATOR: OnceLock<Replicator> = OnceLock::new();
+
+/// 启动时初始化缓存复制，启动后台推送任务。未启用时本节点既不推送也不接收
+pub fn init_replication(config: &ReplicationConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    let node_id = if config.node_id.is_empty() {
+        uuid::Uuid::new_v4().to_string().chars().take(8).collect()
+    } else {
+        config.node_id.clone()
+    };
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log_error!(
+                "创建缓存复制客户端失败: {}",
+                "Failed to create the cache replication client: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+    tokio::spawn(push_task(
+        receiver,
+        client.clone(),
+        node_id.clone(),
+        config.clone(),
+    ));
+
+    log_info!(
+        "已启用缓存复制，节点标识: {}，对等节点数量: {}",
+        "Cache replication enabled, node id: {}, peers: {}",
+        node_id,
+        config.peers.len()
+    );
+    let _ = REPLICATOR.set(Replicator {
+        node_id,
+        sender,
+        client,
+        config: config.clone(),
+    });
+}
+
+/// 以常量时间校验请求携带的复制密钥。先各自取 SHA-256 再逐字节比较，耗时与密钥内容及长度无关；
+/// 未配置密钥时一律拒绝
+pub fn secret_matches(provided: &str, expected: &str) -> bool {
+    if expected.is_empty() {
+        return false;
+    }
+    let provided = Sha256::digest(provided.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    provided
+        .iter()
+        .zip(expected.iter())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// 本节点的标识，未启用缓存复制时返回 None
+pub fn node_id() -> Option<&'static str> {
+    REPLICATOR.get().map(|replicator| replicator.node_id.as_str())
+}
+
+/// 将本节点新写入的缓存条目加入待推送队列
+pub fn publish(question_key: String, answer: Vec<u8>, version: u8) {
+    let Some(replicator) = REPLICATOR.get() else {
+        return;
+    };
+    let entry = ReplicatedEntry {
+        question_key,
+        answer,
+        version: version as u32,
+    };
+    if let Err(mpsc::error::TrySendError::Full(_)) = replicator.sender.try_send(entry) {
+        log_warn!(
+            "缓存复制队列已满，丢弃 1 个待推送条目",
+            "Cache replication queue is full, dropping 1 pending entry"
+        );
+    }
+}
+
+/// 全局缓存纪元更新后立即通知所有对等节点（不含缓存条目），之后的每一批条目也会携带当前纪元
+pub fn publish_epoch(epoch: u64) {
+    let Some(replicator) = REPLICATOR.get() else {
+        return;
+    };
+    let body = ReplicationBatch {
+        origin: replicator.node_id.clone(),
+        entries: Vec::new(),
+        epoch,
+    }
+    .encode_to_vec();
+    tokio::spawn(async move {
+        let config = &replicator.config;
+        for peer in &config.peers {
+            if let Err(e) =
+                push_to_peer(&replicator.client, peer, &config.shared_secret, body.clone()).await
+            {
+                log_warn!(
+                    "通知节点 {} 全局缓存纪元 {} 失败: {}",
+                    "Failed to notify node {} of global cache epoch {}: {}",
+                    endpoint_label(peer),
+                    epoch,
+                    e
+                );
+            }
+        }
+    });
+}
+
+// 按批收集待推送条目，推送给所有对等节点
+async fn push_task(
+    mut receiver: mpsc::Receiver<ReplicatedEntry>,
+    client: reqwest::Client,
+    node_id: String,
+    config: ReplicationConfig,
+) {
+    let batch_size = config.batch_size.max(1);
+    let flush_interval = Duration::from_millis(config.flush_interval_ms);
+
+    while let Some(first) = receiver.recv().await {
+        let mut entries = vec![first];
+        let deadline = tokio::time::Instant::now() + flush_interval;
+        while entries.len() < batch_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(entry)) => entries.push(entry),
+                _ => break,
+            }
+        }
+
+        let count = entries.len();
+        let body = ReplicationBatch {
+            origin: node_id.clone(),
+            entries,
+            epoch: current_epoch(),
+        }
+        .encode_to_vec();
+
+        let pushes = config
+            .peers
+            .iter()
+            .map(|peer| push_to_peer(&client, peer, &config.shared_secret, body.clone()));
+        for (peer, result) in config.peers.iter().zip(futures::future::join_all(pushes).await) {
+            if let Err(e) = result {
+                log_warn!(
+                    "推送 {} 个缓存条目到节点 {} 失败: {}",
+                    "Failed to push {} cache entries to node {}: {}",
+                    count,
+                    endpoint_label(peer),
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn push_to_peer(
+    client: &reqwest::Client,
+    peer: &str,
+    secret: &str,
+    body: Vec<u8>,
+) -> Result<(), String> {
+    let url = format!("{}/internal/replicate", peer.trim_end_matches('/'));
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "application/x-protobuf")
+        .body(body);
+    if !secret.is_empty() {
+        request = request.header(REPLICATION_SECRET_HEADER, secret);
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(tr!("状态码 {}", "status code {}", response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResponseHeadersConfig {
+    // 透传给客户端的上游响应头（不区分大小写），以 * 结尾时按前缀匹配，为空时不透传
+    pub passthrough: Vec<String>,
+}
+
+impl Default for ResponseHeadersConfig {
+    fn default() -> Self {
+        Self {
+            passthrough: vec![
+                "x-ratelimit-*".to_string(),
+                "openai-processing-ms".to_string(),
+                "openai-version".to_string(),
+                "openai-model".to_string(),
+            ],
+        }
+    }
+}
+
+impl ResponseHeadersConfig {
+    fn matches(&self, name: &str) -> bool {
+        self.passthrough.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            }
+        })
+    }
+
+    /// 从上游响应头中挑出允许透传的部分（HeaderName 已是小写）
+    pub fn select(&self, headers: &HeaderMap) -> Vec<(HeaderName, HeaderValue)> {
+        headers
+            .iter()
+            .filter(|(name, _)| self.matches(name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,171 @@
+use crate::models::api_model::{ChatChoice, ChatMessageJson, ChatResponseJson, Usage};
+use crate::utils::config::ApiDefaultsConfig;
+use crate::tr;
+use crate::utils::error::AppError;
+use serde_json::Value;
+
+/// 解析上游的 chat completion 响应体：先按标准结构严格解析，失败时从通用 JSON 中提取可用字段构造兼容的响应。
+/// 两种方式都失败（不是 JSON 对象或没有任何可用的 choice）时返回严格解析的错误信息。
+/// 启用 `strict_response_parsing` 时只做严格解析。两种方式都要求至少一个 choice，负的 token 数按 0 处理。
+pub fn parse_chat_response(
+    text: &str,
+    defaults: &ApiDefaultsConfig,
+) -> Result<ChatResponseJson, String> {
+    let strict_err = match serde_json::from_str::<ChatResponseJson>(text) {
+        Ok(mut response) if !response.choices.is_empty() => {
+            let usage = &mut response.usage;
+            usage.prompt_tokens = usage.prompt_tokens.max(0);
+            usage.completion_tokens = usage.completion_tokens.max(0);
+            usage.total_tokens = usage.total_tokens.max(0);
+            return Ok(response);
+        }
+        Ok(_) => tr!("choices 数组为空", "The choices array is empty"),
+        Err(e) => e.to_string(),
+    };
+    if defaults.strict_response_parsing {
+        return Err(tr!(
+            "上游响应不符合标准结构: {}",
+            "The upstream response does not match the standard structure: {}",
+            strict_err
+        ));
+    }
+
+    let generic_json = match serde_json::from_str::<Value>(text) {
+        Ok(Value::Object(map)) => Value::Object(map),
+        _ => return Err(strict_err),
+    };
+    let choices = extract_choices(&generic_json, defaults);
+    if choices.is_empty() {
+        return Err(strict_err);
+    }
+    Ok(construct_response(&generic_json, choices, defaults))
+}
+
+/// 响应体解析失败时的错误：严格模式下视为上游响应不合规（502），否则为内部错误（500）
+pub fn parse_error(defaults: &ApiDefaultsConfig, context: &str, detail: String) -> AppError {
+    AppError::InvalidUpstreamResponse {
+        message: format!("{}: {}", context, detail),
+        strict: defaults.strict_response_parsing,
+    }
+}
+
+/// 拆分 curl `-w '\n%{http_code}'` 的输出：末行为状态码，其余为响应体（没有换行时响应体为空）
+pub fn split_status_trailer(output: &str) -> (&str, Option<u16>) {
+    match output.rsplit_once('\n') {
+        Some((body, code)) => (body, code.trim().parse::<u16>().ok()),
+        None => ("", output.trim().parse::<u16>().ok()),
+    }
+}
+
+// 只保留对象形式的 choice，内容缺失时按空字符串处理
+fn extract_choices(generic_json: &Value, defaults: &ApiDefaultsConfig) -> Vec<ChatChoice> {
+    let Some(choices) = generic_json.get("choices").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    choices
+        .iter()
+        .filter(|choice| choice.is_object())
+        .enumerate()
+        .map(|(idx, choice)| {
+            let message = choice.get("message");
+            let field = |name: &str| message.and_then(|m| m.get(name));
+            // 兼容旧版 completions 接口的 choice.text
+            let content = field("content")
+                .or_else(|| choice.get("text"))
+                .map(content_text)
+                .unwrap_or_default();
+            let role = field("role")
+                .and_then(Value::as_str)
+                .unwrap_or(&defaults.default_role)
+                .to_string();
+            let finish_reason = choice
+                .get("finish_reason")
+                .and_then(Value::as_str)
+                .unwrap_or(&defaults.default_finish_reason)
+                .to_string();
+            let non_null = |value: Option<&Value>| value.filter(|v| !v.is_null()).cloned();
+
+            ChatChoice {
+                index: choice
+                    .get("index")
+                    .and_then(Value::as_i64)
+                    .map_or(idx as i32, clamp_i32),
+                logprobs: None,
+                finish_reason,
+                message: ChatMessageJson {
+                    role,
+                    content,
+                    tool_calls: non_null(field("tool_calls")),
+                    function_call: non_null(field("function_call")),
+                    ..Default::default()
+                },
+            }
+        })
+        .collect()
+}
+
+// 消息内容为字符串，或由多个 {"type": "text", "text": ...} 片段组成的数组
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.as_str().or_else(|| part.get("text")?.as_str()))
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+fn clamp_i32(value: i64) -> i32 {
+    value.clamp(0, i32::MAX as i64) as i32
+}
+
+// token 数可能是整数、浮点数或数字字符串，其他情况按 0 处理
+fn token_count(usage: Option<&Value>, name: &str) -> i32 {
+    let Some(value) = usage.and_then(|u| u.get(name)) else {
+        return 0;
+    };
+    let number = match value {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_u64().map(|v| v.min(i64::MAX as u64) as i64))
+            .or_else(|| n.as_f64().filter(|v| v.is_finite()).map(|v| v as i64)),
+        Value::String(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    };
+    number.map_or(0, clamp_i32)
+}
+
+fn construct_response(
+    generic_json: &Value,
+    choices: Vec<ChatChoice>,
+    defaults: &ApiDefaultsConfig,
+) -> ChatResponseJson {
+    let text = |name: &str, default: &str| {
+        generic_json
+            .get(name)
+            .and_then(Value::as_str)
+            .unwrap_or(default)
+            .to_string()
+    };
+    let usage = generic_json.get("usage");
+
+    ChatResponseJson {
+        id: text("id", &defaults.default_system_fingerprint),
+        object: text("object", &defaults.default_object),
+        created: generic_json
+            .get("created")
+            .and_then(Value::as_i64)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp()),
+        model: text("model", &defaults.default_system_fingerprint),
+        choices,
+        usage: Usage {
+            prompt_tokens: token_count(usage, "prompt_tokens"),
+            completion_tokens: token_count(usage, "completion_tokens"),
+            total_tokens: token_count(usage, "total_tokens"),
+            estimated: None,
+        },
+        stats: Value::Null,
+        system_fingerprint: text("system_fingerprint", &defaults.default_system_fingerprint),
+    }
+}
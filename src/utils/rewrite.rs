@@ -0,0 +1,162 @@
+use crate::log_debug;
+use crate::models::api_model::{ChatRequestJson, StopSequences};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 请求改写规则：匹配条件全部满足时，在转发上游之前修改请求头与请求参数
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RewriteRule {
+    // 规则名称，仅用于日志
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "match", default)]
+    pub matcher: RewriteMatch,
+    // 设置（覆盖）转发给上游的请求头
+    #[serde(default)]
+    pub set_headers: HashMap<String, String>,
+    // 移除转发给上游的请求头（不区分大小写）
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+    // 覆盖发送给上游的模型（优先于端点配置的模型）
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    // 追加到请求 stop 列表中的停止序列
+    #[serde(default)]
+    pub append_stop: Option<String>,
+}
+
+/// 匹配条件，未设置的条件视为满足
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RewriteMatch {
+    // 请求路径，精确匹配（如 /v1/chat/completions）
+    #[serde(default)]
+    pub path: Option<String>,
+    // 客户端请求的模型，按前缀匹配
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub header: Option<HeaderMatch>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HeaderMatch {
+    pub name: String,
+    // 请求头的值，精确匹配；未设置时只要求请求头存在
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+impl RewriteRule {
+    fn matches(&self, path: &str, model: &str, headers: &HeaderMap) -> bool {
+        let matcher = &self.matcher;
+        if let Some(expected) = &matcher.path
+            && expected != path
+        {
+            return false;
+        }
+        if let Some(prefix) = &matcher.model
+            && !model.starts_with(prefix.as_str())
+        {
+            return false;
+        }
+        if let Some(header) = &matcher.header {
+            let Some(value) = headers.get(header.name.as_str()) else {
+                return false;
+            };
+            if let Some(expected) = &header.value
+                && value.to_str().ok() != Some(expected.as_str())
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn label(&self) -> &str {
+        if self.name.is_empty() {
+            "<unnamed>"
+        } else {
+            &self.name
+        }
+    }
+}
+
+/// 按配置顺序返回与请求匹配的改写规则
+pub fn matching_rewrites<'a>(
+    rules: &'a [RewriteRule],
+    path: &str,
+    model: &str,
+    headers: &HeaderMap,
+) -> Vec<&'a RewriteRule> {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(path, model, headers))
+        .collect()
+}
+
+/// 将改写规则应用到发送给上游的请求参数，后面的规则覆盖前面的规则
+pub fn apply_payload_rewrites(
+    rules: &[&RewriteRule],
+    payload: &mut ChatRequestJson,
+    request_id: &str,
+) {
+    for rule in rules {
+        log_debug!(
+            "[{}] 应用请求改写规则: {}",
+            "[{}] Applying request rewrite rule: {}",
+            request_id,
+            rule.label()
+        );
+        if let Some(model) = &rule.model {
+            payload.model = model.clone();
+        }
+        if let Some(temperature) = rule.temperature {
+            payload.temperature = temperature;
+        }
+        if let Some(stop) = &rule.append_stop {
+            let mut sequences = payload
+                .stop
+                .take()
+                .map(StopSequences::into_vec)
+                .unwrap_or_default();
+            if !sequences.contains(stop) {
+                sequences.push(stop.clone());
+            }
+            payload.stop = Some(StopSequences::Multiple(sequences));
+        }
+    }
+}
+
+/// 匹配规则中会改变请求参数（模型、温度、停止序列）的部分，按规则顺序序列化，计入缓存键，
+/// 使经过改写的回答不会被未匹配这些规则的请求命中；没有这类规则时返回 None
+pub fn payload_rewrite_key(rules: &[&RewriteRule]) -> Option<String> {
+    let effects: Vec<serde_json::Value> = rules
+        .iter()
+        .filter(|rule| {
+            rule.model.is_some() || rule.temperature.is_some() || rule.append_stop.is_some()
+        })
+        .map(|rule| {
+            serde_json::json!({
+                "model": rule.model,
+                "temperature": rule.temperature,
+                "append_stop": rule.append_stop,
+            })
+        })
+        .collect();
+    (!effects.is_empty()).then(|| serde_json::Value::Array(effects).to_string())
+}
+
+/// 将改写规则应用到转发给上游的请求头，先移除再设置
+pub fn apply_header_rewrites(rules: &[&RewriteRule], headers: &mut HashMap<String, String>) {
+    for rule in rules {
+        for name in rule.remove_headers.iter().chain(rule.set_headers.keys()) {
+            headers.retain(|key, _| !key.eq_ignore_ascii_case(name));
+        }
+        for (name, value) in &rule.set_headers {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+}
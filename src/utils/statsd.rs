@@ -0,0 +1,184 @@
+use crate::{log_info, log_warn};
+use crate::models::api_model::AppState;
+use crate::utils::db_writer::db_write_stats;
+use serde::{Deserialize, Serialize};
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatsdConfig {
+    pub enabled: bool,
+    // StatsD 服务地址（UDP）
+    pub address: String,
+    // 指标名前缀
+    pub prefix: String,
+    // 是否使用 DogStatsD 标签扩展（Datadog / Telegraf 的 datadog_extensions），关闭时发送标准 StatsD 格式
+    pub dogstatsd: bool,
+    // 附加到每个指标的全局标签（如 env:home），仅 dogstatsd 开启时生效
+    pub tags: Vec<String>,
+    // 推送内存缓存条目数、命中率等瞬时值的间隔（秒），0 表示不推送
+    pub gauge_interval_seconds: u64,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:8125".to_string(),
+            prefix: "llm_cache".to_string(),
+            dogstatsd: false,
+            tags: Vec::new(),
+            gauge_interval_seconds: 10,
+        }
+    }
+}
+
+/// 通过 UDP 推送计数、耗时与瞬时值的 StatsD 客户端。未启用或初始化失败时所有方法均为空操作。
+pub struct StatsdClient {
+    socket: Option<UdpSocket>,
+    prefix: String,
+    // DogStatsD 模式下的全局标签，None 表示不附加任何标签
+    tags: Option<Vec<String>>,
+}
+
+impl StatsdClient {
+    pub fn new(config: &StatsdConfig) -> Self {
+        let socket = if config.enabled {
+            match Self::connect(&config.address) {
+                Ok(socket) => {
+                    log_info!(
+                        "StatsD 指标将推送到 {}",
+                        "StatsD metrics will be pushed to {}",
+                        config.address
+                    );
+                    Some(socket)
+                }
+                Err(e) => {
+                    log_warn!(
+                        "初始化 StatsD 失败（{}）: {}，不推送指标",
+                        "Failed to initialize StatsD ({}): {}, metrics will not be pushed",
+                        config.address,
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Self {
+            socket,
+            prefix: config.prefix.trim_end_matches('.').to_string(),
+            tags: config.dogstatsd.then(|| config.tags.clone()),
+        }
+    }
+
+    fn connect(address: &str) -> std::io::Result<UdpSocket> {
+        let bind_addr = if address.starts_with('[') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(address)?;
+        // 发送失败不阻塞请求处理
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    // 计数加一
+    pub fn incr(&self, name: &str, tags: &[(&str, &str)]) {
+        self.send(name, "1", "c", tags);
+    }
+
+    // 记录耗时（毫秒）
+    pub fn timing(&self, name: &str, millis: u64, tags: &[(&str, &str)]) {
+        self.send(name, &millis.to_string(), "ms", tags);
+    }
+
+    // 记录瞬时值
+    pub fn gauge(&self, name: &str, value: f64) {
+        self.send(name, &value.to_string(), "g", &[]);
+    }
+
+    fn send(&self, name: &str, value: &str, kind: &str, tags: &[(&str, &str)]) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        let mut line = if self.prefix.is_empty() {
+            format!("{}:{}|{}", name, value, kind)
+        } else {
+            format!("{}.{}:{}|{}", self.prefix, name, value, kind)
+        };
+
+        if let Some(global_tags) = &self.tags {
+            let extra = tags
+                .iter()
+                .map(|(key, value)| format!("{}:{}", key, sanitize_tag(value)));
+            let all: Vec<String> = global_tags.iter().cloned().chain(extra).collect();
+            if !all.is_empty() {
+                line.push_str("|#");
+                line.push_str(&all.join(","));
+            }
+        }
+
+        // UDP 推送尽力而为，失败时忽略
+        let _ = socket.send(line.as_bytes());
+    }
+}
+
+// 标签值中的 `,`、`|`、`#` 与 StatsD 协议冲突，替换为下划线
+fn sanitize_tag(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if matches!(c, ',' | '|' | '#') { '_' } else { c })
+        .collect()
+}
+
+// 启动瞬时值定期推送任务
+pub fn start_statsd_gauge_task(state: Arc<AppState>, interval_seconds: u64) {
+    if !state.statsd.is_enabled() || interval_seconds == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+
+        loop {
+            interval.tick().await;
+            let statsd = &state.statsd;
+
+            let window = state.hit_stats.window();
+            statsd.gauge("cache.hit_rate", window.hit_rate);
+
+            if let Some(cache) = &state.memory_cache {
+                let stats = cache.stats();
+                statsd.gauge("memory_cache.items", stats.items as f64);
+                statsd.gauge("memory_cache.pending_writes", stats.pending_writes as f64);
+                statsd.gauge("memory_cache.bytes", stats.bytes as f64);
+                statsd.gauge("memory_cache.evictions", stats.evictions as f64);
+                statsd.gauge("memory_cache.hits", stats.hits as f64);
+                statsd.gauge("memory_cache.misses", stats.misses as f64);
+            }
+
+            let in_flight: i64 = state
+                .api_endpoints
+                .iter()
+                .map(|endpoint| state.endpoint_stats.snapshot(&endpoint.url).in_flight)
+                .sum();
+            statsd.gauge("upstream.in_flight", in_flight as f64);
+
+            let db_writes = db_write_stats();
+            statsd.gauge("db_writer.busy_retries", db_writes.busy_retries as f64);
+            statsd.gauge("db_writer.busy_failures", db_writes.busy_failures as f64);
+            statsd.gauge("db_writer.queued", db_writes.queued as f64);
+        }
+    });
+}
@@ -0,0 +1,127 @@
+use crate::{log_info, log_warn};
+use std::sync::{Mutex, OnceLock};
+
+// systemd 传递的第一个监听套接字的文件描述符（SD_LISTEN_FDS_START）
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+// systemd 套接字激活传入的监听套接字，按 .socket 单元中 ListenStream 的顺序排列，取用后置为 None
+static LISTENERS: OnceLock<Mutex<Vec<Option<std::net::TcpListener>>>> = OnceLock::new();
+
+/// 根据 LISTEN_PID 与 LISTEN_FDS 计算传给本进程的套接字数量，LISTEN_PID 与当前进程不符时为 0
+pub fn listen_fds_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    let for_us = listen_pid
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .is_some_and(|listen_pid| listen_pid == pid);
+    if !for_us {
+        return 0;
+    }
+    listen_fds
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn inherited_listeners() -> Vec<Option<std::net::TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    let count = listen_fds_count(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    (0..count)
+        .map(|offset| {
+            let fd = LISTEN_FDS_START + offset as i32;
+            // SAFETY: LISTEN_PID 与本进程一致时，systemd 保证 3..3+LISTEN_FDS
+            // 是传给本进程的已打开套接字，每个描述符只在这里转换一次
+            let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            match listener.set_nonblocking(true) {
+                Ok(()) => Some(listener),
+                Err(e) => {
+                    log_warn!(
+                        "systemd 传入的套接字 {} 不可用: {}",
+                        "Socket {} passed by systemd is unusable: {}",
+                        fd,
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn inherited_listeners() -> Vec<Option<std::net::TcpListener>> {
+    Vec::new()
+}
+
+/// 取出 systemd 套接字激活传入的第 `index` 个监听套接字（0 为 HTTP 服务，1 为 gRPC 服务），
+/// 未通过套接字激活启动或已被取走时返回 None
+pub fn take_listener(index: usize) -> Option<std::net::TcpListener> {
+    let listeners = LISTENERS.get_or_init(|| Mutex::new(inherited_listeners()));
+    let listener = listeners.lock().ok()?.get_mut(index)?.take()?;
+    log_info!(
+        "使用 systemd 传入的第 {} 个监听套接字",
+        "Using listening socket #{} passed by systemd",
+        index
+    );
+    Some(listener)
+}
+
+/// 向指定的 sd_notify 套接字发送状态，路径以 @ 开头时为抽象命名空间套接字（仅 Linux）
+#[cfg(unix)]
+pub fn notify_socket(path: &str, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                crate::tr!(
+                    "当前平台不支持抽象命名空间套接字",
+                    "Abstract namespace sockets are not supported on this platform"
+                ),
+            ));
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn notify_socket(_path: &str, _state: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        crate::tr!(
+            "当前平台不支持 sd_notify",
+            "sd_notify is not supported on this platform"
+        ),
+    ))
+}
+
+/// 若由 systemd 以 Type=notify 启动（设置了 NOTIFY_SOCKET），发送状态通知（如 READY=1、STOPPING=1）
+pub fn notify(state: &str) {
+    let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = notify_socket(&path, state) {
+        log_warn!(
+            "向 systemd 发送 {} 失败: {}",
+            "Failed to send {} to systemd: {}",
+            state,
+            e
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{log_warn, tr};
+use crate::utils::error::{AppError, MAX_UPSTREAM_ERROR_BODY};
+use axum::http::{HeaderMap, Method, Request, StatusCode, header};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const UNIX_URL_PREFIX: &str = "unix://";
+
+// 通过 Unix 套接字读取的成功响应体上限，超过时视为上游异常
+const MAX_RESPONSE_BODY: usize = 64 * 1024 * 1024;
+
+/// 通过 Unix 域套接字返回的上游响应
+pub struct UnixSocketResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl UnixSocketResponse {
+    /// 将非成功响应转换为错误，原样保留上游状态码与（最多 MAX_UPSTREAM_ERROR_BODY 字节的）响应体
+    pub fn into_error(self) -> AppError {
+        let retry_after = self
+            .headers
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let body = &self.body.as_bytes()[..self.body.len().min(MAX_UPSTREAM_ERROR_BODY)];
+        AppError::Upstream {
+            status: self.status,
+            body: String::from_utf8_lossy(body).into_owned(),
+            retry_after,
+        }
+    }
+}
+
+/// 判断端点地址是否为 Unix 域套接字（如 `unix:///run/llama.sock`）
+pub fn is_unix_url(url: &str) -> bool {
+    url.starts_with(UNIX_URL_PREFIX)
+}
+
+/// 取出 `unix://` 之后的套接字路径与请求路径部分，地址格式无效时返回 None
+pub fn unix_url_target(url: &str) -> Option<&str> {
+    url.strip_prefix(UNIX_URL_PREFIX)
+        .filter(|rest| !rest.is_empty())
+}
+
+/// 将 `unix:///run/llama.sock/v1/chat/completions` 拆分为套接字路径与 HTTP 请求路径。
+/// 从左到右查找第一个存在且不是目录的路径前缀作为套接字文件，找不到时整个地址视为套接字路径。
+/// 每个请求都会调用，文件检查走 tokio::fs，不阻塞运行时线程
+pub async fn split_unix_url(url: &str) -> Option<(PathBuf, String)> {
+    let rest = unix_url_target(url)?;
+
+    for (idx, _) in rest.match_indices('/').skip(1) {
+        let candidate = Path::new(&rest[..idx]);
+        if tokio::fs::metadata(candidate)
+            .await
+            .is_ok_and(|metadata| !metadata.is_dir())
+        {
+            return Some((candidate.to_path_buf(), rest[idx..].to_string()));
+        }
+    }
+
+    Some((PathBuf::from(rest), "/".to_string()))
+}
+
+/// 通过 Unix 域套接字发送 HTTP/1.1 请求（llama.cpp / vLLM 等本地部署常用的暴露方式）
+pub async fn send_unix_socket_request(
+    method: Method,
+    url: &str,
+    headers: &HashMap<String, String>,
+    body: Option<String>,
+    timeout: Duration,
+) -> Result<UnixSocketResponse, AppError> {
+    let (socket_path, request_path) = split_unix_url(url).await.ok_or_else(|| {
+        AppError::BadGateway(tr!(
+            "无效的 Unix 套接字地址: {}",
+            "Invalid Unix socket address: {}",
+            url
+        ))
+    })?;
+
+    match tokio::time::timeout(
+        timeout,
+        send_request_inner(method, &socket_path, &request_path, headers, body),
+    )
+    .await
+    {
+        Ok(result) => result,
+        Err(_) => Err(AppError::GatewayTimeout(tr!(
+            "通过 Unix 套接字请求上游超时: {}",
+            "Upstream request over Unix socket timed out: {}",
+            socket_path.display()
+        ))),
+    }
+}
+
+#[cfg(unix)]
+async fn send_request_inner(
+    method: Method,
+    socket_path: &Path,
+    request_path: &str,
+    headers: &HashMap<String, String>,
+    body: Option<String>,
+) -> Result<UnixSocketResponse, AppError> {
+    let stream = tokio::net::UnixStream::connect(socket_path)
+        .await
+        .map_err(|e| {
+            AppError::BadGateway(tr!(
+                "无法连接到 Unix 套接字 {}: {}",
+                "Cannot connect to Unix socket {}: {}",
+                socket_path.display(),
+                e
+            ))
+        })?;
+
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(stream))
+            .await
+            .map_err(|e| {
+                AppError::BadGateway(tr!(
+                    "Unix 套接字 HTTP 握手失败: {}",
+                    "Unix socket HTTP handshake failed: {}",
+                    e
+                ))
+            })?;
+
+    // 连接任务在请求完成后自行结束
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log_warn!("Unix 套接字连接异常: {}", "Unix socket connection error: {}", e);
+        }
+    });
+
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(request_path)
+        .header("Host", "localhost");
+    for (key, value) in headers {
+        if !key.eq_ignore_ascii_case("host") {
+            builder = builder.header(key.as_str(), value.as_str());
+        }
+    }
+    if body.is_some() && !headers.keys().any(|k| k.eq_ignore_ascii_case("content-type")) {
+        builder = builder.header("Content-Type", "application/json");
+    }
+
+    let request = builder
+        .body(Full::new(Bytes::from(body.unwrap_or_default())))
+        .map_err(|e| {
+            AppError::Internal(tr!(
+                "构造 Unix 套接字请求失败: {}",
+                "Failed to build the Unix socket request: {}",
+                e
+            ))
+        })?;
+
+    let response = sender.send_request(request).await.map_err(|e| {
+        AppError::BadGateway(tr!(
+            "通过 Unix 套接字请求上游失败: {}",
+            "Upstream request over Unix socket failed: {}",
+            e
+        ))
+    })?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let mut body = response.into_body();
+    let bytes = if status.is_success() {
+        Limited::new(body, MAX_RESPONSE_BODY)
+            .collect()
+            .await
+            .map_err(|e| {
+                AppError::BadGateway(tr!(
+                    "读取 Unix 套接字响应失败: {}",
+                    "Failed to read the Unix socket response: {}",
+                    e
+                ))
+            })?
+            .to_bytes()
+            .to_vec()
+    } else {
+        // 错误响应体只读取转发所需的部分，其余丢弃
+        let mut bytes = Vec::new();
+        while bytes.len() < MAX_UPSTREAM_ERROR_BODY {
+            match body.frame().await {
+                Some(Ok(frame)) => {
+                    if let Some(data) = frame.data_ref() {
+                        bytes.extend_from_slice(data);
+                    }
+                }
+                _ => break,
+            }
+        }
+        bytes
+    };
+
+    Ok(UnixSocketResponse {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&bytes).to_string(),
+    })
+}
+
+#[cfg(not(unix))]
+async fn send_request_inner(
+    _method: Method,
+    socket_path: &Path,
+    _request_path: &str,
+    _headers: &HashMap<String, String>,
+    _body: Option<String>,
+) -> Result<UnixSocketResponse, AppError> {
+    Err(AppError::BadGateway(tr!(
+        "当前平台不支持 Unix 套接字: {}",
+        "Unix sockets are not supported on this platform: {}",
+        socket_path.display()
+    )))
+}
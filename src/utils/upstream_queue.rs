@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpstreamQueueConfig {
+    // 等待上游并发许可的最长时间（秒），超时返回 429，0 表示没有空闲许可时立即返回
+    pub acquire_timeout_seconds: u64,
+    // 同时等待许可的请求数上限，排满后新请求立即返回 429，0 表示不限制
+    #[serde(default)]
+    pub max_queue_depth: usize,
+}
+
+impl Default for UpstreamQueueConfig {
+    fn default() -> Self {
+        Self {
+            acquire_timeout_seconds: 10,
+            max_queue_depth: 0,
+        }
+    }
+}
+
+/// 获取上游并发许可失败的原因
+#[derive(Debug)]
+pub enum AcquireError {
+    // 等待超时，附带超时时仍在等待的请求数（不含本请求）
+    Timeout { waiting: usize },
+    // 等待队列已满，附带当时正在等待的请求数
+    QueueFull { waiting: usize },
+    Closed,
+}
+
+/// 上游并发许可（max_concurrent_requests），记录正在等待许可的请求数用于估算重试时间
+pub struct UpstreamQueue {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+    waiting: AtomicUsize,
+    timeout: Duration,
+    max_depth: usize,
+}
+
+// 等待结束（包括请求被取消）时减少等待计数
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl UpstreamQueue {
+    pub fn new(permits: usize, config: &UpstreamQueueConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(permits)),
+            permits,
+            waiting: AtomicUsize::new(0),
+            timeout: Duration::from_secs(config.acquire_timeout_seconds),
+            max_depth: config.max_queue_depth,
+        }
+    }
+
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// 正在等待许可的请求数
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    /// 在超时时间内等待一个许可，许可随返回值释放；有空闲许可时不进入等待队列
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, AcquireError> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        let ahead = self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _guard = WaitingGuard(&self.waiting);
+        if self.max_depth > 0 && ahead >= self.max_depth {
+            return Err(AcquireError::QueueFull { waiting: ahead });
+        }
+        match tokio::time::timeout(self.timeout, self.semaphore.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(AcquireError::Closed),
+            Err(_) => Err(AcquireError::Timeout {
+                waiting: self.waiting().saturating_sub(1),
+            }),
+        }
+    }
+
+    /// 按排队深度估算客户端应等待的秒数：排在前面的请求每占满一轮许可，
+    /// 约需一次上游请求的平均耗时（尚无统计时按 1 秒计），结果限制在 1 到 300 秒
+    pub fn retry_after_secs(&self, waiting: usize, avg_latency_ms: Option<f64>) -> u64 {
+        let rounds = (waiting / self.permits.max(1) + 1) as f64;
+        let latency_secs = avg_latency_ms.map_or(1.0, |ms| ms / 1000.0);
+        ((rounds * latency_secs).ceil() as u64).clamp(1, 300)
+    }
+}
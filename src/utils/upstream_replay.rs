@@ -0,0 +1,240 @@
+use crate::{log_error, log_info, log_warn, tr};
+use crate::models::api_model::ChatResponseJson;
+use crate::utils::config::ApiDefaultsConfig;
+use crate::utils::error::AppError;
+use crate::utils::response_parser::{parse_chat_response, parse_error};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpstreamReplayConfig {
+    // off：正常请求上游；record：请求上游并记录每次请求与响应；replay：只从记录文件返回响应，不访问网络
+    pub mode: String,
+    // 记录文件路径（JSON Lines，每行一次请求与响应）
+    pub file: String,
+}
+
+impl Default for UpstreamReplayConfig {
+    fn default() -> Self {
+        Self {
+            mode: "off".to_string(),
+            file: "upstream_replay.jsonl".to_string(),
+        }
+    }
+}
+
+// 记录文件中的一行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReplayEntry {
+    // 请求体规范化后的 SHA-256，回放时按该值匹配
+    key: String,
+    endpoint: String,
+    request: serde_json::Value,
+    status: u16,
+    // 上游响应体（成功时为解析后的响应 JSON）
+    body: String,
+    recorded_at: i64,
+}
+
+enum ReplayMode {
+    // 记录行交给专用的写入线程，请求处理不等待文件 I/O
+    Record(mpsc::Sender<String>),
+    Replay(HashMap<String, ReplayEntry>),
+}
+
+/// 上游请求的录制与回放
+pub struct UpstreamReplay {
+    mode: ReplayMode,
+}
+
+impl UpstreamReplay {
+    /// 按配置打开记录文件，mode 为 off 时返回 None。
+    /// 记录文件是明文，启用缓存加密时配置校验会拒绝 record 模式
+    pub fn from_config(config: &UpstreamReplayConfig) -> Result<Option<Self>, String> {
+        let mode = match config.mode.to_lowercase().as_str() {
+            "off" | "" => return Ok(None),
+            "record" => {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&config.file)
+                    .map_err(|e| {
+                        tr!(
+                            "打开上游记录文件 {} 失败: {}",
+                            "Failed to open the upstream recording file {}: {}",
+                            config.file,
+                            e
+                        )
+                    })?;
+                log_info!(
+                    "上游录制模式：请求与响应将追加写入 {}",
+                    "Upstream record mode: requests and responses are appended to {}",
+                    config.file
+                );
+                ReplayMode::Record(spawn_writer(file)?)
+            }
+            "replay" => {
+                let file = std::fs::File::open(&config.file).map_err(|e| {
+                    tr!(
+                        "打开上游记录文件 {} 失败: {}",
+                        "Failed to open the upstream recording file {}: {}",
+                        config.file,
+                        e
+                    )
+                })?;
+                let mut entries = HashMap::new();
+                for (line_no, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.map_err(|e| {
+                        tr!(
+                            "读取上游记录文件失败: {}",
+                            "Failed to read the upstream recording file: {}",
+                            e
+                        )
+                    })?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<ReplayEntry>(&line) {
+                        // 同一请求记录多次时使用最后一次的响应
+                        Ok(entry) => {
+                            entries.insert(entry.key.clone(), entry);
+                        }
+                        Err(e) => log_warn!(
+                            "跳过上游记录文件第 {} 行: {}",
+                            "Skipping line {} of the upstream replay file: {}",
+                            line_no + 1,
+                            e
+                        ),
+                    }
+                }
+                log_info!(
+                    "上游回放模式：从 {} 读取 {} 条记录，不再访问上游",
+                    "Upstream replay mode: loaded {1} entries from {0}, the upstream will not be contacted",
+                    config.file,
+                    entries.len()
+                );
+                ReplayMode::Replay(entries)
+            }
+            other => {
+                return Err(tr!(
+                    "未知的 upstream_replay.mode: {}",
+                    "Unknown upstream_replay.mode: {}",
+                    other
+                ));
+            }
+        };
+        Ok(Some(Self { mode }))
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self.mode, ReplayMode::Replay(_))
+    }
+
+    /// 回放模式下按请求体返回记录的响应，未记录的请求返回 502
+    pub fn replay(
+        &self,
+        payload_json: &str,
+        defaults: &ApiDefaultsConfig,
+    ) -> Result<ChatResponseJson, AppError> {
+        let ReplayMode::Replay(entries) = &self.mode else {
+            return Err(AppError::Internal(tr!(
+                "未启用上游回放模式",
+                "Upstream replay mode is not enabled"
+            )));
+        };
+        let (key, _) = request_key(payload_json);
+        let Some(entry) = entries.get(&key) else {
+            return Err(AppError::BadGateway(tr!(
+                "回放模式下未找到该请求的记录 (key={})",
+                "No recorded response for this request in replay mode (key={})",
+                &key[..16]
+            )));
+        };
+
+        let status = StatusCode::from_u16(entry.status).unwrap_or(StatusCode::BAD_GATEWAY);
+        if !status.is_success() {
+            return Err(AppError::Upstream {
+                status,
+                body: entry.body.clone(),
+                retry_after: None,
+            });
+        }
+        parse_chat_response(&entry.body, defaults).map_err(|e| {
+            let context = tr!("解析回放记录失败", "Failed to parse the recorded response");
+            parse_error(defaults, &context, e)
+        })
+    }
+
+    /// 录制模式下记录一次上游请求的结果；连接失败、超时等没有上游响应的错误不记录。
+    /// endpoint 应为去除凭据后的端点地址（endpoint_label）
+    pub fn record(
+        &self,
+        endpoint: &str,
+        payload_json: &str,
+        result: Result<&ChatResponseJson, &AppError>,
+    ) {
+        let ReplayMode::Record(writer) = &self.mode else {
+            return;
+        };
+        let (status, body) = match result {
+            Ok(response) => match serde_json::to_string(response) {
+                Ok(body) => (StatusCode::OK, body),
+                Err(_) => return,
+            },
+            Err(AppError::Upstream { status, body, .. }) => (*status, body.clone()),
+            Err(_) => return,
+        };
+
+        let (key, request) = request_key(payload_json);
+        let entry = ReplayEntry {
+            key,
+            endpoint: endpoint.to_string(),
+            request,
+            status: status.as_u16(),
+            body,
+            recorded_at: chrono::Utc::now().timestamp(),
+        };
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        let _ = writer.send(line);
+    }
+}
+
+// 启动写入线程，依次追加收到的记录行，发送端全部释放后退出
+fn spawn_writer(mut file: std::fs::File) -> Result<mpsc::Sender<String>, String> {
+    let (sender, receiver) = mpsc::channel::<String>();
+    std::thread::Builder::new()
+        .name("upstream-replay-writer".to_string())
+        .spawn(move || {
+            for line in receiver {
+                if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+                    log_error!(
+                        "写入上游记录文件失败: {}",
+                        "Failed to write the upstream replay file: {}",
+                        e
+                    );
+                }
+            }
+        })
+        .map_err(|e| {
+            tr!(
+                "创建上游记录写入线程失败: {}",
+                "Failed to start the upstream replay writer thread: {}",
+                e
+            )
+        })?;
+    Ok(sender)
+}
+
+// 请求体解析后重新序列化（对象键有序），使字段顺序与空白不同的相同请求得到相同的键
+fn request_key(payload_json: &str) -> (String, serde_json::Value) {
+    let request = serde_json::from_str::<serde_json::Value>(payload_json)
+        .unwrap_or_else(|_| serde_json::Value::String(payload_json.to_string()));
+    let hash = Sha256::digest(request.to_string().as_bytes());
+    (hex::encode(hash), request)
+}
@@ -0,0 +1,158 @@
+use crate::{log_info, log_warn};
+use crate::models::api_model::{ApiEndpoint, AppState};
+use crate::utils::unix_socket::is_unix_url;
+use futures::future::join_all;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WarmupConfig {
+    pub enabled: bool,
+    pub connections_per_endpoint: usize,
+    pub prime_request: bool,
+    pub timeout_seconds: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            connections_per_endpoint: 2,
+            prime_request: false,
+            timeout_seconds: 5,
+        }
+    }
+}
+
+fn endpoint_path(endpoint: &ApiEndpoint, path: &str) -> String {
+    if endpoint.url.ends_with('/') {
+        format!("{}{}", endpoint.url, path)
+    } else {
+        format!("{}/{}", endpoint.url, path)
+    }
+}
+
+// 发送请求并读完响应体，连接只有在响应体读完后才会放回连接池供后续请求复用
+async fn send_and_drain(request: reqwest::RequestBuilder) -> Result<StatusCode, reqwest::Error> {
+    let response = request.send().await?;
+    let status = response.status();
+    response.bytes().await?;
+    Ok(status)
+}
+
+// 预热单个端点：并发建立多个保活连接，可选发送一个极小的推理请求；返回上游正常响应的连接数
+async fn warm_up_endpoint(
+    state: &AppState,
+    endpoint: &ApiEndpoint,
+    config: &WarmupConfig,
+) -> usize {
+    // 与实际请求使用同一客户端，保证预热的连接能被后续请求复用
+    let client = state.client_for(endpoint);
+    let start = Instant::now();
+
+    let models_url = endpoint_path(endpoint, "v1/models");
+    let connections = join_all((0..config.connections_per_endpoint.max(1)).map(|_| {
+        let mut request = client.get(&models_url);
+        for (key, value) in &state.api_headers {
+            request = request.header(key, value);
+        }
+        send_and_drain(request)
+    }))
+    .await;
+    let established = connections
+        .iter()
+        .filter(|result| matches!(result, Ok(status) if status.is_success()))
+        .count();
+    if let Some(status) = connections
+        .iter()
+        .find_map(|result| result.as_ref().ok().filter(|status| !status.is_success()))
+    {
+        log_warn!(
+            "端点预热时上游返回非成功状态码 ({}): {}",
+            "Upstream returned a non-success status during endpoint warm-up ({}): {}",
+            endpoint.url,
+            status
+        );
+    }
+
+    if config.prime_request {
+        let payload = serde_json::json!({
+            "model": endpoint.model.clone().unwrap_or_default(),
+            "messages": [{ "role": "user", "content": "ping" }],
+            "max_tokens": 1,
+            "stream": false,
+        });
+        let mut request = client.post(endpoint_path(endpoint, "v1/chat/completions"));
+        for (key, value) in &state.api_headers {
+            request = request.header(key, value);
+        }
+        match send_and_drain(request.json(&payload)).await {
+            Ok(status) if !status.is_success() => log_warn!(
+                "端点预热请求返回非成功状态码 ({}): {}",
+                "Endpoint warm-up request returned a non-success status ({}): {}",
+                endpoint.url,
+                status
+            ),
+            Ok(_) => {}
+            Err(e) => log_warn!(
+                "端点预热请求失败 ({}): {}",
+                "Endpoint warm-up request failed ({}): {}",
+                endpoint.url,
+                e
+            ),
+        }
+    }
+
+    log_info!(
+        "端点预热完成: {} (成功建立 {}/{} 个连接, 耗时 {:?})",
+        "Endpoint warm-up finished: {} ({}/{} connections established, took {:?})",
+        endpoint.url,
+        established,
+        connections.len(),
+        start.elapsed()
+    );
+    established
+}
+
+/// 启动时预热所有上游端点的连接，避免首个请求承担 TCP/TLS 建连开销；
+/// 返回上游正常响应、已放回连接池的连接总数
+pub async fn warm_up_endpoints(state: &AppState, config: &WarmupConfig) -> usize {
+    if !config.enabled {
+        return 0;
+    }
+    if state.use_curl {
+        log_info!(
+            "curl 模式不维护连接池，跳过连接预热",
+            "curl mode keeps no connection pool, skipping connection warm-up"
+        );
+        return 0;
+    }
+
+    let endpoints: Vec<&ApiEndpoint> = state
+        .api_endpoints
+        .iter()
+        .filter(|endpoint| endpoint.weight > 0 && !is_unix_url(&endpoint.url))
+        .collect();
+
+    log_info!(
+        "开始预热 {} 个上游端点的连接",
+        "Warming up connections to {} upstream endpoints",
+        endpoints.len()
+    );
+
+    let tasks = endpoints
+        .into_iter()
+        .map(|endpoint| warm_up_endpoint(state, endpoint, config));
+    match tokio::time::timeout(Duration::from_secs(config.timeout_seconds), join_all(tasks)).await {
+        Ok(established) => established.into_iter().sum(),
+        Err(_) => {
+            log_warn!(
+                "连接预热超时 ({} 秒)，继续启动",
+                "Connection warm-up timed out ({} s), continuing startup",
+                config.timeout_seconds
+            );
+            0
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::log_warn;
+use crate::utils::plugin::PluginRegistry;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WasmPluginConfig {
+    pub name: String,
+    // .wasm 模块文件路径
+    pub path: String,
+    // 单次调用可消耗的燃料（约等于指令数），用完即中止，防止死循环
+    #[serde(default = "default_wasm_fuel")]
+    pub fuel: u64,
+    // 模块线性内存上限（MB）
+    #[serde(default = "default_wasm_max_memory_mb")]
+    pub max_memory_mb: usize,
+}
+
+fn default_wasm_fuel() -> u64 {
+    100_000_000
+}
+
+fn default_wasm_max_memory_mb() -> usize {
+    64
+}
+
+/// 加载配置的 WASM 插件并按导出的钩子注册为请求和/或响应插件
+#[cfg(feature = "wasm-plugins")]
+pub fn register_wasm_plugins(
+    registry: &mut PluginRegistry,
+    configs: &[WasmPluginConfig],
+) -> Result<(), String> {
+    use crate::utils::wasm_runtime::WasmPlugin;
+    use std::sync::Arc;
+
+    for config in configs {
+        let plugin = Arc::new(WasmPlugin::load(config)?);
+        if plugin.has_on_request() {
+            registry.register_request(plugin.clone());
+        }
+        if plugin.has_on_response() {
+            registry.register_response(plugin);
+        }
+    }
+    Ok(())
+}
+
+/// 未启用 wasm-plugins 特性编译时忽略 WASM 插件配置
+#[cfg(not(feature = "wasm-plugins"))]
+pub fn register_wasm_plugins(
+    _registry: &mut PluginRegistry,
+    configs: &[WasmPluginConfig],
+) -> Result<(), String> {
+    if !configs.is_empty() {
+        log_warn!(
+            "当前版本未启用 wasm-plugins 特性，忽略 {} 个 WASM 插件（请使用 --features wasm-plugins 编译）",
+            "This build does not enable the wasm-plugins feature, ignoring {} WASM plugins (build with --features wasm-plugins)",
+            configs.len()
+        );
+    }
+    Ok(())
+}
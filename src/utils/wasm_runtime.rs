@@ -0,0 +1,301 @@
+use crate::{log_info, log_warn, tr};
+use crate::models::api_model::{ChatRequestJson, ChatResponseJson};
+use crate::utils::error::AppError;
+use crate::utils::plugin::{RequestContext, RequestPlugin, ResponseContext, ResponsePlugin};
+use crate::utils::wasm_plugin::WasmPluginConfig;
+use serde::Deserialize;
+use serde_json::json;
+use wasmtime::{
+    Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+/// 通过 WASM 模块检查或修改请求与响应的插件。
+///
+/// 模块需导出 `memory` 与 `alloc(len: i32) -> i32`，并按需导出以下钩子，
+/// 参数为 JSON 在模块内存中的指针与长度，返回值高 32 位为输出 JSON 的指针、低 32 位为长度，
+/// 长度为 0 表示不做修改：
+/// - `on_request(ptr, len) -> i64`：输入请求 JSON，输出 `{"request": {...}}` 替换请求或 `{"reject": "原因"}` 拒绝请求
+/// - `on_response(ptr, len) -> i64`：输入 `{"model", "cache_hit", "response"}`，输出 `{"response": {...}, "cache": bool}`
+///   替换回答并决定是否写入缓存，或 `{"reject": "原因"}` 以错误代替回答
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    linker: Linker<StoreLimits>,
+    fuel: u64,
+    max_memory_bytes: usize,
+    has_on_request: bool,
+    has_on_response: bool,
+}
+
+// 插件输出
+#[derive(Debug, Default, Deserialize)]
+struct WasmOutput {
+    request: Option<ChatRequestJson>,
+    response: Option<ChatResponseJson>,
+    cache: Option<bool>,
+    reject: Option<String>,
+}
+
+impl WasmPlugin {
+    /// 加载并编译 WASM 模块
+    pub fn load(config: &WasmPluginConfig) -> Result<Self, String> {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).map_err(|e| {
+            tr!(
+                "创建 WASM 引擎失败: {}",
+                "Failed to create the WASM engine: {}",
+                e
+            )
+        })?;
+        let module = Module::from_file(&engine, &config.path).map_err(|e| {
+            tr!(
+                "加载 WASM 插件 {} 失败（{}）: {}",
+                "Failed to load WASM plugin {} ({}): {}",
+                config.name,
+                config.path,
+                e
+            )
+        })?;
+
+        let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+        for required in ["memory", "alloc"] {
+            if !exports.contains(&required) {
+                return Err(tr!(
+                    "WASM 插件 {} 缺少导出项: {}",
+                    "WASM plugin {} is missing the export: {}",
+                    config.name, required
+                ));
+            }
+        }
+        let has_on_request = exports.contains(&"on_request");
+        let has_on_response = exports.contains(&"on_response");
+        if !has_on_request && !has_on_response {
+            return Err(tr!(
+                "WASM 插件 {} 未导出 on_request 或 on_response",
+                "WASM plugin {} exports neither on_request nor on_response",
+                config.name
+            ));
+        }
+
+        Ok(Self {
+            name: config.name.clone(),
+            linker: Linker::new(&engine),
+            engine,
+            module,
+            fuel: config.fuel,
+            max_memory_bytes: config.max_memory_mb * 1024 * 1024,
+            has_on_request,
+            has_on_response,
+        })
+    }
+
+    pub fn has_on_request(&self) -> bool {
+        self.has_on_request
+    }
+
+    pub fn has_on_response(&self) -> bool {
+        self.has_on_response
+    }
+
+    // 插件钩子是同步调用，在多线程运行时中通过 block_in_place 执行，
+    // 模块运行期间该工作线程上的其他任务会被转移到别的线程，不会被阻塞
+    fn call(&self, hook: &str, input: &[u8]) -> Result<Option<WasmOutput>, String> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| self.run(hook, input))
+            }
+            _ => self.run(hook, input),
+        }
+    }
+
+    // 每次调用使用独立的实例，插件之间、请求之间互不影响
+    fn run(&self, hook: &str, input: &[u8]) -> Result<Option<WasmOutput>, String> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory_bytes)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+
+        let instance: Instance = self
+            .linker
+            .instantiate(&mut store, &self.module)
+            .map_err(|e| tr!("实例化失败: {}", "Instantiation failed: {}", e))?;
+        let memory: Memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| tr!("缺少导出的 memory", "The module does not export memory"))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(|e| e.to_string())?;
+        let hook_fn = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, hook)
+            .map_err(|e| e.to_string())?;
+
+        let len = i32::try_from(input.len()).map_err(|_| tr!("输入过大", "Input is too large"))?;
+        let ptr = alloc
+            .call(&mut store, len)
+            .map_err(|e| tr!("alloc 调用失败: {}", "alloc call failed: {}", e))?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input)
+            .map_err(|e| {
+                tr!(
+                    "写入模块内存失败: {}",
+                    "Failed to write module memory: {}",
+                    e
+                )
+            })?;
+
+        let packed = hook_fn
+            .call(&mut store, (ptr, len))
+            .map_err(|e| tr!("{} 调用失败: {}", "{} call failed: {}", hook, e))?
+            as u64;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if out_len == 0 {
+            return Ok(None);
+        }
+
+        let mut output = vec![0u8; out_len];
+        memory.read(&store, out_ptr, &mut output).map_err(|e| {
+            tr!(
+                "读取模块输出失败: {}",
+                "Failed to read module output: {}",
+                e
+            )
+        })?;
+        serde_json::from_slice(&output).map(Some).map_err(|e| {
+            tr!(
+                "解析模块输出失败: {}",
+                "Failed to parse module output: {}",
+                e
+            )
+        })
+    }
+
+    fn run_response_hook(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+        cache_hit: bool,
+    ) -> Result<bool, AppError> {
+        if !self.has_on_response {
+            return Ok(true);
+        }
+
+        let input = json!({
+            "model": ctx.model,
+            "cache_hit": cache_hit,
+            "response": response,
+        });
+        let output = self
+            .call("on_response", input.to_string().as_bytes())
+            .map_err(|e| {
+                log_warn!(
+                    "[{}] WASM 插件 {} 执行失败: {}",
+                    "[{}] WASM plugin {} failed: {}",
+                    ctx.request_id,
+                    self.name,
+                    e
+                );
+                AppError::Internal(tr!(
+                    "WASM 插件 {} 执行失败",
+                    "WASM plugin {} failed",
+                    self.name
+                ))
+            })?;
+
+        let Some(output) = output else {
+            return Ok(true);
+        };
+        if let Some(reason) = output.reject {
+            log_info!(
+                "[{}] WASM 插件 {} 拒绝回答: {}",
+                "[{}] WASM plugin {} rejected the answer: {}",
+                ctx.request_id,
+                self.name,
+                reason
+            );
+            return Err(AppError::BadGateway(reason));
+        }
+        if let Some(new_response) = output.response {
+            *response = new_response;
+        }
+        Ok(output.cache.unwrap_or(true))
+    }
+}
+
+impl RequestPlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pre_routing(
+        &self,
+        ctx: &RequestContext,
+        payload: &mut ChatRequestJson,
+    ) -> Result<(), AppError> {
+        if !self.has_on_request {
+            return Ok(());
+        }
+
+        let input = serde_json::to_vec(payload).map_err(|e| {
+            AppError::Internal(tr!(
+                "序列化请求失败: {}",
+                "Failed to serialize the request: {}",
+                e
+            ))
+        })?;
+        let output = self.call("on_request", &input).map_err(|e| {
+            log_warn!(
+                "[{}] WASM 插件 {} 执行失败: {}",
+                "[{}] WASM plugin {} failed: {}",
+                ctx.request_id,
+                self.name,
+                e
+            );
+            AppError::Internal(tr!(
+                "WASM 插件 {} 执行失败",
+                "WASM plugin {} failed",
+                self.name
+            ))
+        })?;
+
+        let Some(output) = output else {
+            return Ok(());
+        };
+        if let Some(reason) = output.reject {
+            return Err(AppError::BadRequest(reason));
+        }
+        if let Some(request) = output.request {
+            // x_trim 不参与序列化，保留客户端请求中的原值
+            *payload = ChatRequestJson {
+                x_trim: payload.x_trim.take(),
+                ..request
+            };
+        }
+        Ok(())
+    }
+}
+
+impl ResponsePlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pre_cache_store(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<bool, AppError> {
+        self.run_response_hook(ctx, response, false)
+    }
+
+    fn post_cache_hit(
+        &self,
+        ctx: &ResponseContext,
+        response: &mut ChatResponseJson,
+    ) -> Result<(), AppError> {
+        self.run_response_hook(ctx, response, true).map(|_| ())
+    }
+}
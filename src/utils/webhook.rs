@@ -0,0 +1,290 @@
+use crate::{log_error, log_info, log_warn, tr};
+use crate::utils::config::HttpClientConfig;
+use crate::utils::endpoint_stats::endpoint_label;
+use crate::utils::http_client::apply_connection_options;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub targets: Vec<WebhookTarget>,
+    // 端点连续失败多少次后视为不健康
+    pub endpoint_failure_threshold: u64,
+    // 批量写入连续失败多少次后通知
+    pub batch_failure_threshold: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            targets: Vec::new(),
+            endpoint_failure_threshold: 5,
+            batch_failure_threshold: 3,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookTarget {
+    pub url: String,
+    // 消息格式：generic（JSON 事件）、slack、discord
+    #[serde(default = "default_webhook_format")]
+    pub format: String,
+    // 订阅的事件，为空表示全部
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+fn default_webhook_format() -> String {
+    "generic".to_string()
+}
+
+/// 通知事件
+#[derive(Debug, Clone)]
+pub enum WebhookEvent {
+    MaintenanceCompleted {
+        deleted_answers: u64,
+        deleted_questions: u64,
+    },
+    EndpointUnhealthy {
+        endpoint: String,
+        consecutive_errors: u64,
+        last_error: String,
+    },
+    EndpointRecovered {
+        endpoint: String,
+    },
+    BatchWriteFailed {
+        consecutive_failures: u64,
+        failed_items: usize,
+    },
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::MaintenanceCompleted { .. } => "maintenance_completed",
+            WebhookEvent::EndpointUnhealthy { .. } => "endpoint_unhealthy",
+            WebhookEvent::EndpointRecovered { .. } => "endpoint_recovered",
+            WebhookEvent::BatchWriteFailed { .. } => "batch_write_failed",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            WebhookEvent::MaintenanceCompleted {
+                deleted_answers,
+                deleted_questions,
+            } => tr!(
+                "缓存维护完成，清理答案 {} 条，问题 {} 条",
+                "Cache maintenance finished, removed {} answers and {} questions",
+                deleted_answers,
+                deleted_questions
+            ),
+            WebhookEvent::EndpointUnhealthy {
+                endpoint,
+                consecutive_errors,
+                last_error,
+            } => tr!(
+                "端点 {} 连续失败 {} 次，已标记为不健康。最近错误: {}",
+                "Endpoint {} failed {} times in a row and is marked unhealthy. Last error: {}",
+                endpoint,
+                consecutive_errors,
+                last_error
+            ),
+            WebhookEvent::EndpointRecovered { endpoint } => {
+                tr!("端点 {} 已恢复正常", "Endpoint {} has recovered", endpoint)
+            }
+            WebhookEvent::BatchWriteFailed {
+                consecutive_failures,
+                failed_items,
+            } => tr!(
+                "缓存批量写入连续失败 {} 次，最近一次失败 {} 条",
+                "Cache batch writes failed {} times in a row, {} items in the last failure",
+                consecutive_failures,
+                failed_items
+            ),
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            WebhookEvent::MaintenanceCompleted {
+                deleted_answers,
+                deleted_questions,
+            } => json!({
+                "deleted_answers": deleted_answers,
+                "deleted_questions": deleted_questions,
+            }),
+            WebhookEvent::EndpointUnhealthy {
+                endpoint,
+                consecutive_errors,
+                last_error,
+            } => json!({
+                "endpoint": endpoint,
+                "consecutive_errors": consecutive_errors,
+                "last_error": last_error,
+            }),
+            WebhookEvent::EndpointRecovered { endpoint } => json!({ "endpoint": endpoint }),
+            WebhookEvent::BatchWriteFailed {
+                consecutive_failures,
+                failed_items,
+            } => json!({
+                "consecutive_failures": consecutive_failures,
+                "failed_items": failed_items,
+            }),
+        }
+    }
+
+    // 按目标格式构造请求体
+    fn payload(&self, format: &str) -> serde_json::Value {
+        let text = format!("[LLM API Cache] {}", self.message());
+        match format {
+            "slack" => json!({ "text": text }),
+            "discord" => json!({ "content": text }),
+            _ => json!({
+                "event": self.name(),
+                "timestamp": chrono::Utc::now().timestamp(),
+                "message": self.message(),
+                "details": self.details(),
+            }),
+        }
+    }
+}
+
+struct WebhookNotifier {
+    config: WebhookConfig,
+    client: reqwest::Client,
+    batch_failures: AtomicU64,
+}
+
+static NOTIFIER: OnceLock<WebhookNotifier> = OnceLock::new();
+
+// 启动时初始化 Webhook 通知，未启用或未配置目标时不发送任何通知。
+// 客户端与上游请求使用相同的出站代理与 TLS 配置
+pub fn init_webhooks(config: &WebhookConfig, http_client: &HttpClientConfig) {
+    if !config.enabled || config.targets.is_empty() {
+        return;
+    }
+
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(config.timeout_seconds));
+    let client = match apply_connection_options(builder, http_client)
+        .and_then(|builder| Ok(builder.build()?))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log_error!("创建 Webhook 客户端失败: {}", "Failed to create the webhook client: {}", e);
+            return;
+        }
+    };
+
+    log_info!(
+        "已启用 Webhook 通知，目标数量: {}",
+        "Webhook notifications enabled, targets: {}",
+        config.targets.len()
+    );
+    let _ = NOTIFIER.set(WebhookNotifier {
+        config: config.clone(),
+        client,
+        batch_failures: AtomicU64::new(0),
+    });
+}
+
+/// 向订阅了该事件的目标异步发送通知，发送失败只记录日志
+pub fn notify(event: WebhookEvent) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    // 退出阶段可能已不在运行时中，此时放弃通知
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    for target in &notifier.config.targets {
+        if !target.events.is_empty() && !target.events.iter().any(|e| e == event.name()) {
+            continue;
+        }
+
+        let request = notifier
+            .client
+            .post(&target.url)
+            .json(&event.payload(&target.format));
+        let event_name = event.name();
+        let target_label = endpoint_label(&target.url);
+        handle.spawn(async move {
+            match request.send().await {
+                Ok(response) if !response.status().is_success() => {
+                    log_warn!(
+                        "Webhook 通知 {} 发送到 {} 失败，状态码: {}",
+                        "Webhook notification {} to {} failed, status: {}",
+                        event_name,
+                        target_label,
+                        response.status()
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log_warn!(
+                        "Webhook 通知 {} 发送到 {} 失败: {}",
+                        "Webhook notification {} to {} failed: {}",
+                        event_name,
+                        target_label,
+                        e
+                    );
+                }
+            }
+        });
+    }
+}
+
+// 端点请求失败，连续失败次数恰好达到阈值时通知
+pub fn endpoint_failed(url: &str, consecutive_errors: u64, error: &str) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    if consecutive_errors == notifier.config.endpoint_failure_threshold.max(1) {
+        notify(WebhookEvent::EndpointUnhealthy {
+            endpoint: endpoint_label(url),
+            consecutive_errors,
+            last_error: error.to_string(),
+        });
+    }
+}
+
+// 端点请求成功，此前已被标记为不健康时通知恢复
+pub fn endpoint_recovered(url: &str, previous_consecutive_errors: u64) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    if previous_consecutive_errors >= notifier.config.endpoint_failure_threshold.max(1) {
+        notify(WebhookEvent::EndpointRecovered {
+            endpoint: endpoint_label(url),
+        });
+    }
+}
+
+// 记录一次批量写入结果，连续失败次数恰好达到阈值时通知
+pub fn batch_write_result(failed_items: usize) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    if failed_items == 0 {
+        notifier.batch_failures.store(0, Ordering::Relaxed);
+        return;
+    }
+
+    let consecutive = notifier.batch_failures.fetch_add(1, Ordering::Relaxed) + 1;
+    if consecutive == notifier.config.batch_failure_threshold.max(1) {
+        notify(WebhookEvent::BatchWriteFailed {
+            consecutive_failures: consecutive,
+            failed_items,
+        });
+    }
+}
@@ -0,0 +1,108 @@
+//! 配置文件合并：include、config.d/ 与配置档按顺序覆盖主配置
+
+use llm_api::utils::config_include::{load_merged_yaml, take_profile_arg};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+// 在临时目录中写入一组配置文件，drop 时删除
+struct ConfigTree(PathBuf);
+
+impl ConfigTree {
+    fn new(files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("llm_api_cfg_{}", uuid::Uuid::new_v4().simple()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Self(dir)
+    }
+
+    fn load(&self, profile: Option<&str>) -> Result<Value, String> {
+        load_merged_yaml(&self.0.join("config.yaml"), profile)
+    }
+}
+
+impl Drop for ConfigTree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+const BASE: &str = r#"
+include: "local/secrets.yaml"
+cache:
+  enabled: true
+  max_items: 100
+  batch_write_size: 20
+server:
+  host: "127.0.0.1"
+  port: 4321
+api_endpoints:
+  - url: "http://127.0.0.1:8080"
+    weight: 1
+profiles:
+  prod:
+    server:
+      host: "0.0.0.0"
+    api_endpoints:
+      - url: "https://llm.internal"
+        weight: 3
+"#;
+
+#[test]
+fn overlays_are_merged_in_order() {
+    let tree = ConfigTree::new(&[
+        ("config.yaml", BASE),
+        ("local/secrets.yaml", "cache:\n  max_items: 500\nserver:\n  port: 5000\n"),
+        ("config.d/10-cache.yaml", "cache:\n  max_items: 1000\n"),
+        ("config.d/20-server.yml", "server:\n  port: 6000\n"),
+        ("config.d/notes.txt", "not yaml: ["),
+    ]);
+
+    let merged = tree.load(None).unwrap();
+    // 映射逐键合并：后合并的文件覆盖同名键，未提及的键保留
+    assert_eq!(merged["cache"]["max_items"], 1000);
+    assert_eq!(merged["cache"]["batch_write_size"], 20);
+    assert_eq!(merged["server"]["port"], 6000);
+    assert_eq!(merged["server"]["host"], "127.0.0.1");
+    assert!(merged.get("include").is_none() && merged.get("profiles").is_none());
+
+    // 配置档最后合并，列表整体替换
+    let prod = tree.load(Some("prod")).unwrap();
+    assert_eq!(prod["server"]["host"], "0.0.0.0");
+    assert_eq!(prod["server"]["port"], 6000);
+    let endpoints = prod["api_endpoints"].as_sequence().unwrap();
+    assert_eq!(endpoints.len(), 1);
+    assert_eq!(endpoints[0]["weight"], 3);
+}
+
+#[test]
+fn unknown_profile_lists_available_ones() {
+    let tree = ConfigTree::new(&[("config.yaml", BASE), ("local/secrets.yaml", "")]);
+    let err = tree.load(Some("staging")).unwrap_err();
+    assert!(err.contains("staging") && err.contains("prod"), "{}", err);
+}
+
+#[test]
+fn missing_include_is_an_error() {
+    let tree = ConfigTree::new(&[("config.yaml", BASE)]);
+    let err = tree.load(None).unwrap_err();
+    assert!(err.contains("secrets.yaml"), "{}", err);
+}
+
+#[test]
+fn profile_flag_is_removed_from_args() {
+    let mut args: Vec<String> = ["bench", "--profile", "prod", "--requests", "10"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(take_profile_arg(&mut args).unwrap().as_deref(), Some("prod"));
+    assert_eq!(args, ["bench", "--requests", "10"]);
+
+    let mut args = vec!["--profile=laptop".to_string()];
+    assert_eq!(take_profile_arg(&mut args).unwrap().as_deref(), Some("laptop"));
+    assert!(args.is_empty());
+
+    assert!(take_profile_arg(&mut vec!["--profile".to_string()]).is_err());
+}
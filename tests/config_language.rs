@@ -0,0 +1,62 @@
+//! logging.language 为 en 时，配置校验提示与返回给客户端的错误信息输出英文。
+//! 语言是进程级设置，单独放在一个测试程序中，避免影响其他按中文断言的测试
+
+use llm_api::utils::config::{ApiDefaultsConfig, Config};
+use llm_api::utils::config_validation::{applied_defaults, validate_config};
+use llm_api::utils::logging::{LoggingConfig, init_logging};
+use llm_api::utils::response_parser::parse_chat_response;
+
+#[test]
+fn issues_and_client_errors_follow_the_configured_language() {
+    init_logging(&LoggingConfig {
+        level: "info".to_string(),
+        language: "en".to_string(),
+    });
+
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080/v1/chat/completions\"\n    weight: 0\nmax_concurrent_requests: 0\n";
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    let issues = validate_config(&config);
+    assert!(
+        issues
+            .errors
+            .contains(&"max_concurrent_requests: must not be 0".to_string()),
+        "{:?}",
+        issues.errors
+    );
+    assert!(
+        issues
+            .errors
+            .iter()
+            .any(|e| e.starts_with("api_endpoints: every endpoint has weight 0")),
+        "{:?}",
+        issues.errors
+    );
+    assert!(
+        issues
+            .warnings
+            .iter()
+            .any(|w| w.contains("configure only the base address")),
+        "{:?}",
+        issues.warnings
+    );
+
+    let raw: serde_json::Value = serde_yaml::from_str(yaml).unwrap();
+    let lines = applied_defaults(&raw, &config);
+    assert!(
+        lines.iter().any(|l| l.starts_with("Whole sections using defaults: ")),
+        "{:?}",
+        lines
+    );
+    assert!(lines.iter().all(|l| !l.contains("整节")), "{:?}", lines);
+
+    let strict = ApiDefaultsConfig {
+        strict_response_parsing: true,
+        ..ApiDefaultsConfig::default()
+    };
+    let err = parse_chat_response("{\"choices\": 1}", &strict).unwrap_err();
+    assert!(
+        err.starts_with("The upstream response does not match the standard structure"),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,210 @@
+//! 配置校验：示例配置应通过校验，常见的错误配置应给出指向具体配置项的错误
+
+use llm_api::utils::config::Config;
+use llm_api::utils::config_validation::{applied_defaults, validate_config};
+use std::path::Path;
+
+fn parse(yaml: &str) -> Config {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+#[test]
+fn example_config_is_valid() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.yaml");
+    let contents = std::fs::read_to_string(path).unwrap();
+    let issues = validate_config(&parse(&contents));
+    assert!(issues.errors.is_empty(), "{:?}", issues.errors);
+}
+
+#[test]
+fn invalid_values_are_reported_with_their_path() {
+    let config = parse(
+        r#"
+api_endpoints:
+  - url: "localhost:8080/v1/chat/completions"
+    weight: 0
+  - url: "ftp://example.com/v1"
+    weight: 0
+max_concurrent_requests: 0
+context_trim:
+  enabled: false
+  max_context_tokens: 4096
+  smart_enabled: false
+  smart_max_tokens: 4096
+  per_message_overhead: 3
+  min_keep_pairs: 1
+  summary_aggressiveness: 1
+  summary_mode: "remote"
+  summary_api:
+    enabled: false
+    endpoints: []
+    api_key_env: "SUMMARY_API_KEY"
+    max_tokens: 128
+    temperature: 0.2
+    timeout_seconds: 10
+  tokenizer: "cl200k"
+http_client:
+  timeout_seconds: 0
+  connect_timeout_seconds: 10
+  tcp_keepalive_seconds: 60
+  pool_idle_timeout_seconds: 180
+  pool_max_idle_per_host: 50
+  max_redirects: 5
+  http2_keep_alive_interval_seconds: 30
+  http2_keep_alive_timeout_seconds: 30
+  http2_initial_stream_window_size: 1048576
+"#,
+    );
+    let issues = validate_config(&config);
+    let has = |prefix: &str| issues.errors.iter().any(|e| e.starts_with(prefix));
+
+    for prefix in [
+        "api_endpoints[0].url",
+        "api_endpoints[1].url: 不支持的协议",
+        "api_endpoints: 所有端点的 weight 都为 0",
+        "max_concurrent_requests",
+        "context_trim.summary_mode: 未知的取值 \"remote\"",
+        "context_trim.tokenizer",
+        "http_client.timeout_seconds",
+    ] {
+        assert!(has(prefix), "缺少错误 {}: {:?}", prefix, issues.errors);
+    }
+    // 连接超时大于整体超时只是警告
+    assert!(
+        issues
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("http_client.connect_timeout_seconds"))
+    );
+}
+
+#[test]
+fn endpoint_urls_with_the_chat_path_are_warned() {
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080/v1/chat/completions\"\n    weight: 1\n  - url: \"http://127.0.0.1:8081\"\n    weight: 1\n";
+    let issues = validate_config(&parse(yaml));
+    assert!(issues.errors.is_empty(), "{:?}", issues.errors);
+    let warnings: Vec<&String> = issues
+        .warnings
+        .iter()
+        .filter(|w| w.starts_with("api_endpoints["))
+        .collect();
+    assert_eq!(warnings.len(), 1, "{:?}", issues.warnings);
+    assert!(warnings[0].starts_with("api_endpoints[0].url"), "{}", warnings[0]);
+    assert!(warnings[0].contains("\"http://127.0.0.1:8080\""), "{}", warnings[0]);
+}
+
+#[test]
+fn empty_endpoints_are_rejected() {
+    let issues = validate_config(&parse("api_endpoints: []\n"));
+    assert!(issues.errors.iter().any(|e| e.starts_with("api_endpoints:")));
+}
+
+#[test]
+fn unknown_log_level_and_language_are_rejected() {
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080\"\n    weight: 1\nlogging:\n  level: \"verbose\"\n  language: \"fr\"\n";
+    let issues = validate_config(&parse(yaml));
+    for prefix in ["logging.level", "logging.language"] {
+        assert!(
+            issues.errors.iter().any(|e| e.starts_with(prefix)),
+            "缺少错误 {}: {:?}",
+            prefix,
+            issues.errors
+        );
+    }
+}
+
+#[test]
+fn replication_requires_a_shared_secret() {
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080\"\n    weight: 1\nreplication:\n  enabled: true\n  node_id: \"\"\n  peers: [\"http://10.0.0.2:4321\"]\n  shared_secret: \"\"\n  batch_size: 50\n  flush_interval_ms: 500\n  queue_size: 100\n  timeout_seconds: 5\n";
+    let mut config = parse(yaml);
+    let has_error = |config: &Config| {
+        validate_config(config)
+            .errors
+            .iter()
+            .any(|e| e.starts_with("replication.shared_secret"))
+    };
+    assert!(has_error(&config));
+    config.replication.shared_secret = "peer-secret".to_string();
+    assert!(!has_error(&config));
+}
+
+#[test]
+fn invalid_retention_tiers_are_rejected() {
+    let yaml = r#"
+api_endpoints:
+  - url: "http://127.0.0.1:8080"
+    weight: 1
+cache_maintenance:
+  enabled: true
+  interval_hours: 12
+  retention_days: 30
+  cleanup_on_startup: false
+  min_hit_count: 5
+  retention_tiers:
+    - { min_hits: 100, retention_days: 365 }
+    - { min_hits: 5, retention_days: 0 }
+    - { min_hits: 100, retention_days: 30 }
+"#;
+    let issues = validate_config(&parse(yaml));
+    for prefix in [
+        "cache_maintenance.retention_tiers[1].retention_days",
+        "cache_maintenance.retention_tiers[2].min_hits",
+    ] {
+        assert!(
+            issues.errors.iter().any(|e| e.starts_with(prefix)),
+            "缺少错误 {}: {:?}",
+            prefix,
+            issues.errors
+        );
+    }
+    assert_eq!(issues.errors.len(), 2, "{:?}", issues.errors);
+    // 没有 min_hits 为 0 的规则只是警告
+    assert!(
+        issues
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("cache_maintenance.retention_tiers:"))
+    );
+}
+
+#[test]
+fn applied_defaults_lists_unset_options() {
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080\"\n    weight: 1\ncache:\n  enabled: true\n  max_items: 10\n  batch_write_size: 5\n";
+    let raw: serde_json::Value = serde_yaml::from_str(yaml).unwrap();
+    let lines = applied_defaults(&raw, &parse(yaml));
+
+    let cache = lines.iter().find(|l| l.starts_with("cache: ")).unwrap();
+    assert!(cache.contains("key_message=\"first\""), "{}", cache);
+    assert!(cache.contains("redis（整节默认）"), "{}", cache);
+    assert!(!cache.contains("max_items"), "{}", cache);
+    assert!(lines.iter().any(|l| l == "use_curl = false"));
+    let sections = lines.iter().find(|l| l.starts_with("整节使用默认值")).unwrap();
+    let sections: Vec<&str> = sections.split(": ").nth(1).unwrap().split(", ").collect();
+    assert!(sections.contains(&"server") && !sections.contains(&"cache"), "{:?}", sections);
+}
+
+#[test]
+fn replay_recording_is_rejected_with_encryption() {
+    let yaml = "api_endpoints:\n  - url: \"http://127.0.0.1:8080\"\n    weight: 1\nencryption:\n  enabled: true\n  key_env: \"KEY\"\n  key_file: \"\"\nupstream_replay:\n  mode: \"record\"\n  file: \"replay.jsonl\"\n";
+    let issues = validate_config(&parse(yaml));
+    assert!(
+        issues.errors.iter().any(|e| e.starts_with("upstream_replay.mode")),
+        "{:?}",
+        issues.errors
+    );
+}
+
+#[test]
+fn cache_backend_is_matched_case_insensitively() {
+    let mut config = parse("api_endpoints:\n  - url: \"http://127.0.0.1:8080\"\n    weight: 1\n");
+    config.cache.backend = "Redis".to_string();
+    config.cache.redis.url = "ftp://127.0.0.1".to_string();
+    assert!(config.cache.uses_redis());
+    // 校验与启动使用同一判断：大小写不同的 Redis 同样校验连接地址
+    let issues = validate_config(&config);
+    assert!(
+        issues.errors.iter().any(|e| e.starts_with("cache.redis.url")),
+        "{:?}",
+        issues.errors
+    );
+}
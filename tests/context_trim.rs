@@ -0,0 +1,232 @@
+//! 上下文裁切的性质测试：随机生成对话，检查 trim_context / trim_context_smart 的不变量
+
+use llm_api::models::api_model::ChatMessageJson;
+use llm_api::utils::context_trim::{
+    TokenCounter, calculate_total_tokens, trim_context, trim_context_smart,
+};
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+const COUNTER: TokenCounter = TokenCounter::Heuristic;
+
+fn message(role: &str, content: String) -> ChatMessageJson {
+    ChatMessageJson {
+        role: role.to_string(),
+        content,
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+        function_call: None,
+    }
+}
+
+fn content() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        prop_oneof![
+            "[a-z]{1,12}",
+            "[A-Z][a-z]{0,8}[.!?]",
+            "[一-龥]{1,6}",
+            "[0-9]{1,5}",
+        ],
+        0..40,
+    )
+    .prop_map(|words| words.join(" "))
+}
+
+// 一轮对话：user 提问，随后可能是普通回答、工具调用（调用 + 结果 + 回答）或没有回答
+fn turn() -> impl Strategy<Value = Vec<ChatMessageJson>> {
+    (
+        content(),
+        0..3usize,
+        content(),
+        prop::collection::vec(content(), 1..3),
+    )
+        .prop_map(|(question, kind, answer, tool_results)| {
+            let mut turn = vec![message("user", question)];
+            match kind {
+                0 => turn.push(message("assistant", answer)),
+                1 => {
+                    let mut call = message("assistant", String::new());
+                    call.tool_calls = Some(serde_json::json!([{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "lookup", "arguments": "{\"q\":\"x\"}"},
+                    }]));
+                    turn.push(call);
+                    for result in tool_results {
+                        let mut tool = message("tool", result);
+                        tool.tool_call_id = Some("call_1".to_string());
+                        turn.push(tool);
+                    }
+                    turn.push(message("assistant", answer));
+                }
+                _ => {}
+            }
+            turn
+        })
+}
+
+// 完整对话：可选的 system 开头、若干轮历史（中间可能插入 system/prompt），以当前的 user 输入或工具结果结尾
+fn conversation() -> impl Strategy<Value = Vec<ChatMessageJson>> {
+    (
+        prop::option::of(content()),
+        prop::collection::vec((turn(), prop::option::of(("system|prompt", content()))), 0..8),
+        content(),
+        any::<bool>(),
+    )
+        .prop_map(|(system, history, last, ends_with_tool)| {
+            let mut messages = Vec::new();
+            if let Some(system) = system {
+                messages.push(message("system", system));
+            }
+            for (turn, extra) in history {
+                messages.extend(turn);
+                if let Some((role, content)) = extra {
+                    messages.push(message(&role, content));
+                }
+            }
+            messages.push(message("user", last));
+            if ends_with_tool {
+                let mut call = message("assistant", String::new());
+                call.tool_calls = Some(serde_json::json!([{"id": "call_9", "type": "function"}]));
+                messages.push(call);
+                let mut tool = message("tool", "tool output".to_string());
+                tool.tool_call_id = Some("call_9".to_string());
+                messages.push(tool);
+            }
+            // 用 name 标记原始位置，便于检查裁切结果
+            for (i, m) in messages.iter_mut().enumerate() {
+                m.name = Some(i.to_string());
+            }
+            messages
+        })
+}
+
+fn is_pinned(m: &ChatMessageJson) -> bool {
+    m.role.eq_ignore_ascii_case("system") || m.role.eq_ignore_ascii_case("prompt")
+}
+
+fn index_of(m: &ChatMessageJson) -> usize {
+    m.name.as_deref().unwrap().parse().unwrap()
+}
+
+// 最后一轮（最后一条 user 消息及其后的全部消息）的起点
+fn last_turn_start(messages: &[ChatMessageJson]) -> usize {
+    messages
+        .iter()
+        .rposition(|m| m.role == "user")
+        .unwrap_or(messages.len() - 1)
+}
+
+fn smart(
+    messages: &[ChatMessageJson],
+    max_tokens: usize,
+    overhead: usize,
+    keep_pairs: usize,
+) -> Vec<ChatMessageJson> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(trim_context_smart(
+        messages,
+        max_tokens,
+        overhead,
+        keep_pairs,
+        3,
+        "local",
+        false,
+        &[],
+        None,
+        0,
+        0.0,
+        1,
+        &reqwest::Client::new(),
+        &[],
+        &HashMap::new(),
+        COUNTER,
+    ))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn trim_context_invariants(messages in conversation(), max_tokens in 0..600usize) {
+        let result = trim_context(&messages, max_tokens, COUNTER);
+        let kept: Vec<usize> = result.iter().map(index_of).collect();
+
+        // 结果是原消息的子序列，内容不变
+        prop_assert!(kept.windows(2).all(|w| w[0] < w[1]));
+        for m in &result {
+            prop_assert_eq!(
+                serde_json::to_value(m).unwrap(),
+                serde_json::to_value(&messages[index_of(m)]).unwrap()
+            );
+        }
+
+        // 最后一条消息与全部 system/prompt 消息始终保留
+        prop_assert_eq!(kept.last(), Some(&(messages.len() - 1)));
+        for (i, m) in messages.iter().enumerate() {
+            if is_pinned(m) {
+                prop_assert!(kept.contains(&i), "system/prompt 消息 {} 被丢弃", i);
+            }
+        }
+
+        // 不超出预算，除非必须保留的部分（system/prompt 与当前这一轮）本身已超出
+        let tokens = calculate_total_tokens(&result, COUNTER);
+        let tail = last_turn_start(&messages);
+        let mandatory_only = kept.iter().all(|&i| i >= tail || is_pinned(&messages[i]));
+        prop_assert!(
+            tokens <= max_tokens || mandatory_only,
+            "超出预算: {} > {}，保留 {:?}", tokens, max_tokens, kept
+        );
+
+        // 保留的 assistant/tool 消息，其之前最近的非 system 消息也必须保留（不产生孤立的回答或工具结果）
+        for &i in &kept {
+            let role = messages[i].role.as_str();
+            if role != "assistant" && role != "tool" {
+                continue;
+            }
+            if let Some(prev) = (0..i).rev().find(|&j| !is_pinned(&messages[j])) {
+                prop_assert!(kept.contains(&prev), "消息 {} ({}) 的上一条 {} 被丢弃", i, role, prev);
+            }
+        }
+    }
+
+    #[test]
+    fn trim_context_smart_invariants(
+        messages in conversation(),
+        max_tokens in 0..600usize,
+        overhead in 0..5usize,
+        keep_pairs in 0..3usize,
+    ) {
+        let result = smart(&messages, max_tokens, overhead, keep_pairs);
+
+        // 智能裁切只压缩内容，不删除、不重排消息，也不改动工具调用
+        prop_assert_eq!(result.len(), messages.len());
+        for (before, after) in messages.iter().zip(&result) {
+            prop_assert_eq!(&before.role, &after.role);
+            prop_assert_eq!(&before.name, &after.name);
+            prop_assert_eq!(&before.tool_calls, &after.tool_calls);
+            prop_assert_eq!(&before.tool_call_id, &after.tool_call_id);
+        }
+
+        // system/prompt 消息与当前这一轮（最后一条 user 消息及其后的工具调用与结果）内容不变
+        let tail = last_turn_start(&messages);
+        for (i, (before, after)) in messages.iter().zip(&result).enumerate() {
+            if is_pinned(before) || i >= tail {
+                prop_assert_eq!(&before.content, &after.content, "消息 {} 被改动", i);
+            }
+        }
+
+        // 不超出预算，除非其余消息都已压缩到极限长度
+        let tokens = calculate_total_tokens(&result, COUNTER) + overhead * result.len();
+        let exhausted = result.iter().enumerate().all(|(i, m)| {
+            is_pinned(m) || i >= tail || m.content.chars().count() <= 11
+        });
+        prop_assert!(
+            tokens <= max_tokens || exhausted,
+            "超出预算: {} > {}", tokens, max_tokens
+        );
+    }
+}
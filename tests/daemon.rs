@@ -0,0 +1,43 @@
+//! 后台运行：命令行参数解析与进程号文件
+
+use llm_api::utils::daemon::{PidFile, take_daemon_args};
+use std::path::PathBuf;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn daemon_args_are_taken_and_others_kept() {
+    let mut rest = args(&["--daemon", "bench", "--pidfile", "/tmp/a.pid", "--log-file=/tmp/a.log"]);
+    let options = take_daemon_args(&mut rest).unwrap();
+    assert!(options.daemon);
+    assert_eq!(options.pidfile, Some(PathBuf::from("/tmp/a.pid")));
+    assert_eq!(options.log_file(), PathBuf::from("/tmp/a.log"));
+    assert_eq!(rest, args(&["bench"]));
+
+    assert!(take_daemon_args(&mut args(&["--pidfile"])).is_err());
+    let options = take_daemon_args(&mut args(&[])).unwrap();
+    assert!(!options.daemon && options.pidfile.is_none());
+}
+
+#[test]
+fn pidfile_is_written_removed_and_guards_running_processes() {
+    let path = std::env::temp_dir().join(format!("llm_api_test_{}.pid", std::process::id()));
+
+    // 进程已退出留下的旧文件被覆盖
+    std::fs::write(&path, "4294967295\n").unwrap();
+    let pidfile = PidFile::create(&path).unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(written.trim(), std::process::id().to_string());
+    drop(pidfile);
+    assert!(!path.exists());
+
+    // 记录的进程仍在运行时拒绝启动（pid 1 总是存在）
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::write(&path, "1\n").unwrap();
+        assert!(PidFile::create(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,137 @@
+//! 唯一的数据库写入任务：写操作经由写入任务串行执行，写入任务退出后直接写入
+
+use llm_api::models::api_model::ChatResponseJson;
+use llm_api::utils::answer_codec::encode_answer;
+use llm_api::utils::audit::{AuditRecord, record_audit};
+use llm_api::utils::cache_epoch::bump_cache_epoch;
+use llm_api::utils::config::DatabaseConfig;
+use llm_api::utils::db::{create_db_pool, init_db};
+use llm_api::utils::db_writer::{DbWriter, HitKey, db_write_stats, init_db_writer, record_hit};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+fn database_url() -> String {
+    std::env::temp_dir()
+        .join(format!("llm_api_test_{}.db", uuid::Uuid::new_v4().simple()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+async fn open(database_url: &str) -> Arc<SqlitePool> {
+    let pool = create_db_pool(database_url, &DatabaseConfig::default())
+        .await
+        .unwrap();
+    init_db(&pool).await.unwrap();
+    Arc::new(pool)
+}
+
+fn answer(content: &str) -> Vec<u8> {
+    let response: ChatResponseJson = serde_json::from_value(json!({
+        "id": "chatcmpl-writer",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "writer-model",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        }],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+    }))
+    .unwrap();
+    encode_answer(&response, 0, 0).unwrap()
+}
+
+async fn count(pool: &SqlitePool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+// 并发执行各类写操作
+async fn write_concurrently(pool: Arc<SqlitePool>, prefix: &str) {
+    let mut tasks = Vec::new();
+    for i in 0..20 {
+        let pool = pool.clone();
+        let key = format!("{}-{}", prefix, i);
+        tasks.push(tokio::spawn(async move {
+            let writer = DbWriter::new(pool.clone(), 0);
+            assert!(writer.write_single(key.clone(), answer(&key)).await);
+            record_hit(&pool, HitKey::Question(key.clone())).await;
+            let record = AuditRecord {
+                request_id: key,
+                model: "writer-model".to_string(),
+                ..Default::default()
+            };
+            record_audit(&pool, &record, 1, 200, 0).await.unwrap();
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+// 写入任务是进程级的单例，各阶段放在同一个测试中按顺序执行
+#[test]
+fn writes_go_through_the_writer_and_fall_back_once_it_exits() {
+    let primary_url = database_url();
+    let other_url = database_url();
+
+    // 写入任务运行在该运行时上，运行时关闭后写入任务随之退出
+    let writer_runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    writer_runtime.block_on(async {
+        let pool = open(&primary_url).await;
+        init_db_writer(pool.clone(), &DatabaseConfig::default());
+
+        write_concurrently(pool.clone(), "via-writer").await;
+        assert_eq!(count(&pool, "questions").await, 20);
+        assert_eq!(count(&pool, "audit_log").await, 20);
+        assert_eq!(bump_cache_epoch(&pool).await.unwrap(), 1);
+
+        // 命中计数由写入任务合并执行，等待其处理完队列
+        let mut hits = 0;
+        for _ in 0..50 {
+            hits = sqlx::query_scalar("SELECT COALESCE(SUM(hit_count), 0) FROM answers")
+                .fetch_one(pool.as_ref())
+                .await
+                .unwrap();
+            if hits == 20 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(hits, 20);
+        assert_eq!(db_write_stats().busy_failures, 0);
+
+        // 其他数据库不经由写入任务
+        let other = open(&other_url).await;
+        write_concurrently(other.clone(), "other-db").await;
+        assert_eq!(count(&other, "questions").await, 20);
+        assert_eq!(count(&pool, "questions").await, 20);
+    });
+    drop(writer_runtime);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let pool = open(&primary_url).await;
+        write_concurrently(pool.clone(), "after-exit").await;
+        assert_eq!(count(&pool, "questions").await, 40);
+        assert_eq!(count(&pool, "audit_log").await, 40);
+        assert_eq!(bump_cache_epoch(&pool).await.unwrap(), 2);
+    });
+
+    for url in [primary_url, other_url] {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", url, suffix));
+        }
+    }
+}
@@ -0,0 +1,1917 @@
+//! 端到端测试：本地服务 + 内嵌模拟上游（需要 test-support 特性，dev-dependencies 中已启用）
+
+use llm_api::handlers::api_handler::build_curl_command;
+use llm_api::models::api_model::{ChatRequestJson, ChatResponseJson, StopSequences};
+use llm_api::proto::llm_cache_server::LlmCache;
+use llm_api::proto::{ChatMessage, ChatRequest, TopQuestionsRequest};
+use llm_api::utils::ab_test::{ab_report, record_ab_result};
+use llm_api::utils::answer_codec::encode_answer;
+use llm_api::server::listen_address;
+use llm_api::utils::cache_key::KeySource;
+use llm_api::utils::cache_maintenance::{
+    ConsistencyReport, RetentionTier, check_consistency, cleanup_old_entries, run_maintenance,
+};
+use llm_api::utils::compact::{DbFileSizes, compact_database};
+use llm_api::utils::config::Config;
+use llm_api::utils::db_writer::DbWriter;
+use llm_api::utils::error::MAX_UPSTREAM_ERROR_BODY;
+use llm_api::utils::http_client::create_endpoint_clients;
+use llm_api::utils::inspect::{format_entry, inspect_entry};
+use llm_api::utils::purge::{PurgeOptions, purge_entries};
+use llm_api::utils::rehash::{RehashReport, rehash_keys};
+use llm_api::utils::replication::init_replication;
+use llm_api::utils::unix_socket::split_unix_url;
+use llm_api::utils::warmup::{WarmupConfig, warm_up_endpoints};
+use llm_api::utils::websocket::{Frame, read_frame};
+use llm_api::test_support::{
+    MockBehavior, MockUpstream, TEST_ADMIN_TOKEN, TestApp, eventually, test_config,
+};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+fn chat_body(prompt: &str) -> Value {
+    json!({
+        "model": "mock-model",
+        "messages": [{"role": "user", "content": prompt}],
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn miss_then_memory_hit() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let body = chat_body("what is the capital of France?");
+
+    let first = app.chat(&body).await;
+    assert_eq!(first.status(), 200);
+    assert!(!first.headers().contains_key("x-cache-age"));
+    let first: Value = first.json().await.unwrap();
+    assert_eq!(
+        first["choices"][0]["message"]["content"],
+        "mock reply: what is the capital of France?"
+    );
+
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+
+    let second = app.chat(&body).await;
+    assert_eq!(second.status(), 200);
+    assert!(second.headers().contains_key("x-cache-age"));
+    let second: Value = second.json().await.unwrap();
+    assert_eq!(second["choices"][0]["finish_reason"], "stop_from_cache");
+    assert_eq!(
+        second["choices"][0]["message"]["content"],
+        first["choices"][0]["message"]["content"]
+    );
+    assert_eq!(upstream.request_count(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn conversation_key_caches_each_turn() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.key_message = "conversation".to_string();
+    let app = TestApp::spawn(config).await;
+    let turn = |history: &str| {
+        json!({
+            "model": "mock-model",
+            "messages": [
+                {"role": "user", "content": "hello"},
+                {"role": "assistant", "content": history},
+                {"role": "user", "content": "and then?"},
+            ],
+        })
+    };
+    let cache = app.state.memory_cache.clone().unwrap();
+    let cached_items = |n: usize| {
+        let cache = cache.clone();
+        eventually(move || {
+            let cache = cache.clone();
+            async move { cache.stats().items >= n }
+        })
+    };
+
+    assert_eq!(app.chat(&chat_body("hello")).await.status(), 200);
+    assert!(cached_items(1).await);
+    // 第一轮已缓存，第二轮是新的对话前缀
+    let second = app.chat(&turn("mock reply: hello")).await;
+    assert!(!second.headers().contains_key("x-cache-age"));
+    assert!(cached_items(2).await);
+
+    // 重放整段对话时每一轮都命中缓存
+    assert!(app.chat(&chat_body("hello")).await.headers().contains_key("x-cache-age"));
+    let replay = app.chat(&turn("mock reply: hello")).await;
+    assert!(replay.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 2);
+
+    // 同一提问在不同的历史下不共享缓存
+    let other = app.chat(&turn("a different answer")).await;
+    assert!(!other.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compatible_cache_hits_use_standard_fields() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.api_defaults.compatible_cache_hits = true;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("compatible hit");
+
+    let miss = app.chat(&body).await;
+    assert_eq!(miss.headers()["x-cache"], "MISS");
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+
+    // 回答字段与上游一致，是否命中缓存只通过响应头区分
+    let hit = app.chat(&body).await;
+    assert_eq!(hit.headers()["x-cache"], "HIT");
+    let hit: Value = hit.json().await.unwrap();
+    assert_eq!(hit["choices"][0]["finish_reason"], "stop");
+    assert_eq!(hit["system_fingerprint"], "unknown");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cache_hits_are_brotli_encoded_for_br_clients() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    config.cache.brotli_hit_responses = true;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("compress my hit");
+    let chat = |accept_encoding: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", app.url))
+            .header("accept-encoding", accept_encoding)
+            .json(&body)
+            .send()
+    };
+
+    // 未命中时原样返回上游回答
+    let miss = chat("br").await.unwrap();
+    assert_eq!(miss.headers()["x-cache"], "MISS");
+    assert!(!miss.headers().contains_key("content-encoding"));
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+
+    let hit = chat("gzip, br").await.unwrap();
+    assert_eq!(hit.headers()["x-cache"], "HIT");
+    assert_eq!(hit.headers()["content-encoding"], "br");
+    assert_eq!(hit.headers()["vary"], "accept-encoding");
+    let compressed = hit.bytes().await.unwrap();
+    let mut decompressed = Vec::new();
+    std::io::Read::read_to_end(
+        &mut brotli::Decompressor::new(compressed.as_ref(), 4096),
+        &mut decompressed,
+    )
+    .unwrap();
+    let hit: Value = serde_json::from_slice(&decompressed).unwrap();
+    assert_eq!(
+        hit["choices"][0]["message"]["content"],
+        "mock reply: compress my hit"
+    );
+    assert_eq!(hit["object"], "chat.completion");
+
+    // 命中直接返回写入时保存的响应体，不重新压缩
+    let again = chat("br").await.unwrap();
+    assert_eq!(again.headers()["content-encoding"], "br");
+    assert_eq!(again.bytes().await.unwrap(), compressed);
+
+    // q=0 表示拒绝 br，返回未压缩的 JSON
+    let plain = chat("br;q=0, gzip").await.unwrap();
+    assert_eq!(plain.headers()["x-cache"], "HIT");
+    assert!(!plain.headers().contains_key("content-encoding"));
+    let plain: Value = plain.json().await.unwrap();
+    assert_eq!(
+        plain["choices"][0]["message"]["content"],
+        "mock reply: compress my hit"
+    );
+    assert_ne!(plain["id"], hit["id"]);
+    assert_eq!(upstream.request_count(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cache_hits_are_not_brotli_encoded_by_default() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("leave my hit alone");
+    let chat = || {
+        reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", app.url))
+            .header("accept-encoding", "br")
+            .json(&body)
+            .send()
+    };
+
+    assert_eq!(chat().await.unwrap().status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+
+    let hit = chat().await.unwrap();
+    assert_eq!(hit.headers()["x-cache"], "HIT");
+    assert!(!hit.headers().contains_key("content-encoding"));
+    assert!(!hit.headers().contains_key("vary"));
+    let hit: Value = hit.json().await.unwrap();
+    assert_eq!(
+        hit["choices"][0]["message"]["content"],
+        "mock reply: leave my hit alone"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn database_hit_without_memory_cache() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("persist me");
+
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await > 0 }).await);
+
+    let hit = app.chat(&body).await;
+    assert_eq!(hit.status(), 200);
+    assert!(hit.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expired_memory_entries_are_flushed_by_the_background_tasks() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.memory_ttl_seconds = 1;
+    config.cache.adaptive_batch.enabled = true;
+    config.cache.adaptive_batch.max_delay_ms = 100;
+    let app = TestApp::spawn(config).await;
+
+    // 没有后续请求时，只有过期清理任务与按时间触发的批量写入任务会把回答写入数据库
+    assert_eq!(app.chat(&chat_body("expire me")).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+    assert_eq!(app.state.memory_cache.as_ref().unwrap().cache_count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_pragmas_apply_to_every_pooled_connection() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.database.cache_size = -4096;
+    config.database.mmap_size = 1 << 20;
+    let app = TestApp::spawn(config).await;
+
+    // 同时持有多个连接，确保检查的不只是同一个连接
+    let mut connections = Vec::new();
+    for _ in 0..3 {
+        connections.push(app.state.db.acquire().await.unwrap());
+    }
+    for conn in &mut connections {
+        let cache_size: i64 = sqlx::query_scalar("PRAGMA cache_size")
+            .fetch_one(&mut **conn)
+            .await
+            .unwrap();
+        let mmap_size: i64 = sqlx::query_scalar("PRAGMA mmap_size")
+            .fetch_one(&mut **conn)
+            .await
+            .unwrap();
+        let temp_store: i64 = sqlx::query_scalar("PRAGMA temp_store")
+            .fetch_one(&mut **conn)
+            .await
+            .unwrap();
+        assert_eq!(cache_size, -4096);
+        assert_eq!(mmap_size, 1 << 20);
+        assert_eq!(temp_store, 2);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn inspect_decodes_a_stored_entry() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+
+    assert_eq!(app.chat(&chat_body("inspect me")).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await > 0 }).await);
+    let question_key: String = sqlx::query_scalar("SELECT key FROM questions")
+        .fetch_one(app.state.db.as_ref())
+        .await
+        .unwrap();
+
+    let entry = inspect_entry(&app.state.db, &question_key).await.unwrap().unwrap();
+    assert_eq!(entry.answer.content(), "mock reply: inspect me");
+    assert_eq!(entry.hit_count, 0);
+    assert!(entry.size > 0);
+    assert!(entry.created_at > 0);
+    let printed = format_entry(&entry);
+    assert!(printed.contains(&question_key));
+    assert!(printed.contains("mock reply: inspect me"));
+
+    assert!(inspect_entry(&app.state.db, "missing").await.unwrap().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purge_removes_matching_entries_only() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+
+    let entries = [("old-model", "purge a"), ("old-model", "purge b"), ("kept-model", "keep")];
+    for (model, prompt) in entries {
+        let mut body = chat_body(prompt);
+        body["model"] = json!(model);
+        assert_eq!(app.chat(&body).await.status(), 200);
+    }
+    assert!(eventually(|| async { app.db_answer_count().await == 3 }).await);
+    let now = chrono::Utc::now().timestamp();
+
+    let by_model = PurgeOptions {
+        model: Some("old-model".to_string()),
+        ..Default::default()
+    };
+    let dry_run = PurgeOptions {
+        dry_run: true,
+        ..by_model.clone()
+    };
+    let preview = purge_entries(&app.state.db, &dry_run, now).await.unwrap();
+    assert_eq!((preview.answers, preview.questions), (2, 2));
+    assert_eq!(app.db_answer_count().await, 3);
+
+    // 刚写入的回答不满足 --older-than
+    let old = PurgeOptions {
+        older_than: Some(3600),
+        ..by_model.clone()
+    };
+    assert_eq!(purge_entries(&app.state.db, &old, now).await.unwrap().answers, 0);
+
+    let removed = purge_entries(&app.state.db, &by_model, now).await.unwrap();
+    assert_eq!((removed.answers, removed.questions), (2, 2));
+    assert!(removed.bytes > 0);
+    assert_eq!(app.db_answer_count().await, 1);
+    let questions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM questions")
+        .fetch_one(app.state.db.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(questions, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compact_truncates_the_wal_and_keeps_entries() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+
+    for prompt in ["compact a", "compact b"] {
+        assert_eq!(app.chat(&chat_body(prompt)).await.status(), 200);
+    }
+    assert!(eventually(|| async { app.db_answer_count().await == 2 }).await);
+
+    let database_url = &app.state.config.database_url;
+    let report = compact_database(&app.state.db, database_url).await.unwrap();
+    assert!(report.before.wal > 0);
+    assert_eq!(report.after.wal, 0);
+    assert!(report.after.database > 0);
+    assert_eq!(report.after, DbFileSizes::read(database_url));
+    assert_eq!(app.db_answer_count().await, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn identical_answers_are_stored_once() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    // 正文相同，模型、用量与生成时间不同
+    let response = |model: &str, prompt_tokens: i32, created: i64| {
+        let response: ChatResponseJson = serde_json::from_value(json!({
+            "id": "chatcmpl-dedup",
+            "object": "chat.completion",
+            "created": created,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "the same answer"},
+                "finish_reason": "stop",
+            }],
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": 5,
+                "total_tokens": prompt_tokens + 5,
+            },
+        }))
+        .unwrap();
+        encode_answer(&response, 1, 0).unwrap()
+    };
+    let first = response("model-a", 10, 1);
+    let second = response("model-b", 20, 2);
+    assert_ne!(first, second);
+
+    let writer = DbWriter::new(app.state.db.clone(), 1);
+    assert!(writer.write_single("question-a".to_string(), first).await);
+    assert!(writer.write_single("question-b".to_string(), second).await);
+    assert_eq!(app.db_answer_count().await, 1);
+    let answer_keys: i64 =
+        sqlx::query_scalar("SELECT COUNT(DISTINCT answer_key) FROM questions")
+            .fetch_one(app.state.db.as_ref())
+            .await
+            .unwrap();
+    assert_eq!(answer_keys, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rehash_migrates_keys_with_stored_sources() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    config.cache.key_include_system = true;
+    config.cache.store_key_source = true;
+    let app = TestApp::spawn(config).await;
+    let db = app.state.db.as_ref();
+
+    let body = |system: &str, prompt: &str| {
+        json!({
+            "model": "mock-model",
+            "messages": [
+                {"role": "system", "content": system},
+                {"role": "user", "content": prompt},
+            ],
+        })
+    };
+    let bodies = [
+        body("be brief", "rehash me"),
+        body("be verbose", "rehash me"),
+        body("be brief", "rehash other"),
+    ];
+    for body in &bodies {
+        assert_eq!(app.chat(body).await.status(), 200);
+    }
+    let count = |table: &'static str| async move {
+        sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(db)
+            .await
+            .unwrap()
+    };
+    // 前两个问题的回答相同，共用一条回答记录
+    let stored = || async { count("questions").await == 3 && count("question_sources").await == 3 };
+    assert!(eventually(stored).await);
+    // 开启 store_key_source 之前写入的问题没有来源
+    sqlx::query("INSERT INTO questions (key, answer_key) SELECT 'legacy', key FROM answers LIMIT 1")
+        .execute(db)
+        .await
+        .unwrap();
+
+    // 改为不区分系统提示词：前两个问题在新策略下是同一个问题
+    let mut new_config = app.state.config.cache.clone();
+    new_config.key_include_system = false;
+    let expected = RehashReport {
+        unchanged: 0,
+        migrated: 2,
+        merged: 1,
+        unmigratable: 1,
+        unmigratable_keys: vec!["legacy".to_string()],
+    };
+    assert_eq!(rehash_keys(db, &new_config, true).await.unwrap(), expected);
+    assert_eq!(rehash_keys(db, &new_config, false).await.unwrap(), expected);
+
+    let mut keys: Vec<String> = sqlx::query_scalar("SELECT key FROM questions")
+        .fetch_all(db)
+        .await
+        .unwrap();
+    keys.sort();
+    let mut expected_keys: Vec<String> = [&bodies[0], &bodies[2]]
+        .into_iter()
+        .map(|body| {
+            let request: ChatRequestJson = serde_json::from_value(body.clone()).unwrap();
+            KeySource::from_request(&request).question_key(&new_config).unwrap()
+        })
+        .chain(["legacy".to_string()])
+        .collect();
+    expected_keys.sort();
+    assert_eq!(keys, expected_keys);
+    assert_eq!(count("question_sources").await, 2);
+
+    let again = rehash_keys(db, &new_config, false).await.unwrap();
+    assert_eq!((again.unchanged, again.migrated, again.unmigratable), (2, 0, 1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn maintenance_runs_are_recorded_and_listed() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let db = app.state.db.as_ref();
+
+    assert_eq!(app.chat(&chat_body("maintain me")).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+    // 让问题过期，清理时删除问题及其不再被引用的回答
+    for table in ["answers", "questions"] {
+        sqlx::query(&format!("UPDATE {} SET created_at = 0", table))
+            .execute(db)
+            .await
+            .unwrap();
+    }
+
+    let mut maintenance = app.state.config.cache_maintenance.clone();
+    maintenance.retention_days = 1;
+    assert!(run_maintenance(db, &maintenance, 1.0, "scheduled").await);
+    assert!(run_maintenance(db, &maintenance, 1.0, "startup").await);
+
+    let history: Value = app
+        .admin_get("/admin/maintenance?limit=5")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let runs = history["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    // 最新的在前
+    assert_eq!(runs[0]["trigger"], "startup");
+    assert_eq!(runs[0]["deleted_questions"], 0);
+    assert_eq!(runs[0]["reclaimed_bytes"], 0);
+    let first = &runs[1];
+    assert_eq!(first["trigger"], "scheduled");
+    assert_eq!(first["deleted_questions"], 1);
+    assert_eq!(first["deleted_answers"], 1);
+    assert!(first["reclaimed_bytes"].as_i64().unwrap() > 0);
+    assert!(first["started_at"].as_i64().unwrap() > 0);
+    assert!(first["errors"].is_null());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ab_results_are_aggregated_per_arm_and_pruned_by_maintenance() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let db = app.state.db.as_ref();
+
+    for (request_id, arm, latency_ms, chars, success) in [
+        ("a-1", "a", 100, 10, true),
+        ("a-2", "a", 300, 30, true),
+        // 失败的请求只计入请求数，不计入延迟与回答长度
+        ("a-3", "a", 5000, 0, false),
+        ("b-1", "b", 200, 20, true),
+    ] {
+        record_ab_result(
+            db,
+            request_id,
+            arm,
+            "http://mock",
+            latency_ms,
+            chars,
+            success,
+        )
+        .await
+        .unwrap();
+    }
+
+    let report = ab_report(db).await.unwrap();
+    let arms = report["arms"].as_array().unwrap();
+    assert_eq!(arms.len(), 2);
+    let a = &arms[0];
+    assert_eq!(a["arm"], "a");
+    assert_eq!(a["requests"], 3);
+    assert_eq!(a["successes"], 2);
+    assert!((a["success_rate"].as_f64().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(a["latency_ms_avg"], 200.0);
+    assert_eq!(a["latency_ms_min"], 100);
+    assert_eq!(a["latency_ms_max"], 300);
+    assert_eq!(a["response_chars_avg"], 20.0);
+    assert_eq!(arms[1]["arm"], "b");
+    assert_eq!(arms[1]["requests"], 1);
+
+    // B 组的记录已过期
+    sqlx::query("UPDATE ab_results SET created_at = 0 WHERE arm = 'b'")
+        .execute(db)
+        .await
+        .unwrap();
+    let mut maintenance = app.state.config.cache_maintenance.clone();
+    maintenance.ab_results_retention_days = 1;
+    assert!(run_maintenance(db, &maintenance, 1.0, "scheduled").await);
+
+    let report: Value = app.admin_get("/admin/ab").await.json().await.unwrap();
+    let arms = report["arms"].as_array().unwrap();
+    assert_eq!(arms.len(), 1);
+    assert_eq!(arms[0]["arm"], "a");
+    assert_eq!(arms[0]["requests"], 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retention_tiers_keep_entries_by_hit_count() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let db = app.state.db.as_ref();
+
+    for prompt in ["tier a", "tier b", "tier c", "tier d"] {
+        assert_eq!(app.chat(&chat_body(prompt)).await.status(), 200);
+    }
+    assert!(eventually(|| async { app.db_answer_count().await == 4 }).await);
+
+    // 按写入顺序设置（命中次数，写入于几天前）
+    let day = 24 * 60 * 60;
+    let now = chrono::Utc::now().timestamp();
+    let entries = [(150, 200), (10, 100), (10, 40), (1, 40)];
+    for (i, (hits, age_days)) in entries.into_iter().enumerate() {
+        sqlx::query(
+            "UPDATE answers SET hit_count = ?, created_at = ?
+             WHERE rowid = (SELECT rowid FROM answers ORDER BY rowid LIMIT 1 OFFSET ?)",
+        )
+        .bind(hits)
+        .bind(now - age_days * day)
+        .bind(i as i64)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+    sqlx::query(
+        "UPDATE questions
+         SET created_at = (SELECT a.created_at FROM answers a WHERE a.key = questions.answer_key)",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+
+    let mut maintenance = app.state.config.cache_maintenance.clone();
+    maintenance.retention_tiers = vec![
+        RetentionTier { min_hits: 5, retention_days: 90 },
+        RetentionTier { min_hits: 100, retention_days: 365 },
+        RetentionTier { min_hits: 0, retention_days: 30 },
+    ];
+    let stats = cleanup_old_entries(db, &maintenance).await.unwrap();
+    assert_eq!((stats.deleted_questions, stats.deleted_answers), (2, 2));
+
+    let mut kept: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT a.hit_count, (? - a.created_at) / ?
+         FROM questions q JOIN answers a ON a.key = q.answer_key",
+    )
+    .bind(now)
+    .bind(day)
+    .fetch_all(db)
+    .await
+    .unwrap();
+    kept.sort();
+    // 命中 10 次、40 天前写入的与命中 150 次、200 天前写入的保留
+    assert_eq!(kept, vec![(10, 40), (150, 200)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn questions_with_missing_answers_are_reported_and_swept() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let db = app.state.db.as_ref();
+
+    assert_eq!(app.chat(&chat_body("consistent")).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+    assert!(check_consistency(db).await.unwrap().is_consistent());
+
+    // 模拟手动删除答案后留下的问题
+    sqlx::query("INSERT INTO questions (key, answer_key) VALUES ('dangling', 'missing')")
+        .execute(db)
+        .await
+        .unwrap();
+    let expected = ConsistencyReport {
+        dangling_questions: 1,
+        ..Default::default()
+    };
+    assert_eq!(check_consistency(db).await.unwrap(), expected);
+
+    // 关闭 sweep_orphans 时同样清理，未过期的正常记录保留
+    let mut maintenance = app.state.config.cache_maintenance.clone();
+    maintenance.sweep_orphans = false;
+    let stats = cleanup_old_entries(db, &maintenance).await.unwrap();
+    assert_eq!((stats.deleted_questions, stats.deleted_answers), (1, 0));
+    assert!(check_consistency(db).await.unwrap().is_consistent());
+    assert_eq!(app.db_answer_count().await, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upstream_error_is_passed_through_and_not_cached() {
+    let upstream = MockUpstream::start(MockBehavior::failing(
+        1,
+        429,
+        r#"{"error":{"message":"rate limited","type":"rate_limit_error"}}"#,
+    ))
+    .await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let body = chat_body("retry later");
+
+    let failed = app.chat(&body).await;
+    assert_eq!(failed.status(), 429);
+    let failed: Value = failed.json().await.unwrap();
+    assert_eq!(failed["error"]["message"], "rate limited");
+
+    // 失败的回答不写入缓存，上游恢复后再次请求应到达上游
+    let recovered = app.chat(&body).await;
+    assert_eq!(recovered.status(), 200);
+    assert!(!recovered.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unreachable_upstream_returns_bad_gateway() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let url = upstream.url.clone();
+    drop(upstream);
+    let app = TestApp::spawn(test_config(&url)).await;
+
+    let response = app.chat(&chat_body("anyone there?")).await;
+    assert_eq!(response.status(), 502);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn context_overflow_is_retried_with_trimmed_messages() {
+    let upstream = MockUpstream::start(MockBehavior::failing(
+        1,
+        400,
+        r#"{"error":{"message":"This model's maximum context length is 8192 tokens","code":"context_length_exceeded"}}"#,
+    ))
+    .await;
+    let mut config = test_config(&upstream.url);
+    config.api_endpoints[0].prompt_cache_hints = Some(true);
+    config.prompt_cache.min_prefix_tokens = 1;
+    let app = TestApp::spawn(config).await;
+
+    let mut messages = vec![json!({"role": "system", "content": "You are a helpful assistant."})];
+    for i in 0..12 {
+        messages.push(json!({"role": "user", "content": format!("question {} {}", i, "padding ".repeat(40))}));
+        messages.push(json!({"role": "assistant", "content": format!("answer {} {}", i, "padding ".repeat(40))}));
+    }
+    messages.push(json!({"role": "user", "content": "final question"}));
+    let body = json!({"model": "mock-model", "messages": messages});
+
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    let response: Value = response.json().await.unwrap();
+    assert_eq!(
+        response["choices"][0]["message"]["content"],
+        "mock reply: final question"
+    );
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    let original = requests[0]["messages"].to_string().len();
+    let retried = requests[1]["messages"].to_string().len();
+    assert!(retried < original, "重试请求应被裁切: {} -> {}", original, retried);
+    // 重试请求与首次请求一样带有提示词缓存标记
+    for request in &requests {
+        assert_eq!(request["messages"][0]["content"][0]["cache_control"]["type"], "ephemeral");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn long_context_is_trimmed_before_upstream() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.context_trim.enabled = true;
+    config.context_trim.smart_enabled = false;
+    config.context_trim.max_context_tokens = 200;
+    let app = TestApp::spawn(config).await;
+
+    let mut messages = Vec::new();
+    for i in 0..20 {
+        messages.push(json!({"role": "user", "content": format!("old question {} {}", i, "filler ".repeat(30))}));
+        messages.push(json!({"role": "assistant", "content": format!("old answer {} {}", i, "filler ".repeat(30))}));
+    }
+    messages.push(json!({"role": "user", "content": "latest question"}));
+    let sent = messages.len();
+    let body = json!({"model": "mock-model", "messages": messages});
+
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 1);
+    let forwarded = requests[0]["messages"].as_array().unwrap();
+    assert!(forwarded.len() < sent, "上游收到 {} 条消息", forwarded.len());
+    assert_eq!(forwarded.last().unwrap()["content"], "latest question");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streaming_request_is_proxied_and_not_cached() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    // 流式请求通过 curl 模式转发上游的 SSE 输出
+    config.use_curl = true;
+    let app = TestApp::spawn(config).await;
+    let mut body = chat_body("stream please");
+    body["stream"] = json!(true);
+
+    for _ in 0..2 {
+        let response = app.chat(&body).await;
+        assert_eq!(response.status(), 200);
+        let text = response.text().await.unwrap();
+        assert!(text.contains("data: [DONE]"), "SSE 输出不完整: {}", text);
+        assert!(text.contains("stream "));
+    }
+    assert_eq!(upstream.request_count(), 2);
+    assert_eq!(app.db_answer_count().await, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn curl_streams_outlive_the_request_timeout_but_not_the_idle_timeout() {
+    let upstream = MockUpstream::start(MockBehavior {
+        chunk_delay: Duration::from_millis(400),
+        ..MockBehavior::default()
+    })
+    .await;
+    let mut config = test_config(&upstream.url);
+    config.use_curl = true;
+    // 整个流约 2 秒，超过请求超时；相邻 chunk 的间隔短于空闲超时
+    config.proxy.request_timeout_seconds = 1;
+    config.proxy.response_read_timeout_seconds = 1;
+    let app = TestApp::spawn(config).await;
+    let mut body = chat_body("a long slow stream");
+    body["stream"] = json!(true);
+
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap();
+    assert!(text.trim_end().ends_with("data: [DONE]"), "SSE 输出: {}", text);
+
+    // 相邻 chunk 的间隔超过空闲超时时结束转发
+    upstream.set_behavior(MockBehavior {
+        chunk_delay: Duration::from_millis(1500),
+        ..MockBehavior::default()
+    });
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap_or_default();
+    assert!(!text.contains("[DONE]"), "SSE 输出: {}", text);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn curl_stream_upstream_errors_keep_their_status() {
+    let upstream = MockUpstream::start(MockBehavior::failing(
+        1,
+        429,
+        r#"{"error":{"message":"slow down","type":"rate_limit"}}"#,
+    ))
+    .await;
+    let mut config = test_config(&upstream.url);
+    config.use_curl = true;
+    let app = TestApp::spawn(config).await;
+    let mut body = chat_body("stream into a rate limit");
+    body["stream"] = json!(true);
+
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 429);
+    assert_ne!(
+        response.headers()[reqwest::header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["message"], "slow down");
+
+    // 上游恢复后正常转发
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("data: [DONE]"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn curl_upstream_errors_keep_retry_after() {
+    let upstream = MockUpstream::start(MockBehavior {
+        fail_retry_after: Some("7".to_string()),
+        ..MockBehavior::failing(
+            1,
+            429,
+            r#"{"error":{"message":"slow down","type":"rate_limit"}}"#,
+        )
+    })
+    .await;
+    let mut config = test_config(&upstream.url);
+    config.use_curl = true;
+    let app = TestApp::spawn(config).await;
+
+    let response = app.chat(&chat_body("rate limited through curl")).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "7");
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["message"], "slow down");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_upstream_error_bodies_are_truncated() {
+    let upstream = MockUpstream::start(MockBehavior::failing(
+        1,
+        400,
+        &"x".repeat(MAX_UPSTREAM_ERROR_BODY * 3),
+    ))
+    .await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let response = app.chat(&chat_body("huge error page")).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    let message = error["error"]["message"].as_str().unwrap();
+    assert_eq!(message.len(), MAX_UPSTREAM_ERROR_BODY);
+}
+
+fn content_filter_config(upstream_url: &str) -> Config {
+    let mut config = test_config(upstream_url);
+    config.content_filter = serde_json::from_value(json!({
+        "enabled": true,
+        "rules": [{"name": "secret", "pattern": "forbidden", "action": "block"}],
+        "finish_reason": "filtered",
+        "blocked_message": "blocked",
+    }))
+    .unwrap();
+    config
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocked_answers_are_replaced_and_not_cached() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = content_filter_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("say the forbidden word");
+
+    for _ in 0..2 {
+        let response: Value = app.chat(&body).await.json().await.unwrap();
+        assert_eq!(response["choices"][0]["message"]["content"], "blocked");
+        assert_eq!(response["choices"][0]["finish_reason"], "filtered");
+    }
+    assert_eq!(upstream.request_count(), 2);
+    assert_eq!(app.db_answer_count().await, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filtered_streams_are_truncated_with_the_filter_finish_reason() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = content_filter_config(&upstream.url);
+    config.use_curl = true;
+    let app = TestApp::spawn(config).await;
+    let mut body = chat_body("say the forbidden word now");
+    body["stream"] = json!(true);
+
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap();
+    // 命中规则的 chunk 及之后的内容不再转发，流以 filtered 结束
+    assert!(text.contains("say "), "SSE 输出: {}", text);
+    assert!(!text.contains("forbidden"), "SSE 输出: {}", text);
+    assert!(!text.contains("now"), "SSE 输出: {}", text);
+    assert!(text.contains("\"finish_reason\":\"filtered\""), "SSE 输出: {}", text);
+    assert!(text.trim_end().ends_with("data: [DONE]"), "SSE 输出: {}", text);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn curl_mode_keeps_headers_and_body_out_of_argv() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.use_curl = true;
+    config
+        .api_headers
+        .insert("Authorization".to_string(), "Bearer sk-argv-secret".to_string());
+
+    let mut curl = build_curl_command(&config.api_headers, &config.http_client.tls, &config);
+    curl.data("{\"secret prompt\": 1}");
+    let args: Vec<String> = curl
+        .command
+        .as_std()
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    assert!(!args.iter().any(|arg| arg.contains("sk-argv-secret") || arg.contains("secret prompt")));
+
+    // 经标准输入传递的请求体需要正确转义引号、反斜杠与换行
+    let app = TestApp::spawn(config).await;
+    let prompt = "quote \" backslash \\ newline \n tab \t done";
+    let response = app.chat(&chat_body(prompt)).await;
+    assert_eq!(response.status(), 200);
+    let response: Value = response.json().await.unwrap();
+    assert_eq!(
+        response["choices"][0]["message"]["content"],
+        format!("mock reply: {}", prompt)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recorded_upstream_is_replayed_offline() {
+    let file = std::env::temp_dir()
+        .join(format!("llm_api_replay_{}.jsonl", uuid::Uuid::new_v4().simple()))
+        .to_string_lossy()
+        .into_owned();
+    let body = chat_body("record me");
+
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.upstream_replay.mode = "record".to_string();
+    config.upstream_replay.file = file.clone();
+    // 端点地址中的凭据不写入记录文件
+    config.api_endpoints[0].url = upstream.url.replace("http://", "http://user:hunter2@");
+    let recorder = TestApp::spawn(config).await;
+    let recorded: Value = recorder.chat(&body).await.json().await.unwrap();
+    // 记录由后台线程写入
+    assert!(
+        eventually(|| async {
+            std::fs::read_to_string(&file).is_ok_and(|text| text.ends_with('\n'))
+        })
+        .await
+    );
+    assert!(!std::fs::read_to_string(&file).unwrap().contains("hunter2"));
+    drop(recorder);
+    let url = upstream.url.clone();
+    drop(upstream);
+
+    // 上游已关闭，回放模式仍能按记录返回
+    let mut config = test_config(&url);
+    config.upstream_replay.mode = "replay".to_string();
+    config.upstream_replay.file = file.clone();
+    let replayer = TestApp::spawn(config).await;
+
+    let replayed = replayer.chat(&body).await;
+    assert_eq!(replayed.status(), 200);
+    let replayed: Value = replayed.json().await.unwrap();
+    assert_eq!(
+        replayed["choices"][0]["message"]["content"],
+        recorded["choices"][0]["message"]["content"]
+    );
+    assert_eq!(replayed["choices"][0]["message"]["content"], "mock reply: record me");
+
+    let unknown = replayer.chat(&chat_body("never recorded")).await;
+    assert_eq!(unknown.status(), 502);
+
+    drop(replayer);
+    let _ = std::fs::remove_file(&file);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dry_run_does_not_write_cache() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.dry_run = true;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("how big would this be?");
+
+    for _ in 0..2 {
+        let response = app.chat(&body).await;
+        assert_eq!(response.status(), 200);
+        assert!(!response.headers().contains_key("x-cache-age"));
+    }
+    assert_eq!(upstream.request_count(), 2);
+    assert_eq!(app.state.memory_cache.clone().unwrap().stats().items, 0);
+    assert_eq!(app.db_answer_count().await, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admin_api_requires_the_token() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let client = reqwest::Client::new();
+    let invalidate = format!("{}/admin/cache/invalidate", app.url);
+
+    let missing = client.post(&invalidate).send().await.unwrap();
+    assert_eq!(missing.status(), 401);
+    assert_eq!(missing.headers()["www-authenticate"], "Bearer");
+    let wrong = client.post(&invalidate).bearer_auth("guess").send().await.unwrap();
+    assert_eq!(wrong.status(), 401);
+    let query = reqwest::get(format!("{}/admin/stats?access_token={}", app.url, TEST_ADMIN_TOKEN))
+        .await
+        .unwrap();
+    assert_eq!(query.status(), 200);
+    // 公开接口不受影响
+    let models = reqwest::get(format!("{}/v1/models", app.url)).await.unwrap();
+    assert_ne!(models.status(), 401);
+
+    // 未配置令牌时管理接口整体禁用
+    let mut config = test_config(&upstream.url);
+    config.admin.token.clear();
+    let app = TestApp::spawn(config).await;
+    assert_eq!(app.admin_get("/admin/config").await.status(), 403);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoints_sharing_a_url_keep_their_own_clients() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    let mut strict = config.api_endpoints[0].clone();
+    strict.tls = Some(Default::default());
+    let mut lenient = strict.clone();
+    lenient.tls.as_mut().unwrap().accept_invalid_certs = true;
+    config.api_endpoints = vec![strict.clone(), lenient.clone()];
+
+    let clients = create_endpoint_clients(&config.api_endpoints, &config.http_client).unwrap();
+    assert_eq!(clients.len(), 2);
+    assert!(clients.contains_key(&strict.client_key()));
+    assert!(clients.contains_key(&lenient.client_key()));
+
+    // /v1/models 复用端点的共享客户端转发
+    let app = TestApp::spawn(config).await;
+    let models = reqwest::get(format!("{}/v1/models", app.url)).await.unwrap();
+    assert_eq!(models.status(), 200);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_dump_masks_secrets() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config
+        .api_headers
+        .insert("Authorization".to_string(), "Bearer sk-secret".to_string());
+    config.http_client.outbound_proxy.password = "hunter2".to_string();
+    config.replication.shared_secret = "peer-secret".to_string();
+    config.cache.redis.url = "redis://:redis-pass@127.0.0.1:6379".to_string();
+    let app = TestApp::spawn(config).await;
+
+    let response = app.admin_get("/admin/config").await;
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap();
+    for secret in ["sk-secret", "hunter2", "peer-secret", "redis-pass", TEST_ADMIN_TOKEN] {
+        assert!(!text.contains(secret), "配置输出泄露了 {}", secret);
+    }
+
+    let dumped: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(dumped["api_headers"]["Authorization"], "***");
+    assert_eq!(dumped["api_headers"]["Content-Type"], "application/json");
+    assert_eq!(dumped["http_client"]["outbound_proxy"]["password"], "***");
+    assert_eq!(dumped["cache"]["redis"]["url"], "redis://:***@127.0.0.1:6379");
+    // 默认值同样输出，未配置的密钥保持为空
+    assert_eq!(dumped["cache"]["key_message"], "first");
+    assert_eq!(dumped["encryption"]["key_env"], "LLM_CACHE_ENCRYPTION_KEY");
+    assert_eq!(dumped["api_endpoints"][0]["url"], upstream.url.as_str());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replication_requires_the_shared_secret() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.replication.enabled = true;
+    init_replication(&config.replication);
+    let unprotected = TestApp::spawn(config.clone()).await;
+    config.replication.shared_secret = "peer-secret".to_string();
+    let app = TestApp::spawn(config).await;
+
+    // 空请求体是合法的空批次
+    let client = reqwest::Client::new();
+    let replicate = |app: &TestApp, secret: Option<&str>| {
+        let mut request = client.post(format!("{}/internal/replicate", app.url));
+        if let Some(secret) = secret {
+            request = request.header("x-replication-secret", secret);
+        }
+        request.send()
+    };
+    for secret in [None, Some(""), Some("wrong-secret"), Some("peer-secret!")] {
+        assert_eq!(replicate(&app, secret).await.unwrap().status(), 403);
+    }
+    // 未配置密钥的节点拒绝所有复制请求
+    for secret in [None, Some("")] {
+        assert_eq!(replicate(&unprotected, secret).await.unwrap().status(), 403);
+    }
+
+    let accepted = replicate(&app, Some("peer-secret")).await.unwrap();
+    assert_eq!(accepted.status(), 200);
+    let accepted: Value = accepted.json().await.unwrap();
+    assert_eq!(accepted["accepted"], 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn grpc_requests_without_temperature_share_the_http_cache_key() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.key_include_sampling = true;
+    let app = TestApp::spawn(config).await;
+    let grpc = app.grpc_service();
+
+    assert_eq!(app.chat(&chat_body("grpc sampling")).await.status(), 200);
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+
+    let request = |temperature: Option<f32>| {
+        tonic::Request::new(ChatRequest {
+            model: "mock-model".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "grpc sampling".to_string(),
+            }],
+            temperature,
+            ..Default::default()
+        })
+    };
+    // 未设置温度时使用与 HTTP 接口相同的默认值，命中同一条缓存
+    let hit = grpc.chat_completion(request(None)).await.unwrap().into_inner();
+    assert_eq!(hit.choices[0].message.as_ref().unwrap().content, "mock reply: grpc sampling");
+    assert_eq!(upstream.request_count(), 1);
+
+    grpc.chat_completion(request(Some(0.5))).await.unwrap();
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    assert!((requests[0]["temperature"].as_f64().unwrap() - 0.1).abs() < 1e-6);
+    assert_eq!(requests[1]["temperature"], 0.5);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn grpc_maps_payload_too_large_to_resource_exhausted() {
+    let upstream = MockUpstream::start(MockBehavior::failing(
+        1,
+        413,
+        r#"{"error":{"message":"request entity too large"}}"#,
+    ))
+    .await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let request = tonic::Request::new(ChatRequest {
+        model: "mock-model".to_string(),
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "a very large request".to_string(),
+        }],
+        ..Default::default()
+    });
+    let error = app.grpc_service().chat_completion(request).await.unwrap_err();
+    assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(error.message(), "request entity too large");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn top_questions_require_the_admin_token_and_cap_previews() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let grpc = app.grpc_service();
+    let body = chat_body(&"long answer ".repeat(200));
+
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await > 0 }).await);
+    assert!(app.chat(&body).await.headers().contains_key("x-cache-age"));
+
+    let request = || {
+        tonic::Request::new(TopQuestionsRequest {
+            limit: 100_000,
+            preview_chars: 100_000,
+        })
+    };
+    let denied = grpc.top_questions(request()).await.unwrap_err();
+    assert_eq!(denied.code(), tonic::Code::Unauthenticated);
+
+    let authorized = || {
+        let mut request = request();
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", TEST_ADMIN_TOKEN).parse().unwrap(),
+        );
+        request
+    };
+    let mut items = Vec::new();
+    for _ in 0..100 {
+        items = grpc.top_questions(authorized()).await.unwrap().into_inner().items;
+        if !items.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let preview = &items.first().expect("排行中缺少命中过的回答").preview;
+    // 预览最多 1000 个字符加省略号
+    assert_eq!(preview.chars().count(), 1001);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoint_overrides_are_applied_to_forwarded_payload() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    let overrides = &mut config.api_endpoints[0].overrides;
+    overrides.max_tokens = Some(64);
+    overrides.stop = Some(StopSequences::Single("</s>".to_string()));
+    let app = TestApp::spawn(config).await;
+
+    let mut body = chat_body("override me");
+    body["temperature"] = json!(0.5);
+    body["max_tokens"] = json!(4096);
+    body["stop"] = json!(["\n\n"]);
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["max_tokens"], 64);
+    assert_eq!(requests[0]["stop"], "</s>");
+    // 未覆盖的参数保持客户端发送的值
+    assert_eq!(requests[0]["temperature"], 0.5);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn force_model_rejects_other_models_and_rewrites_the_model() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.api_endpoints[0].model = Some("backend-model".to_string());
+    config.force_model.enabled = true;
+    config.force_model.reject_other_models = true;
+    let app = TestApp::spawn(config).await;
+
+    let rejected = app.chat(&chat_body("which model?")).await;
+    assert_eq!(rejected.status(), 400);
+    assert_eq!(upstream.request_count(), 0);
+
+    let mut body = chat_body("which model?");
+    body["model"] = json!("backend-model");
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests()[0]["model"], "backend-model");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rewritten_requests_do_not_share_cache_entries() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.rewrites = vec![
+        serde_json::from_value(json!({
+            "name": "tenant-b",
+            "match": {"header": {"name": "x-tenant", "value": "b"}},
+            "model": "tenant-b-model",
+        }))
+        .unwrap(),
+    ];
+    config.cache.max_items = 0;
+    let app = TestApp::spawn(config).await;
+    let body = chat_body("same question, different tenant");
+
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+
+    // 匹配改写规则的请求发给上游的模型不同，不能命中未改写请求的缓存
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", app.url))
+        .header("x-tenant", "b")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1]["model"], "tenant-b-model");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn per_model_cache_versions_keep_models_apart() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.max_items = 0;
+    config.model_cache_versions.insert("model-a".to_string(), 2);
+    let app = TestApp::spawn(config).await;
+
+    let mut body = chat_body("asked of two models");
+    body["model"] = json!("model-a");
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert!(eventually(|| async { app.db_answer_count().await == 1 }).await);
+    assert!(app.chat(&body).await.headers().contains_key("x-cache-age"));
+
+    // 另一个模型的同一问题不能命中 model-a 的回答
+    body["model"] = json!("model-b");
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_model_uses_the_configured_default() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.default_model = Some("default-model".to_string());
+    let app = TestApp::spawn(config).await;
+
+    let body = json!({"messages": [{"role": "user", "content": "no model here"}]});
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests()[0]["model"], "default-model");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoint_enable_thinking_is_forwarded() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.api_endpoints[0].enable_thinking = Some(true);
+    let app = TestApp::spawn(config).await;
+
+    let response = app.chat(&chat_body("think first")).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests()[0]["enable_thinking"], true);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prompt_cache_hints_mark_stable_prefixes() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.api_endpoints[0].prompt_cache_hints = Some(true);
+    config.prompt_cache.min_prefix_tokens = 1;
+    let app = TestApp::spawn(config).await;
+
+    let body = json!({
+        "model": "mock-model",
+        "messages": [
+            {"role": "system", "content": "you are a long and stable system prompt"},
+            {"role": "user", "content": "first question"},
+            {"role": "assistant", "content": "first answer"},
+            {"role": "user", "content": "second question"},
+        ],
+    });
+    assert_eq!(app.chat(&body).await.status(), 200);
+
+    // 系统提示词与对话历史的末尾带有 cache_control，最新的提问保持原样
+    let messages = &upstream.requests()[0]["messages"];
+    for index in [0, 2] {
+        assert_eq!(messages[index]["content"][0]["cache_control"]["type"], "ephemeral");
+    }
+    assert_eq!(messages[0]["content"][0]["text"], "you are a long and stable system prompt");
+    assert_eq!(messages[1]["content"], "first question");
+    assert_eq!(messages[3]["content"], "second question");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_disconnects_are_counted_as_cancellations() {
+    let upstream = MockUpstream::start(MockBehavior::slow(Duration::from_millis(1500))).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let impatient = reqwest::Client::builder()
+        .timeout(Duration::from_millis(300))
+        .build()
+        .unwrap();
+    let result = impatient
+        .post(format!("{}/v1/chat/completions", app.url))
+        .json(&chat_body("never mind"))
+        .send()
+        .await;
+    assert!(result.is_err());
+
+    // 客户端断开不是端点的失败
+    assert!(
+        eventually(|| async {
+            let stats = app.state.endpoint_stats.snapshot(&upstream.url);
+            stats.in_flight == 0 && stats.cancelled == 1
+        })
+        .await
+    );
+    let stats = app.state.endpoint_stats.snapshot(&upstream.url);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(stats.consecutive_errors, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoint_http_client_overrides_get_their_own_client() {
+    let upstream = MockUpstream::start(MockBehavior::slow(Duration::from_millis(1500))).await;
+    let mut config = test_config(&upstream.url);
+    let client = &mut config.api_endpoints[0].http_client;
+    client.timeout_seconds = Some(1);
+    client.http_version = Some("http1".to_string());
+    let endpoint = config.api_endpoints[0].clone();
+    let app = TestApp::spawn(config).await;
+    assert!(app.state.endpoint_clients.contains_key(&endpoint.client_key()));
+
+    // 端点的超时短于上游延迟，请求以 504 结束
+    let response = app.chat(&chat_body("too slow for this endpoint")).await;
+    assert_eq!(response.status(), 504);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ip_preference_falls_back_to_the_other_family() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    // 模拟上游只监听 IPv4，localhost 解析出 ::1 时应先尝试再回退到 127.0.0.1
+    let url = upstream.url.replace("127.0.0.1", "localhost");
+    let mut config = test_config(&url);
+    config.api_endpoints[0].http_client.ip_preference = Some("ipv6".to_string());
+    let app = TestApp::spawn(config).await;
+
+    let response = app.chat(&chat_body("reach me over any family")).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.request_count(), 1);
+}
+
+#[tokio::test]
+async fn ipv6_listen_addresses_are_bracketed() {
+    assert_eq!(listen_address("0.0.0.0", 4321), "0.0.0.0:4321");
+    assert_eq!(listen_address("::", 4321), "[::]:4321");
+    assert_eq!(listen_address("[::1]", 4321), "[::1]:4321");
+    assert_eq!(listen_address("localhost", 4321), "localhost:4321");
+
+    let listener = tokio::net::TcpListener::bind(listen_address("::1", 0)).await.unwrap();
+    assert!(listener.local_addr().unwrap().is_ipv6());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn warmup_reads_responses_and_counts_only_successful_connections() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let warmup = WarmupConfig {
+        enabled: true,
+        connections_per_endpoint: 3,
+        prime_request: true,
+        timeout_seconds: 5,
+    };
+
+    assert_eq!(warm_up_endpoints(&app.state, &warmup).await, 3);
+    assert_eq!(upstream.request_count(), 4);
+
+    // 上游返回错误状态码时连接不计入预热成功
+    upstream.set_behavior(MockBehavior::failing(usize::MAX, 503, "unavailable"));
+    assert_eq!(warm_up_endpoints(&app.state, &warmup).await, 0);
+    assert_eq!(upstream.request_count(), 8);
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn unix_socket_endpoints_serve_and_cache_requests() {
+    let socket_path =
+        std::env::temp_dir().join(format!("llm_api_{}.sock", uuid::Uuid::new_v4().simple()));
+    let upstream = MockUpstream::start_unix(MockBehavior::default(), &socket_path).await;
+
+    // 套接字文件之后的部分作为 HTTP 请求路径
+    let (path, request_path) = split_unix_url(&format!("{}/v1/chat/completions", upstream.url))
+        .await
+        .unwrap();
+    assert_eq!(path, socket_path);
+    assert_eq!(request_path, "/v1/chat/completions");
+
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let body = chat_body("over a unix socket");
+    let first = app.chat(&body).await;
+    assert_eq!(first.status(), 200);
+    let first: Value = first.json().await.unwrap();
+    assert_eq!(
+        first["choices"][0]["message"]["content"],
+        "mock reply: over a unix socket"
+    );
+
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert_eq!(upstream.request_count(), 1);
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn unix_socket_error_bodies_are_truncated() {
+    let socket_path =
+        std::env::temp_dir().join(format!("llm_api_{}.sock", uuid::Uuid::new_v4().simple()));
+    let upstream = MockUpstream::start_unix(
+        MockBehavior::failing(1, 400, &"x".repeat(MAX_UPSTREAM_ERROR_BODY * 3)),
+        &socket_path,
+    )
+    .await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let response = app.chat(&chat_body("huge error over a socket")).await;
+    assert_eq!(response.status(), 400);
+    let error: Value = response.json().await.unwrap();
+    let message = error["error"]["message"].as_str().unwrap();
+    assert_eq!(message.len(), MAX_UPSTREAM_ERROR_BODY);
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dashboard_lists_recent_requests() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let mut body = chat_body("show me on the dashboard");
+    body["model"] = json!("dashboard-model");
+    assert_eq!(app.chat(&body).await.status(), 200);
+
+    let page = reqwest::get(format!("{}/dashboard", app.url)).await.unwrap();
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("/admin/requests/recent"));
+
+    // 最近请求保存在进程内，其他测试的请求也可能出现，按模型名查找
+    let recent: Value = app
+        .admin_get("/admin/requests/recent?limit=100")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let ours = recent["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["model"] == "dashboard-model")
+        .expect("最近请求中缺少本次请求");
+    assert_eq!(ours["cache_status"], "miss");
+    assert_eq!(ours["status_code"], 200);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn live_events_are_streamed_over_websocket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let mut socket = tokio::net::TcpStream::connect(app.url.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    // RFC 6455 中的示例密钥与对应的 Sec-WebSocket-Accept
+    let handshake = "GET /admin/ws?access_token=test-admin-token HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n\r\n";
+    socket.write_all(handshake.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(socket.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap().to_ascii_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{}", response);
+    assert!(response.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+
+    let mut body = chat_body("watch me live");
+    body["model"] = json!("live-model");
+    assert_eq!(app.chat(&body).await.status(), 200);
+
+    // 其他测试的事件也会出现，按模型名找到本次请求后只看它的事件
+    let mut request_id = None;
+    let mut seen = Vec::new();
+    let read_events = async {
+        loop {
+            let Frame::Text(text) = read_frame(&mut socket).await.unwrap() else {
+                continue;
+            };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            if event["type"] == "request_started" && event["model"] == "live-model" {
+                request_id = Some(event["request_id"].clone());
+            }
+            if request_id.is_some() && Some(&event["request_id"]) == request_id.as_ref() {
+                seen.push(event["type"].as_str().unwrap().to_string());
+                if event["type"] == "request_finished" {
+                    assert_eq!(event["status_code"], 200);
+                    break;
+                }
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), read_events).await.unwrap();
+    assert_eq!(seen, ["request_started", "cache_miss", "request_finished"]);
+
+    // 客户端发送（带掩码的）关闭帧后服务端回应关闭
+    socket.write_all(&[0x88, 0x80, 0, 0, 0, 0]).await.unwrap();
+    let close = async {
+        while read_frame(&mut socket).await.unwrap() != Frame::Close {}
+    };
+    tokio::time::timeout(Duration::from_secs(5), close).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn live_events_are_streamed_as_filtered_sse() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let mut events = app
+        .admin_get("/admin/events?types=request_started,request_finished")
+        .await;
+    assert_eq!(events.status(), 200);
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+    let mut body = chat_body("watch me over sse");
+    body["model"] = json!("sse-model");
+    assert_eq!(app.chat(&body).await.status(), 200);
+
+    let mut request_id = None;
+    let mut seen = Vec::new();
+    let mut buffer = String::new();
+    let read_events = async {
+        loop {
+            let chunk = events.chunk().await.unwrap().unwrap();
+            buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = buffer.find("\n\n") {
+                let message: String = buffer.drain(..end + 2).collect();
+                let Some(data) = message.trim_end().strip_prefix("data: ") else {
+                    continue;
+                };
+                let event: Value = serde_json::from_str(data).unwrap();
+                if event["type"] == "request_started" && event["model"] == "sse-model" {
+                    request_id = Some(event["request_id"].clone());
+                }
+                if request_id.is_some() && Some(&event["request_id"]) == request_id.as_ref() {
+                    seen.push(event["type"].as_str().unwrap().to_string());
+                    if event["type"] == "request_finished" {
+                        return;
+                    }
+                }
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), read_events).await.unwrap();
+    // cache_miss 不在 types 中，不会推送
+    assert_eq!(seen, ["request_started", "request_finished"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_request_fields_are_forwarded() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let mut body = chat_body("keep my fields");
+    body["top_p"] = json!(0.9);
+    body["logit_bias"] = json!({"50256": -100});
+    body["x_vendor"] = json!({"cache": false});
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+
+    let forwarded = &upstream.requests()[0];
+    assert_eq!(forwarded["top_p"], 0.9);
+    assert_eq!(forwarded["logit_bias"]["50256"], -100);
+    assert_eq!(forwarded["x_vendor"]["cache"], false);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sampling_parameters_are_validated_and_keyed() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&upstream.url);
+    config.cache.key_include_sampling = true;
+    let app = TestApp::spawn(config).await;
+
+    let mut invalid = chat_body("sampling");
+    invalid["top_p"] = json!(1.5);
+    assert_eq!(app.chat(&invalid).await.status(), 400);
+    assert_eq!(upstream.request_count(), 0);
+
+    let mut body = chat_body("sampling");
+    body["top_p"] = json!(0.5);
+    body["seed"] = json!(42);
+    assert_eq!(app.chat(&body).await.status(), 200);
+    assert_eq!(upstream.requests()[0]["seed"], 42);
+
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+    let hit = app.chat(&body).await;
+    assert!(hit.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 1);
+
+    // 采样参数不同的同一问题不命中缓存
+    body["seed"] = json!(7);
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn json_mode_is_forwarded_keyed_and_validated() {
+    let upstream = MockUpstream::start(MockBehavior {
+        reply_prefix: String::new(),
+        ..MockBehavior::default()
+    })
+    .await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+
+    let mut body = chat_body(r#"{"answer": 42}"#);
+    body["response_format"] = json!({"type": "json_object"});
+    let response = app.chat(&body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests()[0]["response_format"]["type"], "json_object");
+
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+    // 不带 response_format 的同一问题使用不同的缓存键
+    let plain = app.chat(&chat_body(r#"{"answer": 42}"#)).await;
+    assert!(!plain.headers().contains_key("x-cache-age"));
+    assert_eq!(upstream.request_count(), 2);
+
+    // 上游回答不是有效 JSON 时返回 502
+    let mut invalid = chat_body("plain text answer");
+    invalid["response_format"] = json!({"type": "json_object"});
+    assert_eq!(app.chat(&invalid).await.status(), 502);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn saturated_upstream_returns_429_with_retry_after() {
+    let upstream = MockUpstream::start(MockBehavior::slow(Duration::from_millis(800))).await;
+    let mut config = test_config(&upstream.url);
+    config.max_concurrent_requests = 1;
+    config.upstream_queue.acquire_timeout_seconds = 0;
+    let app = TestApp::spawn(config).await;
+
+    let (first_body, second_body) = (chat_body("slow question one"), chat_body("slow question two"));
+    let (first, second) = tokio::join!(app.chat(&first_body), async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        app.chat(&second_body).await
+    });
+    assert_eq!(first.status(), 200);
+    assert_eq!(second.status(), 429);
+    let retry_after: u64 = second.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after >= 1);
+    let body: Value = second.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "rate_limit_error");
+}
+
+#[tokio::test]
+async fn allowlisted_upstream_headers_are_passed_through() {
+    let upstream = MockUpstream::start(MockBehavior::default()).await;
+    let app = TestApp::spawn(test_config(&upstream.url)).await;
+    let body = chat_body("which headers come back?");
+
+    let miss = app.chat(&body).await;
+    assert_eq!(miss.status(), 200);
+    assert_eq!(miss.headers()["x-ratelimit-remaining-requests"], "99");
+    assert_eq!(miss.headers()["openai-processing-ms"], "12");
+    assert!(!miss.headers().contains_key("x-mock-internal"));
+
+    // 缓存命中的响应不带上游响应头
+    let cache = app.state.memory_cache.clone().unwrap();
+    assert!(
+        eventually(|| {
+            let cache = cache.clone();
+            async move { cache.stats().items > 0 }
+        })
+        .await
+    );
+    let hit = app.chat(&body).await;
+    assert!(hit.headers().contains_key("x-cache-age"));
+    assert!(!hit.headers().contains_key("x-ratelimit-remaining-requests"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unparseable_reply_falls_back_to_another_endpoint() {
+    let broken = MockUpstream::start(MockBehavior {
+        raw_body: Some("<html><body>502 Bad Gateway</body></html>".to_string()),
+        ..MockBehavior::default()
+    })
+    .await;
+    let healthy = MockUpstream::start(MockBehavior::default()).await;
+    let mut config = test_config(&broken.url);
+    // 几乎总是先选中返回 HTML 的端点
+    config.api_endpoints[0].weight = 255;
+    let mut fallback = config.api_endpoints[0].clone();
+    fallback.url = healthy.url.clone();
+    fallback.weight = 1;
+    config.api_endpoints.push(fallback);
+    let app = TestApp::spawn(config).await;
+
+    for prompt in ["fallback one", "fallback two", "fallback three"] {
+        let response = app.chat(&chat_body(prompt)).await;
+        assert_eq!(response.status(), 200);
+    }
+    assert!(broken.request_count() > 0);
+    assert_eq!(healthy.request_count(), 3);
+
+    // 关闭后无法解析的响应直接返回错误
+    let mut config = test_config(&broken.url);
+    config.parse_failure_fallback = false;
+    let app = TestApp::spawn(config).await;
+    assert_eq!(app.chat(&chat_body("no fallback")).await.status(), 500);
+}
+
+#[tokio::test]
+async fn full_wait_queue_rejects_while_queued_request_is_served() {
+    let upstream = MockUpstream::start(MockBehavior::slow(Duration::from_millis(600))).await;
+    let mut config = test_config(&upstream.url);
+    config.max_concurrent_requests = 1;
+    config.upstream_queue.acquire_timeout_seconds = 30;
+    config.upstream_queue.max_queue_depth = 1;
+    let app = TestApp::spawn(config).await;
+
+    let bodies = ["burst one", "burst two", "burst three"].map(chat_body);
+    let (first, second, third) = tokio::join!(
+        app.chat(&bodies[0]),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            app.chat(&bodies[1]).await
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            app.chat(&bodies[2]).await
+        }
+    );
+    // 第二个请求排队等到了许可，第三个请求因队列已满立即被拒绝
+    assert_eq!(first.status(), 200);
+    assert_eq!(second.status(), 200);
+    assert_eq!(third.status(), 429);
+    assert!(third.headers().contains_key("retry-after"));
+}
@@ -0,0 +1,48 @@
+//! purge 子命令：参数与时长解析
+
+use llm_api::utils::purge::{PurgeOptions, parse_age};
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn ages_accept_common_units() {
+    assert_eq!(parse_age("90s"), Ok(90));
+    assert_eq!(parse_age("30m"), Ok(30 * 60));
+    assert_eq!(parse_age("12h"), Ok(12 * 3600));
+    assert_eq!(parse_age("30d"), Ok(30 * 86400));
+    assert_eq!(parse_age("2w"), Ok(14 * 86400));
+    for invalid in ["", "d", "30", "30y", "-1d", "1.5d", "3天"] {
+        assert!(parse_age(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn purge_options_require_a_filter() {
+    let options = PurgeOptions::parse(&args(&[
+        "--model",
+        "gpt-4o",
+        "--older-than",
+        "30d",
+        "--max-hits",
+        "2",
+        "--dry-run",
+    ]))
+    .unwrap();
+    assert_eq!(
+        options,
+        PurgeOptions {
+            model: Some("gpt-4o".to_string()),
+            older_than: Some(30 * 86400),
+            max_hits: Some(2),
+            dry_run: true,
+        }
+    );
+
+    assert!(PurgeOptions::parse(&args(&[])).is_err());
+    assert!(PurgeOptions::parse(&args(&["--dry-run"])).is_err());
+    assert!(PurgeOptions::parse(&args(&["--max-hits"])).is_err());
+    assert!(PurgeOptions::parse(&args(&["--max-hits", "many"])).is_err());
+    assert!(PurgeOptions::parse(&args(&["--since", "1d"])).is_err());
+}
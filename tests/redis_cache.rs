@@ -0,0 +1,106 @@
+use llm_api::models::api_model::ChatResponseJson;
+use llm_api::utils::answer_codec::encode_answer;
+use llm_api::utils::redis_cache::{RedisCache, RedisCacheConfig};
+use serde_json::json;
+use std::time::Duration;
+
+// 需要可用的 Redis：设置 LLM_CACHE_TEST_REDIS_URL（如 redis://127.0.0.1:6379/15）后运行，未设置时跳过。
+// 每个测试使用独立的键前缀，互不影响
+async fn redis(ttl_seconds: u64) -> Option<RedisCache> {
+    let Ok(url) = std::env::var("LLM_CACHE_TEST_REDIS_URL") else {
+        eprintln!("未设置 LLM_CACHE_TEST_REDIS_URL，跳过 Redis 测试");
+        return None;
+    };
+    let config = RedisCacheConfig {
+        url,
+        key_prefix: format!("llm_cache_test:{}:", uuid::Uuid::new_v4().simple()),
+        ttl_seconds,
+        ..Default::default()
+    };
+    Some(RedisCache::connect(&config).await.expect("连接测试 Redis 失败"))
+}
+
+fn answer(content: &str, version: u8, epoch: u64) -> Vec<u8> {
+    let response: ChatResponseJson = serde_json::from_value(json!({
+        "id": "chatcmpl-redis",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "redis-model",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        }],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+    }))
+    .unwrap();
+    encode_answer(&response, version, epoch).unwrap()
+}
+
+#[tokio::test]
+async fn answers_round_trip_and_respect_version_and_epoch() {
+    let Some(redis) = redis(0).await else {
+        return;
+    };
+    let data = answer("from redis", 2, 3);
+    redis
+        .insert_batch(&[("question".to_string(), data.clone())], 0)
+        .await
+        .unwrap();
+
+    let stored = redis.get("question", 2, 3).await.unwrap().unwrap();
+    assert_eq!(stored.response, data);
+    assert_eq!(stored.version, 2);
+    assert!(redis.get("question", 3, 3).await.unwrap().is_none());
+    assert!(redis.get("question", 2, 4).await.unwrap().is_none());
+    assert!(redis.get("missing", 0, 0).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn identical_answers_share_one_entry() {
+    let Some(redis) = redis(0).await else {
+        return;
+    };
+    redis
+        .insert_batch(
+            &[
+                ("first".to_string(), answer("shared", 1, 0)),
+                ("second".to_string(), answer("shared", 1, 0)),
+            ],
+            0,
+        )
+        .await
+        .unwrap();
+
+    let first = redis.get("first", 0, 0).await.unwrap().unwrap();
+    let second = redis.get("second", 0, 0).await.unwrap().unwrap();
+    assert_eq!(first.answer_key, second.answer_key);
+    redis.record_question_hit("first").await.unwrap();
+    redis.record_question_hit("missing").await.unwrap();
+}
+
+#[tokio::test]
+async fn the_shared_epoch_only_grows() {
+    let Some(redis) = redis(0).await else {
+        return;
+    };
+    assert_eq!(redis.epoch().await.unwrap(), 0);
+    assert_eq!(redis.bump_epoch().await.unwrap(), 1);
+    assert_eq!(redis.bump_epoch().await.unwrap(), 2);
+    assert_eq!(redis.epoch().await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn entries_expire_after_the_ttl() {
+    let Some(redis) = redis(1).await else {
+        return;
+    };
+    redis
+        .insert_batch(&[("short-lived".to_string(), answer("soon gone", 0, 0))], 0)
+        .await
+        .unwrap();
+    assert!(redis.get("short-lived", 0, 0).await.unwrap().is_some());
+
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    assert!(redis.get("short-lived", 0, 0).await.unwrap().is_none());
+}
@@ -0,0 +1,139 @@
+//! 上游响应解析：回放 fuzz/corpus 中的语料（与模糊测试目标相同的检查），并校验宽松解析的结果
+
+use axum::response::IntoResponse;
+use llm_api::utils::config::ApiDefaultsConfig;
+use llm_api::utils::response_parser::{parse_chat_response, parse_error, split_status_trailer};
+use std::path::Path;
+
+fn corpus(target: &str) -> Vec<(String, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fuzz/corpus")
+        .join(target);
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("读取语料目录 {} 失败: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, std::fs::read(&path).unwrap())
+        })
+        .collect()
+}
+
+fn parse_seed(name: &str) -> Result<llm_api::models::api_model::ChatResponseJson, String> {
+    let data = std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus/chat_response")
+            .join(name),
+    )
+    .unwrap();
+    parse_chat_response(&String::from_utf8(data).unwrap(), &ApiDefaultsConfig::default())
+}
+
+#[test]
+fn chat_response_corpus_does_not_panic() {
+    let defaults = ApiDefaultsConfig::default();
+    for (name, data) in corpus("chat_response") {
+        let Ok(text) = std::str::from_utf8(&data) else {
+            continue;
+        };
+        if let Ok(response) = parse_chat_response(text, &defaults) {
+            assert!(!response.choices.is_empty(), "{}", name);
+            let usage = &response.usage;
+            assert!(
+                usage.prompt_tokens >= 0 && usage.completion_tokens >= 0 && usage.total_tokens >= 0,
+                "{}",
+                name
+            );
+            serde_json::to_string(&response).unwrap();
+        }
+    }
+}
+
+#[test]
+fn curl_output_corpus_splits_status_trailer() {
+    for (name, data) in corpus("curl_output") {
+        let output = String::from_utf8_lossy(&data);
+        let (body, _) = split_status_trailer(&output);
+        assert!(output.starts_with(body), "{}", name);
+        assert!(body.len() < output.len() || body.is_empty(), "{}", name);
+    }
+    assert_eq!(split_status_trailer("{}\n429"), ("{}", Some(429)));
+    assert_eq!(split_status_trailer("200"), ("", Some(200)));
+    assert_eq!(split_status_trailer("body\nnot-a-status"), ("body", None));
+}
+
+#[test]
+fn lenient_parse_recovers_usable_fields() {
+    let standard = parse_seed("standard.json").unwrap();
+    assert_eq!(standard.choices[0].message.content, "hello");
+    assert_eq!(standard.usage.total_tokens, 6);
+
+    let parts = parse_seed("content_parts.json").unwrap();
+    assert_eq!(parts.choices[0].message.content, "part one part two");
+    assert_eq!(parts.choices[0].finish_reason, "unknown");
+    assert_eq!(parts.usage.prompt_tokens, 12);
+    assert_eq!(parts.usage.completion_tokens, 3);
+    assert_eq!(parts.usage.total_tokens, i32::MAX);
+
+    let legacy = parse_seed("legacy_text.json").unwrap();
+    assert_eq!(legacy.choices[0].message.content, "legacy completion");
+    assert_eq!(legacy.choices[0].index, 3);
+    assert_eq!(legacy.usage.prompt_tokens, 0);
+
+    let tools = parse_seed("tool_calls.json").unwrap();
+    assert!(tools.choices[0].message.tool_calls.is_some());
+    assert_eq!(tools.choices[0].finish_reason, "tool_calls");
+
+    // 符合标准结构的响应同样不保留负的 token 数
+    let negative = parse_seed("negative_usage.json").unwrap();
+    assert_eq!(negative.usage.prompt_tokens, 0);
+    assert_eq!(negative.usage.completion_tokens, 2);
+    assert_eq!(negative.usage.total_tokens, 0);
+}
+
+#[test]
+fn lenient_parse_rejects_bodies_without_choices() {
+    for name in [
+        "non_object_choices.json",
+        "choices_object.json",
+        "empty_choices.json",
+        "top_level_array.json",
+        "error_body.json",
+        "truncated.json",
+        "empty.json",
+    ] {
+        assert!(parse_seed(name).is_err(), "{} 不应解析成功", name);
+    }
+}
+
+#[test]
+fn strict_parse_rejects_bodies_that_need_coercion() {
+    let strict = ApiDefaultsConfig {
+        strict_response_parsing: true,
+        ..ApiDefaultsConfig::default()
+    };
+    let read = |name: &str| {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus/chat_response")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    };
+
+    assert!(parse_chat_response(&read("standard.json"), &strict).is_ok());
+    let empty = parse_chat_response(&read("empty_choices.json"), &strict).unwrap_err();
+    assert!(empty.contains("choices 数组为空"), "{}", empty);
+    let err = parse_chat_response(&read("content_parts.json"), &strict).unwrap_err();
+    assert!(err.starts_with("上游响应不符合标准结构"), "{}", err);
+    // 严格模式下的解析失败视为上游错误（502），默认模式下仍为内部错误
+    let status = |defaults: &ApiDefaultsConfig| {
+        parse_error(defaults, "解析响应JSON失败", err.clone())
+            .into_response()
+            .status()
+    };
+    assert_eq!(status(&strict), 502);
+    assert_eq!(status(&ApiDefaultsConfig::default()), 500);
+}
@@ -0,0 +1,74 @@
+//! 优雅关闭：退出时等待进行中的请求，超过 shutdown_timeout_seconds 后中断剩余连接
+//! （单独的测试进程，关闭时会结束全局的实时事件订阅，不能与 e2e 测试共用进程）
+
+use axum::Router;
+use axum::routing::get;
+use llm_api::server::serve;
+use llm_api::test_support::test_config;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+struct SlowServer {
+    // 进行中的请求，发出后等待处理开始
+    request: JoinHandle<reqwest::Result<String>>,
+    shutdown: oneshot::Sender<()>,
+    server: JoinHandle<()>,
+}
+
+// 启动一个只有 /slow 路由的服务（处理需要 handler_delay），发出一个请求并等到处理开始
+async fn start_slow_request(handler_delay: Duration, shutdown_timeout_seconds: u64) -> SlowServer {
+    let mut config = test_config("http://127.0.0.1:9");
+    config.server.shutdown_timeout_seconds = shutdown_timeout_seconds;
+    let (started_tx, mut started_rx) = mpsc::unbounded_channel::<()>();
+    let app = Router::new().route(
+        "/slow",
+        get(move || async move {
+            let _ = started_tx.send(());
+            tokio::time::sleep(handler_delay).await;
+            "done"
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/slow", listener.local_addr().unwrap());
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        serve(listener, app, &config, async {
+            let _ = shutdown_rx.await;
+        })
+        .await
+        .unwrap();
+    });
+
+    let request = tokio::spawn(async move { reqwest::get(url).await?.text().await });
+    started_rx.recv().await.unwrap();
+    SlowServer {
+        request,
+        shutdown,
+        server,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn in_flight_requests_finish_before_shutdown() {
+    let slow = start_slow_request(Duration::from_millis(500), 30).await;
+
+    slow.shutdown.send(()).unwrap();
+    slow.server.await.unwrap();
+    assert_eq!(slow.request.await.unwrap().unwrap(), "done");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stuck_requests_are_aborted_after_the_shutdown_timeout() {
+    let slow = start_slow_request(Duration::from_secs(600), 1).await;
+
+    let started = Instant::now();
+    slow.shutdown.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), slow.server)
+        .await
+        .expect("serve 未在关闭超时后返回")
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert!(slow.request.await.unwrap().is_err());
+}
@@ -0,0 +1,27 @@
+//! systemd 集成：套接字激活的环境变量解析与 sd_notify 通知
+
+use llm_api::utils::systemd::{listen_fds_count, notify_socket};
+
+#[test]
+fn listen_fds_are_only_taken_for_this_process() {
+    assert_eq!(listen_fds_count(Some("42"), Some("2"), 42), 2);
+    assert_eq!(listen_fds_count(Some("41"), Some("2"), 42), 0);
+    assert_eq!(listen_fds_count(None, Some("1"), 42), 0);
+    assert_eq!(listen_fds_count(Some("42"), Some("x"), 42), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn notify_sends_state_to_the_notify_socket() {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = std::env::temp_dir().join(format!("llm_api_notify_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let receiver = UnixDatagram::bind(&path).unwrap();
+
+    notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+    let mut buf = [0u8; 64];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "wasm-plugins")]
+
+use axum::http::HeaderMap;
+use llm_api::models::api_model::ChatRequestJson;
+use llm_api::utils::error::AppError;
+use llm_api::utils::plugin::{RequestContext, RequestPlugin};
+use llm_api::utils::wasm_plugin::WasmPluginConfig;
+use llm_api::utils::wasm_runtime::WasmPlugin;
+use serde_json::json;
+
+// 将 WAT 文本写入临时文件并加载为插件
+fn load(name: &str, wat: &str, fuel: u64) -> WasmPlugin {
+    let path = std::env::temp_dir().join(format!(
+        "llm_api_{}_{}.wat",
+        name,
+        uuid::Uuid::new_v4().simple()
+    ));
+    std::fs::write(&path, wat).unwrap();
+    let plugin = WasmPlugin::load(&WasmPluginConfig {
+        name: name.to_string(),
+        path: path.to_string_lossy().into_owned(),
+        fuel,
+        max_memory_mb: 16,
+    })
+    .unwrap();
+    let _ = std::fs::remove_file(&path);
+    plugin
+}
+
+// on_request 固定返回数据段中的 JSON
+fn constant_output_module(output: &str) -> String {
+    format!(
+        r#"(module
+  (memory (export "memory") 1)
+  (data (i32.const 16) "{}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "on_request") (param i32 i32) (result i64)
+    (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const {}))))"#,
+        output.replace('"', "\\\""),
+        output.len()
+    )
+}
+
+fn request() -> ChatRequestJson {
+    serde_json::from_value(json!({
+        "model": "mock-model",
+        "messages": [{"role": "user", "content": "hello"}],
+    }))
+    .unwrap()
+}
+
+fn pre_routing(plugin: &WasmPlugin) -> Result<(), AppError> {
+    let headers = HeaderMap::new();
+    let ctx = RequestContext {
+        request_id: "test",
+        headers: &headers,
+    };
+    plugin.pre_routing(&ctx, &mut request())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn on_request_output_rejects_the_request() {
+    let plugin = load(
+        "reject",
+        &constant_output_module(r#"{"reject":"blocked by wasm"}"#),
+        1_000_000,
+    );
+    assert!(plugin.has_on_request());
+    assert!(!plugin.has_on_response());
+
+    // 在运行时的工作线程上调用
+    match pre_routing(&plugin) {
+        Err(AppError::BadRequest(reason)) => assert_eq!(reason, "blocked by wasm"),
+        other => panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+#[test]
+fn runaway_modules_stop_when_fuel_runs_out() {
+    let plugin = load(
+        "spin",
+        r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "on_request") (param i32 i32) (result i64)
+    (loop $spin (br $spin))
+    (i64.const 0)))"#,
+        10_000,
+    );
+
+    assert!(matches!(pre_routing(&plugin), Err(AppError::Internal(_))));
+}